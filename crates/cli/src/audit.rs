@@ -0,0 +1,158 @@
+//! `audit` command: scan installed packages for known vulnerabilities
+
+use anyhow::Result;
+use package_fast_core::PackageInfo;
+use package_fast_security::vulnerability::{Severity, VulnerabilityReport};
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use crate::exit::ExitCode;
+
+/// Aggregated result of auditing a set of packages
+#[derive(Debug, Clone)]
+pub struct AuditSummary {
+    /// One report per scanned package
+    pub reports: Vec<VulnerabilityReport>,
+    /// Number of findings at each severity
+    pub counts: BTreeMap<Severity, usize>,
+    /// Whether any finding met the `--fail-on` threshold
+    pub threshold_exceeded: bool,
+}
+
+impl AuditSummary {
+    /// Total number of findings across all packages
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// Exit code for this audit: `AuditThreshold` when the threshold was met
+    pub fn exit_code(&self) -> ExitCode {
+        if self.threshold_exceeded {
+            ExitCode::AuditThreshold
+        } else {
+            ExitCode::Success
+        }
+    }
+
+    /// Render the findings and per-severity totals as a plain-text table
+    pub fn render_table(&self) -> String {
+        let mut out = String::new();
+
+        for report in &self.reports {
+            for vuln in &report.vulnerabilities {
+                out.push_str(&format!(
+                    "{:<10} {:<30} {:<20} {}\n",
+                    format!("{:?}", vuln.effective_severity()),
+                    format!("{}@{}", report.package_name, report.package_version),
+                    vuln.id,
+                    vuln.title
+                ));
+            }
+        }
+
+        let totals: Vec<String> = self
+            .counts
+            .iter()
+            .rev()
+            .map(|(severity, count)| format!("{} {:?}", count, severity))
+            .collect();
+        if totals.is_empty() {
+            out.push_str(&format!("Scanned {} packages, found 0 vulnerabilities\n", self.reports.len()));
+        } else {
+            out.push_str(&format!(
+                "Scanned {} packages, found {} vulnerabilities ({})\n",
+                self.reports.len(),
+                self.total(),
+                totals.join(", ")
+            ));
+        }
+
+        out
+    }
+}
+
+/// Scan each package and aggregate the findings
+///
+/// # Arguments
+/// * `packages` - Packages to scan
+/// * `fail_on` - Minimum severity that fails the audit, if any
+/// * `scan` - Scanner invoked with each package's name and version
+///
+/// # Returns
+/// * `Ok(AuditSummary)` with the reports and severity totals
+/// * `Err(anyhow::Error)` if any scan fails
+pub async fn audit_packages<F, Fut>(packages: &[PackageInfo], fail_on: Option<Severity>, scan: F) -> Result<AuditSummary>
+where
+    F: Fn(String, String) -> Fut,
+    Fut: Future<Output = Result<VulnerabilityReport>>,
+{
+    let mut reports = Vec::with_capacity(packages.len());
+    let mut counts = BTreeMap::new();
+
+    for pkg in packages {
+        let report = scan(pkg.name.clone(), pkg.version.clone()).await?;
+        for vuln in &report.vulnerabilities {
+            *counts.entry(vuln.effective_severity()).or_insert(0) += 1;
+        }
+        reports.push(report);
+    }
+
+    let threshold_exceeded = fail_on
+        .map(|threshold| reports.iter().any(|report| report.meets_severity(&threshold)))
+        .unwrap_or(false);
+
+    Ok(AuditSummary {
+        reports,
+        counts,
+        threshold_exceeded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use package_fast_security::vulnerability::Vulnerability;
+
+    async fn mock_scan(name: String, version: String) -> Result<VulnerabilityReport> {
+        let mut report = VulnerabilityReport::new(name.clone(), version);
+        if name == "vulnerable" {
+            report.add_vulnerability(Vulnerability {
+                id: "GHSA-test-0001".to_string(),
+                title: "Command injection".to_string(),
+                description: "Test finding".to_string(),
+                severity: Severity::High,
+                cvss_score: None,
+                affected_versions: vec!["< 2.0.0".to_string()],
+                patched_versions: vec!["2.0.0".to_string()],
+                references: vec![],
+                sources: vec![],
+            });
+        }
+        Ok(report)
+    }
+
+    fn packages() -> Vec<PackageInfo> {
+        vec![PackageInfo::new("safe", "1.0.0"), PackageInfo::new("vulnerable", "1.0.0")]
+    }
+
+    #[tokio::test]
+    async fn test_audit_fails_on_threshold() {
+        let summary = audit_packages(&packages(), Some(Severity::High), mock_scan).await.unwrap();
+
+        assert_eq!(summary.reports.len(), 2);
+        assert_eq!(summary.counts.get(&Severity::High), Some(&1));
+        assert!(summary.threshold_exceeded);
+        assert_eq!(summary.exit_code(), ExitCode::AuditThreshold);
+        assert!(summary.render_table().contains("vulnerable@1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_below_threshold_passes() {
+        let summary = audit_packages(&packages(), Some(Severity::Critical), mock_scan).await.unwrap();
+        assert_eq!(summary.total(), 1);
+        assert_eq!(summary.exit_code(), ExitCode::Success);
+
+        let summary = audit_packages(&packages(), None, mock_scan).await.unwrap();
+        assert_eq!(summary.exit_code(), ExitCode::Success);
+    }
+}
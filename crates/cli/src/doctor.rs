@@ -0,0 +1,352 @@
+//! `doctor` command: diagnose a broken install
+//!
+//! Each check reports whether it passed and, when it didn't, a hint on how
+//! to fix it. Checks that depend on an earlier one that failed are skipped,
+//! as is the registry check in offline mode. A project without a lockfile
+//! skips the lockfile checks, since installs neither need nor write one.
+
+use package_fast_core::lockfile::{load_project_lockfile, Lockfile};
+use package_fast_core::prune::find_extraneous;
+use package_fast_core::registry::Registry;
+use package_fast_core::store::Store;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::exit::ExitCode;
+use crate::verify::verify_installed;
+
+/// Most store entries re-hashed by the store check
+pub const STORE_SAMPLE_SIZE: usize = 32;
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// Not run because a check it depends on failed or has nothing to check, or it needs the network while offline
+    Skipped,
+}
+
+/// Result of one diagnostic check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// What the check found
+    pub detail: String,
+    /// How to fix a failure
+    pub hint: Option<&'static str>,
+}
+
+impl Check {
+    fn passed(name: &'static str, detail: String) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Passed,
+            detail,
+            hint: None,
+        }
+    }
+
+    fn failed(name: &'static str, detail: String, hint: &'static str) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Failed,
+            detail,
+            hint: Some(hint),
+        }
+    }
+
+    fn skipped(name: &'static str, detail: &str) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Skipped,
+            detail: detail.to_string(),
+            hint: None,
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            CheckStatus::Passed => "pass",
+            CheckStatus::Failed => "FAIL",
+            CheckStatus::Skipped => "skip",
+        };
+        write!(f, "[{}] {}: {}", status, self.name, self.detail)?;
+        if let Some(hint) = self.hint {
+            write!(f, "\n       hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Results of every check, in the order they ran
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Exit code for the diagnosis: `Failure` if any check failed
+    pub fn exit_code(&self) -> ExitCode {
+        if self.checks.iter().any(|check| check.status == CheckStatus::Failed) {
+            ExitCode::Failure
+        } else {
+            ExitCode::Success
+        }
+    }
+}
+
+/// Run every check against a project
+///
+/// # Arguments
+/// * `project_dir` - Directory holding the lockfile and `node_modules`
+/// * `store` - Store whose entries are sampled
+/// * `registry` - Registry whose reachability is checked
+pub async fn diagnose(project_dir: &Path, store: &Store, registry: &Registry) -> DoctorReport {
+    let mut report = DoctorReport::default();
+
+    let lockfile = match load_project_lockfile(project_dir) {
+        Ok(Some(lockfile)) => {
+            report
+                .checks
+                .push(Check::passed("lockfile", format!("{} packages locked", lockfile.packages.len())));
+            Some(lockfile)
+        }
+        Ok(None) => {
+            report
+                .checks
+                .push(Check::skipped("lockfile", &format!("no lockfile found in {}", project_dir.display())));
+            None
+        }
+        Err(e) => {
+            report.checks.push(Check::failed(
+                "lockfile",
+                format!("{:#}", e),
+                "Fix the lockfile, or delete it to install without pinned versions",
+            ));
+            None
+        }
+    };
+
+    report.checks.push(match &lockfile {
+        Some(lockfile) => check_node_modules(project_dir, lockfile, store),
+        None => Check::skipped("node_modules", "needs a lockfile to compare against"),
+    });
+    report.checks.push(check_store(store));
+    report.checks.push(if registry.config().offline {
+        Check::skipped("registry", "offline mode is on")
+    } else {
+        match registry.ping().await {
+            Ok(url) => Check::passed("registry", format!("{} is reachable", url)),
+            Err(e) => Check::failed(
+                "registry",
+                format!("{:#}", e),
+                "Check your network connection, proxy settings (HTTPS_PROXY, NO_PROXY) and registry URL",
+            ),
+        }
+    });
+
+    report
+}
+
+/// Compare `node_modules` with the lockfile, both ways
+fn check_node_modules(project_dir: &Path, lockfile: &Lockfile, store: &Store) -> Check {
+    const NAME: &str = "node_modules";
+    const HINT: &str = "Run `package-fast install` to restore locked packages and `package-fast prune` to remove the rest";
+
+    let verify = match verify_installed(&project_dir.join("node_modules"), lockfile, store) {
+        Ok(verify) => verify,
+        Err(e) => return Check::failed(NAME, format!("{:#}", e), HINT),
+    };
+    let extraneous = match find_extraneous(project_dir) {
+        Ok(extraneous) => extraneous,
+        Err(e) => return Check::failed(NAME, format!("{:#}", e), HINT),
+    };
+
+    let mut problems: Vec<String> = verify.discrepancies.iter().map(ToString::to_string).collect();
+    problems.extend(extraneous.iter().map(|name| format!("{} is not in the lockfile", name)));
+    if problems.is_empty() {
+        Check::passed(NAME, format!("{} installed packages match the lockfile", verify.checked))
+    } else {
+        Check::failed(NAME, problems.join("; "), HINT)
+    }
+}
+
+/// Re-hash a sample of store entries spread evenly across the store
+///
+/// Unlike `cache verify`, corrupt entries are only reported, not removed.
+fn check_store(store: &Store) -> Check {
+    const NAME: &str = "store";
+    const HINT: &str = "Run `package-fast cache repair` to download corrupt entries again";
+
+    let entries = match store.entries() {
+        Ok(entries) => entries,
+        Err(e) => return Check::failed(NAME, format!("{:#}", e), HINT),
+    };
+    let step = entries.len().div_ceil(STORE_SAMPLE_SIZE).max(1);
+    let sample: Vec<_> = entries.iter().step_by(step).collect();
+
+    let corrupt: Vec<String> = sample
+        .iter()
+        .filter(|entry| !fs::read(&entry.path).is_ok_and(|bytes| entry.integrity.matches(&bytes)))
+        .map(|entry| entry.path.display().to_string())
+        .collect();
+    if corrupt.is_empty() {
+        Check::passed(NAME, format!("{} of {} entries sampled, all intact", sample.len(), entries.len()))
+    } else {
+        Check::failed(NAME, format!("corrupt entries: {}", corrupt.join(", ")), HINT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use package_fast_core::lockfile::{LockedPackage, LOCKFILE_NAME};
+    use package_fast_core::registry::RegistryConfig;
+    use package_fast_core::store::StoreConfig;
+    use package_fast_core::tarball::extract_tarball;
+    use package_fast_core::testing::build_tarball;
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// A project with one package installed, locked and stored
+    fn healthy_project() -> (TempDir, Store) {
+        let project = TempDir::new().unwrap();
+        let store = Store::with_config(StoreConfig {
+            root: project.path().join("store"),
+            ..Default::default()
+        });
+        let bytes = build_tarball(&[("package/package.json", r#"{ "name": "left-pad", "version": "1.3.0" }"#)]);
+        let integrity = store.put(&bytes).unwrap();
+        extract_tarball(&bytes, &project.path().join("node_modules/left-pad"), None).unwrap();
+
+        let mut lockfile = Lockfile::new();
+        let mut locked = LockedPackage::new("left-pad", "1.3.0");
+        locked.integrity = Some(integrity.to_sri());
+        lockfile.insert(locked);
+        lockfile.write(&project.path().join(LOCKFILE_NAME)).unwrap();
+        (project, store)
+    }
+
+    async fn registry() -> (MockServer, Registry) {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/-/ping"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&server)
+            .await;
+        let registry = Registry::with_config(RegistryConfig {
+            url: server.uri(),
+            cache_dir: None,
+            ..Default::default()
+        });
+        (server, registry)
+    }
+
+    fn statuses(report: &DoctorReport) -> Vec<(&str, CheckStatus)> {
+        report.checks.iter().map(|check| (check.name, check.status)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_doctor_healthy_project() {
+        let (project, store) = healthy_project();
+        let (_server, registry) = registry().await;
+
+        let report = diagnose(project.path(), &store, &registry).await;
+        assert_eq!(
+            statuses(&report),
+            vec![
+                ("lockfile", CheckStatus::Passed),
+                ("node_modules", CheckStatus::Passed),
+                ("store", CheckStatus::Passed),
+                ("registry", CheckStatus::Passed),
+            ]
+        );
+        assert_eq!(report.exit_code(), ExitCode::Success);
+        assert_eq!(report.checks[2].detail, "1 of 1 entries sampled, all intact");
+    }
+
+    #[tokio::test]
+    async fn test_doctor_corrupted_lockfile() {
+        let (project, store) = healthy_project();
+        let (_server, registry) = registry().await;
+        fs::write(project.path().join(LOCKFILE_NAME), "{ \"packages\": ").unwrap();
+
+        let report = diagnose(project.path(), &store, &registry).await;
+        assert_eq!(
+            statuses(&report),
+            vec![
+                ("lockfile", CheckStatus::Failed),
+                ("node_modules", CheckStatus::Skipped),
+                ("store", CheckStatus::Passed),
+                ("registry", CheckStatus::Passed),
+            ]
+        );
+        assert_eq!(report.exit_code(), ExitCode::Failure);
+        assert!(report.checks[0].to_string().contains("hint: Fix the lockfile"));
+    }
+
+    #[tokio::test]
+    async fn test_doctor_without_lockfile() {
+        let (project, store) = healthy_project();
+        let (_server, registry) = registry().await;
+        fs::remove_file(project.path().join(LOCKFILE_NAME)).unwrap();
+
+        let report = diagnose(project.path(), &store, &registry).await;
+        assert_eq!(
+            statuses(&report),
+            vec![
+                ("lockfile", CheckStatus::Skipped),
+                ("node_modules", CheckStatus::Skipped),
+                ("store", CheckStatus::Passed),
+                ("registry", CheckStatus::Passed),
+            ]
+        );
+        assert_eq!(report.exit_code(), ExitCode::Success);
+        assert!(report.checks[0].detail.starts_with("no lockfile found in"));
+    }
+
+    #[tokio::test]
+    async fn test_doctor_reports_drift_and_corrupt_store() {
+        let (project, store) = healthy_project();
+        fs::create_dir_all(project.path().join("node_modules/orphan")).unwrap();
+        let entry = &store.entries().unwrap()[0];
+        fs::write(&entry.path, b"garbage").unwrap();
+
+        let unreachable = Registry::with_config(RegistryConfig {
+            url: "http://127.0.0.1:9".to_string(),
+            cache_dir: None,
+            ..Default::default()
+        });
+        let report = diagnose(project.path(), &store, &unreachable).await;
+        let failed: Vec<&str> = report
+            .checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Failed)
+            .map(|check| check.name)
+            .collect();
+        assert_eq!(failed, vec!["node_modules", "store", "registry"]);
+        assert!(report.checks[1].detail.contains("orphan is not in the lockfile"));
+        assert!(entry.path.is_file(), "the doctor never removes store entries");
+    }
+
+    #[tokio::test]
+    async fn test_doctor_skips_registry_offline() {
+        let (project, store) = healthy_project();
+        let offline = Registry::with_config(RegistryConfig {
+            url: "http://127.0.0.1:9".to_string(),
+            cache_dir: None,
+            offline: true,
+            ..Default::default()
+        });
+
+        let report = diagnose(project.path(), &store, &offline).await;
+        assert_eq!(report.checks[3].status, CheckStatus::Skipped);
+        assert_eq!(report.exit_code(), ExitCode::Success);
+    }
+}
@@ -0,0 +1,129 @@
+//! Process exit codes
+//!
+//! Commands report how they finished as an `ExitCode` so scripts can tell
+//! failure kinds apart without parsing output.
+
+use package_fast_core::CoreError;
+use package_fast_security::IntegrityError;
+
+/// How a command finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// The command succeeded
+    Success,
+    /// An error without a more specific code
+    Failure,
+    /// Dependencies could not be resolved (conflicts, unmet peers, invalid names, ranges or overrides, frozen lockfile, too deep)
+    Resolution,
+    /// A package failed integrity verification
+    Integrity,
+    /// `audit` found a vulnerability at or above the `--fail-on` threshold
+    AuditThreshold,
+    /// A script finished with a non-zero status, passed through as-is
+    Script(i32),
+}
+
+impl ExitCode {
+    /// Numeric status reported to the shell
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::Failure => 1,
+            ExitCode::Resolution => 2,
+            ExitCode::Integrity => 3,
+            ExitCode::AuditThreshold => 4,
+            ExitCode::Script(code) => code,
+        }
+    }
+
+    /// Pick the exit code for an error returned by a command
+    ///
+    /// The whole context chain is searched, so errors keep their code when
+    /// wrapped with extra context.
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(core) = cause.downcast_ref::<CoreError>() {
+                return match core {
+                    CoreError::IntegrityMismatch { .. } | CoreError::StoreEntryCorrupt { .. } => ExitCode::Integrity,
+                    CoreError::VersionConflict(_)
+                    | CoreError::FrozenLockfile(_)
+                    | CoreError::InvalidPackageName { .. }
+                    | CoreError::InvalidRange { .. }
+                    | CoreError::InvalidVersion { .. }
+                    | CoreError::PackageNotFound { .. }
+                    | CoreError::DependencyTooDeep { .. }
+                    | CoreError::InvalidOverride { .. }
+                    | CoreError::UnmetPeerDependencies(_) => ExitCode::Resolution,
+                    CoreError::Timeout { .. }
+                    | CoreError::TarballTooLarge { .. }
+                    | CoreError::MetadataTooLarge { .. }
+                    | CoreError::LicenseDenied { .. }
+                    | CoreError::RequiresNetwork(_)
+                    | CoreError::Cancelled => ExitCode::Failure,
+                };
+            }
+            if let Some(integrity) = cause.downcast_ref::<IntegrityError>() {
+                return match integrity {
+                    IntegrityError::IoError(_) => ExitCode::Failure,
+                    _ => ExitCode::Integrity,
+                };
+            }
+        }
+        ExitCode::Failure
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        // Statuses outside 0-255 can't be reported faithfully; keep them non-zero
+        u8::try_from(code.code()).map_or(std::process::ExitCode::FAILURE, std::process::ExitCode::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_from_error_maps_core_errors() {
+        let err = anyhow::Error::from(CoreError::FrozenLockfile("no lockfile".to_string()));
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Resolution);
+
+        let err = anyhow::Error::from(CoreError::UnmetPeerDependencies(vec!["react".to_string()]));
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Resolution);
+
+        let err = Err::<(), _>(CoreError::IntegrityMismatch {
+            expected: "sha512-a".to_string(),
+            actual: "sha512-b".to_string(),
+        })
+        .context("Failed to verify left-pad@1.3.0")
+        .unwrap_err();
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Integrity);
+    }
+
+    #[test]
+    fn test_from_error_maps_security_and_other_errors() {
+        let err = anyhow::Error::from(IntegrityError::HashMismatch {
+            expected: "a".to_string(),
+            actual: "b".to_string(),
+        });
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Integrity);
+
+        assert_eq!(ExitCode::from_error(&anyhow::anyhow!("disk full")), ExitCode::Failure);
+    }
+
+    #[test]
+    fn test_codes_are_distinct() {
+        let codes = [
+            ExitCode::Success,
+            ExitCode::Failure,
+            ExitCode::Resolution,
+            ExitCode::Integrity,
+            ExitCode::AuditThreshold,
+        ]
+        .map(ExitCode::code);
+        assert_eq!(codes, [0, 1, 2, 3, 4]);
+        assert_eq!(ExitCode::Script(7).code(), 7);
+    }
+}
@@ -0,0 +1,180 @@
+//! Install lifecycle scripts
+//!
+//! Core decides which lifecycle scripts may run; the allowed ones are
+//! executed here through the security crate's sandbox, confined to the
+//! package directory. Every script that would run is recorded in the audit
+//! trail, whether it ran or was blocked.
+
+use anyhow::Result;
+use package_fast_core::scripts::{LifecycleScript, ScriptDecision};
+use package_fast_security::audit::{AuditEvent, AuditEventType, AuditTrail};
+use package_fast_security::sandbox::{SandboxConfig, SandboxRuntimeProtection};
+use std::path::Path;
+
+/// File in `node_modules` that lifecycle script events are appended to by default
+pub const AUDIT_LOG_FILE: &str = ".package-fast-audit.jsonl";
+
+/// Run the allowed lifecycle scripts of installed packages
+///
+/// Ignored scripts are skipped silently. Blocked scripts are recorded as
+/// failed `RuntimeProtection` events without running. Allowed scripts run
+/// in order in their package directory; the first one that fails stops the
+/// install.
+///
+/// # Arguments
+/// * `node_modules` - Directory the packages are installed in
+/// * `scripts` - Detected lifecycle scripts, in the order they should run
+/// * `allow_network` - Whether allowed scripts may reach the network
+/// * `audit_trail` - Trail the script events are recorded in
+///
+/// # Returns
+/// * `Ok(usize)` with the number of scripts that ran
+/// * `Err(anyhow::Error)` if a script fails or its event can't be recorded
+pub async fn run_lifecycle_scripts(
+    node_modules: &Path,
+    scripts: &[LifecycleScript],
+    allow_network: bool,
+    audit_trail: &mut AuditTrail,
+) -> Result<usize> {
+    let mut ran = 0;
+    for script in scripts {
+        let event = AuditEvent::new(AuditEventType::RuntimeProtection)
+            .with_package_name(script.package.clone())
+            .with_package_version(script.version.clone())
+            .with_detail("script".to_string(), script.event.clone())
+            .with_detail("command".to_string(), script.command.clone())
+            .with_detail("decision".to_string(), script.decision.to_string());
+
+        match script.decision {
+            ScriptDecision::Ignored => {}
+            ScriptDecision::Blocked => {
+                audit_trail.add_event(event.with_error("package is not allowed to run scripts".to_string()))?;
+            }
+            ScriptDecision::Allowed => {
+                let dir = node_modules.join(&script.package);
+                let config = SandboxConfig {
+                    allowed_directories: [dir.clone()].into_iter().collect(),
+                    allow_process_creation: true,
+                    allow_network,
+                    ..Default::default()
+                };
+                let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+                let result = SandboxRuntimeProtection::with_config(config)
+                    .execute_sandboxed(shell, &[flag.to_string(), script.command.clone()], &dir)
+                    .await?;
+
+                let exit_code = result.exit_code.map_or("none".to_string(), |code| code.to_string());
+                let event = event.with_detail("exit_code".to_string(), exit_code.clone());
+                if result.exit_code == Some(0) {
+                    audit_trail.add_event(event)?;
+                    ran += 1;
+                } else {
+                    let error = result.error.unwrap_or_else(|| format!("exited with status {}", exit_code));
+                    audit_trail.add_event(event.with_error(error.clone()))?;
+                    anyhow::bail!("{}@{} {} script failed: {}", script.package, script.version, script.event, error);
+                }
+            }
+        }
+    }
+    Ok(ran)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use package_fast_core::scripts::detect_install_scripts;
+    use package_fast_core::{InstallOptions, PackageInfo};
+    use tempfile::TempDir;
+
+    /// Lay out installed packages declaring `(name, event, command)` scripts
+    fn installed(packages: &[(&str, &str, &str)]) -> (TempDir, Vec<PackageInfo>) {
+        let project = TempDir::new().unwrap();
+        let packages = packages
+            .iter()
+            .map(|(name, event, command)| {
+                std::fs::create_dir_all(project.path().join("node_modules").join(name)).unwrap();
+                let mut pkg = PackageInfo::new(name, "1.0.0");
+                pkg.scripts.insert(event.to_string(), command.to_string());
+                pkg
+            })
+            .collect();
+        (project, packages)
+    }
+
+    fn allowing(packages: &[&str]) -> InstallOptions {
+        InstallOptions {
+            ignore_scripts: false,
+            allowed_scripts: packages.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scripts_ignored_by_default() {
+        let (project, packages) = installed(&[("native", "postinstall", "echo built > built.txt")]);
+        let scripts = detect_install_scripts(&packages, &InstallOptions::default());
+
+        let mut audit_trail = AuditTrail::new();
+        let node_modules = project.path().join("node_modules");
+        let ran = run_lifecycle_scripts(&node_modules, &scripts, false, &mut audit_trail).await.unwrap();
+        assert_eq!(ran, 0);
+        assert!(audit_trail.events().is_empty());
+        assert!(!node_modules.join("native/built.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_allow_listed_scripts_run_and_others_are_blocked() {
+        let (project, packages) = installed(&[
+            ("native", "postinstall", "echo built > built.txt"),
+            ("sketchy", "preinstall", "echo pwned > pwned.txt"),
+        ]);
+        let scripts = detect_install_scripts(&packages, &allowing(&["native"]));
+
+        let mut audit_trail = AuditTrail::new();
+        let node_modules = project.path().join("node_modules");
+        let ran = run_lifecycle_scripts(&node_modules, &scripts, false, &mut audit_trail).await.unwrap();
+        assert_eq!(ran, 1);
+        assert_eq!(std::fs::read_to_string(node_modules.join("native/built.txt")).unwrap(), "built\n");
+        assert!(!node_modules.join("sketchy/pwned.txt").exists());
+
+        let native = audit_trail.events_for_package("native");
+        assert!(native[0].success);
+        assert_eq!(native[0].details["decision"], "allowed");
+        assert_eq!(native[0].details["exit_code"], "0");
+
+        let sketchy = audit_trail.events_for_package("sketchy");
+        assert_eq!(sketchy[0].event_type, AuditEventType::RuntimeProtection);
+        assert_eq!(sketchy[0].details["decision"], "blocked");
+        assert_eq!(sketchy[0].details["script"], "preinstall");
+        assert!(!sketchy[0].success);
+    }
+
+    #[tokio::test]
+    async fn test_failing_script_stops_install() {
+        let (project, packages) = installed(&[("broken", "install", "exit 7")]);
+        let scripts = detect_install_scripts(&packages, &allowing(&["broken"]));
+
+        let mut audit_trail = AuditTrail::new();
+        let err = run_lifecycle_scripts(&project.path().join("node_modules"), &scripts, false, &mut audit_trail)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("broken@1.0.0 install script failed"));
+        assert_eq!(audit_trail.events()[0].details["exit_code"], "7");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_scripts_network_follows_policy() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connect = format!("bash -c 'echo hi > /dev/tcp/127.0.0.1/{}'", port);
+        let (project, packages) = installed(&[("fetcher", "postinstall", connect.as_str())]);
+        let scripts = detect_install_scripts(&packages, &allowing(&["fetcher"]));
+        let node_modules = project.path().join("node_modules");
+
+        let offline = run_lifecycle_scripts(&node_modules, &scripts, false, &mut AuditTrail::new()).await;
+        assert!(offline.is_err(), "script reached the network without being allowed to");
+        let online = run_lifecycle_scripts(&node_modules, &scripts, true, &mut AuditTrail::new()).await;
+        assert_eq!(online.unwrap(), 1);
+    }
+}
@@ -1,9 +1,36 @@
 //! Package Fast CLI - Command line interface for Package Fast
 
+mod audit;
+mod doctor;
+mod exit;
+mod lifecycle;
+mod run;
+mod verify;
+
 use anyhow::Result;
-use clap::Parser;
-use package_fast_core::{install_all_dependencies, install_packages, InstallOptions};
-use tracing_subscriber;
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
+use exit::ExitCode;
+use package_fast_core::download::default_extract_workers;
+use package_fast_core::global::GlobalPaths;
+use package_fast_core::graph::DependencyGraph;
+use package_fast_core::license::LicensePolicy;
+use package_fast_core::lockfile::load_project_lockfile;
+use package_fast_core::manifest::{
+    default_package_name, init_package_json, read_installed_packages, read_package_info, MANIFEST_FILE,
+};
+use package_fast_core::prune::{find_extraneous, prune_extraneous};
+use package_fast_core::registry::{Registry, RegistryConfig};
+use package_fast_core::scripts::ScriptDecision;
+use package_fast_core::spec::read_spec_file;
+use package_fast_core::store::Store;
+use package_fast_core::{
+    add_packages, install_all_dependencies, install_packages, AddOptions, HttpRegistry, InstallOptions, PackageInfo,
+};
+use package_fast_security::vuln_db::VulnerabilityDatabaseClient;
+use package_fast_security::{scan_with_client, AuditTrail, Ecosystem, Severity};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
 
 /// Package Fast - A very fast Node.js package manager
 #[derive(Parser, Debug)]
@@ -13,11 +40,29 @@ struct Args {
     #[arg(short, long, action = clap::ArgAction::Count)]
     debug: u8,
 
+    /// Print details such as where the time of an install went
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Never touch the network: use cached metadata and stored tarballs
+    #[arg(long, global = true)]
+    offline: bool,
+
     /// Subcommands
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+impl Args {
+    /// Registry configuration honoring `--offline`
+    fn registry_config(&self) -> RegistryConfig {
+        RegistryConfig {
+            offline: self.offline,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(clap::Subcommand, Debug)]
 enum Commands {
     /// Install dependencies
@@ -34,6 +79,70 @@ enum Commands {
         #[arg(short, long)]
         force: bool,
 
+        /// Fail on missing or mismatched peer dependencies
+        #[arg(long)]
+        strict_peer_deps: bool,
+
+        /// Install into the global prefix and link package executables
+        #[arg(short, long)]
+        global: bool,
+
+        /// Fail instead of resolving anything the lockfile doesn't pin
+        #[arg(long)]
+        frozen_lockfile: bool,
+
+        /// Keep installing the remaining packages when one fails, then report the failures
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Read additional specs from a file, one per line (`#` starts a comment)
+        #[arg(long, value_name = "PATH")]
+        from_file: Option<PathBuf>,
+
+        /// Run install lifecycle scripts of these packages, blocking all others
+        #[arg(long, value_name = "NAME", value_delimiter = ',')]
+        allow_scripts: Vec<String>,
+
+        /// Stage downloads and extraction in this directory instead of the default locations
+        #[arg(long, value_name = "DIR")]
+        temp_dir: Option<PathBuf>,
+
+        /// Skip versions published fewer than this many days ago
+        #[arg(long, value_name = "DAYS")]
+        min_version_age: Option<u64>,
+
+        /// Abandon the download of any package tarball larger than this
+        #[arg(long, value_name = "BYTES")]
+        max_package_size: Option<u64>,
+
+        /// Number of tarballs to extract at once (defaults to the number of CPU cores)
+        #[arg(long, value_name = "N")]
+        extract_workers: Option<usize>,
+
+        /// Let ranges match pre-release versions within their bounds
+        #[arg(long)]
+        include_prereleases: bool,
+
+        /// Don't warn about package names that look like typos of popular packages
+        #[arg(long)]
+        no_typosquat_check: bool,
+
+        /// Only install packages under these SPDX licenses (`GPL-*` matches a prefix)
+        #[arg(long, value_name = "LICENSE", value_delimiter = ',')]
+        allow_license: Vec<String>,
+
+        /// Fail the install if a package is under one of these SPDX licenses
+        #[arg(long, value_name = "LICENSE", value_delimiter = ',')]
+        deny_license: Vec<String>,
+
+        /// Let allowed lifecycle scripts reach the network
+        #[arg(long)]
+        allow_script_network: bool,
+
+        /// Append lifecycle script audit events to this file [default: node_modules/.package-fast-audit.jsonl]
+        #[arg(long, value_name = "PATH")]
+        audit_log: Option<PathBuf>,
+
         /// Packages to install
         packages: Vec<String>,
     },
@@ -44,6 +153,10 @@ enum Commands {
         #[arg(short = 'D', long)]
         dev: bool,
 
+        /// Save the exact version instead of a caret range
+        #[arg(short = 'E', long)]
+        save_exact: bool,
+
         /// Packages to add
         packages: Vec<String>,
     },
@@ -59,40 +172,251 @@ enum Commands {
         /// Packages to update
         packages: Vec<String>,
     },
+
+    /// Scan installed packages for known vulnerabilities
+    Audit {
+        /// Exit non-zero if any finding is at least this severe (low, medium, high, critical)
+        #[arg(long)]
+        fail_on: Option<Severity>,
+
+        /// Print the full report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Search the registry for packages
+    Search {
+        /// Search text
+        query: String,
+
+        /// Most results to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check that installed packages match the lockfile
+    Verify,
+
+    /// Diagnose the lockfile, node_modules, store and registry connection
+    Doctor,
+
+    /// Remove packages in node_modules that the lockfile doesn't reference
+    Prune {
+        /// Only report the packages that would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Print the dependency graph of the installed packages
+    Graph {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+
+        /// Let ranges match pre-release versions within their bounds
+        #[arg(long)]
+        include_prereleases: bool,
+    },
+
+    /// Run a script from package.json
+    Run {
+        /// Name of the script
+        script: String,
+
+        /// Extra arguments passed to the script (after `--`)
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Create a package.json in the current directory
+    Init {
+        /// Accept the defaults without prompting
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Overwrite an existing package.json
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: Shell,
+    },
+
+    /// Manage the metadata cache and tarball store
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// JSON with `nodes` and `edges`
+    Json,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CacheAction {
+    /// Remove all cached metadata and stored tarballs
+    Clean,
+
+    /// Re-hash stored tarballs and remove corrupt entries
+    Verify,
+
+    /// Re-hash stored tarballs and download corrupt entries again
+    Repair,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> std::process::ExitCode {
     tracing_subscriber::fmt::init();
     
     let args = Args::parse();
     
+    match run(&args).await {
+        Ok(code) => code.into(),
+        Err(err) => {
+            eprintln!("error: {:#}", err);
+            ExitCode::from_error(&err).into()
+        }
+    }
+}
+
+/// Run the requested command
+///
+/// # Returns
+/// * `Ok(ExitCode)` describing how a command that ran to completion finished
+/// * `Err(anyhow::Error)` if the command failed; see `ExitCode::from_error`
+async fn run(args: &Args) -> Result<ExitCode> {
     match &args.command {
-        Some(Commands::Install { dev, prod, force, packages }) => {
+        Some(Commands::Install {
+            dev,
+            prod,
+            force,
+            strict_peer_deps,
+            global,
+            frozen_lockfile,
+            continue_on_error,
+            from_file,
+            allow_scripts,
+            temp_dir,
+            min_version_age,
+            max_package_size,
+            extract_workers,
+            include_prereleases,
+            no_typosquat_check,
+            allow_license,
+            deny_license,
+            allow_script_network,
+            audit_log,
+            packages,
+        }) => {
             let options = InstallOptions {
                 dev_only: *dev,
                 prod_only: *prod,
                 force: *force,
+                strict_peer_deps: *strict_peer_deps,
+                global: *global,
+                frozen: *frozen_lockfile,
+                continue_on_error: *continue_on_error,
+                ignore_scripts: allow_scripts.is_empty(),
+                allowed_scripts: allow_scripts.iter().cloned().collect(),
+                temp_dir: temp_dir.clone(),
+                min_version_age_days: *min_version_age,
+                max_package_size_bytes: *max_package_size,
+                include_prereleases: *include_prereleases,
+                extract_workers: extract_workers.unwrap_or_else(default_extract_workers),
+                check_typosquats: !*no_typosquat_check,
+                license_policy: (!allow_license.is_empty() || !deny_license.is_empty()).then(|| LicensePolicy {
+                    allow: allow_license.clone(),
+                    deny: deny_license.clone(),
+                }),
+                offline: args.offline,
+                ..Default::default()
             };
             
-            if packages.is_empty() {
+            let mut packages = packages.clone();
+            if let Some(path) = from_file {
+                packages.extend(read_spec_file(path)?);
+            }
+            
+            let result = if packages.is_empty() && from_file.is_none() {
                 println!("Installing all dependencies from package.json");
-                let result = install_all_dependencies(&options).await?;
-                println!("Installed {} packages", result.installed_packages.len());
+                install_all_dependencies(&options).await?
             } else {
                 println!("Installing packages: {:?}", packages);
-                let result = install_packages(packages, &options).await?;
-                println!("Installed {} packages", result.installed_packages.len());
+                install_packages(&packages, &options, &HttpRegistry::with_config(args.registry_config())).await?
+            };
+            
+            println!("Installed {} packages", result.installed_packages.len());
+            println!(
+                "{} direct, {} transitive, {} deduped, {} bytes downloaded",
+                result.direct_count, result.transitive_count, result.deduped_count, result.total_size
+            );
+            if args.verbose {
+                println!("Timing ({:.1?} total):", result.duration);
+                for line in result.report.to_string().lines() {
+                    println!("  {}", line);
+                }
+            }
+            for warning in &result.warnings {
+                println!("warning: {}", warning);
+            }
+            for failure in &result.failed {
+                eprintln!("failed: {}", failure);
+            }
+
+            let node_modules = if *global {
+                GlobalPaths::new(None)?.lib_dir().join("node_modules")
+            } else {
+                std::env::current_dir()?.join("node_modules")
+            };
+            for script in result.lifecycle_scripts.iter().filter(|s| s.decision != ScriptDecision::Allowed) {
+                println!("skipped {}", script);
+            }
+            let audit_log = audit_log.clone().unwrap_or_else(|| node_modules.join(lifecycle::AUDIT_LOG_FILE));
+            let mut audit_trail = AuditTrail::with_output_file(audit_log.to_string_lossy().into_owned());
+            lifecycle::run_lifecycle_scripts(
+                &node_modules,
+                &result.lifecycle_scripts,
+                *allow_script_network,
+                &mut audit_trail,
+            )
+            .await?;
+            if !result.failed.is_empty() {
+                return Ok(ExitCode::Resolution);
             }
         }
-        Some(Commands::Add { dev, packages }) => {
+        Some(Commands::Add { dev, save_exact, packages }) => {
             println!("Adding packages: {:?}", packages);
             if *dev {
                 println!("Adding to devDependencies");
             } else {
                 println!("Adding to dependencies");
             }
-            // TODO: Implement add logic
+            let add_options = AddOptions {
+                dev: *dev,
+                save_exact: *save_exact,
+            };
+            let options = InstallOptions {
+                offline: args.offline,
+                ..Default::default()
+            };
+            let result = add_packages(packages, &add_options, &options).await?;
+
+            println!("Installed {} packages", result.installed_packages.len());
+            for warning in &result.warnings {
+                println!("warning: {}", warning);
+            }
         }
         Some(Commands::Remove { packages }) => {
             println!("Removing packages: {:?}", packages);
@@ -102,10 +426,342 @@ async fn main() -> Result<()> {
             println!("Updating packages: {:?}", packages);
             // TODO: Implement update logic
         }
+        Some(Commands::Audit { fail_on, json }) => {
+            let packages = read_installed_packages(&std::env::current_dir()?.join("node_modules"))?;
+            let mut client = VulnerabilityDatabaseClient::new();
+            client.set_offline(args.offline);
+            let client = &client;
+            let summary = audit::audit_packages(&packages, fail_on.clone(), |name, version| async move {
+                scan_with_client(client, &name, &version, Ecosystem::Npm).await
+            })
+            .await?;
+
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&summary.reports)?);
+            } else {
+                print!("{}", summary.render_table());
+            }
+            return Ok(summary.exit_code());
+        }
+        Some(Commands::Search { query, limit, json }) => {
+            let results = Registry::with_config(args.registry_config()).search(query, *limit).await?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&results)?);
+            } else if results.is_empty() {
+                println!("No packages found matching {:?}", query);
+            } else {
+                for result in &results {
+                    println!(
+                        "{:<32} {:<12} {}",
+                        result.name,
+                        result.version,
+                        result.description.as_deref().unwrap_or("")
+                    );
+                }
+            }
+        }
+        Some(Commands::Verify) => {
+            let project_dir = std::env::current_dir()?;
+            let lockfile = load_project_lockfile(&project_dir)?
+                .ok_or_else(|| anyhow::anyhow!("No lockfile found in {}", project_dir.display()))?;
+            let report = verify::verify_installed(&project_dir.join("node_modules"), &lockfile, &Store::new())?;
+
+            for discrepancy in &report.discrepancies {
+                println!("{}", discrepancy);
+            }
+            for package in &report.unverified {
+                println!("warning: {} could not be checked: its tarball is not in the store", package);
+            }
+            println!(
+                "Verified {} packages, found {} discrepancies",
+                report.checked,
+                report.discrepancies.len()
+            );
+            return Ok(report.exit_code());
+        }
+        Some(Commands::Doctor) => {
+            let registry = Registry::with_config(args.registry_config());
+            let report = doctor::diagnose(&std::env::current_dir()?, &Store::new(), &registry).await;
+            for check in &report.checks {
+                println!("{}", check);
+            }
+            return Ok(report.exit_code());
+        }
+        Some(Commands::Prune { dry_run }) => {
+            let project_dir = std::env::current_dir()?;
+            if *dry_run {
+                let extraneous = find_extraneous(&project_dir)?;
+                for name in &extraneous {
+                    println!("would remove {}", name);
+                }
+                println!("{} extraneous packages", extraneous.len());
+            } else {
+                let removed = prune_extraneous(&project_dir)?;
+                for name in &removed {
+                    println!("removed {}", name);
+                }
+                println!("Pruned {} packages", removed.len());
+            }
+        }
+        Some(Commands::Graph { format, include_prereleases }) => {
+            let packages = graph_packages(&std::env::current_dir()?)?;
+            let graph = DependencyGraph::from_packages(&packages, *include_prereleases);
+            match format {
+                GraphFormat::Dot => print!("{}", graph.to_dot()),
+                GraphFormat::Json => println!("{}", graph.to_json()?),
+            }
+        }
+        Some(Commands::Run { script, args }) => {
+            let result = run::run_script(&std::env::current_dir()?, script, args).await?;
+            if result.timed_out {
+                anyhow::bail!("Script \"{}\" timed out", script);
+            }
+            match result.exit_code {
+                Some(0) => {}
+                Some(code) => return Ok(ExitCode::Script(code)),
+                None => anyhow::bail!(
+                    "Script \"{}\" failed: {}",
+                    script,
+                    result.error.as_deref().unwrap_or("terminated by signal")
+                ),
+            }
+        }
+        Some(Commands::Init { yes, force }) => {
+            let dir = std::env::current_dir()?;
+            let mut name = default_package_name(&dir);
+            let mut version = "1.0.0".to_string();
+            if !*yes {
+                name = prompt("package name", &name)?;
+                version = prompt("version", &version)?;
+            }
+
+            let path = init_package_json(&dir, &PackageInfo::new(&name, &version), *force)?;
+            println!("Wrote {}", path.display());
+        }
+        Some(Commands::Completions { shell }) => {
+            write_completions(*shell, &mut io::stdout());
+        }
+        Some(Commands::Cache { action: CacheAction::Clean }) => {
+            let reclaimed = Store::new().clean()? + Registry::new().clear_metadata_cache()?;
+            println!("Cache cleaned, reclaimed {} bytes", reclaimed);
+        }
+        Some(Commands::Cache { action: CacheAction::Verify }) => {
+            let report = Store::new().verify()?;
+            for path in &report.removed {
+                println!("removed corrupt entry: {}", path.display());
+            }
+            println!(
+                "Verified {} entries, removed {}, reclaimed {} bytes",
+                report.checked,
+                report.removed.len(),
+                report.bytes_reclaimed
+            );
+        }
+        Some(Commands::Cache { action: CacheAction::Repair }) => {
+            let report = Store::new().repair(&HttpRegistry::with_config(args.registry_config())).await?;
+            for path in &report.repaired {
+                println!("repaired: {}", path.display());
+            }
+            for path in &report.skipped {
+                println!("skipped (unknown source): {}", path.display());
+            }
+            for (path, reason) in &report.failed {
+                println!("failed: {}: {}", path.display(), reason);
+            }
+            println!(
+                "Checked {} entries, repaired {}, skipped {}, failed {}",
+                report.checked,
+                report.repaired.len(),
+                report.skipped.len(),
+                report.failed.len()
+            );
+            if !report.failed.is_empty() {
+                return Ok(ExitCode::Integrity);
+            }
+        }
         None => {
             println!("No command provided. Use --help for usage information.");
         }
     }
     
-    Ok(())
-}
\ No newline at end of file
+    Ok(ExitCode::Success)
+}
+
+/// Ask a question on stdin, returning the default for an empty answer
+fn prompt(question: &str, default: &str) -> Result<String> {
+    print!("{}: ({}) ", question, default);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Collect the project package (if any) followed by everything installed
+///
+/// The project's dev dependencies are included as edges from the project.
+fn graph_packages(dir: &std::path::Path) -> Result<Vec<PackageInfo>> {
+    let mut packages = Vec::new();
+    if dir.join(MANIFEST_FILE).is_file() {
+        let mut project = read_package_info(dir)?;
+        let dev_dependencies = std::mem::take(&mut project.dev_dependencies);
+        project.dependencies.extend(dev_dependencies);
+        packages.push(project);
+    }
+    packages.extend(read_installed_packages(&dir.join("node_modules"))?);
+    Ok(packages)
+}
+
+/// Write the completion script for a shell
+fn write_completions(shell: Shell, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut Args::command(), "package-fast", out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+    use package_fast_core::{CoreError, PackageRegistry};
+
+    #[test]
+    fn test_completions_for_every_shell() {
+        for shell in Shell::value_variants() {
+            let mut out = Vec::new();
+            write_completions(*shell, &mut out);
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("package-fast"), "{} completions missing binary name", shell);
+        }
+    }
+
+    #[test]
+    fn test_args_are_consistent() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_graph_format_flag() {
+        let args = Args::try_parse_from(["package-fast", "graph"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Commands::Graph { format: GraphFormat::Dot, include_prereleases: false })
+        ));
+
+        let args =
+            Args::try_parse_from(["package-fast", "graph", "--format", "json", "--include-prereleases"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Commands::Graph { format: GraphFormat::Json, include_prereleases: true })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_maps_resolution_failure() {
+        let args = Args::try_parse_from(["package-fast", "install", "Bad_Name"]).unwrap();
+        let err = run(&args).await.unwrap_err();
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Resolution);
+        assert_eq!(ExitCode::from_error(&err).code(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_succeeds_without_command() {
+        let args = Args::try_parse_from(["package-fast"]).unwrap();
+        assert_eq!(run(&args).await.unwrap(), ExitCode::Success);
+
+        let args = Args::try_parse_from(["package-fast", "completions", "bash"]).unwrap();
+        assert_eq!(run(&args).await.unwrap(), ExitCode::Success);
+    }
+
+    #[test]
+    fn test_install_from_file_flag() {
+        let args = Args::try_parse_from(["package-fast", "install", "--from-file", "specs.txt", "lodash"]).unwrap();
+        match args.command {
+            Some(Commands::Install { from_file, packages, .. }) => {
+                assert_eq!(from_file, Some(PathBuf::from("specs.txt")));
+                assert_eq!(packages, vec!["lodash"]);
+            }
+            other => panic!("expected install, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_from_file_validates_specs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("specs.txt");
+        std::fs::write(&path, "# nothing valid here\n\nNot A Package\n").unwrap();
+
+        let args = Args::try_parse_from(["package-fast", "install", "--from-file", path.to_str().unwrap()]).unwrap();
+        let err = run(&args).await.unwrap_err();
+        assert!(err.to_string().contains("line 3"));
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Resolution);
+    }
+
+    #[test]
+    fn test_offline_flag_is_global() {
+        for argv in [
+            ["package-fast", "--offline", "install", "lodash"],
+            ["package-fast", "install", "lodash", "--offline"],
+        ] {
+            let args = Args::try_parse_from(argv).unwrap();
+            assert!(args.offline);
+            assert!(args.registry_config().offline);
+        }
+        assert!(!Args::try_parse_from(["package-fast", "install"]).unwrap().registry_config().offline);
+    }
+
+    #[tokio::test]
+    async fn test_offline_registry_never_reaches_the_network() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::any())
+            .respond_with(wiremock::ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let cache_dir = tempfile::TempDir::new().unwrap();
+
+        let args = Args::try_parse_from(["package-fast", "--offline", "install", "util"]).unwrap();
+        let registry = HttpRegistry::with_config(RegistryConfig {
+            url: server.uri(),
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..args.registry_config()
+        });
+        let err = registry.metadata("util").await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::RequiresNetwork(_))), "{:#}", err);
+        assert!(err.to_string().contains("util"));
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Failure);
+    }
+
+    #[tokio::test]
+    async fn test_offline_search_requires_network() {
+        let args = Args::try_parse_from(["package-fast", "search", "lodash", "--offline"]).unwrap();
+        let err = run(&args).await.unwrap_err();
+        assert!(err.to_string().contains("requires network access"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_install_script_flags() {
+        let args = Args::try_parse_from(["package-fast", "install", "--allow-script-network", "--audit-log", "a.jsonl"])
+            .unwrap();
+        let Some(Commands::Install { allow_script_network, audit_log, .. }) = args.command else {
+            panic!("expected install");
+        };
+        assert!(allow_script_network);
+        assert_eq!(audit_log, Some(PathBuf::from("a.jsonl")));
+
+        let args = Args::try_parse_from(["package-fast", "install"]).unwrap();
+        let Some(Commands::Install { allow_script_network, audit_log, .. }) = args.command else {
+            panic!("expected install");
+        };
+        assert!(!allow_script_network);
+        assert_eq!(audit_log, None);
+    }
+
+    #[test]
+    fn test_add_save_exact_flag() {
+        let args = Args::try_parse_from(["package-fast", "add", "--save-exact", "lodash"]).unwrap();
+        assert!(matches!(args.command, Some(Commands::Add { save_exact: true, .. })));
+
+        let args = Args::try_parse_from(["package-fast", "add", "lodash"]).unwrap();
+        assert!(matches!(args.command, Some(Commands::Add { save_exact: false, .. })));
+    }
+}
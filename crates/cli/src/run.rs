@@ -0,0 +1,116 @@
+//! `run` command: execute package.json scripts
+//!
+//! Scripts run through the security crate's sandbox so they get the same
+//! limits as package lifecycle scripts. Unlike those, they are the
+//! project's own code, so they keep network access, and their output is
+//! streamed to the terminal as it's produced.
+
+use anyhow::Result;
+use package_fast_core::manifest::read_package_info;
+use package_fast_security::sandbox::{SandboxConfig, SandboxResult, SandboxRuntimeProtection};
+use std::path::Path;
+
+/// Run a script from the `package.json` in a project directory
+///
+/// # Arguments
+/// * `project_dir` - Directory containing the `package.json`
+/// * `script` - Name of the script in the `scripts` map
+/// * `extra_args` - Arguments appended to the script command
+///
+/// # Returns
+/// * `Ok(SandboxResult)` with the script's output and exit code; the output
+///   has already been forwarded to this process's stdout and stderr
+/// * `Err(anyhow::Error)` if the manifest can't be read or has no such script
+pub async fn run_script(project_dir: &Path, script: &str, extra_args: &[String]) -> Result<SandboxResult> {
+    let pkg = read_package_info(project_dir)?;
+    let Some(command) = pkg.scripts.get(script) else {
+        let mut available: Vec<_> = pkg.scripts.keys().map(String::as_str).collect();
+        available.sort_unstable();
+        anyhow::bail!(
+            "Missing script \"{}\" in package.json (available: {})",
+            script,
+            if available.is_empty() { "none".to_string() } else { available.join(", ") }
+        );
+    };
+
+    let mut command_line = command.clone();
+    for arg in extra_args {
+        command_line.push(' ');
+        command_line.push_str(&shell_quote(arg));
+    }
+
+    let config = SandboxConfig {
+        allowed_directories: [project_dir.to_path_buf()].into_iter().collect(),
+        allow_process_creation: true,
+        allow_network: true,
+        stream_output: true,
+        ..Default::default()
+    };
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+
+    SandboxRuntimeProtection::with_config(config)
+        .execute_sandboxed(shell, &[flag.to_string(), command_line], project_dir)
+        .await
+}
+
+/// Quote an argument so the shell passes it through unchanged
+fn shell_quote(arg: &str) -> String {
+    if cfg!(windows) || (!arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:@".contains(c))) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn project(scripts: serde_json::Value) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let manifest = serde_json::json!({ "name": "fixture", "version": "1.0.0", "scripts": scripts });
+        fs::write(dir.path().join("package.json"), manifest.to_string()).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_run_script_captures_output() {
+        let dir = project(serde_json::json!({ "greet": "echo hello" }));
+
+        let result = run_script(dir.path(), "greet", &["it's".to_string(), "me".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, Some(0));
+        assert_eq!(String::from_utf8_lossy(&result.stdout), "hello it's me\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_script_propagates_exit_code() {
+        let dir = project(serde_json::json!({ "fail": "exit 3" }));
+
+        let result = run_script(dir.path(), "fail", &[]).await.unwrap();
+        assert_eq!(result.exit_code, Some(3));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_run_script_has_network_access() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connect = format!("bash -c 'echo hi > /dev/tcp/127.0.0.1/{}'", port);
+        let dir = project(serde_json::json!({ "ping": connect }));
+
+        let result = run_script(dir.path(), "ping", &[]).await.unwrap();
+        assert_eq!(result.exit_code, Some(0), "{}", String::from_utf8_lossy(&result.stderr));
+    }
+
+    #[tokio::test]
+    async fn test_run_missing_script() {
+        let dir = project(serde_json::json!({ "build": "true" }));
+
+        let err = run_script(dir.path(), "test", &[]).await.unwrap_err();
+        assert!(err.to_string().contains("available: build"));
+    }
+}
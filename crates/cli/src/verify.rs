@@ -0,0 +1,251 @@
+//! `verify` command: check installed packages against the lockfile
+
+use anyhow::{Context, Result};
+use package_fast_core::integrity::{parse_sri, HashAlgorithm};
+use package_fast_core::lockfile::{LockedPackage, Lockfile};
+use package_fast_core::manifest::{read_package_info, MANIFEST_FILE};
+use package_fast_core::store::Store;
+use package_fast_core::tarball::read_tarball_files;
+use package_fast_security::integrity::calculate_package_hash;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::exit::ExitCode;
+
+/// How an installed package differs from the lockfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The package isn't installed
+    Missing,
+    /// A version that isn't locked is installed
+    UnlockedVersion { installed: String },
+    /// The store's copy of the tarball no longer matches the locked integrity
+    CorruptTarball,
+    /// Installed files differ from the tarball or are missing
+    ModifiedFiles(Vec<PathBuf>),
+}
+
+/// A discrepancy found for one package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discrepancy {
+    /// The package, as `name@version` of the locked entry
+    pub package: String,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.problem {
+            Problem::Missing => write!(f, "{} is not installed", self.package),
+            Problem::UnlockedVersion { installed } => {
+                write!(f, "{} is locked but {} is installed", self.package, installed)
+            }
+            Problem::CorruptTarball => write!(f, "{} has a corrupt tarball in the store", self.package),
+            Problem::ModifiedFiles(files) => {
+                let files: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+                write!(f, "{} has modified files: {}", self.package, files.join(", "))
+            }
+        }
+    }
+}
+
+/// Result of verifying `node_modules` against the lockfile
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Number of installed packages checked
+    pub checked: usize,
+    pub discrepancies: Vec<Discrepancy>,
+    /// Installed packages whose contents couldn't be checked because their
+    /// tarball isn't in the store under a SHA-512 integrity
+    pub unverified: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Exit code for this verification: `Integrity` on any discrepancy
+    pub fn exit_code(&self) -> ExitCode {
+        if self.discrepancies.is_empty() {
+            ExitCode::Success
+        } else {
+            ExitCode::Integrity
+        }
+    }
+}
+
+/// Verify the packages installed in `node_modules` against a lockfile
+///
+/// Each locked package name must be installed at one of its locked
+/// versions; dev and optional packages may be absent. The installed
+/// package's tarball is looked up in the store by its locked integrity,
+/// re-hashed, and every file in it compared with the installed copy. Files
+/// added after installing, such as build output, are not reported.
+///
+/// # Arguments
+/// * `node_modules` - Directory the packages are installed in
+/// * `lockfile` - Lockfile the installation should match
+/// * `store` - Store holding the installed tarballs
+pub fn verify_installed(node_modules: &Path, lockfile: &Lockfile, store: &Store) -> Result<VerifyReport> {
+    let mut by_name: BTreeMap<&str, Vec<&LockedPackage>> = BTreeMap::new();
+    for locked in lockfile.packages.values() {
+        by_name.entry(locked.name.as_str()).or_default().push(locked);
+    }
+
+    let mut report = VerifyReport::default();
+    for (name, locked) in by_name {
+        let dir = node_modules.join(name);
+        if !dir.join(MANIFEST_FILE).is_file() {
+            if locked.iter().any(|pkg| !pkg.dev && !pkg.optional) {
+                report.discrepancies.push(Discrepancy {
+                    package: format!("{}@{}", name, locked[0].version),
+                    problem: Problem::Missing,
+                });
+            }
+            continue;
+        }
+
+        report.checked += 1;
+        let installed = read_package_info(&dir)?;
+        let Some(entry) = locked.iter().find(|pkg| pkg.version == installed.version) else {
+            report.discrepancies.push(Discrepancy {
+                package: format!("{}@{}", name, locked[0].version),
+                problem: Problem::UnlockedVersion {
+                    installed: installed.version,
+                },
+            });
+            continue;
+        };
+
+        let package = format!("{}@{}", entry.name, entry.version);
+        match stored_tarball(entry, store) {
+            Some((tarball, expected)) => {
+                if let Some(problem) = check_contents(&dir, &tarball, &expected)? {
+                    report.discrepancies.push(Discrepancy { package, problem });
+                }
+            }
+            None => report.unverified.push(package),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Get the store path and expected SHA-512 hex digest of a locked package's tarball
+fn stored_tarball(entry: &LockedPackage, store: &Store) -> Option<(PathBuf, String)> {
+    let integrity = parse_sri(entry.integrity.as_deref()?).ok()?;
+    if integrity.algorithm != HashAlgorithm::Sha512 {
+        return None;
+    }
+    let path = store.path_for(&integrity);
+    let expected: String = integrity.digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    path.is_file().then_some((path, expected))
+}
+
+/// Compare an installed package with its stored tarball
+fn check_contents(dir: &Path, tarball: &Path, expected: &str) -> Result<Option<Problem>> {
+    if calculate_package_hash(tarball)? != expected {
+        return Ok(Some(Problem::CorruptTarball));
+    }
+
+    let bytes = fs::read(tarball).with_context(|| format!("Failed to read {:?}", tarball))?;
+    let modified: Vec<PathBuf> = read_tarball_files(&bytes)?
+        .into_iter()
+        .filter(|(path, contents)| fs::read(dir.join(path)).ok().as_ref() != Some(contents))
+        .map(|(path, _)| path)
+        .collect();
+
+    Ok((!modified.is_empty()).then_some(Problem::ModifiedFiles(modified)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use package_fast_core::store::StoreConfig;
+    use package_fast_core::tarball::extract_tarball;
+    use package_fast_core::testing::build_tarball;
+    use tempfile::TempDir;
+
+    /// Install a package from a generated tarball and lock it
+    fn install(node_modules: &Path, store: &Store, lockfile: &mut Lockfile, name: &str, version: &str) {
+        let manifest = format!(r#"{{ "name": "{}", "version": "{}" }}"#, name, version);
+        let bytes = build_tarball(&[
+            ("package/package.json", &manifest),
+            ("package/index.js", "module.exports = 1;"),
+        ]);
+        let integrity = store.put(&bytes).unwrap();
+        extract_tarball(&bytes, &node_modules.join(name), None).unwrap();
+
+        let mut locked = LockedPackage::new(name, version);
+        locked.integrity = Some(integrity.to_sri());
+        lockfile.insert(locked);
+    }
+
+    #[test]
+    fn test_verify_installed_reports_tampering() {
+        let project = TempDir::new().unwrap();
+        let node_modules = project.path().join("node_modules");
+        let store = Store::with_config(StoreConfig {
+            root: project.path().join("store"),
+            ..Default::default()
+        });
+        let mut lockfile = Lockfile::new();
+        install(&node_modules, &store, &mut lockfile, "left-pad", "1.3.0");
+        install(&node_modules, &store, &mut lockfile, "util", "2.1.0");
+        lockfile.insert(LockedPackage::new("absent", "1.0.0"));
+        let mut dev = LockedPackage::new("dev-only", "1.0.0");
+        dev.dev = true;
+        lockfile.insert(dev);
+
+        let report = verify_installed(&node_modules, &lockfile, &store).unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(
+            report.discrepancies,
+            vec![Discrepancy {
+                package: "absent@1.0.0".to_string(),
+                problem: Problem::Missing,
+            }]
+        );
+
+        fs::write(node_modules.join("util/index.js"), "module.exports = 'pwned';").unwrap();
+        let report = verify_installed(&node_modules, &lockfile, &store).unwrap();
+        assert_eq!(report.discrepancies.len(), 2);
+        assert_eq!(
+            report.discrepancies[1],
+            Discrepancy {
+                package: "util@2.1.0".to_string(),
+                problem: Problem::ModifiedFiles(vec![PathBuf::from("index.js")]),
+            }
+        );
+        assert_eq!(report.discrepancies[1].to_string(), "util@2.1.0 has modified files: index.js");
+        assert_eq!(report.exit_code(), ExitCode::Integrity);
+        assert!(report.unverified.is_empty());
+    }
+
+    #[test]
+    fn test_verify_installed_checks_versions() {
+        let project = TempDir::new().unwrap();
+        let node_modules = project.path().join("node_modules");
+        let store = Store::with_config(StoreConfig {
+            root: project.path().join("store"),
+            ..Default::default()
+        });
+        let mut lockfile = Lockfile::new();
+        install(&node_modules, &store, &mut lockfile, "left-pad", "1.3.0");
+        lockfile.packages.clear();
+        lockfile.insert(LockedPackage::new("left-pad", "1.2.0"));
+
+        let report = verify_installed(&node_modules, &lockfile, &store).unwrap();
+        assert_eq!(
+            report.discrepancies[0].problem,
+            Problem::UnlockedVersion {
+                installed: "1.3.0".to_string()
+            }
+        );
+
+        lockfile.insert(LockedPackage::new("left-pad", "1.3.0"));
+        let report = verify_installed(&node_modules, &lockfile, &store).unwrap();
+        assert!(report.discrepancies.is_empty());
+        assert_eq!(report.unverified, vec!["left-pad@1.3.0"]);
+        assert_eq!(report.exit_code(), ExitCode::Success);
+    }
+}
@@ -0,0 +1,271 @@
+//! Registry backends
+//!
+//! Installs reach the registry through the `PackageRegistry` trait so that
+//! alternative backends, or test doubles, can stand in for the npm HTTP API.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::warn;
+
+use crate::registry::{check_tarball_size, Registry, RegistryConfig};
+use crate::store::to_hex;
+use crate::tarball::prepare_temp_dir;
+use crate::{PackageDistribution, PackageMetadata};
+
+/// A source of package metadata and tarballs
+#[async_trait]
+pub trait PackageRegistry: Send + Sync {
+    /// Fetch the metadata document of a package
+    ///
+    /// # Arguments
+    /// * `name` - Package name, possibly scoped (`@scope/name`)
+    async fn metadata(&self, name: &str) -> Result<PackageMetadata>;
+
+    /// Fetch the metadata document of a package without network access
+    ///
+    /// Used by offline installs whatever the backend's own configuration.
+    /// The default implementation calls `metadata`, which suits backends
+    /// that never use the network; others must serve from a cache or fail
+    /// with `CoreError::RequiresNetwork`.
+    ///
+    /// # Arguments
+    /// * `name` - Package name, possibly scoped (`@scope/name`)
+    async fn metadata_offline(&self, name: &str) -> Result<PackageMetadata> {
+        self.metadata(name).await
+    }
+
+    /// Fetch the tarball of a resolved package version
+    ///
+    /// The bytes are returned as served; checking them against `dist` is
+    /// left to the caller.
+    ///
+    /// # Arguments
+    /// * `dist` - Distribution info of the version to fetch
+    async fn tarball(&self, dist: &PackageDistribution) -> Result<Bytes>;
+
+    /// Fetch the tarball of a resolved package version, refusing oversized ones
+    ///
+    /// The default implementation checks the size once the whole tarball
+    /// has been fetched; backends that stream should stop as soon as the
+    /// limit is passed. Backends that stage downloads on disk should stage
+    /// them in `temp_dir` when one is given.
+    ///
+    /// # Arguments
+    /// * `dist` - Distribution info of the version to fetch
+    /// * `max_size` - Most bytes to accept, or `None` for no limit
+    /// * `temp_dir` - Directory to stage the download in, from `InstallOptions::temp_dir`
+    async fn tarball_with_limit(
+        &self,
+        dist: &PackageDistribution,
+        max_size: Option<u64>,
+        temp_dir: Option<&Path>,
+    ) -> Result<Bytes> {
+        // Nothing is staged on disk, so there is no use for `temp_dir`
+        let _ = temp_dir;
+        let bytes = self.tarball(dist).await?;
+        check_tarball_size(&dist.tarball, bytes.len() as u64, max_size)?;
+        Ok(bytes)
+    }
+
+    /// Describe where packages are served from
+    ///
+    /// A resolution cached while installing from one source isn't reused
+    /// with another. The default is empty, for backends with a single source.
+    fn source_id(&self) -> String {
+        String::new()
+    }
+}
+
+/// The npm HTTP registry, accessed through a `Registry` client
+#[derive(Debug, Default)]
+pub struct HttpRegistry {
+    client: Registry,
+}
+
+impl HttpRegistry {
+    /// Create an HTTP registry with default configuration
+    pub fn new() -> Self {
+        Self::with_config(RegistryConfig::default())
+    }
+
+    /// Create an HTTP registry with custom configuration
+    pub fn with_config(config: RegistryConfig) -> Self {
+        Self {
+            client: Registry::with_config(config),
+        }
+    }
+
+    /// Get the underlying registry client
+    pub fn client(&self) -> &Registry {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl PackageRegistry for HttpRegistry {
+    async fn metadata(&self, name: &str) -> Result<PackageMetadata> {
+        self.client.fetch_metadata(name).await
+    }
+
+    async fn metadata_offline(&self, name: &str) -> Result<PackageMetadata> {
+        self.client.cached_metadata_offline(name)
+    }
+
+    /// With a cache directory configured, tarballs are downloaded through
+    /// `downloads/` in it, so a download cut short by a failed install
+    /// resumes on the next run. These bytes are verified against `dist`
+    /// before being returned.
+    async fn tarball(&self, dist: &PackageDistribution) -> Result<Bytes> {
+        self.tarball_with_limit(dist, None, None).await
+    }
+
+    /// The registry URL, mirrors and scoped registries, as JSON.
+    fn source_id(&self) -> String {
+        let config = self.client.config();
+        let scoped: BTreeMap<&String, &String> = config.scoped_registries.iter().collect();
+        serde_json::json!({
+            "url": config.url,
+            "mirrors": config.registry_mirrors,
+            "scoped": scoped,
+        })
+        .to_string()
+    }
+
+    /// Downloads stop as soon as more than `max_size` bytes have arrived.
+    /// A configured `temp_dir` is staged in instead of the cache directory.
+    async fn tarball_with_limit(
+        &self,
+        dist: &PackageDistribution,
+        max_size: Option<u64>,
+        temp_dir: Option<&Path>,
+    ) -> Result<Bytes> {
+        let dir = match (temp_dir, &self.client.config().cache_dir) {
+            (Some(temp_dir), _) => prepare_temp_dir(Some(temp_dir))?,
+            (None, Some(cache_dir)) => cache_dir.join("downloads"),
+            (None, None) => return self.client.download_tarball(&dist.tarball, max_size).await.map(Bytes::from),
+        };
+
+        let file_name = format!("{}.tgz", to_hex(&Sha256::digest(dist.tarball.as_bytes())));
+        let dest = dir.join(file_name);
+        let bytes = self
+            .client
+            .download_tarball_to(&dist.tarball, &dest, max_size, |bytes| dist.verify(bytes))
+            .await?;
+
+        // The store keeps the contents from here on
+        if let Err(e) = std::fs::remove_file(&dest) {
+            warn!("Failed to remove downloaded tarball {:?}: {}", dest, e);
+        }
+        Ok(Bytes::from(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_http_registry_delegates_to_client() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/left-pad"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "left-pad",
+                "dist-tags": { "latest": "1.3.0" },
+                "versions": {}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/left-pad/-/left-pad-1.3.0.tgz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"tarball".to_vec()))
+            .mount(&server)
+            .await;
+
+        let registry = HttpRegistry::with_config(RegistryConfig {
+            url: server.uri(),
+            cache_dir: None,
+            ..Default::default()
+        });
+        let registry: &dyn PackageRegistry = &registry;
+
+        let metadata = registry.metadata("left-pad").await.unwrap();
+        assert_eq!(metadata.dist_tags["latest"], "1.3.0");
+
+        let dist = PackageDistribution {
+            tarball: format!("{}/left-pad/-/left-pad-1.3.0.tgz", server.uri()),
+            shasum: String::new(),
+            integrity: None,
+        };
+        assert_eq!(registry.tarball(&dist).await.unwrap(), Bytes::from_static(b"tarball"));
+    }
+
+    #[tokio::test]
+    async fn test_http_registry_downloads_through_cache_dir() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/left-pad/-/left-pad-1.3.0.tgz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"tarball".to_vec()))
+            .mount(&server)
+            .await;
+        let cache_dir = tempfile::TempDir::new().unwrap();
+
+        let registry = HttpRegistry::with_config(RegistryConfig {
+            url: server.uri(),
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Default::default()
+        });
+        let mut dist = PackageDistribution {
+            tarball: format!("{}/left-pad/-/left-pad-1.3.0.tgz", server.uri()),
+            shasum: to_hex(&sha1::Sha1::digest(b"tarball")),
+            integrity: None,
+        };
+
+        assert_eq!(registry.tarball(&dist).await.unwrap(), Bytes::from_static(b"tarball"));
+        let downloads = cache_dir.path().join("downloads");
+        assert_eq!(std::fs::read_dir(&downloads).unwrap().count(), 0);
+
+        dist.shasum = to_hex(&sha1::Sha1::digest(b"other"));
+        assert!(registry.tarball(&dist).await.is_err());
+        assert_eq!(std::fs::read_dir(&downloads).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_http_registry_stages_downloads_in_temp_dir() {
+        // Only a request resuming the partial file staged in the temp dir is answered
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/left-pad/-/left-pad-1.3.0.tgz"))
+            .and(header("range", "bytes=3-"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(b"ball".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let registry = HttpRegistry::with_config(RegistryConfig {
+            url: server.uri(),
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Default::default()
+        });
+        let dist = PackageDistribution {
+            tarball: format!("{}/left-pad/-/left-pad-1.3.0.tgz", server.uri()),
+            shasum: to_hex(&sha1::Sha1::digest(b"tarball")),
+            integrity: None,
+        };
+        let file_name = format!("{}.tgz.part", to_hex(&Sha256::digest(dist.tarball.as_bytes())));
+        std::fs::write(temp_dir.path().join(file_name), b"tar").unwrap();
+
+        let bytes = registry.tarball_with_limit(&dist, None, Some(temp_dir.path())).await.unwrap();
+        assert_eq!(bytes, Bytes::from_static(b"tarball"));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+        assert!(!cache_dir.path().join("downloads").exists());
+    }
+}
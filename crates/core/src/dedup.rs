@@ -0,0 +1,265 @@
+//! Deduplication of the resolved tree
+//!
+//! When the same package has been resolved at several versions, dependents
+//! whose ranges are also satisfied by another resolved version share that
+//! version instead, so fewer copies end up installed. Packages only the
+//! dropped versions depended on are dropped with them.
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::semver::{parse_loose_version, parse_range, Range, Version};
+use crate::PackageInfo;
+
+/// Collapse duplicate versions of the same package where semver allows
+///
+/// For each package, the resolved versions that together satisfy every range
+/// requesting it are kept, preferring versions shared by the most ranges and
+/// then the newest. Versions that no range matches at all are kept, since
+/// something outside the tree (such as the project manifest) requested them.
+/// The tree is then walked again from those versions and the roots, and
+/// packages no longer reachable, such as dependencies of dropped versions,
+/// are removed too.
+///
+/// # Arguments
+/// * `graph` - Resolved packages, possibly containing several versions of a name
+///
+/// # Returns
+/// * The packages with redundant versions removed, in their original order
+pub fn dedup(graph: Vec<PackageInfo>) -> Vec<PackageInfo> {
+    dedup_with_roots(graph, &[], false)
+}
+
+/// Deduplicate a tree, also honoring the ranges requested by the roots
+///
+/// With `include_prereleases`, ranges match pre-releases within their bounds,
+/// as they did when the tree was resolved.
+pub(crate) fn dedup_with_roots(
+    graph: Vec<PackageInfo>,
+    roots: &[(String, Option<String>)],
+    include_prereleases: bool,
+) -> Vec<PackageInfo> {
+    let mut ranges: HashMap<&str, Vec<Range>> = HashMap::new();
+    let requests = graph
+        .iter()
+        .flat_map(|pkg| pkg.dependencies.iter().chain(&pkg.optional_dependencies))
+        .map(|(name, range)| (name.as_str(), Some(range.as_str())))
+        .chain(roots.iter().map(|(name, range)| (name.as_str(), range.as_deref())));
+    for (name, range) in requests {
+        // Tags and other unparseable requests can't be matched against versions
+        if let Some(Ok(range)) = range.map(parse_range) {
+            ranges.entry(name).or_default().push(range);
+        }
+    }
+
+    let mut versions: HashMap<&str, BTreeSet<Version>> = HashMap::new();
+    for pkg in &graph {
+        if let Some(version) = parse_loose_version(&pkg.version) {
+            versions.entry(pkg.name.as_str()).or_default().insert(version);
+        }
+    }
+
+    let mut kept: HashSet<(String, Version)> = HashSet::new();
+    let mut unrequested: HashSet<(String, Version)> = HashSet::new();
+    for (name, present) in &versions {
+        let mut unassigned: Vec<&Range> = ranges.get(name).map(|r| r.iter().collect()).unwrap_or_default();
+
+        // Greedily keep the version covering the most remaining ranges; on ties
+        // `max_by_key` returns the last candidate, i.e. the newest version
+        while let Some((version, _)) = present
+            .iter()
+            .map(|version| {
+                let covered = unassigned
+                    .iter()
+                    .filter(|range| range.matches_with(version, include_prereleases))
+                    .count();
+                (version, covered)
+            })
+            .filter(|(_, covered)| *covered > 0)
+            .max_by_key(|(_, covered)| *covered)
+        {
+            unassigned.retain(|range| !range.matches_with(version, include_prereleases));
+            kept.insert((name.to_string(), version.clone()));
+        }
+
+        for version in present {
+            let requested = ranges
+                .get(name)
+                .is_some_and(|name_ranges| {
+                    name_ranges.iter().any(|range| range.matches_with(version, include_prereleases))
+                });
+            if !requested {
+                kept.insert((name.to_string(), version.clone()));
+                unrequested.insert((name.to_string(), version.clone()));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let graph: Vec<PackageInfo> = graph
+        .into_iter()
+        .filter(|pkg| match parse_loose_version(&pkg.version) {
+            Some(version) => kept.contains(&(pkg.name.clone(), version)),
+            None => true,
+        })
+        .filter(|pkg| seen.insert((pkg.name.clone(), pkg.version.clone())))
+        .collect();
+    retain_reachable(graph, roots, &unrequested, include_prereleases)
+}
+
+/// Keep the packages reachable from the roots and from `entries`
+///
+/// Packages whose version doesn't parse can't be matched against ranges
+/// and are entries too.
+fn retain_reachable(
+    graph: Vec<PackageInfo>,
+    roots: &[(String, Option<String>)],
+    entries: &HashSet<(String, Version)>,
+    include_prereleases: bool,
+) -> Vec<PackageInfo> {
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, pkg) in graph.iter().enumerate() {
+        by_name.entry(pkg.name.as_str()).or_default().push(index);
+    }
+    let requested = |name: &str, range: Option<&str>| -> Vec<usize> {
+        let Some(candidates) = by_name.get(name) else {
+            return Vec::new();
+        };
+        let range = range.map(parse_range).and_then(Result::ok);
+        let matching: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|&index| match (&range, parse_loose_version(&graph[index].version)) {
+                (Some(range), Some(version)) => range.matches_with(&version, include_prereleases),
+                _ => true,
+            })
+            .collect();
+        // A request no copy satisfies, such as an overridden one, still reaches the package
+        if matching.is_empty() {
+            candidates.clone()
+        } else {
+            matching
+        }
+    };
+
+    let mut queue: VecDeque<usize> = graph
+        .iter()
+        .enumerate()
+        .filter(|(_, pkg)| match parse_loose_version(&pkg.version) {
+            Some(version) => entries.contains(&(pkg.name.clone(), version)),
+            None => true,
+        })
+        .map(|(index, _)| index)
+        .collect();
+    for (name, range) in roots {
+        queue.extend(requested(name, range.as_deref()));
+    }
+
+    let mut reached = vec![false; graph.len()];
+    while let Some(index) = queue.pop_front() {
+        if std::mem::replace(&mut reached[index], true) {
+            continue;
+        }
+        let pkg = &graph[index];
+        for (name, range) in pkg.dependencies.iter().chain(&pkg.optional_dependencies) {
+            queue.extend(requested(name, Some(range)));
+        }
+    }
+
+    graph
+        .into_iter()
+        .zip(reached)
+        .filter_map(|(pkg, reached)| reached.then_some(pkg))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str, deps: &[(&str, &str)]) -> PackageInfo {
+        let mut pkg = PackageInfo::new(name, version);
+        pkg.dependencies = deps
+            .iter()
+            .map(|(dep, range)| (dep.to_string(), range.to_string()))
+            .collect();
+        pkg
+    }
+
+    fn names(graph: &[PackageInfo]) -> Vec<String> {
+        graph.iter().map(|p| format!("{}@{}", p.name, p.version)).collect()
+    }
+
+    #[test]
+    fn test_dedup_collapses_compatible_ranges() {
+        let graph = vec![
+            package("app-a", "1.0.0", &[("shared", "^1.2.0")]),
+            package("app-b", "1.0.0", &[("shared", "^1.3.0")]),
+            package("shared", "1.2.5", &[]),
+            package("shared", "1.3.1", &[]),
+        ];
+
+        let deduped = dedup(graph);
+        assert_eq!(names(&deduped), vec!["app-a@1.0.0", "app-b@1.0.0", "shared@1.3.1"]);
+    }
+
+    #[test]
+    fn test_dedup_drops_dependencies_of_dropped_versions() {
+        let graph = vec![
+            package("app-a", "1.0.0", &[("shared", "^1.2.0")]),
+            package("app-b", "1.0.0", &[("shared", "^1.3.0")]),
+            package("shared", "1.2.5", &[("helper", "^1.0.0")]),
+            package("shared", "1.3.1", &[("util", "^2.0.0")]),
+            package("helper", "1.0.0", &[("util", "^1.0.0")]),
+            package("util", "1.0.0", &[]),
+            package("util", "2.0.0", &[]),
+        ];
+
+        let deduped = dedup(graph);
+        assert_eq!(names(&deduped), vec!["app-a@1.0.0", "app-b@1.0.0", "shared@1.3.1", "util@2.0.0"]);
+    }
+
+    #[test]
+    fn test_dedup_keeps_incompatible_versions() {
+        let graph = vec![
+            package("app-a", "1.0.0", &[("shared", "^1.2.0")]),
+            package("app-b", "1.0.0", &[("shared", "^2.0.0")]),
+            package("shared", "1.2.5", &[]),
+            package("shared", "2.0.1", &[]),
+        ];
+
+        assert_eq!(dedup(graph).len(), 4);
+    }
+
+    #[test]
+    fn test_dedup_honors_root_ranges() {
+        let graph = vec![
+            package("app", "1.0.0", &[("shared", "^1.2.0")]),
+            package("shared", "1.2.5", &[]),
+            package("shared", "1.3.1", &[]),
+        ];
+        let roots = vec![("shared".to_string(), Some("~1.2.0".to_string()))];
+
+        let deduped = dedup_with_roots(graph, &roots, false);
+        assert_eq!(names(&deduped), vec!["app@1.0.0", "shared@1.2.5"]);
+    }
+
+    #[test]
+    fn test_dedup_with_prereleases() {
+        let graph = vec![
+            package("app", "1.0.0", &[("shared", "^1.0.0")]),
+            package("shared", "1.0.0", &[]),
+            package("shared", "1.1.0-beta.1", &[]),
+        ];
+
+        // A pre-release resolved under the escape hatch satisfies the range on its own
+        let deduped = dedup_with_roots(graph.clone(), &[], true);
+        assert_eq!(names(&deduped), vec!["app@1.0.0", "shared@1.1.0-beta.1"]);
+        assert_eq!(dedup(graph).len(), 3);
+    }
+
+    #[test]
+    fn test_dedup_removes_exact_duplicates() {
+        let graph = vec![package("shared", "1.0.0", &[]), package("shared", "1.0.0", &[])];
+        assert_eq!(dedup(graph).len(), 1);
+    }
+}
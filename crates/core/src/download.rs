@@ -0,0 +1,603 @@
+//! Concurrent package downloads
+//!
+//! After resolution, registry packages are downloaded, added to the store
+//! and extracted into `node_modules`. Downloads run concurrently, bounded by
+//! `InstallOptions::max_concurrency`. Extraction is CPU and disk bound, so it
+//! runs on the blocking thread pool, bounded separately by
+//! `InstallOptions::extract_workers`.
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span, Instrument, Span};
+
+use crate::backend::PackageRegistry;
+use crate::report::{InstallPhase, InstallReport};
+use crate::resolver::range_allows;
+use crate::spec::validate_package_name;
+use crate::store::Store;
+use crate::tarball::extract_tarball;
+use crate::{CoreError, InstallOptions, PackageDistribution, PackageInfo};
+
+/// Default number of packages downloaded at once
+pub const DEFAULT_MAX_CONCURRENCY: usize = 16;
+
+/// Default number of tarballs extracted at once: one per available core
+pub fn default_extract_workers() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get())
+}
+
+/// A package tarball to download and extract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadTask {
+    pub name: String,
+    pub version: String,
+    pub dist: PackageDistribution,
+    /// Directory the package is extracted to, relative to `node_modules`
+    pub path: PathBuf,
+}
+
+/// Plan the downloads for a resolved tree
+///
+/// Only packages with registry distribution info are downloaded; local,
+/// git and tarball packages were already placed while parsing specs. The
+/// first resolved version of each name (roots come first in the tree) is
+/// installed at the top of `node_modules`. Any other version is nested in
+/// the `node_modules` of every package that depends on it at a range the
+/// top-level version doesn't satisfy, as Node resolves the nearest copy.
+///
+/// # Arguments
+/// * `packages` - Resolved packages, in tree order
+/// * `distributions` - Distribution info keyed by `name@version`
+/// * `include_prereleases` - Whether dependency ranges match prerelease versions
+///
+/// # Returns
+/// * `Ok(Vec<DownloadTask>)` with one task per directory to install
+/// * `Err(anyhow::Error)` if a nested version isn't required by any installed package
+pub fn plan_downloads(
+    packages: &[PackageInfo],
+    distributions: &HashMap<String, PackageDistribution>,
+    include_prereleases: bool,
+) -> Result<Vec<DownloadTask>> {
+    let key = |pkg: &PackageInfo| format!("{}@{}", pkg.name, pkg.version);
+    let mut top_level: HashMap<&str, &str> = HashMap::new();
+    for pkg in packages {
+        top_level.entry(pkg.name.as_str()).or_insert(pkg.version.as_str());
+    }
+
+    // Directories each package is installed in, keyed by `name@version`
+    let mut placed: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut nested = Vec::new();
+    for pkg in packages {
+        if top_level[pkg.name.as_str()] == pkg.version {
+            placed.entry(key(pkg)).or_insert_with(|| vec![PathBuf::from(&pkg.name)]);
+        } else if !nested.iter().any(|other: &&PackageInfo| key(other) == key(pkg)) {
+            nested.push(pkg);
+        }
+    }
+
+    // A nested copy goes under its dependents, which may be nested themselves
+    loop {
+        let remaining = nested.len();
+        nested.retain(|pkg| {
+            let top_version = top_level[pkg.name.as_str()];
+            let dirs: Vec<PathBuf> = packages
+                .iter()
+                .filter(|parent| {
+                    depends_on(parent, &pkg.name, &pkg.version, include_prereleases)
+                        && !depends_on(parent, &pkg.name, top_version, include_prereleases)
+                })
+                .filter_map(|parent| placed.get(&key(parent)))
+                .flatten()
+                .map(|dir| dir.join("node_modules").join(&pkg.name))
+                .collect();
+            if dirs.is_empty() {
+                return true;
+            }
+            debug!("Nesting {}@{} in {:?}", pkg.name, pkg.version, dirs);
+            placed.insert(key(pkg), dirs);
+            false
+        });
+        if nested.is_empty() || nested.len() == remaining {
+            break;
+        }
+    }
+    if let Some(pkg) = nested.first() {
+        anyhow::bail!(
+            "Cannot place {}@{}: {}@{} is at the top level and no installed package depends on the other version",
+            pkg.name,
+            pkg.version,
+            pkg.name,
+            top_level[pkg.name.as_str()]
+        );
+    }
+
+    let mut tasks = Vec::new();
+    let mut planned = HashSet::new();
+    for pkg in packages {
+        let Some(dist) = distributions.get(&key(pkg)) else {
+            continue;
+        };
+        if !planned.insert(key(pkg)) {
+            continue;
+        }
+        for path in &placed[&key(pkg)] {
+            tasks.push(DownloadTask {
+                name: pkg.name.clone(),
+                version: pkg.version.clone(),
+                dist: dist.clone(),
+                path: path.clone(),
+            });
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// Whether `parent` depends on `name` at a range accepting `version`
+fn depends_on(parent: &PackageInfo, name: &str, version: &str, include_prereleases: bool) -> bool {
+    if parent.is_bundled(name) {
+        return false;
+    }
+    parent
+        .optional_dependencies
+        .get(name)
+        .or_else(|| parent.dependencies.get(name))
+        .is_some_and(|range| range_allows(Some(range), version, include_prereleases))
+}
+
+/// Run an install step for every task, at most `max_concurrency` at a time
+///
+/// # Arguments
+/// * `tasks` - Packages to install
+/// * `max_concurrency` - Upper bound on concurrently running steps (at least 1)
+/// * `install` - Installs one package and returns its tarball size in bytes
+///
+/// # Returns
+/// * `Ok(u64)` with the summed size of every installed tarball
+/// * `Err(anyhow::Error)` with the first failure; outstanding steps are dropped
+pub async fn install_concurrently<F, Fut>(tasks: Vec<DownloadTask>, max_concurrency: usize, install: F) -> Result<u64>
+where
+    F: Fn(DownloadTask) -> Fut,
+    Fut: Future<Output = Result<u64>>,
+{
+    stream::iter(tasks)
+        .map(install)
+        .buffer_unordered(max_concurrency.max(1))
+        .try_fold(0u64, |total, size| async move { Ok(total + size) })
+        .await
+}
+
+/// Bounded pool extracting tarballs on the blocking thread pool
+///
+/// Clones share the same workers, so one `Extractor` can be handed to every
+/// concurrent install step. Each tarball goes through `extract_tarball`,
+/// which checks every entry for path traversal whatever the concurrency.
+///
+/// An extraction keeps running on its worker when the caller stops waiting
+/// for it; `wait_idle` waits for those, and once the cancellation token
+/// fires, extractions that haven't started yet are abandoned.
+#[derive(Debug, Clone)]
+pub struct Extractor {
+    workers: Arc<Semaphore>,
+    capacity: u32,
+    temp_dir: Option<PathBuf>,
+    cancel: CancellationToken,
+}
+
+impl Extractor {
+    /// Create an extractor running at most `workers` extractions at once (at least 1)
+    ///
+    /// # Arguments
+    /// * `workers` - Upper bound on concurrent extractions
+    /// * `temp_dir` - Temp directory to stage in, or `None` to stage next to each package
+    pub fn new(workers: usize, temp_dir: Option<PathBuf>) -> Self {
+        Self::with_cancel(workers, temp_dir, CancellationToken::new())
+    }
+
+    /// Create an extractor that stops starting extractions once `cancel` fires
+    pub fn with_cancel(workers: usize, temp_dir: Option<PathBuf>, cancel: CancellationToken) -> Self {
+        let capacity = workers.clamp(1, Semaphore::MAX_PERMITS.min(u32::MAX as usize));
+        Self {
+            workers: Arc::new(Semaphore::new(capacity)),
+            capacity: capacity as u32,
+            temp_dir,
+            cancel,
+        }
+    }
+
+    /// Create the extractor of an install, from `extract_workers`, `temp_dir` and `cancel`
+    pub fn from_options(options: &InstallOptions) -> Self {
+        Self::with_cancel(options.extract_workers, options.temp_dir.clone(), options.cancel.clone())
+    }
+
+    /// Extract a tarball into a package directory once a worker is free
+    ///
+    /// The extraction runs in the caller's span.
+    ///
+    /// # Arguments
+    /// * `bytes` - Compressed tarball bytes
+    /// * `dest` - Package directory to extract into
+    pub async fn extract<B>(&self, bytes: B, dest: PathBuf) -> Result<()>
+    where
+        B: AsRef<[u8]> + Send + 'static,
+    {
+        let permit = self.workers.clone().acquire_owned().await?;
+        let temp_dir = self.temp_dir.clone();
+        let cancel = self.cancel.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            // Held until the extraction is over, even if the caller stopped waiting
+            let _permit = permit;
+            if cancel.is_cancelled() {
+                return Err(CoreError::Cancelled.into());
+            }
+            span.in_scope(|| extract_tarball(bytes.as_ref(), &dest, temp_dir.as_deref()))
+        })
+        .await?
+    }
+
+    /// Wait until no extraction is running
+    pub async fn wait_idle(&self) -> Result<()> {
+        let _all = self.workers.acquire_many(self.capacity).await?;
+        Ok(())
+    }
+}
+
+/// Download registry packages into a `node_modules` directory
+///
+/// Each tarball is verified against its distribution checksum (SRI
+/// `integrity` when published, else `shasum`), added to the
+/// content-addressable store and extracted to the task's `path` in
+/// `node_modules`.
+/// Every step runs in a `package` span carrying `package`, `version` and
+/// `phase` (`download`, `verify` or `extract`) fields, and its duration is
+/// added to `report`. `options` bound the downloads: at most
+/// `max_concurrency` run at once and each tarball is cut off past
+/// `max_package_size_bytes`; tarballs are extracted by `extractor`, usually
+/// `Extractor::from_options`. With `offline` set, tarballs are read
+/// from the store instead of the registry, and a package missing from it
+/// fails with `CoreError::RequiresNetwork`.
+///
+/// # Returns
+/// * `Ok(u64)` with the total downloaded size in bytes
+pub async fn download_packages(
+    registry: &dyn PackageRegistry,
+    store: &Store,
+    tasks: Vec<DownloadTask>,
+    node_modules: &Path,
+    options: &InstallOptions,
+    extractor: &Extractor,
+    report: &Mutex<InstallReport>,
+) -> Result<u64> {
+    let max_concurrency = options.max_concurrency;
+    info!("Downloading {} packages ({} at a time)", tasks.len(), max_concurrency.max(1));
+
+    install_concurrently(tasks, max_concurrency, |task| async move {
+        let span = |phase: &'static str| info_span!("package", package = %task.name, version = %task.version, phase);
+
+        let record = |phase, start| report.lock().unwrap().record_since(phase, start);
+
+        // Registry manifests name the directory the package is extracted to
+        validate_package_name(&task.name)?;
+
+        let start = Instant::now();
+        let bytes = if options.offline {
+            span("download").in_scope(|| stored_tarball(store, &task))?
+        } else {
+            registry
+                .tarball_with_limit(&task.dist, options.max_package_size_bytes, options.temp_dir.as_deref())
+                .instrument(span("download"))
+                .await
+                .with_context(|| format!("Failed to download {}@{}", task.name, task.version))?
+        };
+        record(InstallPhase::Download, start);
+
+        let start = Instant::now();
+        span("verify")
+            .in_scope(|| task.dist.verify(&bytes))
+            .with_context(|| format!("Failed to verify {}@{}", task.name, task.version))?;
+        record(InstallPhase::Verification, start);
+        store.put_from(&bytes, &task.dist.tarball)?;
+
+        let size = bytes.len() as u64;
+        let start = Instant::now();
+        extractor
+            .extract(bytes, node_modules.join(&task.path))
+            .instrument(span("extract"))
+            .await
+            .with_context(|| format!("Failed to extract {}@{}", task.name, task.version))?;
+        record(InstallPhase::Extraction, start);
+        Ok(size)
+    })
+    .await
+}
+
+/// Read a package's tarball from the store for an offline install
+fn stored_tarball(store: &Store, task: &DownloadTask) -> Result<Bytes> {
+    let integrity = task.dist.expected_integrity()?;
+    if !store.path_for(&integrity).is_file() {
+        return Err(CoreError::RequiresNetwork(format!(
+            "{}@{} is not in the store; downloading it",
+            task.name, task.version
+        ))
+        .into());
+    }
+    store.get_verified(&integrity.to_sri())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn task(name: &str) -> DownloadTask {
+        DownloadTask {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            dist: PackageDistribution {
+                tarball: format!("https://registry.example/{}.tgz", name),
+                shasum: "0".to_string(),
+                integrity: None,
+            },
+            path: PathBuf::from(name),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_concurrently_bounds_and_sums() {
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let tasks: Vec<DownloadTask> = (1..=12).map(|i| task(&format!("pkg-{}", i))).collect();
+
+        let total = install_concurrently(tasks, 4, |task| {
+            let (in_flight, peak) = (&in_flight, &peak);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(task.name.trim_start_matches("pkg-").parse::<u64>().unwrap() * 100)
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(total, (1..=12).sum::<u64>() * 100);
+        let peak = peak.load(Ordering::SeqCst);
+        assert!(peak > 1, "installs did not overlap");
+        assert!(peak <= 4, "concurrency limit exceeded: {}", peak);
+    }
+
+    #[tokio::test]
+    async fn test_install_concurrently_propagates_failure() {
+        let result = install_concurrently(vec![task("ok"), task("broken")], 2, |task| async move {
+            if task.name == "broken" {
+                anyhow::bail!("HTTP 500");
+            }
+            Ok(1)
+        })
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_plan_downloads_nests_conflicting_versions() {
+        let dist = |key: &str| PackageDistribution {
+            tarball: format!("https://r/{}.tgz", key),
+            shasum: "0".to_string(),
+            integrity: None,
+        };
+        let depending = |name: &str, version: &str, shared: &str| {
+            let mut pkg = PackageInfo::new(name, version);
+            pkg.dependencies.insert("shared".to_string(), shared.to_string());
+            pkg
+        };
+        let mut wrapper = PackageInfo::new("wrapper", "1.0.0");
+        wrapper.dependencies.insert("legacy".to_string(), "^1.0.0".to_string());
+        let packages = vec![
+            depending("app", "0.0.0", "^2.0.0"),
+            wrapper,
+            PackageInfo::new("shared", "2.0.0"),
+            depending("legacy", "1.0.0", "^1.0.0"),
+            depending("modern", "1.0.0", "^2.1.0"),
+            PackageInfo::new("shared", "1.0.0"),
+        ];
+        let distributions: HashMap<String, PackageDistribution> = packages
+            .iter()
+            .filter(|pkg| pkg.name != "app")
+            .map(|pkg| format!("{}@{}", pkg.name, pkg.version))
+            .map(|key| (key.clone(), dist(&key)))
+            .collect();
+
+        let tasks = plan_downloads(&packages, &distributions, false).unwrap();
+        let placed: Vec<(&str, &str, PathBuf)> = tasks
+            .iter()
+            .map(|task| (task.name.as_str(), task.version.as_str(), task.path.clone()))
+            .collect();
+        assert_eq!(
+            placed,
+            vec![
+                ("wrapper", "1.0.0", PathBuf::from("wrapper")),
+                ("shared", "2.0.0", PathBuf::from("shared")),
+                ("legacy", "1.0.0", PathBuf::from("legacy")),
+                ("modern", "1.0.0", PathBuf::from("modern")),
+                ("shared", "1.0.0", PathBuf::from("legacy/node_modules/shared")),
+            ]
+        );
+        assert_eq!(tasks[4].dist.tarball, "https://r/shared@1.0.0.tgz");
+
+        // Nothing installed asks for shared@1.0.0, so there's nowhere to put it
+        let orphaned = vec![PackageInfo::new("shared", "2.0.0"), PackageInfo::new("shared", "1.0.0")];
+        let err = plan_downloads(&orphaned, &distributions, false).unwrap_err();
+        assert!(err.to_string().contains("Cannot place shared@1.0.0"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_extractor_extracts_concurrently() {
+        let node_modules = tempfile::TempDir::new().unwrap();
+        let extractor = Extractor::new(3, None);
+        let names: Vec<String> = (1..=8).map(|i| format!("pkg-{}", i)).collect();
+
+        let extractions = names.iter().map(|name| {
+            let manifest = format!(r#"{{ "name": "{}", "version": "1.0.0" }}"#, name);
+            let index = format!("module.exports = '{}';", name);
+            let tarball = crate::testing::build_tarball(&[
+                ("package/package.json", manifest.as_str()),
+                ("package/lib/index.js", index.as_str()),
+            ]);
+            extractor.extract(tarball, node_modules.path().join(name))
+        });
+        for result in futures::future::join_all(extractions).await {
+            result.unwrap();
+        }
+
+        for name in &names {
+            let dir = node_modules.path().join(name);
+            assert!(dir.join("package.json").is_file());
+            assert_eq!(
+                std::fs::read_to_string(dir.join("lib/index.js")).unwrap(),
+                format!("module.exports = '{}';", name)
+            );
+        }
+
+        let err = extractor
+            .extract(b"not a tarball".to_vec(), node_modules.path().join("broken"))
+            .await;
+        assert!(err.is_err());
+        assert_eq!(extractor.workers.available_permits(), 3, "permits leaked");
+    }
+
+    #[tokio::test]
+    async fn test_extractor_cancellation() {
+        let node_modules = tempfile::TempDir::new().unwrap();
+        let cancel = CancellationToken::new();
+        let extractor = Extractor::with_cancel(2, None, cancel.clone());
+        let tarball = || crate::testing::build_tarball(&[("package/package.json", r#"{ "name": "a" }"#)]);
+
+        // Abandoned after its first poll; the worker still finishes the extraction
+        let abandoned = extractor.extract(tarball(), node_modules.path().join("abandoned"));
+        let _ = tokio::time::timeout(std::time::Duration::ZERO, abandoned).await;
+        extractor.wait_idle().await.unwrap();
+        assert!(node_modules.path().join("abandoned/package.json").is_file());
+
+        cancel.cancel();
+        let err = extractor.extract(tarball(), node_modules.path().join("late")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::Cancelled)), "{:#}", err);
+        assert!(!node_modules.path().join("late").exists());
+        assert_eq!(extractor.workers.available_permits(), 2, "permits leaked");
+    }
+
+    /// Registry serving the same bytes for every tarball
+    struct FixedTarball(&'static [u8]);
+
+    #[async_trait::async_trait]
+    impl PackageRegistry for FixedTarball {
+        async fn metadata(&self, name: &str) -> Result<crate::PackageMetadata> {
+            anyhow::bail!("no metadata for {}", name)
+        }
+
+        async fn tarball(&self, _dist: &PackageDistribution) -> Result<bytes::Bytes> {
+            Ok(bytes::Bytes::from_static(self.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_packages_verifies_before_storing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = Store::with_config(crate::store::StoreConfig {
+            root: dir.path().join("store"),
+            ..Default::default()
+        });
+        let mut tampered = task("tampered");
+        tampered.dist.integrity = Some(crate::integrity::Integrity {
+            algorithm: crate::integrity::HashAlgorithm::Sha512,
+            digest: crate::integrity::HashAlgorithm::Sha512.digest(b"published bytes"),
+        }
+        .to_sri());
+
+        let err = download_packages(
+            &FixedTarball(b"tampered bytes"),
+            &store,
+            vec![tampered],
+            &dir.path().join("node_modules"),
+            &InstallOptions {
+                max_concurrency: 1,
+                ..Default::default()
+            },
+            &Extractor::new(1, None),
+            &Mutex::new(InstallReport::new()),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("Failed to verify tampered@1.0.0"));
+        assert!(store.entries().unwrap().is_empty());
+        assert!(!dir.path().join("node_modules/tampered").exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_packages_rejects_traversing_names() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = Store::with_config(crate::store::StoreConfig {
+            root: dir.path().join("store"),
+            ..Default::default()
+        });
+        let node_modules = dir.path().join("project/node_modules");
+
+        let err = download_packages(
+            &FixedTarball(b"never fetched"),
+            &store,
+            vec![task("../../escape")],
+            &node_modules,
+            &InstallOptions::default(),
+            &Extractor::new(1, None),
+            &Mutex::new(InstallReport::new()),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(err.downcast_ref(), Some(CoreError::InvalidPackageName { .. })), "{:#}", err);
+        assert!(!dir.path().join("escape").exists());
+        assert!(store.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_download_packages_rejects_oversized_tarballs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = Store::with_config(crate::store::StoreConfig {
+            root: dir.path().join("store"),
+            ..Default::default()
+        });
+        let options = InstallOptions {
+            max_package_size_bytes: Some(8),
+            ..Default::default()
+        };
+
+        let err = download_packages(
+            &FixedTarball(b"far more than eight bytes"),
+            &store,
+            vec![task("bloated")],
+            &dir.path().join("node_modules"),
+            &options,
+            &Extractor::from_options(&options),
+            &Mutex::new(InstallReport::new()),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to download bloated@1.0.0: Tarball https://registry.example/bloated.tgz is larger than the limit of 8 bytes"
+        );
+        assert!(store.entries().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+//! Core error types
+
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::resolver::ConflictReport;
+
+/// Error types for core package operations
+#[derive(Error, Debug)]
+pub enum CoreError {
+    #[error("Request to {url} timed out after {timeout:?}")]
+    Timeout { url: String, timeout: Duration },
+
+    #[error("{0}")]
+    VersionConflict(ConflictReport),
+
+    #[error("Invalid package name {name:?}: {reason}")]
+    InvalidPackageName { name: String, reason: String },
+
+    #[error("Invalid version range {range:?}: {reason}")]
+    InvalidRange { range: String, reason: String },
+
+    #[error("Invalid version {version:?}: {reason}")]
+    InvalidVersion { version: String, reason: String },
+
+    #[error("Package {name} was not found in the registry")]
+    PackageNotFound { name: String },
+
+    #[error("Dependency tree is deeper than {max_depth} levels at {name} (required by {required_by})")]
+    DependencyTooDeep {
+        name: String,
+        required_by: String,
+        max_depth: usize,
+    },
+
+    #[error("Override {name}@{version} can't be used: {reason}")]
+    InvalidOverride {
+        name: String,
+        version: String,
+        reason: String,
+    },
+
+    #[error("Frozen lockfile: {0}")]
+    FrozenLockfile(String),
+
+    #[error("Integrity mismatch: expected {expected}, got {actual}")]
+    IntegrityMismatch { expected: String, actual: String },
+
+    #[error("Store entry {} is corrupt: its contents don't match {expected}", .path.display())]
+    StoreEntryCorrupt { path: PathBuf, expected: String },
+
+    #[error("{package} is licensed under {license}, which the license policy does not allow")]
+    LicenseDenied { package: String, license: String },
+
+    #[error("Unmet peer dependencies:\n  {}", .0.join("\n  "))]
+    UnmetPeerDependencies(Vec<String>),
+
+    #[error("Tarball {url} is larger than the limit of {limit} bytes")]
+    TarballTooLarge { url: String, limit: u64 },
+
+    #[error("Metadata response from {url} is larger than the limit of {limit} bytes")]
+    MetadataTooLarge { url: String, limit: u64 },
+
+    #[error("{0} requires network access, but offline mode is on")]
+    RequiresNetwork(String),
+
+    #[error("Install was cancelled")]
+    Cancelled,
+}
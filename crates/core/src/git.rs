@@ -0,0 +1,126 @@
+//! Git dependency resolution
+//!
+//! This module clones git dependencies at their pinned reference and reads
+//! the package manifest from the checkout, which the install copies into
+//! `node_modules`.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use tokio::process::Command;
+use tracing::info;
+
+use crate::manifest::read_package_info;
+use crate::spec::{validate_package_name, GitReference, PackageSpec};
+use crate::PackageInfo;
+
+/// Resolve a git dependency spec to the package it contains
+///
+/// The repository is cloned into a temporary directory at the requested
+/// reference and its `package.json` is read. Requires `git` on the `PATH`.
+///
+/// # Arguments
+/// * `spec` - A git spec such as `git+https://github.com/org/repo#v1.0.0`
+///
+/// # Returns
+/// * `Ok((TempDir, PackageInfo))` with the checkout, removed when dropped,
+///   and the package read from its `package.json`
+/// * `Err(anyhow::Error)` if the spec isn't a git spec or the clone fails
+pub async fn resolve_git_dependency(spec: &str) -> Result<(tempfile::TempDir, PackageInfo)> {
+    let PackageSpec::Git { name, url, reference } = PackageSpec::parse(spec)? else {
+        anyhow::bail!("Not a git dependency spec: {}", spec);
+    };
+
+    let checkout = tempfile::TempDir::new()?;
+    clone_repository(&url, reference.as_ref(), checkout.path()).await?;
+
+    let pkg_info = read_package_info(checkout.path())
+        .with_context(|| format!("Git dependency {} has no valid package.json", spec))?;
+    validate_package_name(&pkg_info.name).with_context(|| format!("Git dependency {} has an invalid name", spec))?;
+
+    if let Some(name) = name {
+        if name != pkg_info.name {
+            anyhow::bail!(
+                "Git dependency {} declares name {} but was requested as {}",
+                url,
+                pkg_info.name,
+                name
+            );
+        }
+    }
+
+    Ok((checkout, pkg_info))
+}
+
+/// Clone a repository into `dest`, checking out the given reference
+async fn clone_repository(url: &str, reference: Option<&GitReference>, dest: &Path) -> Result<()> {
+    // Anything starting with `-` would be parsed by git as an option
+    if url.starts_with('-') {
+        anyhow::bail!("Invalid git URL {}: must not start with '-'", url);
+    }
+    if let Some(GitReference::Commit(name) | GitReference::Branch(name) | GitReference::Tag(name)) = reference {
+        if name.starts_with('-') {
+            anyhow::bail!("Invalid git reference {}: must not start with '-'", name);
+        }
+    }
+
+    info!("Cloning git dependency {} at {:?}", url, reference);
+
+    let dest = dest.to_string_lossy().to_string();
+    match reference {
+        Some(GitReference::Commit(sha)) => {
+            run_git(&["clone", "--quiet", "--", url, &dest], None).await?;
+            run_git(&["checkout", "--quiet", sha, "--"], Some(&dest)).await?;
+        }
+        Some(GitReference::Branch(name)) | Some(GitReference::Tag(name)) => {
+            run_git(&["clone", "--quiet", "--depth", "1", "--branch", name, "--", url, &dest], None).await?;
+        }
+        None => {
+            run_git(&["clone", "--quiet", "--depth", "1", "--", url, &dest], None).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a git command, failing with its stderr on a non-zero exit
+async fn run_git(args: &[&str], working_dir: Option<&str>) -> Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.args(args);
+    if let Some(dir) = working_dir {
+        cmd.current_dir(dir);
+    }
+
+    let output = cmd.output().await.context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_git_dependency_rejects_registry_spec() {
+        let err = resolve_git_dependency("lodash@^4.0.0").await.unwrap_err();
+        assert!(err.to_string().contains("Not a git dependency spec"));
+    }
+
+    #[tokio::test]
+    async fn test_clone_repository_rejects_option_like_arguments() {
+        let dest = tempfile::TempDir::new().unwrap();
+
+        let err = clone_repository("--upload-pack=touch /tmp/pwned", None, dest.path()).await.unwrap_err();
+        assert!(err.to_string().contains("must not start with '-'"));
+
+        let reference = GitReference::Branch("--upload-pack=touch /tmp/pwned".to_string());
+        let err = clone_repository("https://example.com/repo.git", Some(&reference), dest.path()).await.unwrap_err();
+        assert!(err.to_string().contains("must not start with '-'"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Global installs
+//!
+//! Globally installed packages (usually CLI tools) live under a per-user
+//! prefix, laid out like npm's: packages in `<prefix>/lib/node_modules` and
+//! their executables linked into `<prefix>/bin`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::PackageInfo;
+
+/// The `bin` field of a `package.json`
+///
+/// A plain string names a single executable called after the package; a map
+/// gives each command its own script.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PackageBin {
+    Path(String),
+    Map(BTreeMap<String, String>),
+}
+
+impl PackageBin {
+    /// Get the commands and the scripts they run, relative to the package directory
+    ///
+    /// # Arguments
+    /// * `package_name` - Package name, used as the command for the string form
+    pub fn commands(&self, package_name: &str) -> Vec<(String, String)> {
+        match self {
+            PackageBin::Path(path) => {
+                let command = package_name.rsplit('/').next().unwrap_or(package_name);
+                vec![(command.to_string(), path.clone())]
+            }
+            PackageBin::Map(map) => map.iter().map(|(command, path)| (command.clone(), path.clone())).collect(),
+        }
+    }
+}
+
+/// Get the default global prefix
+///
+/// Uses `PACKAGE_FAST_PREFIX` if set, then `$HOME/.package-fast`.
+pub fn default_global_prefix() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("PACKAGE_FAST_PREFIX") {
+        return Some(PathBuf::from(dir));
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".package-fast"))
+}
+
+/// Directories of a global prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalPaths {
+    pub prefix: PathBuf,
+}
+
+impl GlobalPaths {
+    /// Use a specific prefix, or the default one when `None`
+    pub fn new(prefix: Option<&Path>) -> Result<Self> {
+        let prefix = match prefix {
+            Some(prefix) => prefix.to_path_buf(),
+            None => default_global_prefix().context("Cannot determine the global prefix: HOME is not set")?,
+        };
+        Ok(Self { prefix })
+    }
+
+    /// Directory acting as the project root of global installs
+    pub fn lib_dir(&self) -> PathBuf {
+        self.prefix.join("lib")
+    }
+
+    /// Directory globally installed packages are placed in
+    pub fn node_modules(&self) -> PathBuf {
+        self.lib_dir().join("node_modules")
+    }
+
+    /// Directory package executables are linked into
+    pub fn bin_dir(&self) -> PathBuf {
+        self.prefix.join("bin")
+    }
+}
+
+/// Link the executables declared by a package into a bin directory
+///
+/// Existing links for the same commands are replaced, and the scripts are
+/// made executable.
+///
+/// # Arguments
+/// * `package_dir` - Directory the package is installed in
+/// * `pkg` - The package's manifest
+/// * `bin_dir` - Directory to create the links in
+///
+/// # Returns
+/// * `Ok(Vec<PathBuf>)` with the created links (empty if the package has no `bin`)
+/// * `Err(anyhow::Error)` if a script is missing or a link can't be created
+pub fn link_bins(package_dir: &Path, pkg: &PackageInfo, bin_dir: &Path) -> Result<Vec<PathBuf>> {
+    let Some(bin) = &pkg.bin else {
+        return Ok(Vec::new());
+    };
+
+    fs::create_dir_all(bin_dir).with_context(|| format!("Failed to create {}", bin_dir.display()))?;
+    let mut links = Vec::new();
+    for (command, script) in bin.commands(&pkg.name) {
+        if command.is_empty() || command.contains(['/', '\\']) || command.starts_with('.') {
+            anyhow::bail!("{} declares an invalid command name {:?}", pkg.name, command);
+        }
+
+        let target = package_dir.join(&script);
+        let target = target
+            .canonicalize()
+            .with_context(|| format!("Executable {} of {} does not exist", script, pkg.name))?;
+        if !target.starts_with(package_dir.canonicalize()?) {
+            anyhow::bail!("Executable {} of {} is outside the package", script, pkg.name);
+        }
+
+        info!("Linking {} -> {}", command, target.display());
+        links.push(link_bin(&target, bin_dir, &command)?);
+    }
+
+    Ok(links)
+}
+
+#[cfg(unix)]
+fn link_bin(target: &Path, bin_dir: &Path, command: &str) -> Result<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(target)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    fs::set_permissions(target, permissions)?;
+
+    let link = bin_dir.join(command);
+    if fs::symlink_metadata(&link).is_ok() {
+        fs::remove_file(&link)?;
+    }
+    std::os::unix::fs::symlink(target, &link)?;
+    Ok(link)
+}
+
+#[cfg(not(unix))]
+fn link_bin(target: &Path, bin_dir: &Path, command: &str) -> Result<PathBuf> {
+    // Without symlinks to executables, write a command shim that runs the script with node
+    let link = bin_dir.join(format!("{}.cmd", command));
+    fs::write(&link, format!("@node \"{}\" %*\r\n", target.display()))?;
+    Ok(link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{read_package_info, MANIFEST_FILE};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_package_bin_forms() {
+        let bin: PackageBin = serde_json::from_str(r#""./cli.js""#).unwrap();
+        assert_eq!(bin.commands("@scope/tool"), vec![("tool".to_string(), "./cli.js".to_string())]);
+
+        let bin: PackageBin = serde_json::from_str(r#"{ "tsc": "bin/tsc", "tsserver": "bin/tsserver" }"#).unwrap();
+        assert_eq!(bin.commands("typescript").len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_bins_from_map() {
+        let root = TempDir::new().unwrap();
+        let paths = GlobalPaths::new(Some(root.path())).unwrap();
+        let package_dir = paths.node_modules().join("typescript");
+        fs::create_dir_all(package_dir.join("bin")).unwrap();
+        fs::write(package_dir.join("bin/tsc"), "#!/usr/bin/env node\n").unwrap();
+        fs::write(package_dir.join("bin/tsserver"), "#!/usr/bin/env node\n").unwrap();
+        fs::write(
+            package_dir.join(MANIFEST_FILE),
+            r#"{ "name": "typescript", "version": "5.4.5", "bin": { "tsc": "./bin/tsc", "tsserver": "./bin/tsserver" } }"#,
+        )
+        .unwrap();
+
+        let pkg = read_package_info(&package_dir).unwrap();
+        let links = link_bins(&package_dir, &pkg, &paths.bin_dir()).unwrap();
+        assert_eq!(links, vec![paths.bin_dir().join("tsc"), paths.bin_dir().join("tsserver")]);
+
+        let tsc = fs::read_link(paths.bin_dir().join("tsc")).unwrap();
+        assert_eq!(tsc, package_dir.join("bin/tsc").canonicalize().unwrap());
+        use std::os::unix::fs::PermissionsExt;
+        assert_ne!(fs::metadata(&tsc).unwrap().permissions().mode() & 0o111, 0);
+
+        // Relinking replaces the existing links
+        assert_eq!(link_bins(&package_dir, &pkg, &paths.bin_dir()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_link_bins_rejects_escaping_scripts() {
+        let root = TempDir::new().unwrap();
+        let package_dir = root.path().join("evil");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(root.path().join("outside.js"), "").unwrap();
+
+        let mut pkg = PackageInfo::new("evil", "1.0.0");
+        pkg.bin = Some(PackageBin::Path("../outside.js".to_string()));
+        assert!(link_bins(&package_dir, &pkg, &root.path().join("bin")).is_err());
+
+        pkg.bin = Some(PackageBin::Map(BTreeMap::from([("../x".to_string(), "a.js".to_string())])));
+        assert!(link_bins(&package_dir, &pkg, &root.path().join("bin")).is_err());
+    }
+}
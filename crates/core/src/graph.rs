@@ -0,0 +1,221 @@
+//! Dependency graph export
+//!
+//! This module turns a set of resolved or installed packages into a graph of
+//! `name@version` nodes and dependency edges, which can be rendered as
+//! Graphviz DOT or JSON for visualization and debugging.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::semver::{parse_loose_version, parse_range};
+use crate::PackageInfo;
+
+/// A dependency relationship between two packages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphEdge {
+    /// The dependent package, as `name@version`
+    pub from: String,
+    /// The package depended upon, as `name@version`
+    pub to: String,
+    /// The range the dependent requested
+    pub range: String,
+}
+
+/// A graph of packages and their dependencies
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    /// Packages in the graph, as `name@version`
+    pub nodes: Vec<String>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl DependencyGraph {
+    /// Build a graph from a set of packages
+    ///
+    /// Each dependency (including optional ones) is linked to the newest
+    /// package in the set whose version satisfies the requested range.
+    /// Requests that can't be parsed as a range, such as dist-tags, link to
+    /// any version of the package; dependencies absent from the set have no
+    /// edge. Packages are only visited once, so cycles need no special care.
+    ///
+    /// # Arguments
+    /// * `packages` - The packages making up the graph, in display order
+    /// * `include_prereleases` - Whether ranges match pre-releases within their bounds
+    pub fn from_packages(packages: &[PackageInfo], include_prereleases: bool) -> Self {
+        let mut by_name: HashMap<&str, Vec<&PackageInfo>> = HashMap::new();
+        for pkg in packages {
+            by_name.entry(pkg.name.as_str()).or_default().push(pkg);
+        }
+
+        let mut graph = DependencyGraph::default();
+        for pkg in packages {
+            let from = node_id(pkg);
+            if graph.nodes.contains(&from) {
+                continue;
+            }
+
+            let mut dependencies: Vec<(&String, &String)> =
+                pkg.dependencies.iter().chain(&pkg.optional_dependencies).collect();
+            dependencies.sort();
+            dependencies.dedup_by(|a, b| a.0 == b.0);
+
+            for (name, range) in dependencies {
+                let candidates = by_name.get(name.as_str()).map(Vec::as_slice).unwrap_or_default();
+                if let Some(target) = select_target(candidates, range, include_prereleases) {
+                    graph.edges.push(GraphEdge {
+                        from: from.clone(),
+                        to: node_id(target),
+                        range: range.clone(),
+                    });
+                }
+            }
+            graph.nodes.push(from);
+        }
+
+        graph
+    }
+
+    /// Render the graph in Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        for node in &self.nodes {
+            dot.push_str(&format!("    {};\n", dot_quote(node)));
+        }
+        for edge in &self.edges {
+            dot.push_str(&format!(
+                "    {} -> {} [label={}];\n",
+                dot_quote(&edge.from),
+                dot_quote(&edge.to),
+                dot_quote(&edge.range)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the graph as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Identify a package node as `name@version`
+fn node_id(pkg: &PackageInfo) -> String {
+    format!("{}@{}", pkg.name, pkg.version)
+}
+
+/// Pick the package a dependency request resolves to
+fn select_target<'a>(
+    candidates: &[&'a PackageInfo],
+    range: &str,
+    include_prereleases: bool,
+) -> Option<&'a PackageInfo> {
+    let Ok(range) = parse_range(range) else {
+        return candidates.first().copied();
+    };
+    candidates
+        .iter()
+        .filter_map(|pkg| parse_loose_version(&pkg.version).map(|version| (version, *pkg)))
+        .filter(|(version, _)| range.matches_with(version, include_prereleases))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, pkg)| pkg)
+}
+
+/// Quote a DOT identifier
+fn dot_quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str, dependencies: &[(&str, &str)]) -> PackageInfo {
+        let mut pkg = PackageInfo::new(name, version);
+        pkg.dependencies = dependencies
+            .iter()
+            .map(|(name, range)| (name.to_string(), range.to_string()))
+            .collect();
+        pkg
+    }
+
+    fn fixture() -> Vec<PackageInfo> {
+        vec![
+            package("app", "1.0.0", &[("util", "^2.0.0"), ("legacy", "^1.0.0")]),
+            package("legacy", "1.4.0", &[("util", "^1.0.0")]),
+            package("util", "1.9.0", &[]),
+            package("util", "2.1.0", &[]),
+        ]
+    }
+
+    fn edge(from: &str, to: &str, range: &str) -> GraphEdge {
+        GraphEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            range: range.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_from_packages_links_matching_versions() {
+        let graph = DependencyGraph::from_packages(&fixture(), false);
+
+        assert_eq!(graph.nodes, vec!["app@1.0.0", "legacy@1.4.0", "util@1.9.0", "util@2.1.0"]);
+        assert_eq!(
+            graph.edges,
+            vec![
+                edge("app@1.0.0", "legacy@1.4.0", "^1.0.0"),
+                edge("app@1.0.0", "util@2.1.0", "^2.0.0"),
+                edge("legacy@1.4.0", "util@1.9.0", "^1.0.0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_packages_handles_cycles() {
+        let packages = vec![
+            package("a", "1.0.0", &[("b", "^1.0.0")]),
+            package("b", "1.0.0", &[("a", "^1.0.0"), ("missing", "^1.0.0")]),
+        ];
+        let graph = DependencyGraph::from_packages(&packages, false);
+
+        assert_eq!(
+            graph.edges,
+            vec![edge("a@1.0.0", "b@1.0.0", "^1.0.0"), edge("b@1.0.0", "a@1.0.0", "^1.0.0")]
+        );
+    }
+
+    #[test]
+    fn test_from_packages_with_prereleases() {
+        let packages = vec![package("app", "1.0.0", &[("util", "^2.0.0")]), package("util", "2.2.0-rc.1", &[])];
+
+        assert!(DependencyGraph::from_packages(&packages, false).edges.is_empty());
+        assert_eq!(
+            DependencyGraph::from_packages(&packages, true).edges,
+            vec![edge("app@1.0.0", "util@2.2.0-rc.1", "^2.0.0")]
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = DependencyGraph::from_packages(&fixture(), false).to_dot();
+
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains("    \"util@1.9.0\";\n"));
+        assert!(dot.contains("    \"app@1.0.0\" -> \"util@2.1.0\" [label=\"^2.0.0\"];\n"));
+        assert!(dot.contains("    \"legacy@1.4.0\" -> \"util@1.9.0\" [label=\"^1.0.0\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_to_json_round_trips() {
+        let graph = DependencyGraph::from_packages(&fixture(), false);
+        let json = graph.to_json().unwrap();
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["edges"][0]["from"], "app@1.0.0");
+        assert_eq!(value["edges"][0]["to"], "legacy@1.4.0");
+        assert_eq!(serde_json::from_str::<DependencyGraph>(&json).unwrap(), graph);
+    }
+}
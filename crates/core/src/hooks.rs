@@ -0,0 +1,44 @@
+//! Per-package install hooks
+//!
+//! Tooling built on top of the installer can check every resolved package,
+//! for example against a license allow-list, before anything is downloaded.
+//! A hook rejects a package by returning an error, which aborts the install.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::fmt;
+
+use crate::PackageInfo;
+
+/// Custom logic run for each package an install resolves
+#[async_trait]
+pub trait InstallHook: fmt::Debug + Send + Sync {
+    /// Inspect a resolved package, returning an error to abort the install
+    ///
+    /// # Arguments
+    /// * `pkg` - The resolved package, as published
+    async fn on_resolved(&self, pkg: &PackageInfo) -> Result<()>;
+}
+
+/// Run every hook over every resolved package
+///
+/// Packages are visited in order and, for each, hooks run in the order they
+/// were registered. The first rejection stops the run.
+///
+/// # Arguments
+/// * `hooks` - Registered hooks
+/// * `packages` - Packages of the resolved tree
+///
+/// # Returns
+/// * `Ok(())` if no hook rejected a package
+/// * `Err(anyhow::Error)` with the rejecting hook's error, naming the package
+pub async fn run_install_hooks(hooks: &[std::sync::Arc<dyn InstallHook>], packages: &[PackageInfo]) -> Result<()> {
+    for pkg in packages {
+        for hook in hooks {
+            hook.on_resolved(pkg)
+                .await
+                .with_context(|| format!("Install hook {:?} rejected {}@{}", hook, pkg.name, pkg.version))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! Subresource integrity (SRI) support
+//!
+//! This module parses npm-style integrity strings such as
+//! `sha512-<base64 digest>` and verifies downloaded bytes against them.
+
+use anyhow::Result;
+use base64::Engine;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::CoreError;
+
+/// Hash algorithms that can appear in an integrity string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Get the algorithm name as used in SRI strings
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Compute the digest of some bytes with this algorithm
+    pub fn digest(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha1 => Sha1::digest(bytes).to_vec(),
+            HashAlgorithm::Sha256 => Sha256::digest(bytes).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(bytes).to_vec(),
+        }
+    }
+}
+
+/// A parsed integrity value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Integrity {
+    pub algorithm: HashAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl Integrity {
+    /// Render this integrity value as an SRI string
+    pub fn to_sri(&self) -> String {
+        format!(
+            "{}-{}",
+            self.algorithm.name(),
+            base64::engine::general_purpose::STANDARD.encode(&self.digest)
+        )
+    }
+
+    /// Check whether some bytes match this integrity value
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        self.algorithm.digest(bytes) == self.digest
+    }
+}
+
+/// Parse an SRI string
+///
+/// An SRI string may list several space-separated hashes; the strongest
+/// supported one is returned. Unknown algorithms are ignored.
+///
+/// # Arguments
+/// * `sri` - Integrity string, e.g. `sha512-<base64 digest>`
+///
+/// # Returns
+/// * `Ok(Integrity)` with the strongest supported hash
+/// * `Err(anyhow::Error)` if no supported, well-formed hash is present
+pub fn parse_sri(sri: &str) -> Result<Integrity> {
+    sri.split_whitespace()
+        .filter_map(|entry| {
+            let (algorithm, digest) = entry.split_once('-')?;
+            let algorithm = match algorithm {
+                "sha1" => HashAlgorithm::Sha1,
+                "sha256" => HashAlgorithm::Sha256,
+                "sha512" => HashAlgorithm::Sha512,
+                _ => return None,
+            };
+            // Options such as `?foo` may follow the digest
+            let digest = digest.split('?').next().unwrap_or(digest);
+            let digest = base64::engine::general_purpose::STANDARD.decode(digest).ok()?;
+            Some(Integrity { algorithm, digest })
+        })
+        .max_by_key(|integrity| integrity.algorithm)
+        .ok_or_else(|| anyhow::anyhow!("Invalid or unsupported integrity string: {}", sri))
+}
+
+/// Verify bytes against an SRI string
+pub fn verify_sri(bytes: &[u8], sri: &str) -> Result<()> {
+    let expected = parse_sri(sri)?;
+    if expected.matches(bytes) {
+        Ok(())
+    } else {
+        let actual = Integrity {
+            algorithm: expected.algorithm,
+            digest: expected.algorithm.digest(bytes),
+        };
+        Err(CoreError::IntegrityMismatch {
+            expected: expected.to_sri(),
+            actual: actual.to_sri(),
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sri(algorithm: HashAlgorithm, bytes: &[u8]) -> String {
+        Integrity {
+            algorithm,
+            digest: algorithm.digest(bytes),
+        }
+        .to_sri()
+    }
+
+    #[test]
+    fn test_parse_sri() {
+        let integrity = parse_sri(&sri(HashAlgorithm::Sha512, b"hello")).unwrap();
+        assert_eq!(integrity.algorithm, HashAlgorithm::Sha512);
+        assert_eq!(integrity.digest.len(), 64);
+
+        assert!(parse_sri("md5-abc").is_err());
+        assert!(parse_sri("sha512-***").is_err());
+        assert!(parse_sri("").is_err());
+    }
+
+    #[test]
+    fn test_parse_sri_prefers_strongest() {
+        let multi = format!("{} {}", sri(HashAlgorithm::Sha1, b"hello"), sri(HashAlgorithm::Sha512, b"hello"));
+        assert_eq!(parse_sri(&multi).unwrap().algorithm, HashAlgorithm::Sha512);
+    }
+
+    #[test]
+    fn test_verify_sri() {
+        assert!(verify_sri(b"hello", &sri(HashAlgorithm::Sha256, b"hello")).is_ok());
+
+        let err = verify_sri(b"tampered", &sri(HashAlgorithm::Sha256, b"hello")).unwrap_err();
+        assert!(err.to_string().contains("Integrity mismatch"));
+    }
+}
@@ -1,7 +1,42 @@
 //! Package Fast Core - Performance-critical components for Package Fast
 
-use anyhow::Result;
-use reqwest;
+pub mod backend;
+pub mod dedup;
+pub mod download;
+pub mod error;
+pub mod git;
+pub mod global;
+pub mod graph;
+pub mod hooks;
+pub mod integrity;
+pub mod license;
+pub mod local;
+pub mod lockfile;
+pub mod manifest;
+pub mod metadata;
+pub mod overrides;
+pub mod peers;
+pub mod prune;
+pub mod registry;
+pub mod report;
+pub mod resolution_cache;
+pub mod resolver;
+pub mod scripts;
+pub mod search;
+pub mod semver;
+pub mod spec;
+pub mod store;
+pub mod tarball;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod typosquat;
+pub mod workspace;
+pub mod yarn_lock;
+
+pub use backend::{HttpRegistry, PackageRegistry};
+pub use error::CoreError;
+
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::{info, warn};
@@ -11,9 +46,42 @@ use tracing::{info, warn};
 pub struct PackageInfo {
     pub name: String,
     pub version: String,
+    /// SPDX license expression, if the package declares one
+    #[serde(
+        default,
+        deserialize_with = "license::deserialize_license",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub license: Option<String>,
+    #[serde(default)]
     pub dependencies: HashMap<String, String>,
-    #[serde(rename = "devDependencies")]
+    #[serde(rename = "devDependencies", default)]
     pub dev_dependencies: HashMap<String, String>,
+    #[serde(rename = "peerDependencies", default, skip_serializing_if = "HashMap::is_empty")]
+    pub peer_dependencies: HashMap<String, String>,
+    #[serde(rename = "peerDependenciesMeta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub peer_dependencies_meta: HashMap<String, peers::PeerDependencyMeta>,
+    #[serde(rename = "optionalDependencies", default, skip_serializing_if = "HashMap::is_empty")]
+    pub optional_dependencies: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub scripts: HashMap<String, String>,
+    #[serde(
+        rename = "bundledDependencies",
+        alias = "bundleDependencies",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub bundled_dependencies: Option<BundledDependencies>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspaces: Option<workspace::Workspaces>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin: Option<global::PackageBin>,
+    /// npm `overrides`, read with `overrides::project_overrides`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<serde_json::Value>,
+    /// yarn `resolutions`, read with `overrides::project_overrides`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub resolutions: HashMap<String, String>,
 }
 
 impl PackageInfo {
@@ -22,8 +90,60 @@ impl PackageInfo {
         Self {
             name: name.to_string(),
             version: version.to_string(),
+            license: None,
             dependencies: HashMap::new(),
             dev_dependencies: HashMap::new(),
+            peer_dependencies: HashMap::new(),
+            peer_dependencies_meta: HashMap::new(),
+            optional_dependencies: HashMap::new(),
+            scripts: HashMap::new(),
+            bundled_dependencies: None,
+            workspaces: None,
+            bin: None,
+            overrides: None,
+            resolutions: HashMap::new(),
+        }
+    }
+
+    /// Create a package info instance from resolved registry version data
+    pub fn from_version(version_info: &PackageVersion) -> Self {
+        let mut pkg_info = Self::new(&version_info.name, &version_info.version);
+        pkg_info.license = version_info.license.clone();
+        pkg_info.dependencies = version_info.dependencies.clone().unwrap_or_default();
+        pkg_info.dev_dependencies = version_info.dev_dependencies.clone().unwrap_or_default();
+        pkg_info.peer_dependencies = version_info.peer_dependencies.clone().unwrap_or_default();
+        pkg_info.peer_dependencies_meta = version_info.peer_dependencies_meta.clone().unwrap_or_default();
+        pkg_info.optional_dependencies = version_info.optional_dependencies.clone().unwrap_or_default();
+        pkg_info.bundled_dependencies = version_info.bundled_dependencies.clone();
+        pkg_info.bin = version_info.bin.clone();
+        pkg_info.scripts = version_info.scripts.clone().unwrap_or_default();
+        pkg_info
+    }
+
+    /// Check whether a dependency ships inside this package's tarball
+    pub fn is_bundled(&self, name: &str) -> bool {
+        self.bundled_dependencies
+            .as_ref()
+            .is_some_and(|bundled| bundled.contains(name))
+    }
+}
+
+/// Dependencies shipped inside a package's tarball
+///
+/// npm accepts either a list of dependency names or `true` to bundle all of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BundledDependencies {
+    All(bool),
+    Names(Vec<String>),
+}
+
+impl BundledDependencies {
+    /// Check whether a dependency is bundled
+    pub fn contains(&self, name: &str) -> bool {
+        match self {
+            BundledDependencies::All(all) => *all,
+            BundledDependencies::Names(names) => names.iter().any(|bundled| bundled == name),
         }
     }
 }
@@ -35,6 +155,9 @@ pub struct PackageMetadata {
     #[serde(rename = "dist-tags")]
     pub dist_tags: HashMap<String, String>,
     pub versions: HashMap<String, PackageVersion>,
+    /// Publish timestamps keyed by version, plus `created` and `modified`
+    #[serde(default)]
+    pub time: HashMap<String, String>,
 }
 
 /// Package version information
@@ -45,14 +168,74 @@ pub struct PackageVersion {
     pub dependencies: Option<HashMap<String, String>>,
     #[serde(rename = "devDependencies")]
     pub dev_dependencies: Option<HashMap<String, String>>,
+    #[serde(rename = "peerDependencies")]
+    pub peer_dependencies: Option<HashMap<String, String>>,
+    /// Per-peer settings, such as which peers are optional
+    #[serde(rename = "peerDependenciesMeta", default)]
+    pub peer_dependencies_meta: Option<HashMap<String, peers::PeerDependencyMeta>>,
+    #[serde(rename = "optionalDependencies")]
+    pub optional_dependencies: Option<HashMap<String, String>>,
+    /// Runtime version constraints, e.g. `{"node": ">=18"}`
+    pub engines: Option<HashMap<String, String>>,
+    /// Operating systems this version supports, e.g. `["linux", "!win32"]`
+    pub os: Option<Vec<String>>,
+    /// CPU architectures this version supports, e.g. `["x64", "arm64"]`
+    pub cpu: Option<Vec<String>>,
+    /// Deprecation message, present when the publisher deprecated this version
+    pub deprecated: Option<String>,
+    /// Dependencies shipped inside the tarball, which are not fetched separately
+    #[serde(rename = "bundledDependencies", alias = "bundleDependencies", default)]
+    pub bundled_dependencies: Option<BundledDependencies>,
+    /// Executables the package provides, as a single path or a command map
+    #[serde(default)]
+    pub bin: Option<global::PackageBin>,
+    /// Scripts declared by the version, including install lifecycle scripts
+    #[serde(default)]
+    pub scripts: Option<HashMap<String, String>>,
+    /// SPDX license expression, also read from the legacy `{ "type": ... }` form
+    #[serde(default, deserialize_with = "license::deserialize_license")]
+    pub license: Option<String>,
     pub dist: PackageDistribution,
 }
 
 /// Package distribution information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PackageDistribution {
     pub tarball: String,
     pub shasum: String,
+    /// SRI string for the tarball, preferred over the legacy SHA-1 `shasum`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+}
+
+impl PackageDistribution {
+    /// Get the checksum the tarball must match
+    ///
+    /// The SRI `integrity` is used when present and parseable; otherwise the
+    /// hex SHA-1 `shasum` is used.
+    pub fn expected_integrity(&self) -> Result<integrity::Integrity> {
+        if let Some(sri) = &self.integrity {
+            match integrity::parse_sri(sri) {
+                Ok(parsed) => return Ok(parsed),
+                Err(e) => warn!("Ignoring unusable integrity for {}: {}", self.tarball, e),
+            }
+        }
+
+        let digest = store::from_hex(&self.shasum)
+            .filter(|digest| digest.len() == 20)
+            .with_context(|| format!("No usable integrity or shasum for {}", self.tarball))?;
+        Ok(integrity::Integrity {
+            algorithm: integrity::HashAlgorithm::Sha1,
+            digest,
+        })
+    }
+
+    /// Verify downloaded tarball bytes against the expected checksum
+    pub fn verify(&self, bytes: &[u8]) -> Result<()> {
+        let expected = self.expected_integrity()?;
+        integrity::verify_sri(bytes, &expected.to_sri())
+            .with_context(|| format!("Failed to verify {}", self.tarball))
+    }
 }
 
 /// Installation options
@@ -60,7 +243,62 @@ pub struct PackageDistribution {
 pub struct InstallOptions {
     pub dev_only: bool,
     pub prod_only: bool,
+    /// Resolve again even when the previous install's resolution could be reused
     pub force: bool,
+    /// Node.js version to resolve for; versions whose `engines.node` excludes it are skipped
+    pub target_node_version: Option<String>,
+    /// npm platform name to resolve for (e.g. `linux`, `darwin`, `win32`); defaults to the current OS
+    pub target_os: Option<String>,
+    /// npm architecture name to resolve for (e.g. `x64`, `arm64`); defaults to the current CPU
+    pub target_cpu: Option<String>,
+    /// Fail the install on missing or mismatched peer dependencies instead of warning
+    pub strict_peer_deps: bool,
+    /// How to handle a package required at incompatible version ranges
+    pub conflict_strategy: resolver::ConflictStrategy,
+    /// Pinned versions to prefer over the newest matching release
+    pub lockfile: Option<lockfile::Lockfile>,
+    /// Maximum number of packages downloaded at once
+    pub max_concurrency: usize,
+    /// Maximum number of tarballs extracted at once
+    pub extract_workers: usize,
+    /// Install into the global prefix and link package executables
+    pub global: bool,
+    /// Global prefix to use instead of `global::default_global_prefix()`
+    pub global_prefix: Option<std::path::PathBuf>,
+    /// Resolve strictly from the lockfile, failing if it is missing or out of date
+    pub frozen: bool,
+    /// Collect specs that fail to resolve into the result instead of aborting
+    pub continue_on_error: bool,
+    /// Deepest dependency chain to resolve before failing, or `None` for no limit
+    pub max_depth: Option<usize>,
+    /// Skip install lifecycle scripts (`preinstall`, `install`, `postinstall`) entirely
+    pub ignore_scripts: bool,
+    /// Packages allowed to run lifecycle scripts when `ignore_scripts` is off
+    pub allowed_scripts: std::collections::HashSet<String>,
+    /// Directory to stage downloads and extraction in; when unset, registry downloads are staged in
+    /// the registry cache, other downloads in the system temp directory, and extraction next to
+    /// each package
+    pub temp_dir: Option<std::path::PathBuf>,
+    /// Versions or ranges forced for packages anywhere in the tree, keyed by name
+    pub overrides: HashMap<String, String>,
+    /// Hooks run for each resolved package before anything is downloaded
+    pub hooks: Vec<std::sync::Arc<dyn hooks::InstallHook>>,
+    /// Skip versions published fewer than this many days ago, or at an unknown time
+    pub min_version_age_days: Option<u64>,
+    /// Cancelling this token aborts the install with `CoreError::Cancelled`
+    pub cancel: tokio_util::sync::CancellationToken,
+    /// Largest tarball accepted for a single package; bigger downloads are abandoned
+    pub max_package_size_bytes: Option<u64>,
+    /// Let ranges select pre-releases within their bounds, not only on pre-release lines they name
+    pub include_prereleases: bool,
+    /// Warn when a requested name looks like a typo of a popular package
+    pub check_typosquats: bool,
+    /// Licenses the installed packages must have; the install fails on any other
+    pub license_policy: Option<license::LicensePolicy>,
+    /// Install without network access, taking metadata from the cache and tarballs from the store
+    pub offline: bool,
+    /// Content-addressed store that downloaded tarballs are kept in and read back from
+    pub store: store::StoreConfig,
 }
 
 impl Default for InstallOptions {
@@ -69,32 +307,90 @@ impl Default for InstallOptions {
             dev_only: false,
             prod_only: false,
             force: false,
+            target_node_version: None,
+            target_os: None,
+            target_cpu: None,
+            strict_peer_deps: false,
+            conflict_strategy: resolver::ConflictStrategy::default(),
+            lockfile: None,
+            max_concurrency: download::DEFAULT_MAX_CONCURRENCY,
+            extract_workers: download::default_extract_workers(),
+            global: false,
+            global_prefix: None,
+            frozen: false,
+            continue_on_error: false,
+            max_depth: Some(resolver::DEFAULT_MAX_DEPTH),
+            ignore_scripts: true,
+            allowed_scripts: std::collections::HashSet::new(),
+            temp_dir: None,
+            overrides: HashMap::new(),
+            hooks: Vec::new(),
+            min_version_age_days: None,
+            cancel: tokio_util::sync::CancellationToken::new(),
+            max_package_size_bytes: None,
+            include_prereleases: false,
+            check_typosquats: true,
+            license_policy: None,
+            offline: false,
+            store: store::StoreConfig::default(),
         }
     }
 }
 
+/// Options for adding dependencies to the project manifest
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Save to `devDependencies` instead of `dependencies`
+    pub dev: bool,
+    /// Save the exact resolved version instead of a caret range
+    pub save_exact: bool,
+}
+
 /// Package installation result
 #[derive(Debug, Clone)]
 pub struct InstallResult {
+    /// Installed packages, sorted by name then version
     pub installed_packages: Vec<PackageInfo>,
     pub duration: std::time::Duration,
     pub total_size: u64,
+    /// Non-fatal problems found while resolving, such as deprecated versions
+    pub warnings: Vec<String>,
+    /// Peer dependencies that the resolved packages left unsatisfied
+    pub peer_issues: Vec<peers::PeerDependencyIssue>,
+    /// Optional dependencies that could not be installed and were skipped
+    pub skipped_optional: Vec<resolver::SkippedDependency>,
+    /// Specs that failed to install (only with `continue_on_error`)
+    pub failed: Vec<resolver::FailedSpec>,
+    /// Number of duplicate package versions, and packages only they depended on, removed from the tree
+    pub deduplicated: usize,
+    /// Number of installed packages that were requested directly
+    pub direct_count: usize,
+    /// Number of installed packages pulled in as dependencies of others
+    pub transitive_count: usize,
+    /// Number of packages the dedup pass removed, duplicate versions and
+    /// the dependencies only they needed; shown in the install summary
+    pub deduped_count: usize,
+    /// Install lifecycle scripts of the installed packages and whether they may run
+    pub lifecycle_scripts: Vec<scripts::LifecycleScript>,
+    /// Installed packages (`name@version`) grouped by license
+    pub licenses: std::collections::BTreeMap<String, Vec<String>>,
+    /// Time spent in each install phase
+    pub report: report::InstallReport,
 }
 
 /// Fetch package metadata from npm registry
 pub async fn fetch_package_metadata(name: &str) -> Result<PackageMetadata> {
-    let url = format!("https://registry.npmjs.org/{}", name);
-    info!("Fetching package metadata from {}", url);
-    
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await?;
-    
-    if response.status().is_success() {
-        let metadata: PackageMetadata = response.json().await?;
-        Ok(metadata)
-    } else {
-        anyhow::bail!("Failed to fetch package metadata: HTTP {}", response.status());
-    }
+    spec::validate_package_name(name)?;
+    registry::Registry::new().fetch_metadata(name).await
+}
+
+/// Search the npm registry for packages
+///
+/// # Arguments
+/// * `query` - Search text
+/// * `limit` - Most results to return
+pub async fn search_packages(query: &str, limit: usize) -> Result<Vec<search::SearchResult>> {
+    registry::Registry::new().search(query, limit).await
 }
 
 /// Get the latest version of a package
@@ -113,67 +409,406 @@ pub async fn get_latest_package_version(name: &str) -> Result<PackageVersion> {
 }
 
 /// Install packages
-pub async fn install_packages(packages: &[String], options: &InstallOptions) -> Result<InstallResult> {
+///
+/// With `options.check_typosquats`, a requested registry name that looks
+/// like a typo of a popular package adds a warning to the result.
+///
+/// # Arguments
+/// * `packages` - Install specs (`name`, `name@range`, git, path or tarball)
+/// * `options` - Installation options
+/// * `registry` - Registry backend serving metadata and tarballs
+pub async fn install_packages(
+    packages: &[String],
+    options: &InstallOptions,
+    registry: &dyn PackageRegistry,
+) -> Result<InstallResult> {
     info!("Installing packages: {:?}", packages);
     
+    validate_registry_names(packages)?;
+    let typosquats = if options.check_typosquats {
+        typosquat_warnings(packages)?
+    } else {
+        Vec::new()
+    };
+
+    let mut result = if options.global {
+        install_global(packages, options, registry).await?
+    } else {
+        let project_dir = std::env::current_dir()?;
+        let mut options = if options.frozen {
+            with_project_lockfile(options, &project_dir)?
+        } else {
+            options.clone()
+        };
+        if options.overrides.is_empty() && project_dir.join(manifest::MANIFEST_FILE).is_file() {
+            options.overrides = overrides::project_overrides(&manifest::read_package_info(&project_dir)?)?;
+        }
+        install_specs(&project_dir, packages, Vec::new(), &options, registry).await?
+    };
+    result.warnings.extend(typosquats);
+    Ok(result)
+}
+
+/// Install packages into the global prefix and link their executables
+///
+/// Only the requested packages have their `bin` entries linked; their
+/// dependencies are installed alongside them but expose no commands.
+async fn install_global(
+    packages: &[String],
+    options: &InstallOptions,
+    registry: &dyn PackageRegistry,
+) -> Result<InstallResult> {
+    let paths = global::GlobalPaths::new(options.global_prefix.as_deref())?;
+    info!("Installing globally into {}", paths.prefix.display());
+    std::fs::create_dir_all(paths.lib_dir())?;
+    
+    let result = install_specs(&paths.lib_dir(), packages, Vec::new(), options, registry).await?;
+    
+    for package_spec in packages {
+        let name = match spec::PackageSpec::parse(package_spec)? {
+            spec::PackageSpec::Registry { name, .. } => Some(name),
+            spec::PackageSpec::Git { name, .. }
+            | spec::PackageSpec::File { name, .. }
+            | spec::PackageSpec::Tarball { name, .. } => name,
+        };
+        let Some(name) = name else {
+            warn!("Not linking executables of {}: the spec doesn't name the package", package_spec);
+            continue;
+        };
+        let package_dir = paths.node_modules().join(&name);
+        let pkg = manifest::read_package_info(&package_dir)?;
+        global::link_bins(&package_dir, &pkg, &paths.bin_dir())?;
+    }
+    
+    Ok(result)
+}
+
+/// Install packages and record them in package.json
+///
+/// Registry packages are saved with the spec chosen by `manifest::save_spec`;
+/// git, path and tarball specs are saved verbatim when they name the package.
+///
+/// # Arguments
+/// * `packages` - Install specs, as accepted by `install_packages`
+/// * `add_options` - Which section to save to and how to write versions
+/// * `options` - Installation options
+pub async fn add_packages(
+    packages: &[String],
+    add_options: &AddOptions,
+    options: &InstallOptions,
+) -> Result<InstallResult> {
+    info!("Adding packages: {:?}", packages);
+    
+    validate_registry_names(packages)?;
+    let project_dir = std::env::current_dir()?;
+    let result = install_specs(&project_dir, packages, Vec::new(), options, &default_registry(options)).await?;
+    
+    let mut saved = Vec::new();
+    for package_spec in packages {
+        match spec::PackageSpec::parse(package_spec)? {
+            spec::PackageSpec::Registry { name, range } => {
+                let installed = result
+                    .installed_packages
+                    .iter()
+                    .find(|pkg| pkg.name == name)
+                    .with_context(|| format!("{} was not installed", name))?;
+                let version_spec = manifest::save_spec(range.as_deref(), &installed.version, add_options.save_exact);
+                saved.push((name, version_spec));
+            }
+            spec::PackageSpec::Git { name: Some(name), .. }
+            | spec::PackageSpec::File { name: Some(name), .. }
+            | spec::PackageSpec::Tarball { name: Some(name), .. } => {
+                let version_spec = package_spec[name.len() + 1..].to_string();
+                saved.push((name, version_spec));
+            }
+            _ => warn!("Not saving {} to package.json: the spec doesn't name the package", package_spec),
+        }
+    }
+    
+    manifest::save_dependencies(&project_dir, &saved, add_options.dev)?;
+    Ok(result)
+}
+
+/// Install all dependencies from package.json
+///
+/// When the manifest declares workspaces, every member is linked into
+/// `node_modules` and the external dependencies of all members are
+/// installed along with the root's own dependencies.
+pub async fn install_all_dependencies(options: &InstallOptions) -> Result<InstallResult> {
+    info!("Installing all dependencies from package.json");
+    if options.global {
+        anyhow::bail!("Global installs need the names of the packages to install");
+    }
+    
+    let project_dir = std::env::current_dir()?;
+    let manifest = manifest::read_package_info(&project_dir)?;
+    
+    let mut options = with_project_lockfile(options, &project_dir)?;
+    if options.overrides.is_empty() {
+        options.overrides = overrides::project_overrides(&manifest)?;
+    }
+    let options = &options;
+    let workspace = workspace::read_workspace(&project_dir)?;
+    let is_member = |name: &str| workspace.as_ref().is_some_and(|ws| ws.member(name).is_some());
+    
+    let mut specs: Vec<String> = workspace::selected_dependencies(&manifest, options)
+        .filter(|(name, _)| !is_member(name))
+        .map(|(name, range)| format!("{}@{}", name, range))
+        .collect();
+    specs.sort();
+    
+    let mut local_packages = Vec::new();
+    if let Some(workspace) = &workspace {
+        workspace.link_members(&project_dir.join("node_modules"))?;
+        local_packages.extend(workspace.members.iter().map(|member| member.package.clone()));
+        specs.extend(
+            workspace
+                .external_dependencies(options)
+                .into_iter()
+                .filter_map(|(name, range)| range.map(|range| format!("{}@{}", name, range))),
+        );
+    }
+    
+    install_specs(&project_dir, &specs, local_packages, options, &default_registry(options)).await
+}
+
+/// Registry backend for installs whose caller doesn't supply one
+fn default_registry(options: &InstallOptions) -> HttpRegistry {
+    HttpRegistry::with_config(registry::RegistryConfig {
+        offline: options.offline,
+        ..Default::default()
+    })
+}
+
+/// Check the names of registry specs before anything is fetched
+fn validate_registry_names(packages: &[String]) -> Result<()> {
+    for package_spec in packages {
+        if let spec::PackageSpec::Registry { name, .. } = spec::PackageSpec::parse(package_spec)? {
+            spec::validate_package_name(&name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Warn about registry specs whose names look like typos of popular packages
+///
+/// Warnings are logged right away, so they're seen even if the install fails.
+fn typosquat_warnings(packages: &[String]) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    for package_spec in packages {
+        if let spec::PackageSpec::Registry { name, .. } = spec::PackageSpec::parse(package_spec)? {
+            if let Some(intended) = typosquat::detect_typosquat(&name, typosquat::popular_packages()) {
+                let warning = format!("{} looks like a typo of the popular package {}; check the name", name, intended);
+                warn!("{}", warning);
+                warnings.push(warning);
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+/// Fill in the project's lockfile when the options don't already carry one
+fn with_project_lockfile(options: &InstallOptions, project_dir: &std::path::Path) -> Result<InstallOptions> {
+    let mut options = options.clone();
+    if options.lockfile.is_none() {
+        options.lockfile = lockfile::load_project_lockfile(project_dir)?;
+    }
+    Ok(options)
+}
+
+/// Resolve and install a set of package specs into a project
+///
+/// A frozen install never writes the lockfile and fails up front when
+/// there is none to resolve from. Cancelling `options.cancel` stops the
+/// install at its next await point, abandoning in-flight downloads;
+/// extractions already running are waited for, so nothing is written into
+/// the project once `CoreError::Cancelled` is returned. An
+/// offline install fails on git and tarball URL specs with
+/// `CoreError::RequiresNetwork`, and reads metadata through
+/// `PackageRegistry::metadata_offline` however `registry` is configured.
+async fn install_specs(
+    project_dir: &std::path::Path,
+    packages: &[String],
+    local_packages: Vec<PackageInfo>,
+    options: &InstallOptions,
+    registry: &dyn PackageRegistry,
+) -> Result<InstallResult> {
+    let extractor = download::Extractor::from_options(options);
+    tokio::select! {
+        biased;
+        _ = options.cancel.cancelled() => {
+            info!("Install of {:?} was cancelled", packages);
+            extractor.wait_idle().await?;
+            Err(CoreError::Cancelled.into())
+        }
+        result = run_install(project_dir, packages, local_packages, options, registry, &extractor) => result,
+    }
+}
+
+/// Run an install to completion; see `install_specs`
+async fn run_install(
+    project_dir: &std::path::Path,
+    packages: &[String],
+    mut local_packages: Vec<PackageInfo>,
+    options: &InstallOptions,
+    registry: &dyn PackageRegistry,
+    extractor: &download::Extractor,
+) -> Result<InstallResult> {
     let start_time = std::time::Instant::now();
-    let mut installed_packages = Vec::new();
+    let report = std::sync::Mutex::new(report::InstallReport::new());
+    let node_modules = project_dir.join("node_modules");
+    let store = store::Store::with_config(options.store.clone());
+    if options.frozen && options.lockfile.is_none() {
+        return Err(CoreError::FrozenLockfile(format!("no lockfile found in {}", project_dir.display())).into());
+    }
     
+    let mut roots = Vec::new();
+    let mut failed = Vec::new();
     for package_spec in packages {
-        // Parse package name and version (if specified)
-        let parts: Vec<&str> = package_spec.split('@').collect();
-        let (name, version_req) = if parts.len() == 2 {
-            (parts[0], Some(parts[1]))
-        } else {
-            (package_spec.as_str(), None)
-        };
-        
-        info!("Processing package: {} {:?}", name, version_req);
-        
-        let version_info = if let Some(version) = version_req {
-            let metadata = fetch_package_metadata(name).await?;
-            if let Some(version_info) = metadata.versions.get(version) {
-                version_info.clone()
-            } else {
-                warn!("Requested version {} not found for package {}, using latest", version, name);
-                get_latest_package_version(name).await?
+        let outcome = async {
+            match spec::PackageSpec::parse(package_spec)? {
+                spec::PackageSpec::Registry { name, range } => roots.push((name, range)),
+                spec::PackageSpec::Git { .. } => {
+                    if options.offline {
+                        return Err(CoreError::RequiresNetwork(format!("Cloning {}", package_spec)).into());
+                    }
+                    let (checkout, pkg_info) = git::resolve_git_dependency(package_spec).await?;
+                    local::copy_local_package(checkout.path(), &node_modules, &pkg_info.name)?;
+                    local_packages.push(pkg_info);
+                }
+                spec::PackageSpec::File { path, .. } => {
+                    let (dir, pkg_info) = local::resolve_file_dependency(&path, project_dir)?;
+                    local::link_local_package(&dir, &node_modules, &pkg_info.name)?;
+                    local_packages.push(pkg_info);
+                }
+                spec::PackageSpec::Tarball { url, integrity, .. } => {
+                    if options.offline {
+                        return Err(CoreError::RequiresNetwork(format!("Downloading {}", url)).into());
+                    }
+                    let start = std::time::Instant::now();
+                    let (bytes, pkg_info) = tarball::resolve_tarball_dependency(
+                        &url,
+                        integrity.as_deref(),
+                        options.temp_dir.as_deref(),
+                        options.max_package_size_bytes,
+                    )
+                    .await?;
+                    report.lock().unwrap().record_since(report::InstallPhase::Download, start);
+                    store.put_from(&bytes, &url)?;
+                    let start = std::time::Instant::now();
+                    tarball::extract_tarball(&bytes, &node_modules.join(&pkg_info.name), options.temp_dir.as_deref())?;
+                    report.lock().unwrap().record_since(report::InstallPhase::Extraction, start);
+                    local_packages.push(pkg_info);
+                }
             }
-        } else {
-            get_latest_package_version(name).await?
-        };
-        
-        let mut pkg_info = PackageInfo::new(&version_info.name, &version_info.version);
-        
-        if let Some(deps) = version_info.dependencies {
-            pkg_info.dependencies = deps;
+            anyhow::Ok(())
+        }
+        .await;
+        match outcome {
+            Err(e) if options.continue_on_error => {
+                warn!("Failed to install {}: {:#}", package_spec, e);
+                failed.push(resolver::FailedSpec {
+                    spec: package_spec.clone(),
+                    required_by: None,
+                    reason: format!("{:#}", e),
+                });
+            }
+            outcome => outcome?,
+        }
+    }
+    
+    let resolution_start = std::time::Instant::now();
+    let cache_key = if options.force {
+        None
+    } else {
+        resolution_cache::resolution_key(project_dir, packages, &local_packages, options, &registry.source_id())?
+    };
+    let cached = cache_key
+        .as_deref()
+        .and_then(|key| resolution_cache::load_cached_tree(&node_modules, key));
+    let tree = match cached {
+        Some(tree) => {
+            info!("Reusing the resolution of the previous install: package.json and lockfile are unchanged");
+            tree
         }
-        
-        if let Some(dev_deps) = version_info.dev_dependencies {
-            pkg_info.dev_dependencies = dev_deps;
+        None => {
+            resolver::resolve_tree_from(local_packages, &roots, options, |name| async move {
+                if options.offline {
+                    registry.metadata_offline(&name).await
+                } else {
+                    registry.metadata(&name).await
+                }
+            })
+            .await?
         }
-        
-        installed_packages.push(pkg_info);
+    };
+    report.lock().unwrap().record_since(report::InstallPhase::Resolution, resolution_start);
+    hooks::run_install_hooks(&options.hooks, &tree.packages).await?;
+    if let Some(policy) = &options.license_policy {
+        policy.check(&tree.packages)?;
     }
     
+    let mut warnings = tree.warnings.clone();
+    let lifecycle_scripts = scripts::detect_install_scripts(&tree.packages, options);
+    let peer_issues = peers::check_peer_dependencies(&tree.packages, options.include_prereleases);
+    warnings.extend(peers::enforce_peer_dependencies(&peer_issues, options)?);
+    
+    let downloads = download::plan_downloads(&tree.packages, &tree.distributions, options.include_prereleases)?;
+    let total_size = download::download_packages(
+        registry,
+        &store,
+        downloads,
+        &node_modules,
+        options,
+        extractor,
+        &report,
+    )
+    .await?;
+    
+    // Only a tree that installed successfully is worth reusing
+    if let Some(key) = &cache_key {
+        if let Err(e) = resolution_cache::save_cached_tree(&node_modules, key, &tree) {
+            warn!("Failed to cache the resolution: {:#}", e);
+        }
+    }
+    
+    // Wall-clock time of the whole install, not the sum of concurrent downloads
     let duration = start_time.elapsed();
     
+    let mut installed_packages = tree.packages;
+    sort_packages(&mut installed_packages);
+    let licenses = license::collect_licenses(&installed_packages);
     Ok(InstallResult {
         installed_packages,
         duration,
-        total_size: 0, // TODO: Calculate actual size
+        total_size,
+        warnings,
+        peer_issues,
+        skipped_optional: tree.skipped_optional,
+        failed: failed.into_iter().chain(tree.failed).collect(),
+        deduplicated: tree.deduplicated,
+        direct_count: tree.direct_count,
+        transitive_count: tree.transitive_count,
+        deduped_count: tree.deduplicated,
+        lifecycle_scripts,
+        licenses,
+        report: report.into_inner().unwrap(),
     })
 }
 
-/// Install all dependencies from package.json
-pub async fn install_all_dependencies(options: &InstallOptions) -> Result<InstallResult> {
-    info!("Installing all dependencies from package.json");
-    
-    // Placeholder implementation
-    Ok(InstallResult {
-        installed_packages: vec![],
-        duration: std::time::Duration::from_secs(0),
-        total_size: 0,
-    })
+/// Sort packages by name, then by version
+///
+/// Versions compare as semver where both parse and as strings otherwise.
+/// The sort is stable, so packages that compare equal keep their order.
+pub fn sort_packages(packages: &mut [PackageInfo]) {
+    packages.sort_by(|a, b| {
+        a.name.cmp(&b.name).then_with(|| {
+            match (semver::parse_version(&a.version), semver::parse_version(&b.version)) {
+                (Ok(left), Ok(right)) => left.cmp(&right),
+                _ => a.version.cmp(&b.version),
+            }
+        })
+    });
 }
 
 /// Get package information
@@ -185,6 +820,18 @@ pub fn get_package_info(name: &str) -> PackageInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{InMemoryRegistry, VersionBuilder};
+
+    /// Install options with a store of their own, so tests never write to the user cache
+    fn isolated_options(store: &tempfile::TempDir) -> InstallOptions {
+        InstallOptions {
+            store: store::StoreConfig {
+                root: store.path().to_path_buf(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_package_info_creation() {
@@ -193,6 +840,22 @@ mod tests {
         assert_eq!(pkg.version, "1.0.0");
     }
 
+    #[test]
+    fn test_bundled_dependencies_forms() {
+        let pkg: PackageInfo = serde_json::from_str(
+            r#"{ "name": "a", "version": "1.0.0", "bundleDependencies": ["inner"] }"#,
+        )
+        .unwrap();
+        assert!(pkg.is_bundled("inner"));
+        assert!(!pkg.is_bundled("other"));
+
+        let pkg: PackageInfo =
+            serde_json::from_str(r#"{ "name": "a", "version": "1.0.0", "bundledDependencies": true }"#).unwrap();
+        assert!(pkg.is_bundled("anything"));
+
+        assert!(!PackageInfo::new("a", "1.0.0").is_bundled("inner"));
+    }
+
     #[test]
     fn test_get_package_info() {
         let pkg = get_package_info("test-package");
@@ -202,10 +865,812 @@ mod tests {
     
     #[tokio::test]
     async fn test_install_packages() {
+        let store = tempfile::TempDir::new().unwrap();
         let packages = vec!["package-fast-nonexistent-package-12345".to_string()];
-        let options = InstallOptions::default();
-        let result = install_packages(&packages, &options).await;
+        let options = isolated_options(&store);
+        let result = install_packages(&packages, &options, &InMemoryRegistry::new()).await;
         // This should fail because the package doesn't exist
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_install_specs_from_memory_registry() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(VersionBuilder::new("app-lib", "1.0.0").dependency("util", "^2.0.0"))
+            .publish(VersionBuilder::new("util", "2.1.0").file("index.js", "module.exports = {};"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        let result = install_specs(
+            project.path(),
+            &["app-lib".to_string()],
+            Vec::new(),
+            &isolated_options(&store),
+            &registry,
+        )
+        .await
+        .unwrap();
+
+        let names: Vec<&str> = result.installed_packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["app-lib", "util"]);
+        assert_eq!(result.direct_count, 1);
+        assert_eq!(result.transitive_count, 1);
+        assert_eq!(result.deduped_count, 0);
+        assert!(result.total_size > 0);
+        assert!(project.path().join("node_modules/util/index.js").is_file());
+    }
+
+    /// Run git in `dir` for a test fixture, with a fixed identity
+    fn git(dir: &std::path::Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[tokio::test]
+    async fn test_install_git_dependency_from_bare_repo() {
+        let store = tempfile::TempDir::new().unwrap();
+        let root = tempfile::TempDir::new().unwrap();
+        let work = root.path().join("work");
+        std::fs::create_dir(&work).unwrap();
+        std::fs::write(work.join("package.json"), r#"{"name": "git-lib", "version": "1.2.0"}"#).unwrap();
+        std::fs::write(work.join("index.js"), "module.exports = 1;").unwrap();
+        git(&work, &["init", "--quiet"]);
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "--quiet", "-m", "initial"]);
+        git(root.path(), &["clone", "--quiet", "--bare", "work", "repo.git"]);
+
+        let project = tempfile::TempDir::new().unwrap();
+        let spec = format!("git+file://{}", root.path().join("repo.git").display());
+        let registry = InMemoryRegistry::new();
+        let result = install_specs(project.path(), &[spec], Vec::new(), &isolated_options(&store), &registry)
+            .await
+            .unwrap();
+
+        assert_eq!(result.installed_packages[0].name, "git-lib");
+        let installed = project.path().join("node_modules/git-lib");
+        assert!(installed.join("package.json").is_file());
+        assert!(installed.join("index.js").is_file());
+        assert!(!installed.join(".git").exists());
+    }
+
+    #[tokio::test]
+    async fn test_install_nests_conflicting_versions() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(VersionBuilder::new("legacy", "1.0.0").dependency("util", "^1.0.0"))
+            .publish(VersionBuilder::new("util", "1.4.0").file("index.js", "module.exports = 1;"))
+            .publish(VersionBuilder::new("util", "2.1.0").file("index.js", "module.exports = 2;"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        install_specs(
+            project.path(),
+            &["util@^2.0.0".to_string(), "legacy".to_string()],
+            Vec::new(),
+            &isolated_options(&store),
+            &registry,
+        )
+        .await
+        .unwrap();
+
+        let node_modules = project.path().join("node_modules");
+        let read = |path: &str| std::fs::read_to_string(node_modules.join(path)).unwrap();
+        assert_eq!(read("util/index.js"), "module.exports = 2;");
+        assert_eq!(read("legacy/node_modules/util/index.js"), "module.exports = 1;");
+    }
+
+    #[tokio::test]
+    async fn test_install_collects_licenses_and_enforces_policy() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(
+                VersionBuilder::new("app-lib", "1.0.0")
+                    .license("MIT")
+                    .dependency("util", "^2.0.0")
+                    .dependency("copyleft", "^1.0.0"),
+            )
+            .publish(VersionBuilder::new("util", "2.1.0").license("MIT"))
+            .publish(VersionBuilder::new("copyleft", "1.0.0").license("GPL-3.0-only"));
+        let specs = ["app-lib".to_string()];
+
+        let project = tempfile::TempDir::new().unwrap();
+        let result = install_specs(project.path(), &specs, Vec::new(), &isolated_options(&store), &registry)
+            .await
+            .unwrap();
+        assert_eq!(result.licenses["MIT"], vec!["app-lib@1.0.0", "util@2.1.0"]);
+        assert_eq!(result.licenses["GPL-3.0-only"], vec!["copyleft@1.0.0"]);
+
+        let denying = InstallOptions {
+            license_policy: Some(license::LicensePolicy {
+                allow: Vec::new(),
+                deny: vec!["GPL-*".to_string()],
+            }),
+            ..isolated_options(&store)
+        };
+        let project = tempfile::TempDir::new().unwrap();
+        let err = install_specs(project.path(), &specs, Vec::new(), &denying, &registry)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::LicenseDenied { package, license })
+                if package == "copyleft@1.0.0" && license == "GPL-3.0-only"
+        ));
+        assert!(!project.path().join("node_modules/app-lib").exists(), "nothing is downloaded");
+    }
+
+    #[tokio::test]
+    async fn test_install_reports_lifecycle_scripts() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(VersionBuilder::new("app-lib", "1.0.0").dependency("native", "^1.0.0"))
+            .publish(VersionBuilder::new("native", "1.2.0").script("postinstall", "node build.js"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        let options = InstallOptions {
+            ignore_scripts: false,
+            ..isolated_options(&store)
+        };
+        let result = install_specs(project.path(), &["app-lib".to_string()], Vec::new(), &options, &registry)
+            .await
+            .unwrap();
+
+        assert_eq!(result.installed_packages[1].scripts["postinstall"], "node build.js");
+        assert_eq!(
+            result.lifecycle_scripts,
+            vec![scripts::LifecycleScript {
+                package: "native".to_string(),
+                version: "1.2.0".to_string(),
+                event: "postinstall".to_string(),
+                command: "node build.js".to_string(),
+                decision: scripts::ScriptDecision::Blocked,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_install_reports_phase_timings() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(VersionBuilder::new("app-lib", "1.0.0").dependency("util", "^1.0.0"))
+            .publish(VersionBuilder::new("util", "1.1.0").file("index.js", "module.exports = 1;"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        let result = install_specs(
+            project.path(),
+            &["app-lib".to_string()],
+            Vec::new(),
+            &isolated_options(&store),
+            &registry,
+        )
+        .await
+        .unwrap();
+
+        let resolution = result.report.phase(report::InstallPhase::Resolution);
+        assert_eq!(resolution.count, 1);
+        assert!(resolution.total > std::time::Duration::ZERO);
+        for phase in [
+            report::InstallPhase::Download,
+            report::InstallPhase::Verification,
+            report::InstallPhase::Extraction,
+        ] {
+            let timing = result.report.phase(phase);
+            assert_eq!(timing.count, 2, "{} ran once per package", phase);
+            assert!(timing.total > std::time::Duration::ZERO, "{} took no time", phase);
+        }
+    }
+
+    /// Hook rejecting one package by name
+    #[derive(Debug)]
+    struct DenyHook(&'static str);
+
+    #[async_trait::async_trait]
+    impl hooks::InstallHook for DenyHook {
+        async fn on_resolved(&self, pkg: &PackageInfo) -> Result<()> {
+            if pkg.name == self.0 {
+                anyhow::bail!("{} is not allowed", pkg.name);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_hook_aborts_install() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(VersionBuilder::new("app-lib", "1.0.0").dependency("util", "^1.0.0"))
+            .publish(VersionBuilder::new("util", "1.1.0"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        let mut options = isolated_options(&store);
+        options.hooks.push(std::sync::Arc::new(DenyHook("left-pad")));
+        install_specs(project.path(), &["app-lib".to_string()], Vec::new(), &options, &registry)
+            .await
+            .unwrap();
+
+        // A transitive dependency is checked too, and nothing is installed
+        let project = tempfile::TempDir::new().unwrap();
+        options.hooks.push(std::sync::Arc::new(DenyHook("util")));
+        let err = install_specs(project.path(), &["app-lib".to_string()], Vec::new(), &options, &registry)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("rejected util@1.1.0"));
+        assert_eq!(err.root_cause().to_string(), "util is not allowed");
+        assert!(!project.path().join("node_modules/app-lib").exists());
+    }
+
+    /// Registry whose tarballs stall partway through, downloaded through a cache directory
+    struct StallingRegistry {
+        packages: InMemoryRegistry,
+        tarballs: backend::HttpRegistry,
+        server_url: String,
+    }
+
+    #[async_trait::async_trait]
+    impl PackageRegistry for StallingRegistry {
+        async fn metadata(&self, name: &str) -> Result<PackageMetadata> {
+            self.packages.metadata(name).await
+        }
+
+        async fn tarball(&self, dist: &PackageDistribution) -> Result<bytes::Bytes> {
+            let dist = PackageDistribution {
+                tarball: format!("{}/{}", self.server_url, dist.tarball.trim_start_matches("memory://")),
+                ..dist.clone()
+            };
+            self.tarballs.tarball(&dist).await
+        }
+    }
+
+    /// Serve the first bytes of a tarball, then hold the connection open
+    async fn serve_stalled_tarball() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut stalled = Vec::new();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0u8];
+                    if socket.read_exact(&mut byte).await.is_err() {
+                        break;
+                    }
+                    request.push(byte[0]);
+                }
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4096\r\n\r\npartial tarball")
+                    .await;
+                stalled.push(socket);
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_install_cancelled_mid_download() {
+        let store = tempfile::TempDir::new().unwrap();
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        let server_url = serve_stalled_tarball().await;
+        let mut packages = InMemoryRegistry::new();
+        packages.publish(VersionBuilder::new("util", "1.1.0"));
+        let registry = StallingRegistry {
+            packages,
+            tarballs: backend::HttpRegistry::with_config(registry::RegistryConfig {
+                url: server_url.clone(),
+                cache_dir: Some(cache_dir.path().to_path_buf()),
+                ..Default::default()
+            }),
+            server_url,
+        };
+
+        let options = isolated_options(&store);
+        let project = tempfile::TempDir::new().unwrap();
+        let downloads = cache_dir.path().join("downloads");
+        let partial_files = || -> Vec<std::path::PathBuf> {
+            std::fs::read_dir(&downloads)
+                .map(|entries| entries.map(|entry| entry.unwrap().path()).collect())
+                .unwrap_or_default()
+        };
+        let cancel_once_downloading = async {
+            while !partial_files().iter().any(|path| std::fs::metadata(path).is_ok_and(|m| m.len() > 0)) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            options.cancel.cancel();
+        };
+
+        let specs = ["util".to_string()];
+        let install = install_specs(project.path(), &specs, Vec::new(), &options, &registry);
+        let (result, ()) = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            tokio::join!(install, cancel_once_downloading)
+        })
+        .await
+        .expect("the install was never cancelled");
+
+        let err = result.unwrap_err();
+        assert!(matches!(err.downcast_ref::<CoreError>(), Some(CoreError::Cancelled)));
+        assert_eq!(partial_files(), Vec::<std::path::PathBuf>::new());
+        assert!(!project.path().join("node_modules/util").exists());
+
+        // An already cancelled token stops the install before it starts
+        let err = install_specs(project.path(), &specs, Vec::new(), &options, &registry)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Install was cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_install_orders_packages_deterministically() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(
+                VersionBuilder::new("zeta", "1.0.0")
+                    .dependency("beta", "^1.0.0")
+                    .dependency("alpha", "^1.0.0"),
+            )
+            .publish(VersionBuilder::new("mid", "1.0.0").dependency("beta", "^2.0.0"))
+            .publish(VersionBuilder::new("alpha", "1.0.0"))
+            .publish(VersionBuilder::new("beta", "1.10.0"))
+            .publish(VersionBuilder::new("beta", "2.0.0"));
+        let specs = ["zeta".to_string(), "mid".to_string()];
+        let options = InstallOptions {
+            max_concurrency: 4,
+            ..isolated_options(&store)
+        };
+
+        for _ in 0..5 {
+            let project = tempfile::TempDir::new().unwrap();
+            let result = install_specs(project.path(), &specs, Vec::new(), &options, &registry)
+                .await
+                .unwrap();
+            let installed: Vec<String> = result
+                .installed_packages
+                .iter()
+                .map(|p| format!("{}@{}", p.name, p.version))
+                .collect();
+            assert_eq!(installed, vec!["alpha@1.0.0", "beta@1.10.0", "beta@2.0.0", "mid@1.0.0", "zeta@1.0.0"]);
+        }
+    }
+
+    #[test]
+    fn test_sort_packages_compares_versions_as_semver() {
+        let mut packages = vec![
+            PackageInfo::new("b", "1.10.0"),
+            PackageInfo::new("a", "2.0.0"),
+            PackageInfo::new("b", "1.9.0"),
+            PackageInfo::new("b", "1.10.0-rc.1"),
+        ];
+        packages[0].scripts.insert("first".to_string(), String::new());
+        packages.push(PackageInfo::new("b", "1.10.0"));
+
+        sort_packages(&mut packages);
+        let sorted: Vec<String> = packages.iter().map(|p| format!("{}@{}", p.name, p.version)).collect();
+        assert_eq!(sorted, vec!["a@2.0.0", "b@1.9.0", "b@1.10.0-rc.1", "b@1.10.0", "b@1.10.0"]);
+        // Equal packages keep their relative order
+        assert!(packages[3].scripts.contains_key("first"));
+    }
+
+    /// Records the fields of every `package` span once it closes
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        open: std::sync::Arc<std::sync::Mutex<HashMap<tracing::span::Id, HashMap<String, String>>>>,
+        closed: std::sync::Arc<std::sync::Mutex<Vec<HashMap<String, String>>>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanRecorder {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attrs.metadata().name() == "package" {
+                let mut fields = HashMap::new();
+                attrs.record(&mut FieldVisitor(&mut fields));
+                self.open.lock().unwrap().insert(id.clone(), fields);
+            }
+        }
+
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if let Some(fields) = self.open.lock().unwrap().get_mut(id) {
+                values.record(&mut FieldVisitor(fields));
+            }
+        }
+
+        fn on_close(&self, id: tracing::span::Id, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+            if let Some(fields) = self.open.lock().unwrap().remove(&id) {
+                self.closed.lock().unwrap().push(fields);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_records_package_spans() {
+        let store = tempfile::TempDir::new().unwrap();
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let mut registry = InMemoryRegistry::new();
+        registry.publish(VersionBuilder::new("util", "2.1.0"));
+        let recorder = SpanRecorder::default();
+        let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let project = tempfile::TempDir::new().unwrap();
+        install_specs(
+            project.path(),
+            &["util@^2.0.0".to_string()],
+            Vec::new(),
+            &isolated_options(&store),
+            &registry,
+        )
+        .await
+        .unwrap();
+
+        let spans = recorder.closed.lock().unwrap().clone();
+        let phases: Vec<&str> = spans
+            .iter()
+            .filter(|fields| fields["package"] == "util" && fields["version"] == "2.1.0")
+            .map(|fields| fields["phase"].as_str())
+            .collect();
+        for phase in ["resolve", "download", "verify", "extract"] {
+            assert!(phases.contains(&phase), "no {} span in {:?}", phase, spans);
+        }
+        let resolve = spans.iter().find(|fields| fields["phase"] == "resolve").unwrap();
+        assert_eq!(resolve["range"], "^2.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_frozen_install_from_memory_registry() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry.publish(VersionBuilder::new("util", "2.1.0"));
+        registry.publish(VersionBuilder::new("util", "2.3.0"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        let lockfile_path = project.path().join(lockfile::LOCKFILE_NAME);
+        let mut locked = lockfile::Lockfile::new();
+        locked.insert(lockfile::LockedPackage::new("util", "2.1.0"));
+        locked.write(&lockfile_path).unwrap();
+        let before = std::fs::read(&lockfile_path).unwrap();
+
+        let frozen = with_project_lockfile(
+            &InstallOptions {
+                frozen: true,
+                ..isolated_options(&store)
+            },
+            project.path(),
+        )
+        .unwrap();
+        let specs = ["util@^2.0.0".to_string()];
+        let result = install_specs(project.path(), &specs, Vec::new(), &frozen, &registry).await.unwrap();
+        assert_eq!(result.installed_packages[0].version, "2.1.0");
+
+        let specs = ["util@^2.2.0".to_string()];
+        let err = install_specs(project.path(), &specs, Vec::new(), &frozen, &registry).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<CoreError>(), Some(CoreError::FrozenLockfile(_))));
+        assert_eq!(std::fs::read(&lockfile_path).unwrap(), before);
+
+        // Without a lockfile there is nothing to resolve from
+        let empty = tempfile::TempDir::new().unwrap();
+        let options = InstallOptions {
+            frozen: true,
+            ..isolated_options(&store)
+        };
+        let err = install_specs(empty.path(), &["util".to_string()], Vec::new(), &options, &registry)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no lockfile found"));
+    }
+
+    /// Registry counting the metadata and tarball requests it serves
+    #[derive(Default)]
+    struct CountingRegistry {
+        packages: InMemoryRegistry,
+        metadata_requests: std::sync::atomic::AtomicUsize,
+        tarball_requests: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl PackageRegistry for CountingRegistry {
+        async fn metadata(&self, name: &str) -> Result<PackageMetadata> {
+            self.metadata_requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.packages.metadata(name).await
+        }
+
+        async fn tarball(&self, dist: &PackageDistribution) -> Result<bytes::Bytes> {
+            self.tarball_requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.packages.tarball(dist).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offline_install_reads_tarballs_from_store() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = CountingRegistry::default();
+        registry.packages.publish(VersionBuilder::new("app-lib", "1.0.0").dependency("util", "^2.0.0"));
+        registry.packages.publish(VersionBuilder::new("util", "2.1.0"));
+        registry.packages.publish(VersionBuilder::new("never-stored", "1.0.0"));
+        let tarballs = || registry.tarball_requests.load(std::sync::atomic::Ordering::SeqCst);
+        let specs = ["app-lib@^1.0.0".to_string()];
+
+        let online = tempfile::TempDir::new().unwrap();
+        install_specs(online.path(), &specs, Vec::new(), &isolated_options(&store), &registry)
+            .await
+            .unwrap();
+        assert_eq!(tarballs(), 2);
+
+        let offline = InstallOptions {
+            offline: true,
+            ..isolated_options(&store)
+        };
+        let project = tempfile::TempDir::new().unwrap();
+        let result = install_specs(project.path(), &specs, Vec::new(), &offline, &registry).await.unwrap();
+        assert_eq!(result.installed_packages.len(), 2);
+        assert!(project.path().join("node_modules/util/package.json").is_file());
+        assert_eq!(tarballs(), 2);
+
+        let err = install_specs(project.path(), &["never-stored".to_string()], Vec::new(), &offline, &registry)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::RequiresNetwork(_))), "{:#}", err);
+        let git = ["git+https://github.com/org/repo#v1.0.0".to_string()];
+        let err = install_specs(project.path(), &git, Vec::new(), &offline, &registry).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::RequiresNetwork(_))), "{:#}", err);
+        assert_eq!(tarballs(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_offline_install_ignores_an_online_registry() {
+        let store = tempfile::TempDir::new().unwrap();
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::any())
+            .respond_with(wiremock::ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let cache_dir = tempfile::TempDir::new().unwrap();
+        // Not configured offline itself: the install options alone keep it off the network
+        let registry = backend::HttpRegistry::with_config(registry::RegistryConfig {
+            url: server.uri(),
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Default::default()
+        });
+        let offline = InstallOptions {
+            offline: true,
+            ..isolated_options(&store)
+        };
+
+        let project = tempfile::TempDir::new().unwrap();
+        let specs = ["util@^1.0.0".to_string()];
+        let err = install_specs(project.path(), &specs, Vec::new(), &offline, &registry).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::RequiresNetwork(_))), "{:#}", err);
+        assert!(err.to_string().contains("util"));
+    }
+
+    #[tokio::test]
+    async fn test_install_never_fetches_dev_dependencies_of_packages() {
+        let store = tempfile::TempDir::new().unwrap();
+        // The dev dependencies aren't published: fetching either one would fail the install
+        let mut registry = CountingRegistry::default();
+        registry.packages.publish(
+            VersionBuilder::new("app-lib", "1.0.0")
+                .dependency("util", "^2.0.0")
+                .dev_dependency("test-runner", "^1.0.0"),
+        );
+        registry.packages.publish(VersionBuilder::new("util", "2.1.0").dev_dependency("linter", "^3.0.0"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        let specs = ["app-lib@^1.0.0".to_string()];
+        let result = install_specs(project.path(), &specs, Vec::new(), &isolated_options(&store), &registry)
+            .await
+            .unwrap();
+
+        let names: Vec<&str> = result.installed_packages.iter().map(|pkg| pkg.name.as_str()).collect();
+        assert_eq!(names, vec!["app-lib", "util"]);
+        assert_eq!(registry.metadata_requests.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert!(!project.path().join("node_modules/test-runner").exists());
+    }
+
+    #[tokio::test]
+    async fn test_install_reuses_resolution_of_unchanged_manifest() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = CountingRegistry::default();
+        registry.packages.publish(VersionBuilder::new("app-lib", "1.0.0").dependency("util", "^2.0.0"));
+        registry.packages.publish(VersionBuilder::new("util", "2.1.0"));
+        let requests = || registry.metadata_requests.load(std::sync::atomic::Ordering::SeqCst);
+
+        let project = tempfile::TempDir::new().unwrap();
+        let manifest = project.path().join(manifest::MANIFEST_FILE);
+        std::fs::write(&manifest, r#"{ "name": "app", "version": "1.0.0" }"#).unwrap();
+        let specs = ["app-lib@^1.0.0".to_string()];
+        let options = isolated_options(&store);
+
+        let first = install_specs(project.path(), &specs, Vec::new(), &options, &registry).await.unwrap();
+        assert_eq!(requests(), 2);
+        assert!(project.path().join("node_modules").join(resolution_cache::RESOLUTION_CACHE_FILE).is_file());
+
+        // Unchanged manifest: no metadata is fetched, and the same tree is installed
+        let second = install_specs(project.path(), &specs, Vec::new(), &options, &registry).await.unwrap();
+        assert_eq!(requests(), 2);
+        let versions = |result: &InstallResult| -> Vec<String> {
+            result.installed_packages.iter().map(|pkg| format!("{}@{}", pkg.name, pkg.version)).collect()
+        };
+        assert_eq!(versions(&second), versions(&first));
+
+        // A newer util is only picked up once the manifest changes
+        registry.packages.publish(VersionBuilder::new("util", "2.2.0"));
+        install_specs(project.path(), &specs, Vec::new(), &options, &registry).await.unwrap();
+        assert_eq!(requests(), 2);
+        std::fs::write(&manifest, r#"{ "name": "app", "version": "1.0.1" }"#).unwrap();
+        let third = install_specs(project.path(), &specs, Vec::new(), &options, &registry).await.unwrap();
+        assert_eq!(requests(), 4);
+        assert!(versions(&third).contains(&"util@2.2.0".to_string()));
+
+        // Forcing always resolves again
+        let forced = InstallOptions {
+            force: true,
+            ..isolated_options(&store)
+        };
+        install_specs(project.path(), &specs, Vec::new(), &forced, &registry).await.unwrap();
+        assert_eq!(requests(), 6);
+    }
+
+    fn util_registry() -> InMemoryRegistry {
+        let mut registry = InMemoryRegistry::new();
+        registry.publish(VersionBuilder::new("util", "2.1.0"));
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_install_missing_package_aborts() {
+        let store = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        let specs = ["util".to_string(), "missing-pkg@^1.0.0".to_string()];
+
+        let err = install_specs(project.path(), &specs, Vec::new(), &isolated_options(&store), &util_registry())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::PackageNotFound { name }) if name == "missing-pkg"
+        ));
+        assert!(!project.path().join("node_modules/util").exists());
+    }
+
+    #[tokio::test]
+    async fn test_install_missing_package_continues_on_error() {
+        let store = tempfile::TempDir::new().unwrap();
+        let project = tempfile::TempDir::new().unwrap();
+        let specs = ["util".to_string(), "missing-pkg@^1.0.0".to_string(), "file:./absent".to_string()];
+        let options = InstallOptions {
+            continue_on_error: true,
+            ..isolated_options(&store)
+        };
+
+        let result = install_specs(project.path(), &specs, Vec::new(), &options, &util_registry())
+            .await
+            .unwrap();
+
+        let names: Vec<&str> = result.installed_packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["util"]);
+        assert!(project.path().join("node_modules/util/package.json").is_file());
+
+        let failed: Vec<&str> = result.failed.iter().map(|f| f.spec.as_str()).collect();
+        assert_eq!(failed, vec!["file:./absent", "missing-pkg@^1.0.0"]);
+        assert!(result.failed[0].reason.contains("does not exist"));
+        assert_eq!(result.failed[1].reason, "Package missing-pkg was not found in the registry");
+        assert_eq!(result.failed[1].required_by, None);
+    }
+
+    #[tokio::test]
+    async fn test_install_packages_warns_about_typosquats() {
+        let store = tempfile::TempDir::new().unwrap();
+        let mut registry = InMemoryRegistry::new();
+        registry.publish(VersionBuilder::new("lodahs", "1.0.0")).publish(VersionBuilder::new("lodash", "4.17.21"));
+        let prefix = tempfile::TempDir::new().unwrap();
+        let options = InstallOptions {
+            global: true,
+            global_prefix: Some(prefix.path().to_path_buf()),
+            ..isolated_options(&store)
+        };
+
+        let result = install_packages(&["lodahs".to_string(), "lodash".to_string()], &options, &registry)
+            .await
+            .unwrap();
+        assert_eq!(
+            result.warnings,
+            vec!["lodahs looks like a typo of the popular package lodash; check the name"]
+        );
+
+        let unchecked = InstallOptions {
+            check_typosquats: false,
+            ..options
+        };
+        let result = install_packages(&["lodahs".to_string()], &unchecked, &registry).await.unwrap();
+        assert!(result.warnings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_install_packages_rejects_invalid_names() {
+        let store = tempfile::TempDir::new().unwrap();
+        let packages = vec!["lodash".to_string(), "Bad_Name@1.0.0".to_string()];
+        let err = install_packages(&packages, &isolated_options(&store), &InMemoryRegistry::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::InvalidPackageName { name, .. }) if name == "Bad_Name"
+        ));
+
+        assert!(fetch_package_metadata("../etc/passwd").await.is_err());
+    }
+
+    #[test]
+    fn test_distribution_prefers_sri() {
+        let bytes = b"tarball bytes";
+        let sri = integrity::Integrity {
+            algorithm: integrity::HashAlgorithm::Sha512,
+            digest: integrity::HashAlgorithm::Sha512.digest(bytes),
+        }
+        .to_sri();
+        let shasum: String = integrity::HashAlgorithm::Sha1
+            .digest(bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let with_sri: PackageDistribution = serde_json::from_value(serde_json::json!({
+            "tarball": "https://registry.example/a.tgz",
+            "shasum": shasum,
+            "integrity": sri
+        }))
+        .unwrap();
+        assert_eq!(with_sri.expected_integrity().unwrap().algorithm, integrity::HashAlgorithm::Sha512);
+        assert!(with_sri.verify(bytes).is_ok());
+        assert!(with_sri.verify(b"tampered").is_err());
+
+        let legacy: PackageDistribution = serde_json::from_value(serde_json::json!({
+            "tarball": "https://registry.example/a.tgz",
+            "shasum": shasum
+        }))
+        .unwrap();
+        assert!(legacy.integrity.is_none());
+        assert_eq!(legacy.expected_integrity().unwrap().algorithm, integrity::HashAlgorithm::Sha1);
+        assert!(legacy.verify(bytes).is_ok());
+        assert!(legacy.verify(b"tampered").is_err());
+
+        let unusable = PackageDistribution {
+            shasum: "not hex".to_string(),
+            ..legacy
+        };
+        assert!(unusable.verify(bytes).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! License collection and policy
+//!
+//! Every installed package's `license` is collected into the install
+//! result, and an optional `LicensePolicy` rejects packages whose license
+//! the project can't accept, such as GPL code in a proprietary project.
+//! Licenses are SPDX expressions: `MIT OR Apache-2.0` is acceptable when
+//! either license is, and `MIT AND BSD-3-Clause` only when both are. `AND`
+//! binds tighter than `OR`, parentheses group, operators are matched
+//! case-insensitively, and `GPL-2.0-only WITH Classpath-exception-2.0` is
+//! judged by its license.
+
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+
+use crate::{CoreError, PackageInfo};
+
+/// Name used for packages that declare no license
+pub const UNKNOWN_LICENSE: &str = "UNKNOWN";
+
+/// A `license` field as published: an SPDX string, or the legacy `{ "type": ... }` object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LicenseField {
+    Spdx(String),
+    Legacy {
+        #[serde(rename = "type")]
+        kind: String,
+    },
+    Other(serde::de::IgnoredAny),
+}
+
+/// Deserialize a `license` field into its SPDX expression
+///
+/// Malformed values are treated as missing rather than failing the whole
+/// manifest.
+pub(crate) fn deserialize_license<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<LicenseField>::deserialize(deserializer)? {
+        Some(LicenseField::Spdx(license)) | Some(LicenseField::Legacy { kind: license }) => {
+            Some(license).filter(|license| !license.trim().is_empty())
+        }
+        Some(LicenseField::Other(_)) | None => None,
+    })
+}
+
+/// Licenses a project accepts
+///
+/// Entries are SPDX identifiers compared case-insensitively; a trailing `*`
+/// matches any identifier with that prefix (`GPL-*`). Denied identifiers
+/// always lose. When `allow` is empty every other license is accepted,
+/// otherwise a license must be allowed, and packages without one are
+/// rejected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LicensePolicy {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl LicensePolicy {
+    /// Check whether a package with the given license may be installed
+    ///
+    /// A value that isn't a valid expression (`SEE LICENSE IN LICENSE.txt`)
+    /// is checked as a single identifier.
+    ///
+    /// # Arguments
+    /// * `license` - SPDX expression, or `None` if the package declares none
+    pub fn permits(&self, license: Option<&str>) -> bool {
+        let Some(license) = license else {
+            return self.allow.is_empty();
+        };
+        match LicenseExpr::parse(license) {
+            Some(expr) => self.permits_expr(&expr),
+            None => self.permits_id(license.trim()),
+        }
+    }
+
+    /// Evaluate a parsed expression against the lists
+    fn permits_expr(&self, expr: &LicenseExpr) -> bool {
+        match expr {
+            LicenseExpr::License(id) => self.permits_id(id),
+            LicenseExpr::And(terms) => terms.iter().all(|term| self.permits_expr(term)),
+            LicenseExpr::Or(terms) => terms.iter().any(|term| self.permits_expr(term)),
+        }
+    }
+
+    /// Check a single SPDX identifier against the lists
+    fn permits_id(&self, id: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => id.to_ascii_lowercase().starts_with(&prefix.to_ascii_lowercase()),
+            None => pattern.eq_ignore_ascii_case(id),
+        };
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+
+    /// Check every package against the policy
+    ///
+    /// # Returns
+    /// * `Ok(())` if every package's license is permitted
+    /// * `Err(CoreError::LicenseDenied)` for the first package that isn't
+    pub fn check(&self, packages: &[PackageInfo]) -> Result<(), CoreError> {
+        match packages.iter().find(|pkg| !self.permits(pkg.license.as_deref())) {
+            Some(pkg) => Err(CoreError::LicenseDenied {
+                package: format!("{}@{}", pkg.name, pkg.version),
+                license: pkg.license.clone().unwrap_or_else(|| UNKNOWN_LICENSE.to_string()),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A parsed SPDX license expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LicenseExpr {
+    /// A license identifier, without any `WITH` exception
+    License(String),
+    And(Vec<LicenseExpr>),
+    Or(Vec<LicenseExpr>),
+}
+
+impl LicenseExpr {
+    /// Parse an SPDX expression
+    ///
+    /// # Returns
+    /// * `Some(LicenseExpr)` for a well-formed expression
+    /// * `None` if it is empty, unbalanced or misplaces an operator
+    fn parse(expr: &str) -> Option<Self> {
+        let mut tokens = Vec::new();
+        for word in expr.split_whitespace() {
+            let mut rest = word;
+            while !rest.is_empty() {
+                let end = match rest.find(['(', ')']) {
+                    Some(0) => 1,
+                    Some(index) => index,
+                    None => rest.len(),
+                };
+                tokens.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+
+        let mut tokens = tokens.into_iter().peekable();
+        let parsed = Self::parse_or(&mut tokens)?;
+        tokens.next().is_none().then_some(parsed)
+    }
+
+    /// Parse terms joined by `OR`, the loosest operator
+    fn parse_or<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Option<Self> {
+        let mut terms = vec![Self::parse_and(tokens)?];
+        while tokens.next_if(|token| token.eq_ignore_ascii_case("OR")).is_some() {
+            terms.push(Self::parse_and(tokens)?);
+        }
+        Some(if terms.len() == 1 { terms.remove(0) } else { Self::Or(terms) })
+    }
+
+    /// Parse terms joined by `AND`
+    fn parse_and<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Option<Self> {
+        let mut terms = vec![Self::parse_term(tokens)?];
+        while tokens.next_if(|token| token.eq_ignore_ascii_case("AND")).is_some() {
+            terms.push(Self::parse_term(tokens)?);
+        }
+        Some(if terms.len() == 1 { terms.remove(0) } else { Self::And(terms) })
+    }
+
+    /// Parse a parenthesized expression or a license with an optional `WITH` exception
+    fn parse_term<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Option<Self> {
+        let token = tokens.next()?;
+        if token == "(" {
+            let inner = Self::parse_or(tokens)?;
+            return tokens.next_if_eq(&")").map(|_| inner);
+        }
+        if token == ")" || ["AND", "OR", "WITH"].iter().any(|op| token.eq_ignore_ascii_case(op)) {
+            return None;
+        }
+
+        if tokens.next_if(|next| next.eq_ignore_ascii_case("WITH")).is_some() {
+            tokens.next_if(|exception| *exception != "(" && *exception != ")")?;
+        }
+        Some(Self::License(token.to_string()))
+    }
+}
+
+/// Group packages by license
+///
+/// # Returns
+/// * A map from each license expression (or `UNKNOWN_LICENSE`) to the
+///   `name@version` of its packages, sorted
+pub fn collect_licenses(packages: &[PackageInfo]) -> BTreeMap<String, Vec<String>> {
+    let mut licenses: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for pkg in packages {
+        let license = pkg.license.clone().unwrap_or_else(|| UNKNOWN_LICENSE.to_string());
+        licenses.entry(license).or_default().push(format!("{}@{}", pkg.name, pkg.version));
+    }
+    for packages in licenses.values_mut() {
+        packages.sort();
+        packages.dedup();
+    }
+    licenses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> LicensePolicy {
+        LicensePolicy {
+            allow: allow.iter().map(|id| id.to_string()).collect(),
+            deny: deny.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_license_forms() {
+        let license = |manifest: serde_json::Value| serde_json::from_value::<PackageInfo>(manifest).unwrap().license;
+        let base = |license: serde_json::Value| serde_json::json!({ "name": "a", "version": "1.0.0", "license": license });
+
+        assert_eq!(license(base(serde_json::json!("MIT"))).as_deref(), Some("MIT"));
+        assert_eq!(license(base(serde_json::json!({ "type": "ISC", "url": "x" }))).as_deref(), Some("ISC"));
+        assert_eq!(license(base(serde_json::json!(42))), None);
+        assert_eq!(license(serde_json::json!({ "name": "a", "version": "1.0.0" })), None);
+    }
+
+    #[test]
+    fn test_policy_permits() {
+        let deny_gpl = policy(&[], &["GPL-*", "AGPL-3.0-only"]);
+        assert!(deny_gpl.permits(Some("MIT")));
+        assert!(deny_gpl.permits(None));
+        assert!(!deny_gpl.permits(Some("GPL-3.0-or-later")));
+        assert!(!deny_gpl.permits(Some("agpl-3.0-only")));
+        assert!(deny_gpl.permits(Some("(MIT OR GPL-2.0-only)")));
+        assert!(!deny_gpl.permits(Some("MIT AND GPL-2.0-only")));
+
+        let allow_permissive = policy(&["MIT", "ISC", "Apache-2.0"], &[]);
+        assert!(allow_permissive.permits(Some("ISC")));
+        assert!(allow_permissive.permits(Some("MIT OR BSD-3-Clause")));
+        assert!(!allow_permissive.permits(Some("BSD-3-Clause")));
+        assert!(!allow_permissive.permits(None));
+    }
+
+    #[test]
+    fn test_policy_permits_compound_expressions() {
+        let deny_bsd = policy(&[], &["BSD-3-Clause"]);
+        assert!(!deny_bsd.permits(Some("(MIT OR GPL-2.0-only) AND BSD-3-Clause")));
+        assert!(deny_bsd.permits(Some("MIT OR GPL-2.0-only AND BSD-3-Clause")));
+        assert!(!deny_bsd.permits(Some("(MIT and BSD-3-Clause) or (ISC AND (BSD-3-Clause OR BSD-3-Clause))")));
+        assert!(deny_bsd.permits(Some("BSD-3-Clause or ISC")));
+
+        let deny_gpl = policy(&[], &["GPL-*"]);
+        assert!(!deny_gpl.permits(Some("GPL-2.0-only WITH Classpath-exception-2.0")));
+        assert!(deny_gpl.permits(Some("MIT OR (GPL-2.0-only WITH Classpath-exception-2.0)")));
+
+        let allow_mit = policy(&["MIT"], &[]);
+        assert!(allow_mit.permits(Some("(MIT)")));
+        assert!(!allow_mit.permits(Some("SEE LICENSE IN LICENSE.txt")));
+        assert!(!allow_mit.permits(Some("MIT AND (ISC")));
+    }
+
+    #[test]
+    fn test_parse_license_expression() {
+        let id = |id: &str| LicenseExpr::License(id.to_string());
+        assert_eq!(
+            LicenseExpr::parse("MIT or Apache-2.0 AND (ISC OR BSD-2-Clause)"),
+            Some(LicenseExpr::Or(vec![
+                id("MIT"),
+                LicenseExpr::And(vec![id("Apache-2.0"), LicenseExpr::Or(vec![id("ISC"), id("BSD-2-Clause")])]),
+            ]))
+        );
+        assert_eq!(LicenseExpr::parse("(GPL-2.0+ WITH Bison-exception-2.2)"), Some(id("GPL-2.0+")));
+        for malformed in ["", "MIT AND", "OR MIT", "(MIT", "MIT)", "MIT WITH", "MIT ISC"] {
+            assert_eq!(LicenseExpr::parse(malformed), None, "{:?} parsed", malformed);
+        }
+    }
+
+    #[test]
+    fn test_collect_licenses() {
+        let mut mit = PackageInfo::new("a", "1.0.0");
+        mit.license = Some("MIT".to_string());
+        let mut also_mit = PackageInfo::new("b", "2.0.0");
+        also_mit.license = Some("MIT".to_string());
+        let unknown = PackageInfo::new("c", "3.0.0");
+
+        let licenses = collect_licenses(&[also_mit, unknown, mit]);
+        assert_eq!(licenses["MIT"], vec!["a@1.0.0", "b@2.0.0"]);
+        assert_eq!(licenses[UNKNOWN_LICENSE], vec!["c@3.0.0"]);
+    }
+}
@@ -0,0 +1,213 @@
+//! Local path dependencies
+//!
+//! This module resolves `file:` dependencies and links them into
+//! `node_modules`.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::manifest::{read_package_info, MANIFEST_FILE};
+use crate::spec::validate_package_name;
+use crate::PackageInfo;
+
+/// Resolve a local directory dependency
+///
+/// # Arguments
+/// * `path` - Path to the package directory, relative to `base_dir` unless absolute
+/// * `base_dir` - Directory relative paths are resolved against (the project root)
+///
+/// # Returns
+/// * `Ok((PathBuf, PackageInfo))` with the package directory and its manifest
+/// * `Err(anyhow::Error)` if the directory or its `package.json` is missing
+pub fn resolve_file_dependency(path: &Path, base_dir: &Path) -> Result<(PathBuf, PackageInfo)> {
+    let dir = base_dir.join(path);
+
+    if !dir.is_dir() {
+        anyhow::bail!("Local dependency path {} does not exist or is not a directory", dir.display());
+    }
+    if !dir.join(MANIFEST_FILE).is_file() {
+        anyhow::bail!("Local dependency path {} does not contain a {}", dir.display(), MANIFEST_FILE);
+    }
+
+    let pkg_info = read_package_info(&dir)?;
+    validate_package_name(&pkg_info.name)
+        .with_context(|| format!("Local dependency {} has an invalid name", dir.display()))?;
+    Ok((dir, pkg_info))
+}
+
+/// Link a local package into `node_modules`
+///
+/// A symlink is created where the platform allows it; otherwise the package
+/// directory is copied. Any existing entry for the package is replaced.
+///
+/// # Arguments
+/// * `source` - The package directory
+/// * `node_modules` - The `node_modules` directory to link into
+/// * `name` - Package name, possibly scoped (`@scope/name`)
+///
+/// # Returns
+/// * `Ok(PathBuf)` with the path of the created entry
+pub fn link_local_package(source: &Path, node_modules: &Path, name: &str) -> Result<PathBuf> {
+    let dest = node_modules.join(name);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    remove_existing(&dest)?;
+
+    let source = source
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", source.display()))?;
+
+    info!("Linking {} into {}", source.display(), dest.display());
+    if let Err(e) = symlink_dir(&source, &dest) {
+        warn!("Symlinking {} failed ({}), copying instead", name, e);
+        copy_dir(&source, &dest)?;
+    }
+
+    Ok(dest)
+}
+
+/// Copy a package directory into `node_modules`
+///
+/// Unlike `link_local_package`, the package never refers back to `source`,
+/// so `source` can be a temporary directory such as a git checkout. Nested
+/// `node_modules` and `.git` are skipped. Any existing entry for the
+/// package is replaced.
+///
+/// # Arguments
+/// * `source` - The package directory
+/// * `node_modules` - The `node_modules` directory to copy into
+/// * `name` - Package name, possibly scoped (`@scope/name`)
+///
+/// # Returns
+/// * `Ok(PathBuf)` with the path of the copied package
+pub fn copy_local_package(source: &Path, node_modules: &Path, name: &str) -> Result<PathBuf> {
+    let dest = node_modules.join(name);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    remove_existing(&dest)?;
+
+    info!("Copying {} into {}", source.display(), dest.display());
+    copy_dir(source, &dest)?;
+    Ok(dest)
+}
+
+/// Remove a file, symlink, or directory if present
+fn remove_existing(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink_dir(source: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, dest)
+}
+
+#[cfg(windows)]
+fn symlink_dir(source: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(source, dest)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink_dir(_source: &Path, _dest: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "symlinks are not supported"))
+}
+
+/// Recursively copy a package directory, skipping nested `node_modules` and `.git`
+fn copy_dir(source: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if file_name == "node_modules" || file_name == ".git" {
+            continue;
+        }
+
+        let target = dest.join(&file_name);
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn fixture_layout() -> TempDir {
+        let root = TempDir::new().unwrap();
+        let sibling = root.path().join("sibling");
+        fs::create_dir_all(sibling.join("lib")).unwrap();
+        fs::write(
+            sibling.join(MANIFEST_FILE),
+            r#"{ "name": "@local/sibling", "version": "0.2.0", "dependencies": { "ms": "^2.1.0" } }"#,
+        )
+        .unwrap();
+        fs::write(sibling.join("lib/index.js"), "module.exports = 42;").unwrap();
+        fs::create_dir_all(root.path().join("app")).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_resolve_file_dependency() {
+        let root = fixture_layout();
+        let app = root.path().join("app");
+
+        let (dir, pkg) = resolve_file_dependency(Path::new("../sibling"), &app).unwrap();
+        assert_eq!(pkg.name, "@local/sibling");
+        assert_eq!(pkg.version, "0.2.0");
+        assert_eq!(pkg.dependencies.get("ms"), Some(&"^2.1.0".to_string()));
+        assert!(dir.ends_with("sibling"));
+    }
+
+    #[test]
+    fn test_resolve_file_dependency_invalid_paths() {
+        let root = fixture_layout();
+        let app = root.path().join("app");
+
+        let err = resolve_file_dependency(Path::new("../missing"), &app).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+
+        let err = resolve_file_dependency(Path::new("."), &app).unwrap_err();
+        assert!(err.to_string().contains("does not contain a package.json"));
+    }
+
+    #[test]
+    fn test_link_local_package() {
+        let root = fixture_layout();
+        let node_modules = root.path().join("app/node_modules");
+
+        let dest = link_local_package(&root.path().join("sibling"), &node_modules, "@local/sibling").unwrap();
+        assert_eq!(dest, node_modules.join("@local/sibling"));
+        assert!(dest.join("lib/index.js").is_file());
+
+        // Relinking replaces the previous entry
+        let dest = link_local_package(&root.path().join("sibling"), &node_modules, "@local/sibling").unwrap();
+        assert!(dest.join(MANIFEST_FILE).is_file());
+    }
+
+    #[test]
+    fn test_copy_dir_fallback() {
+        let root = fixture_layout();
+        let dest = root.path().join("copied");
+
+        copy_dir(&root.path().join("sibling"), &dest).unwrap();
+        assert!(dest.join("lib/index.js").is_file());
+        assert!(dest.join(MANIFEST_FILE).is_file());
+    }
+}
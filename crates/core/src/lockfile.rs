@@ -0,0 +1,408 @@
+//! Lockfile support
+//!
+//! This module defines package-fast's own lockfile and imports the
+//! lockfiles written by other package managers into it, so existing pins
+//! are honored when installing.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+use crate::semver::{parse_loose_version, parse_range};
+use crate::yarn_lock::{import_yarn_lockfile, YARN_LOCKFILE_NAME};
+
+/// Name of package-fast's lockfile
+pub const LOCKFILE_NAME: &str = "package-fast-lock.json";
+
+/// Name of npm's lockfile
+pub const NPM_LOCKFILE_NAME: &str = "package-lock.json";
+
+/// Current lockfile format version
+pub const LOCKFILE_VERSION: u32 = 1;
+
+/// A pinned package version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// URL the tarball was downloaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+    /// SRI integrity of the tarball
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    /// Only needed for development
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dev: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+}
+
+impl LockedPackage {
+    /// Create a locked package without source information
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            resolved: None,
+            integrity: None,
+            dependencies: BTreeMap::new(),
+            dev: false,
+            optional: false,
+        }
+    }
+}
+
+/// package-fast's lockfile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(rename = "lockfileVersion")]
+    pub lockfile_version: u32,
+    /// Locked packages keyed by `name@version`
+    #[serde(default)]
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+impl Lockfile {
+    /// Create an empty lockfile
+    pub fn new() -> Self {
+        Self {
+            lockfile_version: LOCKFILE_VERSION,
+            packages: BTreeMap::new(),
+        }
+    }
+
+    /// Add a package, replacing any existing entry for the same version
+    pub fn insert(&mut self, package: LockedPackage) {
+        self.packages
+            .insert(format!("{}@{}", package.name, package.version), package);
+    }
+
+    /// Get the entry for an exact package version
+    pub fn get(&self, name: &str, version: &str) -> Option<&LockedPackage> {
+        self.packages.get(&format!("{}@{}", name, version))
+    }
+
+    /// Find the pinned version to use for a request
+    ///
+    /// Returns the newest locked version of the package that satisfies the
+    /// range. Requests without a range accept any locked version, while
+    /// requests that aren't semver ranges (such as dist-tags) never match.
+    /// With `include_prereleases`, ranges match pre-releases within their bounds.
+    pub fn pinned_version(&self, name: &str, range: Option<&str>, include_prereleases: bool) -> Option<&LockedPackage> {
+        let range = match range {
+            Some(range) => Some(parse_range(range).ok()?),
+            None => None,
+        };
+
+        self.packages
+            .values()
+            .filter(|locked| locked.name == name)
+            .filter_map(|locked| parse_loose_version(&locked.version).map(|version| (version, locked)))
+            .filter(|(version, _)| range.as_ref().is_none_or(|range| range.matches_with(version, include_prereleases)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, locked)| locked)
+    }
+
+    /// Read a package-fast lockfile
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Write this lockfile
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut content = serde_json::to_string_pretty(self)?;
+        content.push('\n');
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Load the lockfile of a project, importing another package manager's if needed
+///
+/// `package-fast-lock.json` is preferred, then `package-lock.json`, then
+/// `yarn.lock`.
+///
+/// # Returns
+/// * `Ok(Some(Lockfile))` if a supported lockfile exists
+/// * `Ok(None)` if the project has no lockfile
+/// * `Err(anyhow::Error)` if the lockfile exists but can't be parsed
+pub fn load_project_lockfile(project_dir: &Path) -> Result<Option<Lockfile>> {
+    let own = project_dir.join(LOCKFILE_NAME);
+    if own.is_file() {
+        return Lockfile::read(&own).map(Some);
+    }
+
+    let npm = project_dir.join(NPM_LOCKFILE_NAME);
+    if npm.is_file() {
+        info!("Importing pins from {}", npm.display());
+        return import_npm_lockfile(&npm).map(Some);
+    }
+
+    let yarn = project_dir.join(YARN_LOCKFILE_NAME);
+    if yarn.is_file() {
+        info!("Importing pins from {}", yarn.display());
+        return import_yarn_lockfile(&yarn).map(Some);
+    }
+
+    Ok(None)
+}
+
+/// An entry in the `packages` section of an npm lockfile
+#[derive(Debug, Deserialize)]
+struct NpmPackageEntry {
+    name: Option<String>,
+    version: Option<String>,
+    resolved: Option<String>,
+    integrity: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(rename = "optionalDependencies", default)]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    dev: bool,
+    #[serde(default)]
+    optional: bool,
+    #[serde(default)]
+    link: bool,
+}
+
+/// An entry in the legacy `dependencies` section of an npm lockfile
+#[derive(Debug, Deserialize)]
+struct NpmDependencyEntry {
+    version: String,
+    resolved: Option<String>,
+    integrity: Option<String>,
+    #[serde(default)]
+    requires: BTreeMap<String, String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, NpmDependencyEntry>,
+    #[serde(default)]
+    dev: bool,
+    #[serde(default)]
+    optional: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmLockfile {
+    #[serde(rename = "lockfileVersion", default)]
+    lockfile_version: u32,
+    packages: Option<BTreeMap<String, NpmPackageEntry>>,
+    dependencies: Option<BTreeMap<String, NpmDependencyEntry>>,
+}
+
+/// Import an npm `package-lock.json`
+///
+/// The `packages` section of v2/v3 lockfiles is used when present, falling
+/// back to the `dependencies` section written by npm 6 and earlier. Resolved
+/// URLs and integrity values are preserved; workspace links and the root
+/// project entry are skipped.
+///
+/// # Arguments
+/// * `path` - Path of the `package-lock.json`
+///
+/// # Returns
+/// * `Ok(Lockfile)` with one entry per locked package version
+/// * `Err(anyhow::Error)` if the file can't be read or parsed
+pub fn import_npm_lockfile(path: &Path) -> Result<Lockfile> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let npm: NpmLockfile =
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let mut lockfile = Lockfile::new();
+
+    if let Some(packages) = npm.packages {
+        for (install_path, entry) in packages {
+            if install_path.is_empty() || entry.link {
+                continue;
+            }
+            let Some(version) = entry.version else {
+                continue;
+            };
+            let name = entry.name.unwrap_or_else(|| package_name_from_path(&install_path).to_string());
+
+            let mut dependencies = entry.dependencies;
+            dependencies.extend(entry.optional_dependencies);
+            lockfile.insert(LockedPackage {
+                resolved: entry.resolved,
+                integrity: entry.integrity,
+                dependencies,
+                dev: entry.dev,
+                optional: entry.optional,
+                ..LockedPackage::new(&name, &version)
+            });
+        }
+    } else if let Some(dependencies) = npm.dependencies {
+        import_npm_dependencies(dependencies, &mut lockfile);
+    } else {
+        anyhow::bail!(
+            "{} (lockfileVersion {}) has neither a packages nor a dependencies section",
+            path.display(),
+            npm.lockfile_version
+        );
+    }
+
+    Ok(lockfile)
+}
+
+/// Recursively import a legacy `dependencies` section
+fn import_npm_dependencies(dependencies: BTreeMap<String, NpmDependencyEntry>, lockfile: &mut Lockfile) {
+    for (name, entry) in dependencies {
+        lockfile.insert(LockedPackage {
+            resolved: entry.resolved,
+            integrity: entry.integrity,
+            dependencies: entry.requires,
+            dev: entry.dev,
+            optional: entry.optional,
+            ..LockedPackage::new(&name, &entry.version)
+        });
+        import_npm_dependencies(entry.dependencies, lockfile);
+    }
+}
+
+/// Get the package name from an npm lockfile install path
+///
+/// `node_modules/a/node_modules/@scope/b` names `@scope/b`.
+fn package_name_from_path(install_path: &str) -> &str {
+    install_path
+        .rsplit_once("node_modules/")
+        .map_or(install_path, |(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const NPM_V3_FIXTURE: &str = r#"{
+        "name": "fixture",
+        "version": "1.0.0",
+        "lockfileVersion": 3,
+        "requires": true,
+        "packages": {
+            "": {
+                "name": "fixture",
+                "version": "1.0.0",
+                "dependencies": { "@babel/code-frame": "^7.22.0", "debug": "^4.3.0" },
+                "workspaces": ["packages/*"]
+            },
+            "node_modules/@babel/code-frame": {
+                "version": "7.22.13",
+                "resolved": "https://registry.npmjs.org/@babel/code-frame/-/code-frame-7.22.13.tgz",
+                "integrity": "sha512-XktuhWlJ5g+3TJXc5upd9Ks1HutSArik6jf2eAjYFdZ5ntJUlOAllbatqX0eb8a9XVuwGqKMKVF8o9Z9e0CZbA==",
+                "dev": true
+            },
+            "node_modules/debug": {
+                "version": "4.3.4",
+                "resolved": "https://registry.npmjs.org/debug/-/debug-4.3.4.tgz",
+                "integrity": "sha512-PRWFHuSU3eDtQJPvnNY7Jcket1j0t5OuOsFzPPzsekD52Zl8qUfFIPEiswXqIvHWGVHOgX+7G/vCNNhehwxfkQ==",
+                "dependencies": { "ms": "2.1.2" }
+            },
+            "node_modules/debug/node_modules/ms": {
+                "version": "2.1.2",
+                "resolved": "https://registry.npmjs.org/ms/-/ms-2.1.2.tgz",
+                "integrity": "sha512-sGkPx+VjMtmA6MX27oA4FBFELFCZZ4S4XqeGOXCv68tT+jb3vk/RyaKWP0PTKyWtmLSM0b+adUTEvbs1PEaH2w=="
+            },
+            "node_modules/ms": {
+                "version": "2.1.3",
+                "resolved": "https://registry.npmjs.org/ms/-/ms-2.1.3.tgz",
+                "integrity": "sha512-6FlzubTLZG3J2a/NVCAleEhjzq5oxgHyaCU9yYXvcLsvoVaHJq/s5xXI6/XXP6tz7R9xAOtHnSO/tXtF3WRTlA=="
+            },
+            "node_modules/@repo/app": { "resolved": "packages/app", "link": true },
+            "packages/app": { "name": "@repo/app", "version": "0.1.0" }
+        }
+    }"#;
+
+    fn write_fixture(content: &str) -> (TempDir, std::path::PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(NPM_LOCKFILE_NAME);
+        fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_import_npm_lockfile_v3() {
+        let (_dir, path) = write_fixture(NPM_V3_FIXTURE);
+        let lockfile = import_npm_lockfile(&path).unwrap();
+
+        let keys: Vec<&str> = lockfile.packages.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            vec!["@babel/code-frame@7.22.13", "@repo/app@0.1.0", "debug@4.3.4", "ms@2.1.2", "ms@2.1.3"]
+        );
+
+        let debug = lockfile.get("debug", "4.3.4").unwrap();
+        assert_eq!(debug.resolved.as_deref(), Some("https://registry.npmjs.org/debug/-/debug-4.3.4.tgz"));
+        assert!(debug.integrity.as_deref().unwrap().starts_with("sha512-PRWFHuSU3eDt"));
+        assert_eq!(debug.dependencies.get("ms"), Some(&"2.1.2".to_string()));
+
+        assert!(lockfile.get("@babel/code-frame", "7.22.13").unwrap().dev);
+    }
+
+    #[test]
+    fn test_import_npm_lockfile_v1_dependencies() {
+        let (_dir, path) = write_fixture(
+            r#"{
+                "lockfileVersion": 1,
+                "dependencies": {
+                    "debug": {
+                        "version": "4.3.4",
+                        "resolved": "https://registry.npmjs.org/debug/-/debug-4.3.4.tgz",
+                        "integrity": "sha512-abc=",
+                        "requires": { "ms": "2.1.2" },
+                        "dependencies": { "ms": { "version": "2.1.2", "integrity": "sha512-def=" } }
+                    }
+                }
+            }"#,
+        );
+
+        let lockfile = import_npm_lockfile(&path).unwrap();
+        assert_eq!(lockfile.packages.len(), 2);
+        assert_eq!(lockfile.get("ms", "2.1.2").unwrap().integrity.as_deref(), Some("sha512-def="));
+    }
+
+    #[test]
+    fn test_pinned_version() {
+        let (_dir, path) = write_fixture(NPM_V3_FIXTURE);
+        let lockfile = import_npm_lockfile(&path).unwrap();
+
+        assert_eq!(lockfile.pinned_version("ms", Some("^2.1.0"), false).unwrap().version, "2.1.3");
+        assert_eq!(lockfile.pinned_version("ms", Some("2.1.2"), false).unwrap().version, "2.1.2");
+        assert_eq!(lockfile.pinned_version("ms", None, false).unwrap().version, "2.1.3");
+        assert!(lockfile.pinned_version("ms", Some("^3.0.0"), false).is_none());
+        assert!(lockfile.pinned_version("ms", Some("latest"), false).is_none());
+        assert!(lockfile.pinned_version("left-pad", None, false).is_none());
+
+        let mut lockfile = Lockfile::default();
+        lockfile.insert(LockedPackage::new("ms", "2.2.0-beta.1"));
+        assert!(lockfile.pinned_version("ms", Some("^2.1.0"), false).is_none());
+        assert_eq!(lockfile.pinned_version("ms", Some("^2.1.0"), true).unwrap().version, "2.2.0-beta.1");
+    }
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+
+        let mut lockfile = Lockfile::new();
+        lockfile.insert(LockedPackage {
+            integrity: Some("sha512-abc=".to_string()),
+            ..LockedPackage::new("left-pad", "1.3.0")
+        });
+        lockfile.write(&path).unwrap();
+
+        assert_eq!(Lockfile::read(&path).unwrap(), lockfile);
+        assert_eq!(load_project_lockfile(dir.path()).unwrap(), Some(lockfile));
+    }
+}
@@ -0,0 +1,300 @@
+//! package.json handling
+//!
+//! This module reads package manifests from disk, scaffolds new ones and
+//! records added dependencies.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::PackageInfo;
+
+/// Name of the package manifest file
+pub const MANIFEST_FILE: &str = "package.json";
+
+/// Read the `package.json` in a package directory
+///
+/// # Arguments
+/// * `dir` - Directory containing the `package.json`
+///
+/// # Returns
+/// * `Ok(PackageInfo)` with the manifest contents
+/// * `Err(anyhow::Error)` if the file is missing or invalid
+pub fn read_package_info(dir: &Path) -> Result<PackageInfo> {
+    let path = dir.join(MANIFEST_FILE);
+    let content = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Read the manifests of all packages installed under a `node_modules` directory
+///
+/// Scoped packages and nested `node_modules` directories are included.
+/// A missing directory yields an empty list.
+///
+/// # Arguments
+/// * `node_modules` - Path of the `node_modules` directory
+///
+/// # Returns
+/// * `Ok(Vec<PackageInfo>)` sorted by name and version
+/// * `Err(anyhow::Error)` if a directory can't be read
+pub fn read_installed_packages(node_modules: &Path) -> Result<Vec<PackageInfo>> {
+    let mut packages = Vec::new();
+    collect_installed_packages(node_modules, &mut packages)?;
+    packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+    Ok(packages)
+}
+
+fn collect_installed_packages(node_modules: &Path, packages: &mut Vec<PackageInfo>) -> Result<()> {
+    if !node_modules.is_dir() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(node_modules)? {
+        let path = entry?.path();
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+        if file_name.starts_with('.') {
+            continue;
+        }
+        if file_name.starts_with('@') {
+            // Scope directories hold the actual packages one level down
+            for scoped in fs::read_dir(&path)? {
+                collect_installed_package(&scoped?.path(), packages)?;
+            }
+        } else {
+            collect_installed_package(&path, packages)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn collect_installed_package(dir: &Path, packages: &mut Vec<PackageInfo>) -> Result<()> {
+    if dir.join(MANIFEST_FILE).is_file() {
+        packages.push(read_package_info(dir)?);
+    }
+    collect_installed_packages(&dir.join("node_modules"), packages)
+}
+
+/// Derive a package name from a directory name
+///
+/// npm names are lowercase and can't contain spaces, so the directory name
+/// is lowercased and whitespace is replaced with `-`.
+pub fn default_package_name(dir: &Path) -> String {
+    dir.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.trim().to_lowercase().split_whitespace().collect::<Vec<_>>().join("-"))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "package".to_string())
+}
+
+/// Write a new `package.json`
+///
+/// # Arguments
+/// * `dir` - Directory to create the `package.json` in
+/// * `pkg` - Package information to write
+/// * `force` - Overwrite an existing `package.json`
+///
+/// # Returns
+/// * `Ok(PathBuf)` with the path of the written file
+/// * `Err(anyhow::Error)` if a `package.json` already exists and `force` is not set, or writing fails
+pub fn init_package_json(dir: &Path, pkg: &PackageInfo, force: bool) -> Result<PathBuf> {
+    let path = dir.join(MANIFEST_FILE);
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists, use --force to overwrite it", path.display());
+    }
+
+    let mut content = serde_json::to_string_pretty(pkg)?;
+    content.push('\n');
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Build the version spec saved for an added dependency
+///
+/// An explicit semver range given by the user is kept. Otherwise (no range,
+/// or a dist-tag such as `latest`) a caret range on the resolved version is
+/// saved. With `save_exact`, the resolved version itself is always saved.
+///
+/// # Arguments
+/// * `requested` - Range or tag given in the install spec, if any
+/// * `version` - Version that was resolved
+/// * `save_exact` - Pin the exact version instead of a range
+pub fn save_spec(requested: Option<&str>, version: &str, save_exact: bool) -> String {
+    if save_exact {
+        return version.to_string();
+    }
+    match requested {
+        Some(range) if crate::semver::parse_range(range).is_ok() && range != "*" => range.to_string(),
+        _ => format!("^{}", version),
+    }
+}
+
+/// Record dependencies in the `package.json` in a directory
+///
+/// The manifest is edited in place so unrelated fields keep their order.
+/// A dependency saved to one section is removed from the other, and the
+/// updated section is sorted by name.
+///
+/// # Arguments
+/// * `dir` - Directory containing the `package.json`
+/// * `dependencies` - Names and version specs to save
+/// * `dev` - Save to `devDependencies` instead of `dependencies`
+///
+/// # Returns
+/// * `Ok(PathBuf)` with the path of the written file
+/// * `Err(anyhow::Error)` if the manifest can't be read, parsed or written
+pub fn save_dependencies(dir: &Path, dependencies: &[(String, String)], dev: bool) -> Result<PathBuf> {
+    let path = dir.join(MANIFEST_FILE);
+    let content = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let object = manifest
+        .as_object_mut()
+        .with_context(|| format!("{} is not a JSON object", path.display()))?;
+
+    let (section, other) = if dev {
+        ("devDependencies", "dependencies")
+    } else {
+        ("dependencies", "devDependencies")
+    };
+
+    if let Some(other) = object.get_mut(other).and_then(|value| value.as_object_mut()) {
+        for (name, _) in dependencies {
+            other.remove(name);
+        }
+    }
+
+    let entry = object
+        .entry(section)
+        .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+    let existing = entry
+        .as_object_mut()
+        .with_context(|| format!("{} in {} is not an object", section, path.display()))?;
+    for (name, spec) in dependencies {
+        existing.insert(name.clone(), serde_json::Value::String(spec.clone()));
+    }
+    let mut sorted: Vec<(String, serde_json::Value)> = std::mem::take(existing).into_iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    existing.extend(sorted);
+
+    let mut content = serde_json::to_string_pretty(&manifest)?;
+    content.push('\n');
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_package_info() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join(MANIFEST_FILE),
+            r#"{ "name": "fixture", "version": "0.3.0", "dependencies": { "left-pad": "^1.3.0" } }"#,
+        )
+        .unwrap();
+
+        let pkg = read_package_info(dir.path()).unwrap();
+        assert_eq!(pkg.name, "fixture");
+        assert_eq!(pkg.version, "0.3.0");
+        assert_eq!(pkg.dependencies.get("left-pad"), Some(&"^1.3.0".to_string()));
+        assert!(pkg.dev_dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_read_package_info_missing() {
+        let dir = TempDir::new().unwrap();
+        assert!(read_package_info(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_default_package_name() {
+        assert_eq!(default_package_name(Path::new("/work/My Project")), "my-project");
+        assert_eq!(default_package_name(Path::new("/work/left-pad")), "left-pad");
+        assert_eq!(default_package_name(Path::new("/")), "package");
+    }
+
+    #[test]
+    fn test_init_package_json() {
+        let dir = TempDir::new().unwrap();
+        let name = default_package_name(dir.path());
+
+        init_package_json(dir.path(), &PackageInfo::new(&name, "1.0.0"), false).unwrap();
+
+        let content = fs::read_to_string(dir.path().join(MANIFEST_FILE)).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(json["name"], name.as_str());
+        assert_eq!(json["version"], "1.0.0");
+        assert_eq!(json["dependencies"], serde_json::json!({}));
+        assert_eq!(json["devDependencies"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_init_package_json_refuses_overwrite() {
+        let dir = TempDir::new().unwrap();
+        init_package_json(dir.path(), &PackageInfo::new("first", "1.0.0"), false).unwrap();
+
+        assert!(init_package_json(dir.path(), &PackageInfo::new("second", "1.0.0"), false).is_err());
+        assert_eq!(read_package_info(dir.path()).unwrap().name, "first");
+
+        init_package_json(dir.path(), &PackageInfo::new("second", "1.0.0"), true).unwrap();
+        assert_eq!(read_package_info(dir.path()).unwrap().name, "second");
+    }
+
+    #[test]
+    fn test_read_installed_packages() {
+        let dir = TempDir::new().unwrap();
+        let node_modules = dir.path().join("node_modules");
+        for (path, name, version) in [
+            ("left-pad", "left-pad", "1.3.0"),
+            ("@types/node", "@types/node", "20.1.0"),
+            ("left-pad/node_modules/is-odd", "is-odd", "0.1.0"),
+        ] {
+            let pkg_dir = node_modules.join(path);
+            fs::create_dir_all(&pkg_dir).unwrap();
+            init_package_json(&pkg_dir, &PackageInfo::new(name, version), false).unwrap();
+        }
+        fs::create_dir_all(node_modules.join(".bin")).unwrap();
+
+        let packages = read_installed_packages(&node_modules).unwrap();
+        let names: Vec<_> = packages.iter().map(|p| format!("{}@{}", p.name, p.version)).collect();
+        assert_eq!(names, vec!["@types/node@20.1.0", "is-odd@0.1.0", "left-pad@1.3.0"]);
+
+        assert!(read_installed_packages(&dir.path().join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_save_spec() {
+        assert_eq!(save_spec(None, "1.2.3", false), "^1.2.3");
+        assert_eq!(save_spec(Some("latest"), "1.2.3", false), "^1.2.3");
+        assert_eq!(save_spec(Some("~1.2.0"), "1.2.3", false), "~1.2.0");
+        assert_eq!(save_spec(None, "1.2.3", true), "1.2.3");
+        assert_eq!(save_spec(Some("~1.2.0"), "1.2.3", true), "1.2.3");
+    }
+
+    #[test]
+    fn test_save_dependencies_exact_and_caret() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join(MANIFEST_FILE),
+            r#"{ "name": "fixture", "version": "1.0.0", "scripts": { "test": "jest" }, "devDependencies": { "zod": "^3.0.0" } }"#,
+        )
+        .unwrap();
+
+        save_dependencies(dir.path(), &[("zod".to_string(), save_spec(None, "3.22.4", true))], false).unwrap();
+        save_dependencies(dir.path(), &[("chalk".to_string(), save_spec(None, "5.3.0", false))], false).unwrap();
+
+        let content = fs::read_to_string(dir.path().join(MANIFEST_FILE)).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(json["dependencies"], serde_json::json!({ "chalk": "^5.3.0", "zod": "3.22.4" }));
+        assert_eq!(json["devDependencies"], serde_json::json!({}));
+
+        // Unrelated fields keep their position
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["name", "version", "scripts", "devDependencies", "dependencies"]);
+    }
+}
@@ -0,0 +1,175 @@
+//! Selective parsing of registry metadata
+//!
+//! Packuments of popular packages list thousands of versions, each with its
+//! own manifest, and parsing all of them dominates the memory used by a
+//! metadata fetch. Parsing selectively keeps every version as an unparsed
+//! slice of the response body while scanning it, and only builds
+//! `PackageVersion` values for the versions that are actually wanted.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::{HashMap, HashSet};
+
+use crate::semver::{parse_loose_version, parse_range};
+use crate::{PackageMetadata, PackageVersion};
+
+/// Versions to keep when parsing metadata selectively
+///
+/// The targets of every dist-tag are always kept, so tag lookups such as
+/// `latest` still work on the parsed metadata.
+#[derive(Debug, Clone, Default)]
+pub struct VersionFilter {
+    /// Keep versions satisfying any of these npm ranges
+    pub ranges: Vec<String>,
+    /// Keep these exact versions
+    pub versions: HashSet<String>,
+}
+
+impl VersionFilter {
+    /// Create a filter keeping the versions that satisfy a range
+    pub fn range(range: &str) -> Self {
+        Self {
+            ranges: vec![range.to_string()],
+            versions: HashSet::new(),
+        }
+    }
+
+    /// Check whether a version passes the filter, ignoring dist-tags
+    ///
+    /// Ranges that don't parse (such as dist-tag names) match nothing.
+    pub fn matches(&self, version: &str) -> bool {
+        if self.versions.contains(version) {
+            return true;
+        }
+        let Some(parsed) = parse_loose_version(version) else {
+            return false;
+        };
+        self.ranges.iter().any(|range| {
+            parse_range(range).is_ok_and(|range| range.matches(&parsed))
+        })
+    }
+}
+
+/// Packument with each version left unparsed
+#[derive(Deserialize)]
+struct RawMetadata<'a> {
+    name: String,
+    #[serde(rename = "dist-tags")]
+    dist_tags: HashMap<String, String>,
+    #[serde(borrow)]
+    versions: HashMap<String, &'a RawValue>,
+    #[serde(default)]
+    time: HashMap<String, String>,
+}
+
+/// Parse registry metadata, keeping only the versions a filter selects
+///
+/// # Arguments
+/// * `body` - Raw JSON body of a metadata response
+/// * `filter` - Versions to keep in addition to dist-tag targets
+///
+/// # Returns
+/// * `Ok(PackageMetadata)` with all dist-tags and only the selected versions
+/// * `Err(anyhow::Error)` if the document or a selected version is malformed
+pub fn parse_metadata_filtered(body: &[u8], filter: &VersionFilter) -> Result<PackageMetadata> {
+    let raw: RawMetadata = serde_json::from_slice(body).context("Failed to parse package metadata")?;
+    let tagged: HashSet<&str> = raw.dist_tags.values().map(String::as_str).collect();
+
+    let mut versions = HashMap::new();
+    for (version, manifest) in raw.versions {
+        if tagged.contains(version.as_str()) || filter.matches(&version) {
+            let parsed: PackageVersion = serde_json::from_str(manifest.get())
+                .with_context(|| format!("Failed to parse metadata for {}@{}", raw.name, version))?;
+            versions.insert(version, parsed);
+        }
+    }
+
+    let mut time = raw.time;
+    time.retain(|key, _| versions.contains_key(key) || key == "created" || key == "modified");
+    Ok(PackageMetadata {
+        name: raw.name,
+        dist_tags: raw.dist_tags,
+        versions,
+        time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A packument with `count` minor releases, each padded like a real manifest
+    fn large_fixture(count: usize) -> Vec<u8> {
+        let padding = "x".repeat(2048);
+        let versions: serde_json::Map<String, serde_json::Value> = (0..count)
+            .map(|minor| {
+                let version = format!("1.{}.0", minor);
+                let manifest = json!({
+                    "name": "huge",
+                    "version": version,
+                    "description": padding,
+                    "dependencies": { "dep": format!("^{}.0.0", minor % 7) },
+                    "dist": {
+                        "tarball": format!("https://registry.npmjs.org/huge/-/huge-{}.tgz", version),
+                        "shasum": "0000000000000000000000000000000000000000"
+                    }
+                });
+                (version, manifest)
+            })
+            .collect();
+        serde_json::to_vec(&json!({
+            "name": "huge",
+            "readme": padding,
+            "dist-tags": { "latest": format!("1.{}.0", count - 1), "legacy": "1.3.0" },
+            "versions": versions,
+            "time": { "created": "2015-01-01T00:00:00.000Z" }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_metadata_filtered_large_fixture() {
+        let body = large_fixture(3000);
+        assert!(body.len() > 6_000_000);
+
+        let filter = VersionFilter::range(">=1.2500.0 <1.2510.0 || 1.42.0");
+        let metadata = parse_metadata_filtered(&body, &filter).unwrap();
+        let full: PackageMetadata = serde_json::from_slice(&body).unwrap();
+
+        let mut kept: Vec<&str> = metadata.versions.keys().map(String::as_str).collect();
+        kept.sort_by_key(|version| parse_loose_version(version));
+        let mut expected = vec!["1.3.0", "1.42.0"];
+        let wanted: Vec<String> = (2500..2510).map(|minor| format!("1.{}.0", minor)).collect();
+        expected.extend(wanted.iter().map(String::as_str));
+        expected.push("1.2999.0");
+        assert_eq!(kept, expected);
+
+        assert_eq!(metadata.name, "huge");
+        assert_eq!(metadata.dist_tags, full.dist_tags);
+        for (version, parsed) in &metadata.versions {
+            let reference = &full.versions[version];
+            assert_eq!(parsed.dependencies, reference.dependencies);
+            assert_eq!(parsed.dist, reference.dist);
+        }
+    }
+
+    #[test]
+    fn test_parse_metadata_filtered_exact_versions_and_errors() {
+        let body = large_fixture(10);
+        let filter = VersionFilter {
+            ranges: vec!["next".to_string()],
+            versions: ["1.5.0".to_string()].into_iter().collect(),
+        };
+        let metadata = parse_metadata_filtered(&body, &filter).unwrap();
+        let mut kept: Vec<&String> = metadata.versions.keys().collect();
+        kept.sort();
+        assert_eq!(kept, vec!["1.3.0", "1.5.0", "1.9.0"]);
+
+        let malformed = br#"{ "name": "bad", "dist-tags": { "latest": "1.0.0" }, "versions": { "1.0.0": { "name": "bad" } } }"#;
+        let err = parse_metadata_filtered(malformed, &VersionFilter::default()).unwrap_err();
+        assert!(err.to_string().contains("bad@1.0.0"));
+        assert!(parse_metadata_filtered(b"not json", &VersionFilter::default()).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Forced versions for packages anywhere in the tree
+//!
+//! A project can force the version of a dependency, however deep it sits,
+//! with npm's `overrides` or yarn's `resolutions` field in `package.json`.
+//! Both are read into one flat map from package name to the forced version
+//! or range. Overrides that only apply below a particular parent aren't
+//! supported and are skipped with a warning.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::PackageInfo;
+
+/// Read the forced versions declared by a project manifest
+///
+/// npm `overrides` entries map a package name to a version, to `$name` for
+/// the version the project itself depends on, or to an object whose `.`
+/// key holds the version. yarn `resolutions` keys are a package name,
+/// optionally prefixed with `**/`. When both fields force the same package,
+/// `overrides` wins.
+///
+/// # Arguments
+/// * `manifest` - The project's `package.json`
+///
+/// # Returns
+/// * `Ok(HashMap<String, String>)` mapping package names to forced versions or ranges
+/// * `Err(anyhow::Error)` if an entry is malformed or a `$name` reference can't be resolved
+pub fn project_overrides(manifest: &PackageInfo) -> Result<HashMap<String, String>> {
+    let mut overrides = HashMap::new();
+
+    for (pattern, version) in &manifest.resolutions {
+        let name = pattern.strip_prefix("**/").unwrap_or(pattern);
+        if is_package_name(name) {
+            overrides.insert(name.to_string(), version.clone());
+        } else {
+            warn!("Ignoring resolution {:?}: only package names and **/name patterns are supported", pattern);
+        }
+    }
+
+    let Some(npm_overrides) = &manifest.overrides else {
+        return Ok(overrides);
+    };
+    let entries = npm_overrides
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("\"overrides\" in package.json must be an object"))?;
+    for (name, value) in entries {
+        if !is_package_name(name) {
+            warn!("Ignoring override {:?}: only package names are supported", name);
+            continue;
+        }
+
+        let version = match value {
+            serde_json::Value::String(version) => Some(version.as_str()),
+            serde_json::Value::Object(nested) => {
+                if nested.keys().any(|key| key != ".") {
+                    warn!("Ignoring the overrides nested under {}: they only apply below it", name);
+                }
+                match nested.get(".") {
+                    Some(serde_json::Value::String(version)) => Some(version.as_str()),
+                    Some(_) => anyhow::bail!("Override {:?} for {} must be a string", ".", name),
+                    None => None,
+                }
+            }
+            _ => anyhow::bail!("Override for {} must be a string or an object", name),
+        };
+        let Some(version) = version else {
+            continue;
+        };
+
+        let version = match version.strip_prefix('$') {
+            Some(reference) => root_dependency(manifest, reference)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Override for {} refers to ${}, which the project doesn't depend on", name, reference)
+                })?
+                .to_string(),
+            None => version.to_string(),
+        };
+        if let Some(previous) = overrides.insert(name.clone(), version.clone()) {
+            if previous != version {
+                warn!("Override {}@{} replaces resolution {}@{}", name, version, name, previous);
+            }
+        }
+    }
+
+    Ok(overrides)
+}
+
+/// Check whether an override key names a package, rather than a path or a versioned spec
+fn is_package_name(key: &str) -> bool {
+    match key.strip_prefix('@') {
+        Some(scoped) => scoped.split('/').count() == 2 && !scoped.contains('@'),
+        None => !key.is_empty() && !key.contains('/') && !key.contains('@'),
+    }
+}
+
+/// Find the range a project declares for one of its own dependencies
+fn root_dependency<'a>(manifest: &'a PackageInfo, name: &str) -> Option<&'a str> {
+    manifest
+        .dependencies
+        .get(name)
+        .or_else(|| manifest.dev_dependencies.get(name))
+        .or_else(|| manifest.optional_dependencies.get(name))
+        .or_else(|| manifest.peer_dependencies.get(name))
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn manifest(value: serde_json::Value) -> PackageInfo {
+        let mut value = value;
+        value["name"] = json!("app");
+        value["version"] = json!("1.0.0");
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_project_overrides() {
+        let manifest = manifest(json!({
+            "dependencies": { "minimist": "^1.2.6" },
+            "overrides": {
+                "semver": "7.5.4",
+                "@types/node": { ".": "18.0.0", "undici-types": "5.0.0" },
+                "minimist": "$minimist",
+                "lodash": "4.17.21",
+                "foo@1.x": "1.2.0",
+                "react": { "scheduler": "0.20.0" }
+            },
+            "resolutions": {
+                "**/lodash": "4.17.20",
+                "qs": "6.11.0",
+                "webpack/**/acorn": "8.0.0"
+            }
+        }));
+
+        let overrides = project_overrides(&manifest).unwrap();
+        let expected: HashMap<String, String> = [
+            ("semver", "7.5.4"),
+            ("@types/node", "18.0.0"),
+            ("minimist", "^1.2.6"),
+            ("lodash", "4.17.21"),
+            ("qs", "6.11.0"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+        .collect();
+        assert_eq!(overrides, expected);
+    }
+
+    #[test]
+    fn test_project_overrides_errors() {
+        let dangling = manifest(json!({ "overrides": { "minimist": "$minimist" } }));
+        let err = project_overrides(&dangling).unwrap_err();
+        assert!(err.to_string().contains("refers to $minimist"));
+
+        let not_object = manifest(json!({ "overrides": ["semver"] }));
+        assert!(project_overrides(&not_object).is_err());
+
+        assert!(project_overrides(&manifest(json!({}))).unwrap().is_empty());
+    }
+}
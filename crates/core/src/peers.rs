@@ -0,0 +1,297 @@
+//! Peer dependency checking
+//!
+//! This module verifies that the peer dependencies declared by resolved
+//! packages are satisfied by other packages in the same resolved tree.
+//! Peers marked optional in `peerDependenciesMeta` may be left out, which is
+//! only warned about, but once installed they must satisfy their range too.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use tracing::warn;
+
+use crate::semver::{parse_loose_version, parse_range, parse_version};
+use crate::{CoreError, InstallOptions, PackageInfo};
+
+/// Settings for one peer dependency, from `peerDependenciesMeta`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerDependencyMeta {
+    /// The package works without the peer installed
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// An unsatisfied peer dependency
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerDependencyIssue {
+    /// Package declaring the peer dependency
+    pub package: String,
+    /// Version of the declaring package
+    pub version: String,
+    /// Name of the peer package
+    pub peer: String,
+    /// Range the declaring package requires
+    pub required_range: String,
+    /// Version of the peer found in the tree, if any
+    pub found_version: Option<String>,
+    /// Whether the declaring package marks the peer optional and it is missing
+    ///
+    /// An optional peer installed at the wrong version is a regular issue.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl PeerDependencyIssue {
+    /// Check whether the peer is absent from the tree entirely
+    pub fn is_missing(&self) -> bool {
+        self.found_version.is_none()
+    }
+}
+
+impl fmt::Display for PeerDependencyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.found_version {
+            Some(found) => write!(
+                f,
+                "{}@{} requires peer {}@{} but {} is installed",
+                self.package, self.version, self.peer, self.required_range, found
+            ),
+            None => write!(
+                f,
+                "{}@{} requires peer {}@{} which is not installed",
+                self.package, self.version, self.peer, self.required_range
+            ),
+        }?;
+        if self.optional {
+            write!(f, " (optional)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check every declared peer dependency against the resolved packages
+///
+/// When a package has nested copies, peers are checked against its first
+/// version, the one `plan_downloads` places at the top level.
+///
+/// # Arguments
+/// * `packages` - The resolved package tree
+/// * `include_prereleases` - Whether ranges match pre-releases within their bounds
+///
+/// # Returns
+/// * Every peer that is missing or whose installed version is outside the required range
+pub fn check_peer_dependencies(packages: &[PackageInfo], include_prereleases: bool) -> Vec<PeerDependencyIssue> {
+    let mut installed: HashMap<&str, &str> = HashMap::new();
+    for pkg in packages {
+        installed.entry(pkg.name.as_str()).or_insert(pkg.version.as_str());
+    }
+
+    let mut issues = Vec::new();
+    for pkg in packages {
+        let mut peers: Vec<(&String, &String)> = pkg.peer_dependencies.iter().collect();
+        peers.sort();
+
+        for (peer, range) in peers {
+            let found = installed.get(peer.as_str()).copied();
+            if found.is_some_and(|version| satisfies(version, range, include_prereleases)) {
+                continue;
+            }
+
+            issues.push(PeerDependencyIssue {
+                package: pkg.name.clone(),
+                version: pkg.version.clone(),
+                peer: peer.clone(),
+                required_range: range.clone(),
+                found_version: found.map(str::to_string),
+                optional: found.is_none() && pkg.peer_dependencies_meta.get(peer).is_some_and(|meta| meta.optional),
+            });
+        }
+    }
+
+    issues
+}
+
+/// Apply the peer dependency policy from the install options
+///
+/// Missing optional peers are warnings even under `strict_peer_deps`.
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with a warning per issue when peers aren't strict or
+///   every issue concerns a missing optional peer
+/// * `Err(anyhow::Error)` listing every required issue when `strict_peer_deps` is set
+pub fn enforce_peer_dependencies(issues: &[PeerDependencyIssue], options: &InstallOptions) -> Result<Vec<String>> {
+    let messages: Vec<String> = issues.iter().map(ToString::to_string).collect();
+
+    if options.strict_peer_deps {
+        let required: Vec<String> = issues
+            .iter()
+            .filter(|issue| !issue.optional)
+            .map(ToString::to_string)
+            .collect();
+        if !required.is_empty() {
+            return Err(CoreError::UnmetPeerDependencies(required).into());
+        }
+    }
+
+    for message in &messages {
+        warn!("{}", message);
+    }
+
+    Ok(messages)
+}
+
+/// Check whether an installed version satisfies a peer range
+///
+/// Unparseable ranges or versions are given the benefit of the doubt.
+fn satisfies(version: &str, range: &str, include_prereleases: bool) -> bool {
+    let Some(version) = parse_version(version).ok().or_else(|| parse_loose_version(version)) else {
+        return true;
+    };
+
+    match parse_range(range) {
+        Ok(range) => range.matches_with(&version, include_prereleases),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str, peers: &[(&str, &str)]) -> PackageInfo {
+        let mut pkg = PackageInfo::new(name, version);
+        pkg.peer_dependencies = peers
+            .iter()
+            .map(|(peer, range)| (peer.to_string(), range.to_string()))
+            .collect();
+        pkg
+    }
+
+    #[test]
+    fn test_satisfied_peer() {
+        let packages = vec![
+            package("react-dom", "18.2.0", &[("react", "^18.0.0")]),
+            package("react", "18.2.0", &[]),
+        ];
+
+        assert!(check_peer_dependencies(&packages, false).is_empty());
+    }
+
+    #[test]
+    fn test_missing_peer() {
+        let packages = vec![package("react-dom", "18.2.0", &[("react", "^18.0.0")])];
+
+        let issues = check_peer_dependencies(&packages, false);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_missing());
+        assert_eq!(issues[0].peer, "react");
+        assert_eq!(
+            issues[0].to_string(),
+            "react-dom@18.2.0 requires peer react@^18.0.0 which is not installed"
+        );
+    }
+
+    #[test]
+    fn test_prerelease_peer() {
+        let packages = vec![
+            package("react-dom", "18.2.0", &[("react", "^18.0.0")]),
+            package("react", "18.3.0-canary.1", &[]),
+        ];
+
+        assert_eq!(check_peer_dependencies(&packages, false).len(), 1);
+        assert!(check_peer_dependencies(&packages, true).is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_peer() {
+        let packages = vec![
+            package("react-dom", "18.2.0", &[("react", "^18.0.0")]),
+            package("react", "17.0.2", &[]),
+        ];
+
+        let issues = check_peer_dependencies(&packages, false);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].found_version.as_deref(), Some("17.0.2"));
+        assert!(issues[0].to_string().contains("but 17.0.2 is installed"));
+    }
+
+    #[test]
+    fn test_peer_checked_against_top_level_copy() {
+        let packages = vec![
+            package("react-dom", "18.2.0", &[("react", "^18.0.0")]),
+            package("react", "18.2.0", &[]),
+            package("legacy-ui", "1.0.0", &[]),
+            package("react", "17.0.2", &[]),
+        ];
+        assert!(check_peer_dependencies(&packages, false).is_empty());
+
+        let packages = vec![
+            package("react-dom", "18.2.0", &[("react", "^18.0.0")]),
+            package("react", "17.0.2", &[]),
+            package("react", "18.2.0", &[]),
+        ];
+        let issues = check_peer_dependencies(&packages, false);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].found_version.as_deref(), Some("17.0.2"));
+    }
+
+    #[test]
+    fn test_enforce_peer_dependencies() {
+        let issues = check_peer_dependencies(&[package("react-dom", "18.2.0", &[("react", "^18.0.0")])], false);
+
+        let warnings = enforce_peer_dependencies(&issues, &InstallOptions::default()).unwrap();
+        assert_eq!(warnings.len(), 1);
+
+        let strict = InstallOptions {
+            strict_peer_deps: true,
+            ..Default::default()
+        };
+        let err = enforce_peer_dependencies(&issues, &strict).unwrap_err();
+        assert!(err.to_string().contains("Unmet peer dependencies"));
+
+        assert!(enforce_peer_dependencies(&[], &strict).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_optional_peers_only_warn() {
+        let mut plugin = package(
+            "eslint-plugin",
+            "2.0.0",
+            &[("eslint", "^8.0.0"), ("typescript", ">=4.0.0"), ("prettier", "^3.0.0")],
+        );
+        plugin.peer_dependencies_meta = serde_json::from_value(serde_json::json!({
+            "typescript": { "optional": true },
+            "prettier": { "optional": false }
+        }))
+        .unwrap();
+        let strict = InstallOptions {
+            strict_peer_deps: true,
+            ..Default::default()
+        };
+
+        // Only the optional peer is unmet
+        let packages = vec![plugin.clone(), package("eslint", "8.50.0", &[]), package("prettier", "3.0.0", &[])];
+        let issues = check_peer_dependencies(&packages, false);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].optional);
+        assert_eq!(
+            issues[0].to_string(),
+            "eslint-plugin@2.0.0 requires peer typescript@>=4.0.0 which is not installed (optional)"
+        );
+        assert_eq!(enforce_peer_dependencies(&issues, &strict).unwrap().len(), 1);
+
+        // Required peers still fail a strict install, and so does an optional
+        // peer installed out of range
+        let packages = vec![plugin, package("typescript", "3.9.0", &[]), package("prettier", "2.8.0", &[])];
+        let issues = check_peer_dependencies(&packages, false);
+        assert_eq!(issues.len(), 3);
+        assert!(issues.iter().all(|issue| !issue.optional));
+        let err = enforce_peer_dependencies(&issues, &strict).unwrap_err();
+        let Some(CoreError::UnmetPeerDependencies(unmet)) = err.downcast_ref::<CoreError>() else {
+            panic!("unexpected error: {}", err);
+        };
+        let unmet_peers: Vec<&str> = unmet.iter().map(|message| message.split(' ').nth(3).unwrap()).collect();
+        assert_eq!(unmet_peers, vec!["eslint@^8.0.0", "prettier@^3.0.0", "typescript@>=4.0.0"]);
+    }
+}
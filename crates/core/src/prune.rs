@@ -0,0 +1,217 @@
+//! Removal of extraneous packages
+//!
+//! Packages left in `node_modules` after their dependency was removed are
+//! extraneous: neither the lockfile nor the project's `package.json`
+//! (directly or through installed dependencies) refers to them any more.
+//! The manifest counts too, so packages added since the lockfile was last
+//! written aren't mistaken for leftovers.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::lockfile::{load_project_lockfile, Lockfile};
+use crate::manifest::read_package_info;
+
+/// Find the packages in a project's `node_modules` that nothing references
+///
+/// A package is referenced if the lockfile lists it, or if the project's
+/// `package.json` depends on it directly or through the manifests of
+/// installed packages. Only packages installed at the top of `node_modules`
+/// (including scoped packages) are considered; dot-directories such as
+/// `.bin` are skipped.
+///
+/// # Arguments
+/// * `project_dir` - Directory containing the lockfile and `node_modules`
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with the extraneous package names, sorted
+/// * `Err(anyhow::Error)` if the project has no lockfile or `node_modules` can't be read
+pub fn find_extraneous(project_dir: &Path) -> Result<Vec<String>> {
+    let lockfile = load_project_lockfile(project_dir)?
+        .ok_or_else(|| anyhow::anyhow!("No lockfile found in {}", project_dir.display()))?;
+    let node_modules = project_dir.join("node_modules");
+    let referenced = referenced_packages(project_dir, &node_modules, &lockfile);
+    extraneous_in(&node_modules, &referenced)
+}
+
+/// Remove the packages in a project's `node_modules` that nothing references
+///
+/// See `find_extraneous` for what counts as referenced.
+///
+/// # Arguments
+/// * `project_dir` - Directory containing the lockfile and `node_modules`
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with the removed package names, sorted
+/// * `Err(anyhow::Error)` if the project has no lockfile or a package can't be removed
+pub fn prune_extraneous(project_dir: &Path) -> Result<Vec<String>> {
+    let node_modules = project_dir.join("node_modules");
+    let extraneous = find_extraneous(project_dir)?;
+
+    for name in &extraneous {
+        let dir = node_modules.join(name);
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+
+        // Drop scope directories left empty
+        if let Some((scope, _)) = name.split_once('/') {
+            let scope_dir = node_modules.join(scope);
+            if fs::read_dir(&scope_dir)?.next().is_none() {
+                fs::remove_dir(&scope_dir)?;
+            }
+        }
+    }
+
+    Ok(extraneous)
+}
+
+/// Names of the packages the lockfile or the project's dependency closure refers to
+///
+/// The closure follows the dependencies, optional and peer dependencies of
+/// installed manifests, starting from every dependency of the project's
+/// `package.json`, dev dependencies included. Manifests that are missing or
+/// can't be read end that branch of the walk.
+fn referenced_packages(project_dir: &Path, node_modules: &Path, lockfile: &Lockfile) -> HashSet<String> {
+    let mut referenced: HashSet<String> = lockfile.packages.values().map(|pkg| pkg.name.clone()).collect();
+
+    let Ok(manifest) = read_package_info(project_dir) else {
+        return referenced;
+    };
+    let mut pending: Vec<String> = manifest
+        .dependencies
+        .keys()
+        .chain(manifest.dev_dependencies.keys())
+        .chain(manifest.optional_dependencies.keys())
+        .chain(manifest.peer_dependencies.keys())
+        .cloned()
+        .collect();
+    let mut visited = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+        if let Ok(pkg) = read_package_info(&node_modules.join(&name)) {
+            pending.extend(
+                pkg.dependencies
+                    .keys()
+                    .chain(pkg.optional_dependencies.keys())
+                    .chain(pkg.peer_dependencies.keys())
+                    .cloned(),
+            );
+        }
+        referenced.insert(name);
+    }
+    referenced
+}
+
+/// List the installed package names in `node_modules` missing from `referenced`
+fn extraneous_in(node_modules: &Path, referenced: &HashSet<String>) -> Result<Vec<String>> {
+    if !node_modules.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut installed = Vec::new();
+    for entry in fs::read_dir(node_modules)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with('.') || !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if file_name.starts_with('@') {
+            for scoped in fs::read_dir(entry.path())? {
+                installed.push(format!("{}/{}", file_name, scoped?.file_name().to_string_lossy()));
+            }
+        } else {
+            installed.push(file_name);
+        }
+    }
+
+    let mut extraneous: Vec<String> = installed
+        .into_iter()
+        .filter(|name| !referenced.contains(name))
+        .collect();
+    extraneous.sort();
+    Ok(extraneous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile::{LockedPackage, LOCKFILE_NAME};
+    use tempfile::TempDir;
+
+    fn fixture() -> TempDir {
+        let project = TempDir::new().unwrap();
+        let mut lockfile = Lockfile::new();
+        lockfile.insert(LockedPackage::new("left-pad", "1.3.0"));
+        lockfile.insert(LockedPackage::new("@scope/kept", "1.0.0"));
+        lockfile.write(&project.path().join(LOCKFILE_NAME)).unwrap();
+
+        for name in ["left-pad", "orphan", "@scope/kept", "@scope/stale", "@gone/pkg", ".bin"] {
+            let dir = project.path().join("node_modules").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("package.json"), "{}").unwrap();
+        }
+        project
+    }
+
+    #[test]
+    fn test_find_extraneous() {
+        let project = fixture();
+        assert_eq!(
+            find_extraneous(project.path()).unwrap(),
+            vec!["@gone/pkg", "@scope/stale", "orphan"]
+        );
+        // Finding doesn't touch anything
+        assert!(project.path().join("node_modules/orphan").is_dir());
+    }
+
+    #[test]
+    fn test_prune_extraneous() {
+        let project = fixture();
+        let node_modules = project.path().join("node_modules");
+
+        let removed = prune_extraneous(project.path()).unwrap();
+        assert_eq!(removed, vec!["@gone/pkg", "@scope/stale", "orphan"]);
+        assert!(!node_modules.join("orphan").exists());
+        assert!(!node_modules.join("@scope/stale").exists());
+        assert!(!node_modules.join("@gone").exists());
+        assert!(node_modules.join("left-pad/package.json").is_file());
+        assert!(node_modules.join("@scope/kept").is_dir());
+        assert!(node_modules.join(".bin").is_dir());
+
+        assert!(prune_extraneous(project.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_prune_keeps_manifest_dependencies_missing_from_lockfile() {
+        let project = fixture();
+        let node_modules = project.path().join("node_modules");
+        let write_manifest = |dir: &Path, manifest: serde_json::Value| {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("package.json"), manifest.to_string()).unwrap();
+        };
+        // Added after the lockfile was written, with a dependency of its own
+        write_manifest(
+            project.path(),
+            serde_json::json!({ "name": "app", "version": "1.0.0", "dependencies": { "foo": "^1.0.0" } }),
+        );
+        write_manifest(
+            &node_modules.join("foo"),
+            serde_json::json!({ "name": "foo", "version": "1.0.0", "dependencies": { "@scope/stale": "^1.0.0" } }),
+        );
+
+        let removed = prune_extraneous(project.path()).unwrap();
+        assert_eq!(removed, vec!["@gone/pkg", "orphan"]);
+        assert!(node_modules.join("foo/package.json").is_file());
+        assert!(node_modules.join("@scope/stale").is_dir());
+    }
+
+    #[test]
+    fn test_prune_requires_lockfile() {
+        let project = TempDir::new().unwrap();
+        let err = prune_extraneous(project.path()).unwrap_err();
+        assert!(err.to_string().contains("No lockfile found"));
+    }
+}
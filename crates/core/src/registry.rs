@@ -0,0 +1,1698 @@
+//! npm registry client
+//!
+//! This module fetches package metadata from an npm-compatible registry,
+//! caching responses and revalidating them with conditional requests. When
+//! mirrors are configured, requests fail over to them in order. Tarballs can
+//! be downloaded to disk, resuming interrupted transfers with `Range` requests.
+//! Failovers and resumed downloads draw from a retry budget shared by every
+//! request of a client, so a struggling registry isn't retried without end.
+//! Metadata bodies are capped in size, so a hostile registry can't exhaust
+//! memory with an endless response. In offline mode nothing is requested:
+//! metadata is served from the cache, and everything else fails with
+//! `CoreError::RequiresNetwork`.
+
+use anyhow::{Context, Result};
+use reqwest::header::{ACCEPT_RANGES, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE};
+use reqwest::{Client, Proxy, RequestBuilder, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::metadata::{parse_metadata_filtered, VersionFilter};
+use crate::search::{SearchResponse, SearchResult, MAX_SEARCH_LIMIT};
+use crate::store::remove_dir_contents;
+use crate::{CoreError, PackageMetadata};
+
+/// Default npm registry URL
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
+/// Number of attempts `download_tarball_to` makes before giving up
+pub const DOWNLOAD_ATTEMPTS: usize = 3;
+
+/// Default number of retries a `Registry` makes over its lifetime
+pub const DEFAULT_RETRY_BUDGET: usize = 64;
+
+/// Default cap on a metadata response body, far above the largest real packument
+pub const DEFAULT_MAX_METADATA_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Registry client configuration
+#[derive(Debug, Clone)]
+pub struct RegistryConfig {
+    /// Base URL of the registry
+    pub url: String,
+    /// Mirror registries tried in order when the primary is unreachable or failing
+    pub registry_mirrors: Vec<String>,
+    /// Registries serving the packages of a scope, keyed by scope (e.g. `@mycorp`)
+    ///
+    /// Packages of a mapped scope are fetched from its registry alone, without
+    /// the mirrors; every other package uses `url`.
+    pub scoped_registries: HashMap<String, String>,
+    /// Directory where metadata responses are cached between runs (optional)
+    pub cache_dir: Option<PathBuf>,
+    /// Maximum time to establish a connection
+    pub connect_timeout: Duration,
+    /// Maximum time for a whole request, including reading the body
+    pub request_timeout: Duration,
+    /// Explicit proxy settings; when unset they are read from the environment
+    pub proxy: Option<ProxyConfig>,
+    /// Retries shared by every request, after which failures are returned at once
+    pub retry_budget: usize,
+    /// Largest metadata or search response read, in bytes; bigger bodies are abandoned
+    pub max_metadata_size: Option<u64>,
+    /// Never send a request; metadata is served from the cache however old it is
+    pub offline: bool,
+}
+
+/// HTTP(S) proxy settings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Proxy for `http://` URLs
+    pub http: Option<String>,
+    /// Proxy for `https://` URLs
+    pub https: Option<String>,
+    /// Hosts (and their subdomains) that bypass the proxy; `*` bypasses everything
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Read proxy settings from `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY`
+    ///
+    /// Lowercase variants are honored too, taking precedence as in curl.
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Build proxy settings from an environment-style lookup
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| {
+            lookup(&name.to_lowercase())
+                .or_else(|| lookup(name))
+                .filter(|value| !value.trim().is_empty())
+        };
+
+        Self {
+            http: var("HTTP_PROXY"),
+            https: var("HTTPS_PROXY"),
+            no_proxy: var("NO_PROXY")
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|entry| entry.trim().to_string())
+                        .filter(|entry| !entry.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Get the proxy to use for a URL, if any
+    pub fn proxy_for(&self, url: &Url) -> Option<&str> {
+        if url.host_str().is_some_and(|host| self.is_excluded(host)) {
+            return None;
+        }
+
+        match url.scheme() {
+            "https" => self.https.as_deref(),
+            "http" => self.http.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Check whether a host matches a `NO_PROXY` entry
+    fn is_excluded(&self, host: &str) -> bool {
+        self.no_proxy.iter().any(|entry| {
+            let entry = entry.rsplit_once(':').map_or(entry.as_str(), |(host, _port)| host);
+            let entry = entry.trim_start_matches('.');
+            entry == "*" || host == entry || host.ends_with(&format!(".{}", entry))
+        })
+    }
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_REGISTRY.to_string(),
+            registry_mirrors: Vec::new(),
+            scoped_registries: HashMap::new(),
+            cache_dir: default_cache_dir(),
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(30),
+            proxy: None,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            max_metadata_size: Some(DEFAULT_MAX_METADATA_SIZE),
+            offline: false,
+        }
+    }
+}
+
+/// Get the default cache directory
+///
+/// Uses `PACKAGE_FAST_CACHE` if set, then `$XDG_CACHE_HOME/package-fast`,
+/// then `$HOME/.cache/package-fast`.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("PACKAGE_FAST_CACHE") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("package-fast"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("package-fast"))
+}
+
+/// Cached metadata along with the validators needed to revalidate it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedMetadata {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    metadata: PackageMetadata,
+}
+
+/// Client for an npm-compatible registry
+#[derive(Debug)]
+pub struct Registry {
+    config: RegistryConfig,
+    client: Client,
+    metadata_cache: Mutex<HashMap<String, CachedMetadata>>,
+    served_by: Mutex<HashMap<String, String>>,
+    in_flight: Mutex<HashMap<String, Arc<MetadataFlight>>>,
+    /// Retries left out of `config.retry_budget`
+    retries_left: AtomicUsize,
+}
+
+/// How a single attempt at downloading into a partial file ended
+#[derive(Debug)]
+enum PartOutcome {
+    /// The server sent the rest of the file
+    Complete,
+    /// The transfer broke off; the partial file holds what was received
+    Interrupted(anyhow::Error),
+}
+
+/// Removes a partial download when dropped, unless disarmed first
+#[derive(Debug)]
+struct RemoveOnDrop(Option<PathBuf>);
+
+impl RemoveOnDrop {
+    /// Keep the file, whatever state it is in
+    fn disarm(mut self) {
+        self.0.take();
+    }
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to remove partial download {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+}
+
+/// Coordinates concurrent metadata fetches for one package
+#[derive(Debug, Default)]
+struct MetadataFlight {
+    /// Held while a request for the package is in flight
+    lock: tokio::sync::Mutex<()>,
+    /// Number of successful fetches completed
+    completed: AtomicU64,
+}
+
+impl Registry {
+    /// Create a new registry client with default configuration
+    pub fn new() -> Self {
+        Self::with_config(RegistryConfig::default())
+    }
+
+    /// Create a new registry client with custom configuration
+    pub fn with_config(config: RegistryConfig) -> Self {
+        let proxy_config = config.proxy.clone().unwrap_or_else(ProxyConfig::from_env);
+
+        // Proxy selection (including NO_PROXY) is handled by ProxyConfig rather
+        // than reqwest's own environment detection so both paths behave the same
+        let client = Client::builder()
+            .user_agent("package-fast/0.1.0")
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.request_timeout)
+            .no_proxy()
+            .proxy(Proxy::custom(move |url| {
+                proxy_config.proxy_for(url).and_then(|proxy| Url::parse(proxy).ok())
+            }))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            retries_left: AtomicUsize::new(config.retry_budget),
+            config,
+            client,
+            metadata_cache: Mutex::new(HashMap::new()),
+            served_by: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get a reference to the current configuration
+    pub fn config(&self) -> &RegistryConfig {
+        &self.config
+    }
+
+    /// Get the number of retries left in the budget
+    pub fn retries_left(&self) -> usize {
+        self.retries_left.load(Ordering::SeqCst)
+    }
+
+    /// Take one retry from the budget, or explain why a failure can't be retried
+    fn spend_retry(&self, error: anyhow::Error) -> Result<()> {
+        match self.retries_left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(error.context(format!(
+                "Retry budget of {} exhausted, not retrying",
+                self.config.retry_budget
+            ))),
+        }
+    }
+
+    /// Fail with `CoreError::RequiresNetwork` in offline mode
+    ///
+    /// # Arguments
+    /// * `operation` - Describes what needed the network, e.g. `Searching the registry`
+    fn require_network(&self, operation: impl FnOnce() -> String) -> Result<()> {
+        if self.config.offline {
+            return Err(CoreError::RequiresNetwork(operation()).into());
+        }
+        Ok(())
+    }
+
+    /// Get the registry URLs to try, primary first
+    pub fn registry_urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(&self.config.url)
+            .chain(&self.config.registry_mirrors)
+            .map(|url| url.trim_end_matches('/'))
+    }
+
+    /// Get the registry mapped to a package's scope, if it has one
+    ///
+    /// # Arguments
+    /// * `name` - Package name, possibly scoped (`@scope/name`)
+    ///
+    /// # Returns
+    /// * `Some(&str)` with the scope's registry URL, without a trailing `/`
+    /// * `None` for unscoped packages and scopes without a registry of their own
+    pub fn scoped_registry(&self, name: &str) -> Option<&str> {
+        let (scope, _) = name.split_once('/').filter(|(scope, _)| scope.starts_with('@'))?;
+        self.config
+            .scoped_registries
+            .get(scope)
+            .map(|url| url.trim_end_matches('/'))
+    }
+
+    /// Get the registry URLs to fetch a package's metadata from, in order
+    ///
+    /// A package whose scope is mapped to a registry only uses that registry;
+    /// any other package uses the primary registry and then the mirrors.
+    pub fn registry_urls_for<'a>(&'a self, name: &str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        match self.scoped_registry(name) {
+            Some(url) => Box::new(std::iter::once(url)),
+            None => Box::new(self.registry_urls()),
+        }
+    }
+
+    /// Get the registry that served the most recent metadata for a package
+    pub fn served_by(&self, name: &str) -> Option<String> {
+        self.served_by.lock().unwrap().get(name).cloned()
+    }
+
+    /// Fetch package metadata, revalidating any cached copy
+    ///
+    /// When a cached response carries an `ETag` or `Last-Modified` header,
+    /// the request is made conditional and a `304 Not Modified` response is
+    /// served from the cache. In offline mode the cached copy is served
+    /// without revalidation, and a package without one fails with
+    /// `CoreError::RequiresNetwork`. Only responses carrying a validator are
+    /// cached on disk, which the npm registry always sends.
+    ///
+    /// Concurrent calls for the same package share a single request: callers
+    /// arriving while it is in flight wait for it and are served its result
+    /// from the in-memory cache. If it fails, the next waiter retries.
+    pub async fn fetch_metadata(&self, name: &str) -> Result<PackageMetadata> {
+        let flight = self.in_flight.lock().unwrap().entry(name.to_string()).or_default().clone();
+        let completed = flight.completed.load(Ordering::Acquire);
+
+        let _guard = flight.lock.lock().await;
+        if flight.completed.load(Ordering::Acquire) != completed {
+            if let Some(cached) = self.metadata_cache.lock().unwrap().get(name) {
+                debug!("Metadata for {} was fetched by a concurrent request", name);
+                return Ok(cached.metadata.clone());
+            }
+        }
+
+        let metadata = self.fetch_metadata_uncoordinated(name).await?;
+        flight.completed.fetch_add(1, Ordering::Release);
+        Ok(metadata)
+    }
+
+    /// Serve package metadata from the cache alone, as in offline mode
+    ///
+    /// # Returns
+    /// * `Ok(PackageMetadata)` with the cached copy, however old it is
+    /// * `Err(CoreError::RequiresNetwork)` if the package has no cached copy
+    pub fn cached_metadata_offline(&self, name: &str) -> Result<PackageMetadata> {
+        match self.cached_metadata(name) {
+            Some(cached) => {
+                debug!("Offline, using cached metadata for {}", name);
+                Ok(cached.metadata)
+            }
+            None => Err(CoreError::RequiresNetwork(format!("Metadata for {} is not cached; fetching it", name)).into()),
+        }
+    }
+
+    /// Fetch package metadata without coordinating with concurrent callers
+    async fn fetch_metadata_uncoordinated(&self, name: &str) -> Result<PackageMetadata> {
+        if self.config.offline {
+            return self.cached_metadata_offline(name);
+        }
+        let cached = self.cached_metadata(name);
+
+        let urls: Vec<(String, String)> = self
+            .registry_urls_for(name)
+            .map(|base| (base.to_string(), format!("{}/{}", base, encode_package_name(name))))
+            .collect();
+
+        let (registry_url, url, response) = self
+            .send_with_failover(urls, |url| {
+                info!("Fetching package metadata from {}", url);
+                let mut request = self.client.get(url);
+                if let Some(cached) = &cached {
+                    if let Some(etag) = &cached.etag {
+                        request = request.header(IF_NONE_MATCH, etag);
+                    }
+                    if let Some(last_modified) = &cached.last_modified {
+                        request = request.header(IF_MODIFIED_SINCE, last_modified);
+                    }
+                }
+                request
+            })
+            .await?;
+
+        self.served_by.lock().unwrap().insert(name.to_string(), registry_url);
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                debug!("Metadata for {} not modified, using cached copy", name);
+                return Ok(cached.metadata);
+            }
+            anyhow::bail!("Registry returned 304 for {} without a cached copy", name);
+        }
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(CoreError::PackageNotFound { name: name.to_string() }.into());
+        }
+        if !response.status().is_success() {
+            anyhow::bail!("Failed to fetch package metadata: HTTP {}", response.status());
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let body = self.read_metadata_body(response, &url).await?;
+        let metadata: PackageMetadata =
+            serde_json::from_slice(&body).with_context(|| format!("Failed to parse package metadata from {}", url))?;
+
+        self.store_metadata(
+            name,
+            CachedMetadata {
+                etag,
+                last_modified,
+                metadata: metadata.clone(),
+            },
+        );
+
+        Ok(metadata)
+    }
+
+    /// Fetch package metadata, parsing only the versions a filter selects
+    ///
+    /// Meant for packages with huge metadata documents: the body is scanned
+    /// without building the manifests of unwanted versions, which keeps peak
+    /// memory close to the size of the response. The partial result is
+    /// neither read from nor written to the metadata cache. In offline mode
+    /// the full cached document is served instead, as by `fetch_metadata`.
+    ///
+    /// # Arguments
+    /// * `name` - Package name
+    /// * `filter` - Versions to keep in addition to dist-tag targets
+    pub async fn fetch_metadata_filtered(&self, name: &str, filter: &VersionFilter) -> Result<PackageMetadata> {
+        if self.config.offline {
+            return self.fetch_metadata(name).await;
+        }
+
+        let urls: Vec<(String, String)> = self
+            .registry_urls_for(name)
+            .map(|base| (base.to_string(), format!("{}/{}", base, encode_package_name(name))))
+            .collect();
+
+        let (registry_url, url, response) = self
+            .send_with_failover(urls, |url| {
+                info!("Fetching package metadata from {}", url);
+                self.client.get(url)
+            })
+            .await?;
+        self.served_by.lock().unwrap().insert(name.to_string(), registry_url);
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(CoreError::PackageNotFound { name: name.to_string() }.into());
+        }
+        if !response.status().is_success() {
+            anyhow::bail!("Failed to fetch package metadata: HTTP {}", response.status());
+        }
+
+        let body = self.read_metadata_body(response, &url).await?;
+        parse_metadata_filtered(&body, filter)
+    }
+
+    /// Search the registry for packages
+    ///
+    /// Mirrors are tried in order when the primary registry fails.
+    ///
+    /// # Arguments
+    /// * `query` - Search text, matched against names, descriptions and keywords
+    /// * `limit` - Most results to return, capped at `MAX_SEARCH_LIMIT`
+    ///
+    /// # Returns
+    /// * `Ok(Vec<SearchResult>)` with the matches, best first
+    /// * `Err(CoreError::RequiresNetwork)` in offline mode
+    /// * `Err(anyhow::Error)` if no registry answered successfully
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        self.require_network(|| "Searching the registry".to_string())?;
+        let size = limit.clamp(1, MAX_SEARCH_LIMIT).to_string();
+        let urls = self
+            .registry_urls()
+            .map(|registry_url| (registry_url.to_string(), format!("{}/-/v1/search", registry_url)))
+            .collect();
+        let (_, url, response) = self
+            .send_with_failover(urls, |url| {
+                info!("Searching {} for {:?}", url, query);
+                self.client.get(url).query(&[("text", query), ("size", size.as_str())])
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Search failed: HTTP {}", response.status());
+        }
+        let body = self.read_metadata_body(response, &url).await?;
+        let body: SearchResponse =
+            serde_json::from_slice(&body).with_context(|| format!("Failed to parse search results from {}", url))?;
+        let mut results = body.into_results();
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// Check that a registry answers its `/-/ping` endpoint
+    ///
+    /// Mirrors are tried in order when the primary registry fails.
+    ///
+    /// # Returns
+    /// * `Ok(String)` with the URL of the registry that answered
+    /// * `Err(CoreError::RequiresNetwork)` in offline mode
+    /// * `Err(anyhow::Error)` if no registry answered successfully
+    pub async fn ping(&self) -> Result<String> {
+        self.require_network(|| "Pinging the registry".to_string())?;
+        let urls = self
+            .registry_urls()
+            .map(|registry_url| (registry_url.to_string(), format!("{}/-/ping", registry_url)))
+            .collect();
+        let (registry_url, url, response) = self.send_with_failover(urls, |url| self.client.get(url)).await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Ping to {} failed: HTTP {}", url, response.status());
+        }
+        Ok(registry_url)
+    }
+
+    /// Download a package tarball
+    ///
+    /// # Arguments
+    /// * `url` - URL of the `.tgz` file
+    /// * `max_size` - Most bytes to accept, or `None` for no limit
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` with the compressed tarball bytes
+    /// * `Err(CoreError::RequiresNetwork)` in offline mode
+    /// * `Err(anyhow::Error)` if the request fails or the tarball exceeds
+    ///   `max_size`, in which case the transfer is abandoned right away
+    ///
+    /// Tarballs hosted on the primary registry are fetched from the mirrors
+    /// under the same path if the primary fails.
+    pub async fn download_tarball(&self, url: &str, max_size: Option<u64>) -> Result<Vec<u8>> {
+        self.require_network(|| format!("Downloading {}", url))?;
+        let (_, url, mut response) = self
+            .send_with_failover(self.tarball_urls(url), |url| {
+                info!("Downloading tarball from {}", url);
+                self.client.get(url)
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Failed to download tarball {}: HTTP {}", url, response.status());
+        }
+        check_tarball_size(&url, response.content_length().unwrap_or(0), max_size)?;
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| self.map_request_error(e, &url))? {
+            bytes.extend_from_slice(&chunk);
+            check_tarball_size(&url, bytes.len() as u64, max_size)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Download a package tarball to a file, resuming interrupted transfers
+    ///
+    /// Bytes are written to `<dest>.part` as they arrive. When a transfer
+    /// breaks off, the next attempt continues from the end of the partial
+    /// file with a `Range` request, provided the server advertised
+    /// `Accept-Ranges: bytes`; otherwise it starts over. A partial file left
+    /// behind by an earlier run is resumed the same way. Once complete, the
+    /// bytes must pass `verify` before the partial file is renamed to `dest`;
+    /// bytes failing verification are discarded. A download abandoned
+    /// mid-transfer, such as by a cancelled install dropping it, or grown
+    /// past `max_size` removes its partial file.
+    ///
+    /// # Arguments
+    /// * `url` - URL of the `.tgz` file
+    /// * `dest` - Path the finished tarball is written to
+    /// * `max_size` - Most bytes to accept, or `None` for no limit
+    /// * `verify` - Check of the complete bytes, such as `PackageDistribution::verify`
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` with the verified tarball bytes
+    /// * `Err(CoreError::RequiresNetwork)` in offline mode
+    /// * `Err(anyhow::Error)` if the request fails, every one of the
+    ///   `DOWNLOAD_ATTEMPTS` attempts breaks off, the tarball exceeds
+    ///   `max_size`, or verification fails
+    pub async fn download_tarball_to(
+        &self,
+        url: &str,
+        dest: &Path,
+        max_size: Option<u64>,
+        verify: impl Fn(&[u8]) -> Result<()>,
+    ) -> Result<Vec<u8>> {
+        self.require_network(|| format!("Downloading {}", url))?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+        let mut part = dest.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        let abandoned = RemoveOnDrop(Some(part.clone()));
+        let result = self.complete_download(url, dest, &part, max_size, verify).await;
+        abandoned.disarm();
+        result
+    }
+
+    /// Download into a partial file until complete, then verify and move it to `dest`
+    async fn complete_download(
+        &self,
+        url: &str,
+        dest: &Path,
+        part: &Path,
+        max_size: Option<u64>,
+        verify: impl Fn(&[u8]) -> Result<()>,
+    ) -> Result<Vec<u8>> {
+        let mut attempt = 1;
+        while let PartOutcome::Interrupted(error) = self.download_part(url, part, max_size).await? {
+            if attempt == DOWNLOAD_ATTEMPTS {
+                return Err(error.context(format!("Failed to download tarball {} after {} attempts", url, attempt)));
+            }
+            warn!("Download of {} was interrupted (attempt {}/{}): {:#}", url, attempt, DOWNLOAD_ATTEMPTS, error);
+            self.spend_retry(error)?;
+            attempt += 1;
+        }
+
+        let bytes = fs::read(part)?;
+        if let Err(e) = verify(&bytes) {
+            fs::remove_file(part)?;
+            return Err(e);
+        }
+        fs::rename(part, dest).with_context(|| format!("Failed to move {:?} into place", part))?;
+        Ok(bytes)
+    }
+
+    /// Make one attempt at completing a partial tarball download
+    ///
+    /// Errors are returned for failures that retrying won't fix, such as an
+    /// unsuccessful status or a tarball over `max_size`, whose partial file
+    /// is removed; a transfer that breaks off is `Interrupted`.
+    async fn download_part(&self, url: &str, part: &Path, max_size: Option<u64>) -> Result<PartOutcome> {
+        let offset = fs::metadata(part).map(|metadata| metadata.len()).unwrap_or(0);
+
+        let (_, url, mut response) = self
+            .send_with_failover(self.tarball_urls(url), |url| {
+                if offset > 0 {
+                    info!("Resuming tarball download from {} at byte {}", url, offset);
+                    self.client.get(url).header(RANGE, format!("bytes={}-", offset))
+                } else {
+                    info!("Downloading tarball from {}", url);
+                    self.client.get(url)
+                }
+            })
+            .await?;
+
+        let status = response.status();
+        let start = if status == StatusCode::PARTIAL_CONTENT { offset } else { 0 };
+        if let Err(e) = check_tarball_size(&url, start + response.content_length().unwrap_or(0), max_size) {
+            remove_part(part)?;
+            return Err(e);
+        }
+        let mut file = match status {
+            StatusCode::PARTIAL_CONTENT if offset > 0 => OpenOptions::new().append(true).open(part)?,
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                fs::remove_file(part)?;
+                return Ok(PartOutcome::Interrupted(anyhow::anyhow!(
+                    "Server rejected resuming {} at byte {}",
+                    url,
+                    offset
+                )));
+            }
+            status if status.is_success() => {
+                if offset > 0 {
+                    debug!("Server ignored the range request for {}, starting over", url);
+                }
+                fs::File::create(part)?
+            }
+            status => anyhow::bail!("Failed to download tarball {}: HTTP {}", url, status),
+        };
+        let resumable = status == StatusCode::PARTIAL_CONTENT
+            || response
+                .headers()
+                .get(ACCEPT_RANGES)
+                .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"bytes"));
+
+        let mut size = start;
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    file.write_all(&chunk)?;
+                    size += chunk.len() as u64;
+                    if let Err(e) = check_tarball_size(&url, size, max_size) {
+                        drop(file);
+                        remove_part(part)?;
+                        return Err(e);
+                    }
+                }
+                Ok(None) => return Ok(PartOutcome::Complete),
+                Err(e) => {
+                    if !resumable {
+                        drop(file);
+                        fs::remove_file(part)?;
+                    }
+                    return Ok(PartOutcome::Interrupted(self.map_request_error(e, &url)));
+                }
+            }
+        }
+    }
+
+    /// Get the `(registry, url)` candidates for a tarball URL
+    ///
+    /// Tarballs hosted on the primary registry can also be fetched from the
+    /// mirrors under the same path, unless the path names a scope mapped to
+    /// another registry: those are fetched from the scope's registry instead.
+    fn tarball_urls(&self, url: &str) -> Vec<(String, String)> {
+        let primary = self.config.url.trim_end_matches('/');
+        let Some(path) = url.strip_prefix(primary) else {
+            return vec![(primary.to_string(), url.to_string())];
+        };
+        if let Some(scoped) = self.scoped_registry(path.trim_start_matches('/')) {
+            return vec![(scoped.to_string(), format!("{}{}", scoped, path))];
+        }
+        self.registry_urls()
+            .map(|base| (base.to_string(), format!("{}{}", base, path)))
+            .collect()
+    }
+
+    /// Send a request to each candidate URL in turn until one responds
+    ///
+    /// Connection errors, timeouts and 5xx responses move on to the next
+    /// candidate, spending a retry from the budget; any other response is
+    /// returned as-is. Retries spent on a request that a later candidate
+    /// answered go back into the budget, so a primary that is down for good
+    /// doesn't use it up while its mirrors keep serving.
+    ///
+    /// # Arguments
+    /// * `urls` - `(registry, url)` pairs to try in order
+    /// * `build` - Builds the request for a URL
+    ///
+    /// # Returns
+    /// * `Ok((registry, url, response))` for the first candidate that answered
+    /// * `Err(anyhow::Error)` with the last failure if every candidate failed,
+    ///   or the first one once the retry budget is exhausted
+    async fn send_with_failover(
+        &self,
+        urls: Vec<(String, String)>,
+        build: impl Fn(&str) -> RequestBuilder,
+    ) -> Result<(String, String, Response)> {
+        let mut urls = urls.into_iter().peekable();
+        let mut spent = 0;
+        while let Some((registry_url, url)) = urls.next() {
+            let error = match build(&url).send().await {
+                Ok(response) if response.status().is_server_error() => {
+                    anyhow::anyhow!("Request to {} failed: HTTP {}", url, response.status())
+                }
+                Ok(response) => {
+                    self.retries_left.fetch_add(spent, Ordering::SeqCst);
+                    return Ok((registry_url, url, response));
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => self.map_request_error(e, &url),
+                Err(e) => return Err(self.map_request_error(e, &url)),
+            };
+
+            warn!("Registry {} failed: {}", registry_url, error);
+            if urls.peek().is_none() {
+                return Err(error);
+            }
+            self.spend_retry(error)?;
+            spent += 1;
+        }
+
+        Err(anyhow::anyhow!("No registry configured"))
+    }
+
+    /// Read a metadata response body, abandoning it past `max_metadata_size`
+    ///
+    /// An announced `Content-Length` over the limit fails before anything is
+    /// read; otherwise the body is streamed and cut off once it grows too big,
+    /// so a hostile registry can't exhaust memory.
+    async fn read_metadata_body(&self, mut response: Response, url: &str) -> Result<Vec<u8>> {
+        let limit = self.config.max_metadata_size;
+        let check = |size: u64| match limit {
+            Some(limit) if size > limit => Err(CoreError::MetadataTooLarge {
+                url: url.to_string(),
+                limit,
+            }),
+            _ => Ok(()),
+        };
+
+        check(response.content_length().unwrap_or(0))?;
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| self.map_request_error(e, url))? {
+            body.extend_from_slice(&chunk);
+            check(body.len() as u64)?;
+        }
+        Ok(body)
+    }
+
+    /// Turn a request failure into a descriptive error
+    fn map_request_error(&self, error: reqwest::Error, url: &str) -> anyhow::Error {
+        if error.is_timeout() {
+            let timeout = if error.is_connect() {
+                self.config.connect_timeout
+            } else {
+                self.config.request_timeout
+            };
+            CoreError::Timeout {
+                url: url.to_string(),
+                timeout,
+            }
+            .into()
+        } else {
+            error.into()
+        }
+    }
+
+    /// Remove all cached metadata, in memory and on disk
+    ///
+    /// # Returns
+    /// * `Ok(u64)` with the number of bytes reclaimed on disk
+    pub fn clear_metadata_cache(&self) -> Result<u64> {
+        self.metadata_cache.lock().unwrap().clear();
+
+        match &self.config.cache_dir {
+            Some(dir) => remove_dir_contents(&dir.join("metadata")),
+            None => Ok(0),
+        }
+    }
+
+    /// Look up cached metadata in memory, falling back to the on-disk cache
+    fn cached_metadata(&self, name: &str) -> Option<CachedMetadata> {
+        if let Some(cached) = self.metadata_cache.lock().unwrap().get(name) {
+            return Some(cached.clone());
+        }
+
+        let path = self.metadata_cache_path(name)?;
+        let content = fs::read_to_string(path).ok()?;
+        let cached: CachedMetadata = serde_json::from_str(&content).ok()?;
+        self.metadata_cache
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), cached.clone());
+        Some(cached)
+    }
+
+    /// Record metadata in memory and, when configured, on disk
+    fn store_metadata(&self, name: &str, cached: CachedMetadata) {
+        if cached.etag.is_some() || cached.last_modified.is_some() {
+            if let Some(path) = self.metadata_cache_path(name) {
+                if let Err(e) = write_cache_file(&path, &cached) {
+                    warn!("Failed to cache metadata for {}: {}", name, e);
+                }
+            }
+        }
+
+        self.metadata_cache.lock().unwrap().insert(name.to_string(), cached);
+    }
+
+    /// Path of the on-disk cache entry for a package
+    fn metadata_cache_path(&self, name: &str) -> Option<PathBuf> {
+        self.config
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join("metadata").join(format!("{}.json", encode_package_name(name))))
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fail with `CoreError::TarballTooLarge` once a tarball grows past `max_size` bytes
+pub(crate) fn check_tarball_size(url: &str, size: u64, max_size: Option<u64>) -> Result<()> {
+    match max_size {
+        Some(limit) if size > limit => Err(CoreError::TarballTooLarge {
+            url: url.to_string(),
+            limit,
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Remove a partial download, if there is one
+fn remove_part(part: &Path) -> Result<()> {
+    match fs::remove_file(part) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Write a metadata cache entry to disk
+fn write_cache_file(path: &Path, cached: &CachedMetadata) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create metadata cache directory")?;
+    }
+    fs::write(path, serde_json::to_vec(cached)?)?;
+    Ok(())
+}
+
+/// Encode a package name for use in a registry URL or file name
+///
+/// Scoped names keep their `@` but have the `/` escaped, as npm does.
+fn encode_package_name(name: &str) -> String {
+    name.replace('/', "%2f")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn metadata_body() -> serde_json::Value {
+        serde_json::json!({
+            "name": "cached-pkg",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "1.0.0": {
+                    "name": "cached-pkg",
+                    "version": "1.0.0",
+                    "dist": {
+                        "tarball": "https://registry.npmjs.org/cached-pkg/-/cached-pkg-1.0.0.tgz",
+                        "shasum": "0000000000000000000000000000000000000000"
+                    }
+                }
+            }
+        })
+    }
+
+    fn config(server: &MockServer, cache_dir: Option<PathBuf>) -> RegistryConfig {
+        RegistryConfig {
+            url: server.uri(),
+            cache_dir,
+            ..Default::default()
+        }
+    }
+
+    async fn mount_etag_mocks(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_json(metadata_body()),
+            )
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_uses_cache_on_not_modified() {
+        let server = MockServer::start().await;
+        mount_etag_mocks(&server).await;
+
+        let registry = Registry::with_config(config(&server, None));
+        let first = registry.fetch_metadata("cached-pkg").await.unwrap();
+        let second = registry.fetch_metadata("cached-pkg").await.unwrap();
+
+        assert_eq!(first.name, "cached-pkg");
+        assert_eq!(second.name, "cached-pkg");
+        assert!(second.versions.contains_key("1.0.0"));
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_fetches_share_one_request() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(metadata_body())
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let registry = Registry::with_config(config(&server, None));
+        let fetches = (0..8).map(|_| registry.fetch_metadata("cached-pkg"));
+        let results = futures::future::join_all(fetches).await;
+
+        for result in results {
+            assert_eq!(result.unwrap().name, "cached-pkg");
+        }
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_persists_etag_on_disk() {
+        let server = MockServer::start().await;
+        mount_etag_mocks(&server).await;
+        let cache_dir = TempDir::new().unwrap();
+
+        Registry::with_config(config(&server, Some(cache_dir.path().to_path_buf())))
+            .fetch_metadata("cached-pkg")
+            .await
+            .unwrap();
+
+        // A fresh client revalidates using the ETag persisted by the first one
+        let metadata = Registry::with_config(config(&server, Some(cache_dir.path().to_path_buf())))
+            .fetch_metadata("cached-pkg")
+            .await
+            .unwrap();
+        assert_eq!(metadata.dist_tags.get("latest"), Some(&"1.0.0".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_offline_serves_cached_metadata_without_requests() {
+        let server = MockServer::start().await;
+        mount_etag_mocks(&server).await;
+        let cache_dir = TempDir::new().unwrap();
+        Registry::with_config(config(&server, Some(cache_dir.path().to_path_buf())))
+            .fetch_metadata("cached-pkg")
+            .await
+            .unwrap();
+
+        let offline = Registry::with_config(RegistryConfig {
+            offline: true,
+            ..config(&server, Some(cache_dir.path().to_path_buf()))
+        });
+        let metadata = offline.fetch_metadata("cached-pkg").await.unwrap();
+        assert_eq!(metadata.name, "cached-pkg");
+        let filtered = offline.fetch_metadata_filtered("cached-pkg", &VersionFilter::range("^1.0.0")).await;
+        assert!(filtered.unwrap().versions.contains_key("1.0.0"));
+
+        let requires_network = |err: anyhow::Error| matches!(err.downcast_ref(), Some(CoreError::RequiresNetwork(_)));
+        assert!(requires_network(offline.fetch_metadata("uncached-pkg").await.unwrap_err()));
+        assert!(requires_network(offline.search("cached", 10).await.unwrap_err()));
+        assert!(requires_network(offline.ping().await.unwrap_err()));
+        let tarball = format!("{}/cached-pkg/-/cached-pkg-1.0.0.tgz", server.uri());
+        assert!(requires_network(offline.download_tarball(&tarball, None).await.unwrap_err()));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_filtered() {
+        let server = MockServer::start().await;
+        let mut body = metadata_body();
+        let mut second = body["versions"]["1.0.0"].clone();
+        second["version"] = serde_json::json!("2.0.0");
+        body["versions"]["2.0.0"] = second;
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&server)
+            .await;
+
+        let registry = Registry::with_config(config(&server, None));
+        let metadata = registry
+            .fetch_metadata_filtered("cached-pkg", &VersionFilter::range("^2.0.0"))
+            .await
+            .unwrap();
+        let mut versions: Vec<&String> = metadata.versions.keys().collect();
+        versions.sort();
+        assert_eq!(versions, vec!["1.0.0", "2.0.0"]);
+
+        let metadata = registry
+            .fetch_metadata_filtered("cached-pkg", &VersionFilter::range("^3.0.0"))
+            .await
+            .unwrap();
+        assert_eq!(metadata.versions.keys().collect::<Vec<_>>(), vec!["1.0.0"]);
+
+        // Partial metadata never lands in the cache
+        assert!(registry.cached_metadata("cached-pkg").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_http_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let registry = Registry::with_config(config(&server, None));
+        assert!(registry.fetch_metadata("cached-pkg").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_fails_over_to_mirror() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(metadata_body()))
+            .expect(1)
+            .mount(&mirror)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            registry_mirrors: vec![mirror.uri()],
+            ..config(&primary, None)
+        });
+
+        let metadata = registry.fetch_metadata("cached-pkg").await.unwrap();
+        assert_eq!(metadata.name, "cached-pkg");
+        assert_eq!(registry.served_by("cached-pkg"), Some(mirror.uri()));
+    }
+
+    #[tokio::test]
+    async fn test_ping_fails_over_to_mirror() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&primary)
+            .await;
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/-/ping"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&mirror)
+            .await;
+
+        let registry = Registry::with_config(config(&primary, None));
+        assert!(registry.ping().await.unwrap_err().to_string().contains("HTTP 503"));
+
+        let registry = Registry::with_config(RegistryConfig {
+            registry_mirrors: vec![mirror.uri()],
+            ..config(&primary, None)
+        });
+        assert_eq!(registry.ping().await.unwrap(), mirror.uri());
+    }
+
+    #[tokio::test]
+    async fn test_download_tarball_fails_over_to_mirror() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(502))
+            .mount(&primary)
+            .await;
+
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg/-/cached-pkg-1.0.0.tgz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"tarball".to_vec()))
+            .mount(&mirror)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            registry_mirrors: vec![mirror.uri()],
+            ..config(&primary, None)
+        });
+
+        let url = format!("{}/cached-pkg/-/cached-pkg-1.0.0.tgz", primary.uri());
+        assert_eq!(registry.download_tarball(&url, None).await.unwrap(), b"tarball");
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/-/v1/search"))
+            .and(query_param("text", "left pad"))
+            .and(query_param("size", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "objects": [
+                    {
+                        "package": {
+                            "name": "left-pad",
+                            "version": "1.3.0",
+                            "description": "String left pad",
+                            "links": { "npm": "https://www.npmjs.com/package/left-pad" }
+                        },
+                        "score": { "final": 0.92, "detail": { "quality": 0.8, "popularity": 0.6, "maintenance": 0.3 } },
+                        "searchScore": 100000.5
+                    },
+                    {
+                        "package": { "name": "pad-left", "version": "2.1.0" },
+                        "score": { "final": 0.41 }
+                    }
+                ],
+                "total": 2,
+                "time": "Sat Oct 17 2026 10:00:00 GMT+0000"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let results = Registry::with_config(config(&server, None)).search("left pad", 2).await.unwrap();
+        assert_eq!(
+            results,
+            vec![
+                SearchResult {
+                    name: "left-pad".to_string(),
+                    version: "1.3.0".to_string(),
+                    description: Some("String left pad".to_string()),
+                    score: 0.92,
+                },
+                SearchResult {
+                    name: "pad-left".to_string(),
+                    version: "2.1.0".to_string(),
+                    description: None,
+                    score: 0.41,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scoped_packages_use_their_registry() {
+        let default = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(metadata_body()))
+            .expect(1)
+            .mount(&default)
+            .await;
+
+        let internal = MockServer::start().await;
+        let mut scoped_body = metadata_body();
+        scoped_body["name"] = "@mycorp/cached-pkg".into();
+        Mock::given(method("GET"))
+            .and(path("/@mycorp%2fcached-pkg"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(scoped_body))
+            .expect(1)
+            .mount(&internal)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/@mycorp/cached-pkg/-/cached-pkg-1.0.0.tgz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"internal tarball".to_vec()))
+            .expect(1)
+            .mount(&internal)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            scoped_registries: HashMap::from([("@mycorp".to_string(), format!("{}/", internal.uri()))]),
+            ..config(&default, None)
+        });
+
+        let scoped = registry.fetch_metadata("@mycorp/cached-pkg").await.unwrap();
+        assert_eq!(scoped.name, "@mycorp/cached-pkg");
+        assert_eq!(registry.served_by("@mycorp/cached-pkg"), Some(internal.uri()));
+
+        // Unscoped packages and other scopes stay on the default registry
+        registry.fetch_metadata("cached-pkg").await.unwrap();
+        assert_eq!(registry.served_by("cached-pkg"), Some(default.uri()));
+        assert_eq!(registry.scoped_registry("@other/cached-pkg"), None);
+        assert_eq!(registry.scoped_registry("mycorp/cached-pkg"), None);
+
+        // A scoped tarball linked on the default registry is fetched from the scope's
+        let url = format!("{}/@mycorp/cached-pkg/-/cached-pkg-1.0.0.tgz", default.uri());
+        assert_eq!(registry.download_tarball(&url, None).await.unwrap(), b"internal tarball");
+    }
+
+    /// Serve `body` over two connections: the first breaks off halfway and
+    /// the second answers a `Range` request for the rest
+    ///
+    /// Returns the server URL and a handle resolving to the requests received.
+    async fn serve_interrupted(body: &'static [u8]) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0u8];
+                    socket.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap().to_ascii_lowercase();
+
+                let range_start = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .map(|range| range.trim_end_matches('-').parse::<usize>().unwrap());
+                let response = match range_start {
+                    None => {
+                        let head = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n\r\n",
+                            body.len()
+                        );
+                        [head.as_bytes(), &body[..body.len() / 2]].concat()
+                    }
+                    Some(start) => {
+                        let head = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                            body.len() - start,
+                            start,
+                            body.len() - 1,
+                            body.len()
+                        );
+                        [head.as_bytes(), &body[start..]].concat()
+                    }
+                };
+                socket.write_all(&response).await.unwrap();
+                socket.shutdown().await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_download_tarball_to_resumes_with_range() {
+        let body: &'static [u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let (url, server) = serve_interrupted(body).await;
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("downloads/pkg-1.0.0.tgz");
+
+        let registry = Registry::with_config(RegistryConfig {
+            url: url.clone(),
+            cache_dir: None,
+            proxy: Some(ProxyConfig::default()),
+            ..Default::default()
+        });
+        let bytes = registry
+            .download_tarball_to(&format!("{}/pkg/-/pkg-1.0.0.tgz", url), &dest, None, |bytes| {
+                anyhow::ensure!(bytes == body, "unexpected tarball contents");
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(bytes, body);
+        assert_eq!(fs::read(&dest).unwrap(), body);
+        assert!(!dir.path().join("downloads/pkg-1.0.0.tgz.part").exists());
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains(&format!("range: bytes={}-", body.len() / 2)));
+    }
+
+    #[tokio::test]
+    async fn test_download_tarball_to_discards_unverified_bytes() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"tampered".to_vec()))
+            .mount(&server)
+            .await;
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("pkg.tgz");
+
+        let registry = Registry::with_config(config(&server, None));
+        let url = format!("{}/pkg/-/pkg-1.0.0.tgz", server.uri());
+        let result = registry
+            .download_tarball_to(&url, &dest, None, |_| anyhow::bail!("integrity mismatch"))
+            .await;
+
+        assert!(result.is_err());
+        assert!(!dest.exists());
+        assert!(!dir.path().join("pkg.tgz.part").exists());
+    }
+
+    /// Stream zeros without announcing a length, until the client hangs up
+    async fn serve_endless_response() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0u8];
+                        if socket.read_exact(&mut byte).await.is_err() {
+                            return;
+                        }
+                        request.push(byte[0]);
+                    }
+                    let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n").await;
+                    let chunk = [0u8; 1024];
+                    while socket.write_all(&chunk).await.is_ok() {}
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_download_aborts_past_size_limit() {
+        let url = serve_endless_response().await;
+        let registry = Registry::with_config(RegistryConfig {
+            url: url.clone(),
+            cache_dir: None,
+            proxy: Some(ProxyConfig::default()),
+            ..Default::default()
+        });
+        let tarball = format!("{}/huge/-/huge-1.0.0.tgz", url);
+
+        let err = registry.download_tarball(&tarball, Some(64 * 1024)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::TarballTooLarge { limit: 65536, .. })));
+
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("huge.tgz");
+        let err = registry
+            .download_tarball_to(&tarball, &dest, Some(64 * 1024), |_| Ok(()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("larger than the limit of 65536 bytes"));
+        assert!(!dest.exists());
+        assert!(!dir.path().join("huge.tgz.part").exists());
+    }
+
+    #[tokio::test]
+    async fn test_metadata_aborts_past_size_limit() {
+        let server = MockServer::start().await;
+        let mut body = metadata_body();
+        body["description"] = serde_json::json!("x".repeat(4096));
+        Mock::given(method("GET"))
+            .and(path("/bloated"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&server)
+            .await;
+        let capped = |limit| {
+            Registry::with_config(RegistryConfig {
+                max_metadata_size: Some(limit),
+                ..config(&server, None)
+            })
+        };
+
+        let err = capped(1024).fetch_metadata("bloated").await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::MetadataTooLarge { limit: 1024, .. })));
+        assert!(capped(1024 * 1024).fetch_metadata("bloated").await.is_ok());
+
+        // Without a Content-Length the cap applies while streaming
+        let url = serve_endless_response().await;
+        let endless = Registry::with_config(RegistryConfig {
+            url,
+            cache_dir: None,
+            proxy: Some(ProxyConfig::default()),
+            max_metadata_size: Some(64 * 1024),
+            ..Default::default()
+        });
+        let err = endless.fetch_metadata("huge").await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::MetadataTooLarge { limit: 65536, .. })));
+        let filter = VersionFilter::default();
+        assert!(endless.fetch_metadata_filtered("huge", &filter).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_rejects_oversized_content_length() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 4096]))
+            .mount(&server)
+            .await;
+        let registry = Registry::with_config(config(&server, None));
+        let url = format!("{}/pkg/-/pkg-1.0.0.tgz", server.uri());
+
+        let err = registry.download_tarball(&url, Some(1024)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::TarballTooLarge { .. })));
+        assert_eq!(registry.download_tarball(&url, Some(4096)).await.unwrap().len(), 4096);
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_fails_fast_once_spent() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&primary)
+            .await;
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(502))
+            .mount(&mirror)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            registry_mirrors: vec![mirror.uri()],
+            retry_budget: 3,
+            ..config(&primary, None)
+        });
+
+        let mut results = Vec::new();
+        for i in 0..10 {
+            results.push(registry.fetch_metadata(&format!("pkg-{}", i)).await);
+        }
+
+        // The first failures fail over to the mirror, the rest fail at once
+        for result in &results[..3] {
+            assert!(result.as_ref().unwrap_err().to_string().contains("HTTP 502"));
+        }
+        for result in &results[3..] {
+            let err = result.as_ref().unwrap_err();
+            assert_eq!(err.to_string(), "Retry budget of 3 exhausted, not retrying");
+            assert!(format!("{:#}", err).contains("HTTP 503"));
+        }
+        assert_eq!(registry.retries_left(), 0);
+        assert_eq!(mirror.received_requests().await.unwrap().len(), 3);
+        assert_eq!(primary.received_requests().await.unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_successful_failover_keeps_retry_budget() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&primary)
+            .await;
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(metadata_body()))
+            .mount(&mirror)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            registry_mirrors: vec![mirror.uri()],
+            retry_budget: 3,
+            ..config(&primary, None)
+        });
+
+        // Far more packages than the budget, all served by the mirror
+        for i in 0..20 {
+            registry.fetch_metadata(&format!("pkg-{}", i)).await.unwrap();
+        }
+        assert_eq!(registry.retries_left(), 3);
+        assert_eq!(mirror.received_requests().await.unwrap().len(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_not_found_does_not_fail_over() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&primary)
+            .await;
+
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(metadata_body()))
+            .expect(0)
+            .mount(&mirror)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            registry_mirrors: vec![mirror.uri()],
+            ..config(&primary, None)
+        });
+
+        let err = registry.fetch_metadata("cached-pkg").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::PackageNotFound { name }) if name == "cached-pkg"
+        ));
+    }
+
+    #[test]
+    fn test_default_timeouts() {
+        let config = RegistryConfig::default();
+        assert_eq!(config.connect_timeout, Duration::from_secs(10));
+        assert_eq!(config.request_timeout, Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(metadata_body())
+                    .set_delay(Duration::from_secs(2)),
+            )
+            .mount(&server)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            request_timeout: Duration::from_millis(200),
+            ..config(&server, None)
+        });
+        let err = registry.fetch_metadata("cached-pkg").await.unwrap_err();
+
+        match err.downcast_ref::<CoreError>() {
+            Some(CoreError::Timeout { url, timeout }) => {
+                assert!(url.ends_with("/cached-pkg"));
+                assert_eq!(*timeout, Duration::from_millis(200));
+            }
+            other => panic!("Expected timeout error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_proxy_config_from_env() {
+        let env: HashMap<&str, &str> = [
+            ("HTTP_PROXY", "http://proxy.corp:3128"),
+            ("https_proxy", "http://secure-proxy.corp:3128"),
+            ("HTTPS_PROXY", "http://ignored.corp:3128"),
+            ("NO_PROXY", "localhost, .internal.corp,registry.local:4873"),
+        ]
+        .into_iter()
+        .collect();
+
+        let proxy = ProxyConfig::from_lookup(|key| env.get(key).map(|v| v.to_string()));
+        assert_eq!(proxy.http.as_deref(), Some("http://proxy.corp:3128"));
+        assert_eq!(proxy.https.as_deref(), Some("http://secure-proxy.corp:3128"));
+        assert_eq!(proxy.no_proxy, vec!["localhost", ".internal.corp", "registry.local:4873"]);
+    }
+
+    #[test]
+    fn test_proxy_for_respects_no_proxy() {
+        let proxy = ProxyConfig {
+            http: Some("http://proxy.corp:3128".to_string()),
+            https: Some("http://secure-proxy.corp:3128".to_string()),
+            no_proxy: vec![".internal.corp".to_string(), "registry.local:4873".to_string()],
+        };
+
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert_eq!(proxy.proxy_for(&url("https://registry.npmjs.org/lodash")), Some("http://secure-proxy.corp:3128"));
+        assert_eq!(proxy.proxy_for(&url("http://registry.npmjs.org/lodash")), Some("http://proxy.corp:3128"));
+        assert_eq!(proxy.proxy_for(&url("https://npm.internal.corp/lodash")), None);
+        assert_eq!(proxy.proxy_for(&url("https://internal.corp/lodash")), None);
+        assert_eq!(proxy.proxy_for(&url("http://registry.local:4873/lodash")), None);
+
+        let bypass_all = ProxyConfig {
+            no_proxy: vec!["*".to_string()],
+            ..proxy
+        };
+        assert_eq!(bypass_all.proxy_for(&url("https://registry.npmjs.org/lodash")), None);
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_sent_through_proxy() {
+        // The mock server stands in for the proxy; the registry host itself doesn't resolve
+        let proxy_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cached-pkg"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(metadata_body()))
+            .expect(1)
+            .mount(&proxy_server)
+            .await;
+
+        let registry = Registry::with_config(RegistryConfig {
+            url: "http://registry.invalid".to_string(),
+            cache_dir: None,
+            proxy: Some(ProxyConfig {
+                http: Some(proxy_server.uri()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let metadata = registry.fetch_metadata("cached-pkg").await.unwrap();
+        assert_eq!(metadata.name, "cached-pkg");
+    }
+
+    #[tokio::test]
+    async fn test_clear_metadata_cache() {
+        let server = MockServer::start().await;
+        mount_etag_mocks(&server).await;
+        let cache_dir = TempDir::new().unwrap();
+
+        let registry = Registry::with_config(config(&server, Some(cache_dir.path().to_path_buf())));
+        registry.fetch_metadata("cached-pkg").await.unwrap();
+
+        assert!(registry.clear_metadata_cache().unwrap() > 0);
+        assert!(registry.cached_metadata("cached-pkg").is_none());
+        assert_eq!(registry.clear_metadata_cache().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_encode_package_name() {
+        assert_eq!(encode_package_name("lodash"), "lodash");
+        assert_eq!(encode_package_name("@types/node"), "@types%2fnode");
+    }
+}
@@ -0,0 +1,125 @@
+//! Install timing reports
+//!
+//! An install spends its time resolving the tree, then downloading,
+//! verifying and extracting every package. Downloads run concurrently, so
+//! the per-package phases are aggregated: their totals add up the time of
+//! each package and can exceed the wall-clock duration of the install.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Phase of an install
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstallPhase {
+    Resolution,
+    Download,
+    Verification,
+    Extraction,
+}
+
+impl InstallPhase {
+    /// Every phase, in the order an install goes through them
+    pub const ALL: [InstallPhase; 4] = [
+        InstallPhase::Resolution,
+        InstallPhase::Download,
+        InstallPhase::Verification,
+        InstallPhase::Extraction,
+    ];
+}
+
+impl fmt::Display for InstallPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InstallPhase::Resolution => "resolution",
+            InstallPhase::Download => "download",
+            InstallPhase::Verification => "verification",
+            InstallPhase::Extraction => "extraction",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Running totals for one install phase
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTiming {
+    /// Number of times the phase ran
+    pub count: u64,
+    /// Summed duration of every run
+    pub total: Duration,
+}
+
+/// Where the time of an install went
+#[derive(Debug, Clone, Default)]
+pub struct InstallReport {
+    phases: HashMap<InstallPhase, PhaseTiming>,
+}
+
+impl InstallReport {
+    /// Create an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one run of a phase
+    pub fn record(&mut self, phase: InstallPhase, duration: Duration) {
+        let timing = self.phases.entry(phase).or_default();
+        timing.count += 1;
+        timing.total += duration;
+    }
+
+    /// Record the time elapsed since `start` as one run of a phase
+    pub fn record_since(&mut self, phase: InstallPhase, start: Instant) {
+        self.record(phase, start.elapsed());
+    }
+
+    /// Get the running totals of a phase
+    pub fn phase(&self, phase: InstallPhase) -> PhaseTiming {
+        self.phases.get(&phase).copied().unwrap_or_default()
+    }
+}
+
+/// One line per phase that ran, in install order
+impl fmt::Display for InstallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for phase in InstallPhase::ALL {
+            let timing = self.phase(phase);
+            if timing.count == 0 {
+                continue;
+            }
+            write!(f, "{:<14}{:>12.1?}", phase.to_string(), timing.total)?;
+            if timing.count > 1 {
+                write!(f, " ({} runs)", timing.count)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_report_aggregates_phases() {
+        let mut report = InstallReport::new();
+        report.record(InstallPhase::Resolution, Duration::from_millis(40));
+        report.record(InstallPhase::Download, Duration::from_millis(10));
+        report.record(InstallPhase::Download, Duration::from_millis(15));
+        report.record(InstallPhase::Download, Duration::from_millis(5));
+
+        assert_eq!(
+            report.phase(InstallPhase::Download),
+            PhaseTiming {
+                count: 3,
+                total: Duration::from_millis(30),
+            }
+        );
+        assert_eq!(report.phase(InstallPhase::Extraction), PhaseTiming::default());
+        assert_eq!(
+            report.to_string(),
+            "resolution          40.0ms\ndownload            30.0ms (3 runs)\n"
+        );
+    }
+}
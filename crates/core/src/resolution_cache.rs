@@ -0,0 +1,192 @@
+//! Reuse of the previous install's resolution
+//!
+//! Resolving a tree fetches the metadata of every package in it, which
+//! dominates the time of an install that has nothing new to do. After an
+//! install, the resolved tree is saved in `node_modules` together with a
+//! key hashing everything the resolution depended on: `package.json`, the
+//! lockfile, the requested specs, the resolution options, the registries
+//! packages come from and the cache format. The next
+//! install with the same key reuses the tree without contacting the
+//! registry; any change to the manifest or lockfile changes the key.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+use crate::manifest::MANIFEST_FILE;
+use crate::resolver::ResolvedTree;
+use crate::{InstallOptions, PackageInfo};
+
+/// Name of the cached resolution inside `node_modules`
+pub const RESOLUTION_CACHE_FILE: &str = ".package-fast-resolution.json";
+
+/// Version of the cached resolution format; bump it when `ResolvedTree` changes
+const RESOLUTION_CACHE_VERSION: u32 = 1;
+
+/// A resolved tree and the key it was resolved under
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResolution {
+    key: String,
+    tree: ResolvedTree,
+}
+
+/// Compute the key identifying the inputs of a project's resolution
+///
+/// # Arguments
+/// * `project_dir` - Directory holding `package.json`
+/// * `specs` - Install specs requested
+/// * `local_packages` - Workspace members and other packages resolved without the registry
+/// * `options` - Installation options; the lockfile they carry is part of the key
+/// * `registry_source` - Where packages are fetched from, see `PackageRegistry::source_id`
+///
+/// # Returns
+/// * `Ok(Some(String))` holding a hex SHA-256 key
+/// * `Ok(None)` if the project has no `package.json`, so nothing is cached
+/// * `Err(anyhow::Error)` if the manifest can't be read
+pub fn resolution_key(
+    project_dir: &Path,
+    specs: &[String],
+    local_packages: &[PackageInfo],
+    options: &InstallOptions,
+    registry_source: &str,
+) -> Result<Option<String>> {
+    let manifest_path = project_dir.join(MANIFEST_FILE);
+    if !manifest_path.is_file() {
+        return Ok(None);
+    }
+    let manifest = fs::read(&manifest_path).with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+
+    // HashMaps iterate in a different order in every process, so everything
+    // hashed goes through a BTreeMap first
+    let local: Vec<_> = local_packages
+        .iter()
+        .map(|pkg| {
+            serde_json::json!({
+                "name": pkg.name,
+                "version": pkg.version,
+                "dependencies": sorted(&pkg.dependencies),
+                "devDependencies": sorted(&pkg.dev_dependencies),
+                "optionalDependencies": sorted(&pkg.optional_dependencies),
+                "peerDependencies": sorted(&pkg.peer_dependencies),
+            })
+        })
+        .collect();
+    let inputs = serde_json::json!({
+        "cacheVersion": RESOLUTION_CACHE_VERSION,
+        "registry": registry_source,
+        "specs": specs,
+        "local": local,
+        "lockfile": options.lockfile,
+        "devOnly": options.dev_only,
+        "prodOnly": options.prod_only,
+        "targetNode": options.target_node_version,
+        "targetOs": options.target_os,
+        "targetCpu": options.target_cpu,
+        "conflictStrategy": format!("{:?}", options.conflict_strategy),
+        "frozen": options.frozen,
+        "continueOnError": options.continue_on_error,
+        "maxDepth": options.max_depth,
+        "overrides": sorted(&options.overrides),
+        "minVersionAgeDays": options.min_version_age_days,
+        "includePrereleases": options.include_prereleases,
+    });
+
+    let mut hasher = Sha256::new();
+    hasher.update(&manifest);
+    hasher.update([0]);
+    hasher.update(inputs.to_string());
+    let key = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(Some(key))
+}
+
+/// Load the tree cached in `node_modules` if it was resolved under `key`
+///
+/// A missing, unreadable or stale cache is treated the same: `None`.
+pub fn load_cached_tree(node_modules: &Path, key: &str) -> Option<ResolvedTree> {
+    let path = node_modules.join(RESOLUTION_CACHE_FILE);
+    let content = fs::read(&path).ok()?;
+    match serde_json::from_slice::<CachedResolution>(&content) {
+        Ok(cached) if cached.key == key => Some(cached.tree),
+        Ok(_) => {
+            debug!("Cached resolution in {} is out of date", path.display());
+            None
+        }
+        Err(e) => {
+            debug!("Ignoring unreadable cached resolution {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Save a resolved tree in `node_modules` under `key`
+pub fn save_cached_tree(node_modules: &Path, key: &str, tree: &ResolvedTree) -> Result<()> {
+    fs::create_dir_all(node_modules)?;
+    let cached = CachedResolution {
+        key: key.to_string(),
+        tree: tree.clone(),
+    };
+    fs::write(node_modules.join(RESOLUTION_CACHE_FILE), serde_json::to_vec(&cached)?)?;
+    Ok(())
+}
+
+fn sorted(map: &HashMap<String, String>) -> BTreeMap<&String, &String> {
+    map.iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::RegistryConfig;
+    use crate::{HttpRegistry, PackageRegistry};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolution_key_changes_with_inputs() {
+        let project = TempDir::new().unwrap();
+        let options = InstallOptions::default();
+        let specs = vec!["util@^1.0.0".to_string()];
+        let key = |options: &InstallOptions| resolution_key(project.path(), &specs, &[], options, "").unwrap();
+
+        assert_eq!(key(&options), None, "no manifest, no cache");
+
+        fs::write(project.path().join(MANIFEST_FILE), r#"{ "name": "app", "version": "1.0.0" }"#).unwrap();
+        let first = key(&options).unwrap();
+        assert_eq!(key(&options).unwrap(), first);
+
+        let mut locked = options.clone();
+        let mut lockfile = crate::lockfile::Lockfile::new();
+        lockfile.insert(crate::lockfile::LockedPackage::new("util", "1.0.0"));
+        locked.lockfile = Some(lockfile);
+        assert_ne!(key(&locked).unwrap(), first);
+
+        let mirrored = HttpRegistry::with_config(RegistryConfig {
+            registry_mirrors: vec!["https://mirror.example.com".to_string()],
+            ..Default::default()
+        });
+        let source = |registry: &HttpRegistry| {
+            resolution_key(project.path(), &specs, &[], &options, &registry.source_id()).unwrap().unwrap()
+        };
+        assert_ne!(source(&HttpRegistry::new()), first);
+        assert_ne!(source(&mirrored), source(&HttpRegistry::new()));
+
+        fs::write(project.path().join(MANIFEST_FILE), r#"{ "name": "app", "version": "1.0.1" }"#).unwrap();
+        assert_ne!(key(&options).unwrap(), first);
+    }
+
+    #[test]
+    fn test_cached_tree_round_trip() {
+        let node_modules = TempDir::new().unwrap();
+        let mut tree = ResolvedTree::default();
+        tree.packages.push(PackageInfo::new("util", "1.0.0"));
+
+        assert!(load_cached_tree(node_modules.path(), "abc").is_none());
+        save_cached_tree(node_modules.path(), "abc", &tree).unwrap();
+        let cached = load_cached_tree(node_modules.path(), "abc").unwrap();
+        assert_eq!(cached.packages[0].name, "util");
+        assert!(load_cached_tree(node_modules.path(), "def").is_none());
+    }
+}
@@ -0,0 +1,1623 @@
+//! Version resolution
+//!
+//! This module selects which published version of a package satisfies a
+//! requested range, taking the target runtime into account.
+
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::future::Future;
+use tracing::{debug, field, info, info_span, warn, Instrument};
+
+use crate::dedup::dedup_with_roots;
+use crate::semver::{parse_loose_version, parse_range, parse_version, Range, Version};
+use crate::{CoreError, InstallOptions, PackageDistribution, PackageInfo, PackageMetadata, PackageVersion};
+
+/// Default limit on dependency chain length, far beyond real-world trees
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// An optional dependency that was skipped because it could not be installed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedDependency {
+    pub name: String,
+    pub range: String,
+    /// Package that declared the optional dependency
+    pub required_by: String,
+    pub reason: String,
+}
+
+/// A spec that could not be installed, collected when `continue_on_error` is set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedSpec {
+    /// The spec as requested, e.g. `name@range`
+    pub spec: String,
+    /// Package that declared the dependency, or `None` for a requested spec
+    pub required_by: Option<String>,
+    pub reason: String,
+}
+
+impl fmt::Display for FailedSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.required_by {
+            Some(parent) => write!(f, "{} (required by {}): {}", self.spec, parent, self.reason),
+            None => write!(f, "{}: {}", self.spec, self.reason),
+        }
+    }
+}
+
+/// What to do when a package is required at incompatible version ranges
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Install an additional, nested copy satisfying the conflicting range
+    #[default]
+    Nested,
+    /// Fail the resolution with a `CoreError::VersionConflict`
+    Error,
+}
+
+/// A range requested for a package, and who requested it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictingRequest {
+    pub range: String,
+    /// Package that declared the dependency, or `(root)` for a requested package
+    pub required_by: String,
+}
+
+/// A package required at ranges no single version satisfies
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionConflict {
+    pub name: String,
+    pub requests: Vec<ConflictingRequest>,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let requests: Vec<String> = self
+            .requests
+            .iter()
+            .map(|request| format!("{} (from {})", request.range, request.required_by))
+            .collect();
+        write!(f, "{} is required at incompatible ranges: {}", self.name, requests.join(", "))
+    }
+}
+
+/// All version conflicts found while resolving a tree
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictReport {
+    pub conflicts: Vec<VersionConflict>,
+}
+
+impl fmt::Display for ConflictReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Found {} version conflict(s)", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            write!(f, "\n  {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of walking the dependency tree
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResolvedTree {
+    /// Resolved packages, roots first, in breadth-first order
+    pub packages: Vec<PackageInfo>,
+    pub warnings: Vec<String>,
+    pub skipped_optional: Vec<SkippedDependency>,
+    /// Required dependencies that failed to resolve (only with `continue_on_error`)
+    pub failed: Vec<FailedSpec>,
+    /// Number of duplicate package versions, and packages only they depended on, removed by deduplication
+    pub deduplicated: usize,
+    /// Packages required at incompatible ranges and installed nested
+    pub conflicts: Vec<VersionConflict>,
+    /// Registry distribution info of resolved packages, keyed by `name@version`
+    pub distributions: HashMap<String, PackageDistribution>,
+    /// Number of packages requested directly (roots and local packages)
+    pub direct_count: usize,
+    /// Number of packages pulled in only as dependencies of other packages
+    pub transitive_count: usize,
+    /// Number of dependency requests satisfied by an already resolved version
+    pub reused: usize,
+}
+
+/// A dependency waiting to be resolved
+struct PendingDependency {
+    name: String,
+    range: Option<String>,
+    required_by: Option<String>,
+    optional: bool,
+    /// Position in the dependency chain; requested packages are at depth 1
+    depth: usize,
+}
+
+/// Resolve root requests and their transitive dependencies
+///
+/// Metadata is obtained through `fetch`, which is called at most once per
+/// package name. Failures resolving a required dependency abort the walk,
+/// while failures on optional dependencies are recorded as skipped. When a
+/// package is required at a range its resolved version doesn't satisfy, the
+/// conflict is handled according to `options.conflict_strategy`. Packages
+/// in `options.overrides` are resolved at their forced version or range,
+/// wherever they are required, and fail the walk if it doesn't exist.
+/// The `devDependencies` of resolved packages are never followed: only the
+/// project's own dev dependencies are installed, and only when the caller
+/// passes them in `roots` (see `workspace::selected_dependencies`).
+///
+/// # Arguments
+/// * `roots` - Package names and optional requested ranges to resolve
+/// * `options` - Installation options
+/// * `fetch` - Function retrieving registry metadata for a package name
+pub async fn resolve_tree<F, Fut>(
+    roots: &[(String, Option<String>)],
+    options: &InstallOptions,
+    fetch: F,
+) -> Result<ResolvedTree>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<PackageMetadata>>,
+{
+    resolve_tree_from(Vec::new(), roots, options, fetch).await
+}
+
+/// Resolve a tree that also contains packages obtained outside the registry
+///
+/// `local_packages` (e.g. git or path dependencies) are taken as already
+/// resolved: they are placed first in the tree and their dependencies are
+/// resolved from the registry along with `roots`.
+pub async fn resolve_tree_from<F, Fut>(
+    local_packages: Vec<PackageInfo>,
+    roots: &[(String, Option<String>)],
+    options: &InstallOptions,
+    fetch: F,
+) -> Result<ResolvedTree>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<PackageMetadata>>,
+{
+    let mut tree = ResolvedTree::default();
+    let mut metadata_cache: HashMap<String, PackageMetadata> = HashMap::new();
+    let mut resolved: HashMap<String, Vec<String>> = HashMap::new();
+    let mut requests: HashMap<String, Vec<ConflictingRequest>> = HashMap::new();
+    let mut conflicts: Vec<VersionConflict> = Vec::new();
+    let mut direct: HashSet<String> = HashSet::new();
+
+    let mut queue: VecDeque<PendingDependency> = roots
+        .iter()
+        .map(|(name, range)| PendingDependency {
+            name: name.clone(),
+            range: range.clone(),
+            required_by: None,
+            optional: false,
+            depth: 1,
+        })
+        .collect();
+
+    for pkg_info in local_packages {
+        direct.insert(format!("{}@{}", pkg_info.name, pkg_info.version));
+        resolved.entry(pkg_info.name.clone()).or_default().push(pkg_info.version.clone());
+        enqueue_dependencies(&pkg_info, 1, &mut queue);
+        tree.packages.push(pkg_info);
+    }
+
+    while let Some(mut pending) = queue.pop_front() {
+        if let Some(forced) = options.overrides.get(&pending.name) {
+            if pending.range.as_ref() != Some(forced) {
+                debug!(
+                    "Overriding {}@{} with {}",
+                    pending.name,
+                    pending.range.as_deref().unwrap_or("*"),
+                    forced
+                );
+            }
+            pending.range = Some(forced.clone());
+        }
+
+        let request = ConflictingRequest {
+            range: pending.range.clone().unwrap_or_else(|| "*".to_string()),
+            required_by: pending.required_by.clone().unwrap_or_else(|| "(root)".to_string()),
+        };
+        let name_requests = requests.entry(pending.name.clone()).or_default();
+        if !name_requests.contains(&request) {
+            name_requests.push(request);
+        }
+
+        if let Some(versions) = resolved.get(&pending.name) {
+            if versions
+                .iter()
+                .any(|v| range_allows(pending.range.as_deref(), v, options.include_prereleases))
+            {
+                tree.reused += 1;
+                continue;
+            }
+
+            // A single registry pick can give way to a version every request so far accepts; the
+            // earlier pick is left for deduplication to drop, with the dependencies only it needed
+            let from_registry = |v: &String| tree.distributions.contains_key(&format!("{}@{}", pending.name, v));
+            let reselected = match versions.as_slice() {
+                [current] if !options.frozen && from_registry(current) => {
+                    let ranges: Vec<&str> = requests[&pending.name].iter().map(|r| r.range.as_str()).collect();
+                    metadata_cache
+                        .get(&pending.name)
+                        .and_then(|metadata| select_for_all_ranges(metadata, &ranges, options))
+                        .map(|version| (current.clone(), version))
+                }
+                _ => None,
+            };
+            if let Some((current, version)) = reselected {
+                let old_key = format!("{}@{}", pending.name, current);
+                let pkg_info = PackageInfo::from_version(version);
+                let key = format!("{}@{}", pkg_info.name, pkg_info.version);
+                debug!("Selecting {} over {} to satisfy every request for {}", key, old_key, pending.name);
+
+                tree.distributions.insert(key.clone(), version.dist.clone());
+                if direct.contains(&old_key) {
+                    direct.insert(key);
+                }
+                resolved.entry(pending.name.clone()).or_default().push(pkg_info.version.clone());
+                enqueue_dependencies(&pkg_info, pending.depth, &mut queue);
+                tree.packages.push(pkg_info);
+                continue;
+            }
+
+            let conflict = VersionConflict {
+                name: pending.name.clone(),
+                requests: requests[&pending.name].clone(),
+            };
+            if options.conflict_strategy == ConflictStrategy::Error {
+                // Keep walking so the report lists every conflict
+                conflicts.retain(|c| c.name != conflict.name);
+                conflicts.push(conflict);
+                continue;
+            }
+
+            let warning = format!("{}; installing a nested copy", conflict);
+            warn!("{}", warning);
+            tree.warnings.push(warning);
+            conflicts.retain(|c| c.name != conflict.name);
+            conflicts.push(conflict);
+        }
+
+        if let Some(max_depth) = options.max_depth.filter(|max| pending.depth > *max) {
+            return Err(CoreError::DependencyTooDeep {
+                name: pending.name.clone(),
+                required_by: pending.required_by.clone().unwrap_or_else(|| "(root)".to_string()),
+                max_depth,
+            }
+            .into());
+        }
+
+        info!("Processing package: {} {:?}", pending.name, pending.range);
+
+        let span = info_span!(
+            "package",
+            package = %pending.name,
+            range = pending.range.as_deref().unwrap_or("*"),
+            version = field::Empty,
+            phase = "resolve"
+        );
+        let outcome = resolve_pending(&pending, options, &mut metadata_cache, &fetch)
+            .instrument(span.clone())
+            .await;
+        if let Ok((pkg_info, _, _)) = &outcome {
+            span.record("version", pkg_info.version.as_str());
+        }
+        let (pkg_info, dist, warnings) = match outcome {
+            Ok(resolution) => resolution,
+            Err(e) if matches!(e.downcast_ref(), Some(CoreError::InvalidOverride { .. })) => return Err(e),
+            Err(e) if pending.optional => {
+                let skipped = SkippedDependency {
+                    name: pending.name.clone(),
+                    range: pending.range.clone().unwrap_or_else(|| "*".to_string()),
+                    required_by: pending.required_by.clone().unwrap_or_default(),
+                    reason: e.to_string(),
+                };
+                let warning = format!(
+                    "Skipping optional dependency {}@{} of {}: {}",
+                    skipped.name, skipped.range, skipped.required_by, skipped.reason
+                );
+                warn!("{}", warning);
+                tree.warnings.push(warning);
+                tree.skipped_optional.push(skipped);
+                continue;
+            }
+            Err(e) if options.continue_on_error => {
+                let failed = FailedSpec {
+                    spec: match &pending.range {
+                        Some(range) => format!("{}@{}", pending.name, range),
+                        None => pending.name.clone(),
+                    },
+                    required_by: pending.required_by.clone(),
+                    reason: format!("{:#}", e),
+                };
+                warn!("Failed to resolve {}", failed);
+                tree.failed.push(failed);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        tree.warnings.extend(warnings);
+        let key = format!("{}@{}", pkg_info.name, pkg_info.version);
+        if pending.required_by.is_none() {
+            direct.insert(key.clone());
+        }
+        tree.distributions.insert(key, dist);
+        resolved.entry(pending.name.clone()).or_default().push(pkg_info.version.clone());
+        enqueue_dependencies(&pkg_info, pending.depth, &mut queue);
+        tree.packages.push(pkg_info);
+    }
+
+    if options.conflict_strategy == ConflictStrategy::Error && !conflicts.is_empty() {
+        return Err(CoreError::VersionConflict(ConflictReport { conflicts }).into());
+    }
+    tree.conflicts = conflicts;
+
+    let before = tree.packages.len();
+    tree.packages = dedup_with_roots(tree.packages, roots, options.include_prereleases);
+    tree.deduplicated = before - tree.packages.len();
+    tree.direct_count = tree
+        .packages
+        .iter()
+        .filter(|p| direct.contains(&format!("{}@{}", p.name, p.version)))
+        .count();
+    tree.transitive_count = tree.packages.len() - tree.direct_count;
+
+    Ok(tree)
+}
+
+/// Check whether a requested range accepts an already resolved version
+///
+/// Requests that aren't semver ranges (such as dist-tags) can't be checked
+/// and are assumed to be satisfied.
+pub(crate) fn range_allows(range: Option<&str>, version: &str, include_prereleases: bool) -> bool {
+    let (Some(range), Some(version)) = (range, parse_loose_version(version)) else {
+        return true;
+    };
+    match parse_range(range) {
+        Ok(range) => range.matches_with(&version, include_prereleases),
+        Err(_) => true,
+    }
+}
+
+/// Select the newest installable version satisfying every one of `ranges`
+///
+/// Returns `None` when a range isn't a semver range (such as a dist-tag)
+/// or no compatible, old enough version satisfies them all.
+fn select_for_all_ranges<'a>(
+    metadata: &'a PackageMetadata,
+    ranges: &[&str],
+    options: &InstallOptions,
+) -> Option<&'a PackageVersion> {
+    let ranges: Vec<Range> = ranges.iter().map(|range| parse_range(range)).collect::<Result<_>>().ok()?;
+    metadata
+        .versions
+        .values()
+        .filter_map(|info| parse_version(&info.version).ok().map(|v| (v, info)))
+        .filter(|(v, _)| ranges.iter().all(|range| range.matches_with(v, options.include_prereleases)))
+        .filter(|(_, info)| is_compatible(info, options) && quarantine_reason(metadata, info, options).is_none())
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, info)| info)
+}
+
+/// Queue the dependencies and optional dependencies of a resolved package
+///
+/// Bundled dependencies ship inside the package's tarball and are skipped,
+/// as are dev dependencies, which are only needed to develop the package
+/// itself. Dependencies are queued one level below the package's `depth`.
+fn enqueue_dependencies(pkg_info: &PackageInfo, depth: usize, queue: &mut VecDeque<PendingDependency>) {
+    let parent = format!("{}@{}", pkg_info.name, pkg_info.version);
+    let mut dependencies: Vec<(&String, &String, bool)> = pkg_info
+        .dependencies
+        .iter()
+        .filter(|(name, _)| !pkg_info.optional_dependencies.contains_key(*name))
+        .map(|(name, range)| (name, range, false))
+        .chain(pkg_info.optional_dependencies.iter().map(|(name, range)| (name, range, true)))
+        .filter(|(name, _, _)| !pkg_info.is_bundled(name))
+        .collect();
+    dependencies.sort();
+
+    for (name, range, optional) in dependencies {
+        queue.push_back(PendingDependency {
+            name: name.clone(),
+            range: Some(range.clone()),
+            required_by: Some(parent.clone()),
+            optional,
+            depth: depth + 1,
+        });
+    }
+}
+
+/// Fetch (or reuse) metadata for a pending dependency and resolve it
+async fn resolve_pending<F, Fut>(
+    pending: &PendingDependency,
+    options: &InstallOptions,
+    metadata_cache: &mut HashMap<String, PackageMetadata>,
+    fetch: &F,
+) -> Result<(PackageInfo, PackageDistribution, Vec<String>)>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<PackageMetadata>>,
+{
+    if !metadata_cache.contains_key(&pending.name) {
+        let metadata = fetch(pending.name.clone()).await?;
+        metadata_cache.insert(pending.name.clone(), metadata);
+    }
+    let metadata = &metadata_cache[&pending.name];
+
+    // A locked version satisfying the request takes precedence over the newest match
+    let pinned = options
+        .lockfile
+        .as_ref()
+        .and_then(|lockfile| {
+            lockfile.pinned_version(&pending.name, pending.range.as_deref(), options.include_prereleases)
+        })
+        .filter(|locked| metadata.versions.contains_key(&locked.version))
+        .map(|locked| locked.version.as_str());
+    if let Some(version) = pinned {
+        debug!("Using locked version {}@{}", pending.name, version);
+    } else if options.frozen {
+        return Err(CoreError::FrozenLockfile(format!(
+            "{}@{} is not satisfied by a locked version",
+            pending.name,
+            pending.range.as_deref().unwrap_or("*")
+        ))
+        .into());
+    }
+
+    let resolution = match resolve(metadata, pinned.or(pending.range.as_deref()), options) {
+        Err(e) if options.overrides.contains_key(&pending.name) => {
+            return Err(CoreError::InvalidOverride {
+                name: pending.name.clone(),
+                version: pending.range.clone().unwrap_or_default(),
+                reason: e.to_string(),
+            }
+            .into());
+        }
+        resolution => resolution?,
+    };
+    Ok((
+        PackageInfo::from_version(resolution.version),
+        resolution.version.dist.clone(),
+        resolution.warnings,
+    ))
+}
+
+/// A selected version together with any warnings raised while resolving it
+#[derive(Debug, Clone)]
+pub struct Resolution<'a> {
+    pub version: &'a PackageVersion,
+    pub warnings: Vec<String>,
+}
+
+/// Resolve a request to a version, collecting warnings about the selection
+///
+/// This wraps `select_version` and reports non-fatal problems, such as the
+/// selected version being deprecated, without failing the resolution.
+pub fn resolve<'a>(
+    metadata: &'a PackageMetadata,
+    version_req: Option<&str>,
+    options: &InstallOptions,
+) -> Result<Resolution<'a>> {
+    let version = select_version(metadata, version_req, options)?;
+    let mut warnings = Vec::new();
+
+    if let Some(message) = &version.deprecated {
+        let warning = format!("{}@{} is deprecated: {}", version.name, version.version, message);
+        warn!("{}", warning);
+        warnings.push(warning);
+    }
+
+    Ok(Resolution { version, warnings })
+}
+
+/// Select the version of a package that best satisfies a request
+///
+/// The request may be an exact version, a dist-tag, or an npm-style range.
+/// When no request is given the `latest` dist-tag is preferred. Versions
+/// that are incompatible with the configured target runtime are skipped.
+/// Ranges only select pre-releases on a pre-release line they name, unless
+/// `options.include_prereleases` is set.
+///
+/// # Arguments
+/// * `metadata` - Registry metadata for the package
+/// * `version_req` - Requested version, tag, or range (if any)
+/// * `options` - Installation options describing the target runtime
+///
+/// # Returns
+/// * `Ok(&PackageVersion)` with the selected version
+/// * `Err(anyhow::Error)` if no compatible version exists
+pub fn select_version<'a>(
+    metadata: &'a PackageMetadata,
+    version_req: Option<&str>,
+    options: &InstallOptions,
+) -> Result<&'a PackageVersion> {
+    let requested = version_req.map(str::trim).filter(|req| !req.is_empty());
+
+    // Dist-tags and exact versions name a single candidate
+    let tag = requested.unwrap_or("latest");
+    if let Some(tagged) = metadata.dist_tags.get(tag) {
+        if let Some(version_info) = metadata.versions.get(tagged) {
+            if is_compatible(version_info, options) {
+                match quarantine_reason(metadata, version_info, options) {
+                    None => return Ok(version_info),
+                    Some(reason) if requested.is_some() => {
+                        anyhow::bail!("{}@{} ({}) {}", metadata.name, tag, tagged, reason)
+                    }
+                    // The search below warns about skipping the latest version
+                    Some(_) => {}
+                }
+            } else if requested.is_some() {
+                anyhow::bail!(
+                    "{}@{} ({}) is not compatible with the target runtime: {}",
+                    metadata.name,
+                    tag,
+                    tagged,
+                    incompatibility_reason(version_info, options)
+                );
+            } else {
+                warn!(
+                    "Latest version {} of {} is not compatible with the target runtime, looking for an older release",
+                    tagged, metadata.name
+                );
+            }
+        }
+    }
+
+    if let Some(version_info) = requested.and_then(|req| metadata.versions.get(req.trim_start_matches('='))) {
+        if is_compatible(version_info, options) {
+            if let Some(reason) = quarantine_reason(metadata, version_info, options) {
+                anyhow::bail!("{}@{} {}", metadata.name, version_info.version, reason);
+            }
+            return Ok(version_info);
+        }
+        anyhow::bail!(
+            "{}@{} is not compatible with the target runtime: {}",
+            metadata.name,
+            version_info.version,
+            incompatibility_reason(version_info, options)
+        );
+    }
+
+    // Without an explicit range, never move past the latest tag
+    let range = match requested {
+        Some(req) => parse_range(req)?,
+        None => match latest_version(metadata) {
+            Some(latest) => parse_range(&format!("<={}", latest))?,
+            None => Range::any(),
+        },
+    };
+    let range_display = requested.unwrap_or("latest");
+
+    let mut matching: Vec<(Version, &PackageVersion)> = metadata
+        .versions
+        .values()
+        .filter_map(|info| parse_version(&info.version).ok().map(|v| (v, info)))
+        .filter(|(v, _)| range.matches_with(v, options.include_prereleases))
+        .collect();
+
+    if matching.is_empty() {
+        anyhow::bail!("No version of {} matches {}", metadata.name, range_display);
+    }
+
+    matching.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let compatible: Vec<&(Version, &PackageVersion)> =
+        matching.iter().filter(|(_, info)| is_compatible(info, options)).collect();
+    let eligible = compatible
+        .iter()
+        .find(|(_, info)| quarantine_reason(metadata, info, options).is_none());
+    let quarantined_newest = compatible
+        .first()
+        .and_then(|(version, info)| Some((version, quarantine_reason(metadata, info, options)?)));
+    if let Some((newest, reason)) = quarantined_newest {
+        match eligible {
+            Some((version, _)) => warn!("Skipping {}@{}, which {}; using {}", metadata.name, newest, reason, version),
+            None => anyhow::bail!(
+                "No version of {} matching {} is old enough to install (newest {} {})",
+                metadata.name,
+                range_display,
+                newest,
+                reason
+            ),
+        }
+    }
+
+    match eligible {
+        Some((version, info)) => {
+            debug!("Selected {}@{} for {}", metadata.name, version, range_display);
+            Ok(info)
+        }
+        None => anyhow::bail!(
+            "No version of {} matching {} is compatible with the target runtime ({} candidates excluded; newest {}: {})",
+            metadata.name,
+            range_display,
+            matching.len(),
+            matching[0].1.version,
+            incompatibility_reason(matching[0].1, options)
+        ),
+    }
+}
+
+/// Check whether a version can run on the configured target
+pub fn is_compatible(version_info: &PackageVersion, options: &InstallOptions) -> bool {
+    satisfies_node_engine(version_info, options.target_node_version.as_deref())
+        && supports_platform(version_info.os.as_deref(), &target_os(options))
+        && supports_platform(version_info.cpu.as_deref(), &target_cpu(options))
+}
+
+/// Get the time a version was published, from the metadata's `time` map
+///
+/// # Returns
+/// * `Some(DateTime<Utc>)` with the publish time
+/// * `None` if the registry didn't report one or it doesn't parse
+pub fn published_at(metadata: &PackageMetadata, version: &str) -> Option<DateTime<Utc>> {
+    let time = metadata.time.get(version)?;
+    DateTime::parse_from_rfc3339(time).ok().map(|time| time.with_timezone(&Utc))
+}
+
+/// Describe why a version is too new to install under `min_version_age_days`
+///
+/// Versions without a known publish time are quarantined too, since their
+/// age can't be checked.
+fn quarantine_reason(metadata: &PackageMetadata, version_info: &PackageVersion, options: &InstallOptions) -> Option<String> {
+    let min_age_days = options.min_version_age_days?;
+    let Some(published) = published_at(metadata, &version_info.version) else {
+        return Some(format!(
+            "has no known publish time, so it can't be checked against the minimum age of {} days",
+            min_age_days
+        ));
+    };
+    let age = Utc::now().signed_duration_since(published);
+    let min_age = i64::try_from(min_age_days).ok().and_then(TimeDelta::try_days);
+    if min_age.is_some_and(|min_age| age >= min_age) {
+        return None;
+    }
+    Some(format!(
+        "was published {} days ago, less than the minimum age of {} days",
+        age.num_days().max(0),
+        min_age_days
+    ))
+}
+
+/// Get the npm platform name being resolved for
+pub fn target_os(options: &InstallOptions) -> String {
+    options.target_os.clone().unwrap_or_else(|| {
+        match std::env::consts::OS {
+            "macos" => "darwin",
+            "windows" => "win32",
+            "solaris" => "sunos",
+            other => other,
+        }
+        .to_string()
+    })
+}
+
+/// Get the npm architecture name being resolved for
+pub fn target_cpu(options: &InstallOptions) -> String {
+    options.target_cpu.clone().unwrap_or_else(|| {
+        match std::env::consts::ARCH {
+            "x86_64" => "x64",
+            "x86" => "ia32",
+            "aarch64" => "arm64",
+            "powerpc64" => "ppc64",
+            "powerpc" => "ppc",
+            other => other,
+        }
+        .to_string()
+    })
+}
+
+/// Check a declared `os`/`cpu` list against a target, following npm's rules
+///
+/// An absent or empty list supports everything. Entries prefixed with `!`
+/// exclude a platform; if any plain entries exist the target must be one of them.
+fn supports_platform(declared: Option<&[String]>, target: &str) -> bool {
+    let Some(declared) = declared.filter(|list| !list.is_empty()) else {
+        return true;
+    };
+
+    if declared.iter().any(|entry| entry.strip_prefix('!') == Some(target)) {
+        return false;
+    }
+
+    let allowed: Vec<&String> = declared.iter().filter(|entry| !entry.starts_with('!')).collect();
+    allowed.is_empty() || allowed.iter().any(|entry| entry.as_str() == target)
+}
+
+/// Describe why a version is not compatible with the configured target
+fn incompatibility_reason(version_info: &PackageVersion, options: &InstallOptions) -> String {
+    let mut reasons = Vec::new();
+
+    if !satisfies_node_engine(version_info, options.target_node_version.as_deref()) {
+        reasons.push(format!(
+            "requires node {} but target is {}",
+            node_engine(version_info).unwrap_or("*"),
+            options.target_node_version.as_deref().unwrap_or("unknown")
+        ));
+    }
+
+    let os = target_os(options);
+    if !supports_platform(version_info.os.as_deref(), &os) {
+        reasons.push(format!(
+            "supports os {:?} but target is {}",
+            version_info.os.as_deref().unwrap_or_default(),
+            os
+        ));
+    }
+
+    let cpu = target_cpu(options);
+    if !supports_platform(version_info.cpu.as_deref(), &cpu) {
+        reasons.push(format!(
+            "supports cpu {:?} but target is {}",
+            version_info.cpu.as_deref().unwrap_or_default(),
+            cpu
+        ));
+    }
+
+    reasons.join(", ")
+}
+
+/// Get the `engines.node` range declared by a version, if any
+fn node_engine(version_info: &PackageVersion) -> Option<&str> {
+    version_info
+        .engines
+        .as_ref()
+        .and_then(|engines| engines.get("node"))
+        .map(String::as_str)
+}
+
+/// Check whether a version's `engines.node` range accepts the target node version
+///
+/// Versions without an `engines.node` field, or with a range that can't be
+/// parsed, are treated as compatible.
+fn satisfies_node_engine(version_info: &PackageVersion, target_node_version: Option<&str>) -> bool {
+    let (Some(target), Some(engine)) = (target_node_version, node_engine(version_info)) else {
+        return true;
+    };
+
+    let Some(target) = parse_loose_version(target) else {
+        warn!("Ignoring unparseable target node version {}", target);
+        return true;
+    };
+
+    match parse_range(engine) {
+        Ok(range) => range.matches(&target),
+        Err(_) => {
+            warn!(
+                "Ignoring unparseable engines.node range {:?} on {}@{}",
+                engine, version_info.name, version_info.version
+            );
+            true
+        }
+    }
+}
+
+/// Get the version pointed to by the `latest` dist-tag
+fn latest_version(metadata: &PackageMetadata) -> Option<Version> {
+    metadata
+        .dist_tags
+        .get("latest")
+        .and_then(|latest| parse_version(latest).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metadata(value: serde_json::Value) -> PackageMetadata {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn version(name: &str, version: &str, extra: serde_json::Value) -> serde_json::Value {
+        let mut value = json!({
+            "name": name,
+            "version": version,
+            "dist": {
+                "tarball": format!("https://registry.npmjs.org/{0}/-/{0}-{1}.tgz", name, version),
+                "shasum": "0000000000000000000000000000000000000000"
+            }
+        });
+        value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        value
+    }
+
+    fn engine_metadata() -> PackageMetadata {
+        metadata(json!({
+            "name": "engine-pkg",
+            "dist-tags": { "latest": "3.0.0" },
+            "versions": {
+                "1.0.0": version("engine-pkg", "1.0.0", json!({ "engines": { "node": ">=12" } })),
+                "2.0.0": version("engine-pkg", "2.0.0", json!({ "engines": { "node": ">=16 <20" } })),
+                "2.1.0": version("engine-pkg", "2.1.0", json!({ "engines": { "node": "^18 || ^20" } })),
+                "3.0.0": version("engine-pkg", "3.0.0", json!({ "engines": { "node": ">=20.0.0" } }))
+            }
+        }))
+    }
+
+    fn with_node(target: &str) -> InstallOptions {
+        InstallOptions {
+            target_node_version: Some(target.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_latest_without_target() {
+        let metadata = engine_metadata();
+        let selected = select_version(&metadata, None, &InstallOptions::default()).unwrap();
+        assert_eq!(selected.version, "3.0.0");
+    }
+
+    #[test]
+    fn test_select_skips_incompatible_engine() {
+        let metadata = engine_metadata();
+
+        let selected = select_version(&metadata, None, &with_node("18.17.0")).unwrap();
+        assert_eq!(selected.version, "2.1.0");
+
+        let selected = select_version(&metadata, None, &with_node("v16")).unwrap();
+        assert_eq!(selected.version, "2.0.0");
+
+        let selected = select_version(&metadata, Some("^2.0.0"), &with_node("19.0.0")).unwrap();
+        assert_eq!(selected.version, "2.0.0");
+    }
+
+    #[test]
+    fn test_select_errors_when_all_excluded() {
+        let metadata = engine_metadata();
+
+        let err = select_version(&metadata, Some("^3.0.0"), &with_node("18.0.0")).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("engine-pkg"));
+        assert!(message.contains("requires node >=20.0.0"));
+
+        let err = select_version(&metadata, Some("2.0.0"), &with_node("10.0.0")).unwrap_err();
+        assert!(err.to_string().contains("not compatible"));
+    }
+
+    #[test]
+    fn test_select_missing_engines_is_compatible() {
+        let metadata = metadata(json!({
+            "name": "plain",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": { "1.0.0": version("plain", "1.0.0", json!({})) }
+        }));
+
+        let selected = select_version(&metadata, None, &with_node("4.0.0")).unwrap();
+        assert_eq!(selected.version, "1.0.0");
+    }
+
+    #[test]
+    fn test_select_range_and_tags() {
+        let metadata = engine_metadata();
+        let options = InstallOptions::default();
+
+        assert_eq!(select_version(&metadata, Some("latest"), &options).unwrap().version, "3.0.0");
+        assert_eq!(select_version(&metadata, Some("2.0.0"), &options).unwrap().version, "2.0.0");
+        assert_eq!(select_version(&metadata, Some("^2.0.0"), &options).unwrap().version, "2.1.0");
+        assert_eq!(select_version(&metadata, Some("1.x"), &options).unwrap().version, "1.0.0");
+        assert!(select_version(&metadata, Some("^4.0.0"), &options).is_err());
+    }
+
+    fn prerelease_metadata() -> PackageMetadata {
+        metadata(json!({
+            "name": "beta-pkg",
+            "dist-tags": { "latest": "1.1.0", "next": "1.2.0-beta.2" },
+            "versions": {
+                "1.1.0": version("beta-pkg", "1.1.0", json!({})),
+                "1.2.0-beta.1": version("beta-pkg", "1.2.0-beta.1", json!({})),
+                "1.2.0-beta.2": version("beta-pkg", "1.2.0-beta.2", json!({})),
+                "2.0.0-alpha.1": version("beta-pkg", "2.0.0-alpha.1", json!({}))
+            }
+        }))
+    }
+
+    #[test]
+    fn test_select_excludes_prereleases_by_default() {
+        let metadata = prerelease_metadata();
+        let options = InstallOptions::default();
+        let select = |range| select_version(&metadata, Some(range), &options).unwrap().version.as_str();
+
+        assert_eq!(select("^1.0.0"), "1.1.0");
+        assert_eq!(select(">=1.0.0"), "1.1.0");
+        assert_eq!(select("^1.2.0-beta"), "1.2.0-beta.2");
+        assert_eq!(select("^1.2.0-beta.1 <1.2.0-beta.2"), "1.2.0-beta.1");
+        assert_eq!(select("next"), "1.2.0-beta.2");
+        assert!(select_version(&metadata, Some("^2.0.0"), &options).is_err());
+    }
+
+    #[test]
+    fn test_select_includes_prereleases_when_asked() {
+        let metadata = prerelease_metadata();
+        let options = InstallOptions {
+            include_prereleases: true,
+            ..Default::default()
+        };
+        let select = |range| select_version(&metadata, Some(range), &options).unwrap().version.as_str();
+
+        assert_eq!(select("^1.0.0"), "1.2.0-beta.2");
+        assert_eq!(select(">=1.0.0"), "2.0.0-alpha.1");
+        assert_eq!(select("~1.1.0"), "1.1.0");
+        // Without a range, `latest` still wins
+        assert_eq!(select_version(&metadata, None, &options).unwrap().version, "1.1.0");
+
+        assert!(range_allows(Some("^1.0.0"), "1.2.0-beta.1", true));
+        assert!(!range_allows(Some("^1.0.0"), "1.2.0-beta.1", false));
+    }
+
+    fn platform_metadata() -> PackageMetadata {
+        metadata(json!({
+            "name": "native-pkg",
+            "dist-tags": { "latest": "2.0.0" },
+            "versions": {
+                "1.0.0": version("native-pkg", "1.0.0", json!({})),
+                "2.0.0": version("native-pkg", "2.0.0", json!({ "os": ["linux"], "cpu": ["x64"] }))
+            }
+        }))
+    }
+
+    fn with_platform(os: &str, cpu: &str) -> InstallOptions {
+        InstallOptions {
+            target_os: Some(os.to_string()),
+            target_cpu: Some(cpu.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_matching_platform() {
+        let metadata = platform_metadata();
+        let selected = select_version(&metadata, None, &with_platform("linux", "x64")).unwrap();
+        assert_eq!(selected.version, "2.0.0");
+    }
+
+    #[test]
+    fn test_select_skips_incompatible_platform() {
+        let metadata = platform_metadata();
+
+        let selected = select_version(&metadata, None, &with_platform("win32", "x64")).unwrap();
+        assert_eq!(selected.version, "1.0.0");
+
+        let selected = select_version(&metadata, None, &with_platform("linux", "arm64")).unwrap();
+        assert_eq!(selected.version, "1.0.0");
+
+        let err = select_version(&metadata, Some("2.0.0"), &with_platform("win32", "x64")).unwrap_err();
+        assert!(err.to_string().contains("target is win32"));
+    }
+
+    /// Versions published 400, 30 and 2 days ago; 2.1.0 has no publish time
+    fn quarantine_metadata() -> PackageMetadata {
+        let days_ago = |days: i64| (Utc::now() - TimeDelta::days(days)).to_rfc3339();
+        metadata(json!({
+            "name": "fresh-pkg",
+            "dist-tags": { "latest": "2.0.0", "legacy": "2.1.0" },
+            "versions": {
+                "1.0.0": version("fresh-pkg", "1.0.0", json!({})),
+                "1.1.0": version("fresh-pkg", "1.1.0", json!({})),
+                "2.0.0": version("fresh-pkg", "2.0.0", json!({})),
+                "2.1.0": version("fresh-pkg", "2.1.0", json!({}))
+            },
+            "time": {
+                "created": days_ago(400),
+                "1.0.0": days_ago(400),
+                "1.1.0": days_ago(30),
+                "2.0.0": days_ago(2)
+            }
+        }))
+    }
+
+    fn with_min_age(days: u64) -> InstallOptions {
+        InstallOptions {
+            min_version_age_days: Some(days),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_skips_quarantined_versions() {
+        let metadata = quarantine_metadata();
+
+        // The latest release is too new, so the newest old enough one is used
+        assert_eq!(select_version(&metadata, None, &with_min_age(7)).unwrap().version, "1.1.0");
+        assert_eq!(select_version(&metadata, None, &with_min_age(60)).unwrap().version, "1.0.0");
+        // 2.1.0 is newer, but its age is unknown
+        assert_eq!(select_version(&metadata, Some("*"), &with_min_age(7)).unwrap().version, "1.1.0");
+        assert_eq!(select_version(&metadata, Some("^1.0.0"), &with_min_age(7)).unwrap().version, "1.1.0");
+
+        // Without a threshold the latest release is used
+        assert_eq!(select_version(&metadata, None, &InstallOptions::default()).unwrap().version, "2.0.0");
+        assert_eq!(select_version(&metadata, None, &with_min_age(1)).unwrap().version, "2.0.0");
+
+        assert_eq!(published_at(&metadata, "2.1.0"), None);
+        assert!(published_at(&metadata, "1.0.0").is_some());
+    }
+
+    #[test]
+    fn test_select_quarantine_errors() {
+        let metadata = quarantine_metadata();
+
+        let err = select_version(&metadata, Some("2.0.0"), &with_min_age(7)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fresh-pkg@2.0.0 was published 2 days ago, less than the minimum age of 7 days"
+        );
+        let err = select_version(&metadata, Some("latest"), &with_min_age(7)).unwrap_err();
+        assert!(err.to_string().starts_with("fresh-pkg@latest (2.0.0) was published"));
+
+        let err = select_version(&metadata, Some("legacy"), &with_min_age(7)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fresh-pkg@legacy (2.1.0) has no known publish time, \
+             so it can't be checked against the minimum age of 7 days"
+        );
+        assert_eq!(select_version(&metadata, Some("legacy"), &InstallOptions::default()).unwrap().version, "2.1.0");
+
+        let err = select_version(&metadata, Some("^1.0.0"), &with_min_age(500)).unwrap_err();
+        assert!(err.to_string().contains("No version of fresh-pkg matching ^1.0.0 is old enough"));
+    }
+
+    #[test]
+    fn test_supports_platform_negation() {
+        let declared = vec!["!win32".to_string()];
+        assert!(supports_platform(Some(&declared), "linux"));
+        assert!(!supports_platform(Some(&declared), "win32"));
+        assert!(supports_platform(None, "win32"));
+        assert!(supports_platform(Some(&[]), "win32"));
+    }
+
+    #[test]
+    fn test_resolve_collects_deprecation_warning() {
+        let metadata = metadata(json!({
+            "name": "old-pkg",
+            "dist-tags": { "latest": "1.1.0" },
+            "versions": {
+                "1.0.0": version("old-pkg", "1.0.0", json!({ "deprecated": "Use new-pkg instead" })),
+                "1.1.0": version("old-pkg", "1.1.0", json!({}))
+            }
+        }));
+        let options = InstallOptions::default();
+
+        let resolution = resolve(&metadata, Some("1.0.0"), &options).unwrap();
+        assert_eq!(resolution.version.version, "1.0.0");
+        assert_eq!(resolution.warnings, vec!["old-pkg@1.0.0 is deprecated: Use new-pkg instead".to_string()]);
+
+        let resolution = resolve(&metadata, None, &options).unwrap();
+        assert_eq!(resolution.version.version, "1.1.0");
+        assert!(resolution.warnings.is_empty());
+    }
+
+    fn registry(entries: Vec<PackageMetadata>) -> HashMap<String, PackageMetadata> {
+        entries.into_iter().map(|m| (m.name.clone(), m)).collect()
+    }
+
+    fn fetcher(
+        registry: &HashMap<String, PackageMetadata>,
+    ) -> impl Fn(String) -> std::future::Ready<Result<PackageMetadata>> + '_ {
+        |name| {
+            std::future::ready(
+                registry
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Failed to fetch package metadata: HTTP 404 for {}", name)),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_transitive() {
+        let registry = registry(vec![
+            metadata(json!({
+                "name": "app-lib",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("app-lib", "1.0.0", json!({ "dependencies": { "util": "^2.0.0" } })) }
+            })),
+            metadata(json!({
+                "name": "util",
+                "dist-tags": { "latest": "2.1.0" },
+                "versions": { "2.1.0": version("util", "2.1.0", json!({})) }
+            })),
+        ]);
+
+        let roots = vec![("app-lib".to_string(), None)];
+        let tree = resolve_tree(&roots, &InstallOptions::default(), fetcher(&registry)).await.unwrap();
+
+        let names: Vec<&str> = tree.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["app-lib", "util"]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_skips_failed_optional() {
+        let registry = registry(vec![
+            metadata(json!({
+                "name": "watcher",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": {
+                    "1.0.0": version("watcher", "1.0.0", json!({
+                        "dependencies": { "util": "^2.0.0" },
+                        "optionalDependencies": { "fsevents": "^2.3.0", "native-ext": "^1.0.0" }
+                    }))
+                }
+            })),
+            metadata(json!({
+                "name": "util",
+                "dist-tags": { "latest": "2.1.0" },
+                "versions": { "2.1.0": version("util", "2.1.0", json!({})) }
+            })),
+            metadata(json!({
+                "name": "native-ext",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("native-ext", "1.0.0", json!({ "os": ["darwin"] })) }
+            })),
+        ]);
+
+        let roots = vec![("watcher".to_string(), None)];
+        let options = InstallOptions {
+            target_os: Some("linux".to_string()),
+            ..Default::default()
+        };
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+
+        let names: Vec<&str> = tree.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["watcher", "util"]);
+
+        let skipped: Vec<&str> = tree.skipped_optional.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(skipped, vec!["fsevents", "native-ext"]);
+        assert_eq!(tree.skipped_optional[0].required_by, "watcher@1.0.0");
+        assert!(tree.skipped_optional[0].reason.contains("404"));
+        assert_eq!(tree.warnings.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_from_local_packages() {
+        let registry = registry(vec![metadata(json!({
+            "name": "util",
+            "dist-tags": { "latest": "2.1.0" },
+            "versions": { "2.1.0": version("util", "2.1.0", json!({})) }
+        }))]);
+
+        let mut local = PackageInfo::new("from-git", "0.1.0");
+        local.dependencies.insert("util".to_string(), "^2.0.0".to_string());
+
+        let tree = resolve_tree_from(vec![local], &[], &InstallOptions::default(), fetcher(&registry))
+            .await
+            .unwrap();
+
+        let names: Vec<&str> = tree.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["from-git", "util"]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_required_failure_aborts() {
+        let registry = registry(vec![metadata(json!({
+            "name": "broken",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": { "1.0.0": version("broken", "1.0.0", json!({ "dependencies": { "missing": "^1.0.0" } })) }
+        }))]);
+
+        let roots = vec![("broken".to_string(), None)];
+        let result = resolve_tree(&roots, &InstallOptions::default(), fetcher(&registry)).await;
+        assert!(result.is_err());
+    }
+
+    fn override_registry() -> HashMap<String, PackageMetadata> {
+        registry(vec![
+            metadata(json!({
+                "name": "app-lib",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": {
+                    "1.0.0": version("app-lib", "1.0.0", json!({ "dependencies": { "middle": "^1.0.0", "util": "^2.0.0" } }))
+                }
+            })),
+            metadata(json!({
+                "name": "middle",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("middle", "1.0.0", json!({ "dependencies": { "util": "^2.1.0" } })) }
+            })),
+            metadata(json!({
+                "name": "util",
+                "dist-tags": { "latest": "2.2.0" },
+                "versions": {
+                    "2.0.1": version("util", "2.0.1", json!({})),
+                    "2.1.0": version("util", "2.1.0", json!({})),
+                    "2.2.0": version("util", "2.2.0", json!({}))
+                }
+            })),
+        ])
+    }
+
+    fn with_overrides(overrides: &[(&str, &str)]) -> InstallOptions {
+        InstallOptions {
+            overrides: overrides
+                .iter()
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_overrides_transitive_version() {
+        let registry = override_registry();
+        let roots = vec![("app-lib".to_string(), None)];
+
+        let tree = resolve_tree(&roots, &InstallOptions::default(), fetcher(&registry)).await.unwrap();
+        let util: Vec<&str> = tree.packages.iter().filter(|p| p.name == "util").map(|p| p.version.as_str()).collect();
+        assert_eq!(util, vec!["2.2.0"]);
+
+        // The override wins over both ranges, even the one it doesn't satisfy
+        let options = with_overrides(&[("util", "2.0.1")]);
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+        let util: Vec<&str> = tree.packages.iter().filter(|p| p.name == "util").map(|p| p.version.as_str()).collect();
+        assert_eq!(util, vec!["2.0.1"]);
+        assert!(tree.conflicts.is_empty());
+        assert_eq!(tree.distributions["util@2.0.1"].tarball, "https://registry.npmjs.org/util/-/util-2.0.1.tgz");
+
+        let options = with_overrides(&[("util", "~2.1.0")]);
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+        assert!(tree.packages.iter().any(|p| p.name == "util" && p.version == "2.1.0"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_override_must_exist() {
+        let registry = override_registry();
+        let roots = vec![("app-lib".to_string(), None)];
+
+        let options = InstallOptions {
+            continue_on_error: true,
+            ..with_overrides(&[("util", "3.0.0")])
+        };
+        let err = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::InvalidOverride { name, version, .. }) if name == "util" && version == "3.0.0"
+        ));
+        assert!(err.to_string().contains("No version of util matches 3.0.0"));
+    }
+
+    /// A chain `pkg-0 -> pkg-1 -> ... -> pkg-{length - 1}`
+    fn chain(length: usize) -> HashMap<String, PackageMetadata> {
+        registry(
+            (0..length)
+                .map(|i| {
+                    let name = format!("pkg-{}", i);
+                    let extra = if i + 1 < length {
+                        json!({ "dependencies": { format!("pkg-{}", i + 1): "^1.0.0" } })
+                    } else {
+                        json!({})
+                    };
+                    metadata(json!({
+                        "name": name,
+                        "dist-tags": { "latest": "1.0.0" },
+                        "versions": { "1.0.0": version(&name, "1.0.0", extra) }
+                    }))
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_max_depth() {
+        let roots = vec![("pkg-0".to_string(), None)];
+        let options = InstallOptions {
+            max_depth: Some(5),
+            ..Default::default()
+        };
+
+        let registry = chain(5);
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+        assert_eq!(tree.packages.len(), 5);
+
+        let registry = chain(6);
+        let err = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::DependencyTooDeep { name, required_by, max_depth: 5 })
+                if name == "pkg-5" && required_by == "pkg-4@1.0.0"
+        ));
+
+        // The default limit is generous but still stops runaway chains
+        let registry = chain(DEFAULT_MAX_DEPTH + 1);
+        let err = resolve_tree(&roots, &InstallOptions::default(), fetcher(&registry)).await.unwrap_err();
+        assert!(err.to_string().contains("deeper than 64 levels"));
+
+        let unlimited = InstallOptions {
+            max_depth: None,
+            ..Default::default()
+        };
+        let tree = resolve_tree(&roots, &unlimited, fetcher(&registry)).await.unwrap();
+        assert_eq!(tree.packages.len(), DEFAULT_MAX_DEPTH + 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_skips_bundled_dependencies() {
+        // `inner` is absent from the registry, so fetching it would fail the walk
+        let registry = registry(vec![
+            metadata(json!({
+                "name": "bundler",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": {
+                    "1.0.0": version("bundler", "1.0.0", json!({
+                        "dependencies": { "inner": "^1.0.0", "util": "^2.0.0" },
+                        "bundleDependencies": ["inner"]
+                    }))
+                }
+            })),
+            metadata(json!({
+                "name": "util",
+                "dist-tags": { "latest": "2.1.0" },
+                "versions": { "2.1.0": version("util", "2.1.0", json!({})) }
+            })),
+        ]);
+
+        let roots = vec![("bundler".to_string(), None)];
+        let tree = resolve_tree(&roots, &InstallOptions::default(), fetcher(&registry)).await.unwrap();
+
+        let names: Vec<&str> = tree.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["bundler", "util"]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_never_follows_dev_dependencies() {
+        // Neither dev dependency is published, so fetching one would fail the walk
+        let registry = registry(vec![
+            metadata(json!({
+                "name": "app-lib",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": {
+                    "1.0.0": version("app-lib", "1.0.0", json!({
+                        "dependencies": { "util": "^2.0.0" },
+                        "devDependencies": { "test-runner": "^1.0.0" }
+                    }))
+                }
+            })),
+            metadata(json!({
+                "name": "util",
+                "dist-tags": { "latest": "2.1.0" },
+                "versions": {
+                    "2.1.0": version("util", "2.1.0", json!({ "devDependencies": { "linter": "^3.0.0" } }))
+                }
+            })),
+        ]);
+        let fetched = std::sync::Mutex::new(Vec::new());
+        let fetch = |name: String| {
+            fetched.lock().unwrap().push(name.clone());
+            fetcher(&registry)(name)
+        };
+
+        let mut local = PackageInfo::new("workspace-member", "0.0.0");
+        local.dependencies.insert("app-lib".to_string(), "^1.0.0".to_string());
+        local.dev_dependencies.insert("test-runner".to_string(), "^1.0.0".to_string());
+        let tree = resolve_tree_from(vec![local], &[], &InstallOptions::default(), fetch).await.unwrap();
+
+        let names: Vec<&str> = tree.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["workspace-member", "app-lib", "util"]);
+        assert_eq!(*fetched.lock().unwrap(), vec!["app-lib", "util"]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_prefers_locked_versions() {
+        let registry = registry(vec![metadata(json!({
+            "name": "util",
+            "dist-tags": { "latest": "2.3.0" },
+            "versions": {
+                "2.1.0": version("util", "2.1.0", json!({})),
+                "2.3.0": version("util", "2.3.0", json!({}))
+            }
+        }))]);
+
+        let mut lockfile = crate::lockfile::Lockfile::new();
+        lockfile.insert(crate::lockfile::LockedPackage::new("util", "2.1.0"));
+        let options = InstallOptions {
+            lockfile: Some(lockfile),
+            ..Default::default()
+        };
+
+        let roots = vec![("util".to_string(), Some("^2.0.0".to_string()))];
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+        assert_eq!(tree.packages[0].version, "2.1.0");
+
+        // A pin outside the requested range is ignored
+        let roots = vec![("util".to_string(), Some("^2.2.0".to_string()))];
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+        assert_eq!(tree.packages[0].version, "2.3.0");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_frozen_uses_only_locked_versions() {
+        let registry = registry(vec![metadata(json!({
+            "name": "util",
+            "dist-tags": { "latest": "2.3.0" },
+            "versions": {
+                "2.1.0": version("util", "2.1.0", json!({})),
+                "2.3.0": version("util", "2.3.0", json!({}))
+            }
+        }))]);
+
+        let mut lockfile = crate::lockfile::Lockfile::new();
+        lockfile.insert(crate::lockfile::LockedPackage::new("util", "2.1.0"));
+        let options = InstallOptions {
+            lockfile: Some(lockfile),
+            frozen: true,
+            ..Default::default()
+        };
+
+        let roots = vec![("util".to_string(), Some("^2.0.0".to_string()))];
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+        assert_eq!(tree.packages[0].version, "2.1.0");
+
+        // package.json moved on to a range the lockfile doesn't cover
+        let roots = vec![("util".to_string(), Some("^2.2.0".to_string()))];
+        let err = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<CoreError>(), Some(CoreError::FrozenLockfile(_))));
+        assert!(err.to_string().contains("util@^2.2.0"));
+    }
+
+    fn conflict_registry() -> HashMap<String, PackageMetadata> {
+        registry(vec![
+            metadata(json!({
+                "name": "app-a",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("app-a", "1.0.0", json!({ "dependencies": { "shared": "^1.0.0" } })) }
+            })),
+            metadata(json!({
+                "name": "app-b",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("app-b", "1.0.0", json!({ "dependencies": { "shared": "^2.0.0" } })) }
+            })),
+            metadata(json!({
+                "name": "shared",
+                "dist-tags": { "latest": "2.1.0" },
+                "versions": {
+                    "1.4.0": version("shared", "1.4.0", json!({})),
+                    "2.1.0": version("shared", "2.1.0", json!({}))
+                }
+            })),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_conflict_errors() {
+        let registry = conflict_registry();
+        let roots = vec![("app-a".to_string(), None), ("app-b".to_string(), None)];
+        let options = InstallOptions {
+            conflict_strategy: ConflictStrategy::Error,
+            ..Default::default()
+        };
+
+        let err = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap_err();
+        match err.downcast_ref::<CoreError>() {
+            Some(CoreError::VersionConflict(report)) => {
+                assert_eq!(report.conflicts.len(), 1);
+                let conflict = &report.conflicts[0];
+                assert_eq!(conflict.name, "shared");
+                let ranges: Vec<(&str, &str)> = conflict
+                    .requests
+                    .iter()
+                    .map(|r| (r.range.as_str(), r.required_by.as_str()))
+                    .collect();
+                assert_eq!(ranges, vec![("^1.0.0", "app-a@1.0.0"), ("^2.0.0", "app-b@1.0.0")]);
+            }
+            other => panic!("Expected version conflict, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_reselects_a_version_satisfying_every_range() {
+        let registry = registry(vec![
+            metadata(json!({
+                "name": "app-a",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("app-a", "1.0.0", json!({ "dependencies": { "shared": "^1.0.0" } })) }
+            })),
+            metadata(json!({
+                "name": "app-b",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("app-b", "1.0.0", json!({ "dependencies": { "shared": "~1.2.0" } })) }
+            })),
+            metadata(json!({
+                "name": "shared",
+                "dist-tags": { "latest": "1.5.0" },
+                "versions": {
+                    "1.2.0": version("shared", "1.2.0", json!({})),
+                    "1.2.3": version("shared", "1.2.3", json!({})),
+                    "1.5.0": version("shared", "1.5.0", json!({ "dependencies": { "extra": "^1.0.0" } }))
+                }
+            })),
+            metadata(json!({
+                "name": "extra",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("extra", "1.0.0", json!({})) }
+            })),
+        ]);
+        let roots = vec![("app-a".to_string(), None), ("app-b".to_string(), None)];
+        let options = InstallOptions {
+            conflict_strategy: ConflictStrategy::Error,
+            ..Default::default()
+        };
+
+        let tree = resolve_tree(&roots, &options, fetcher(&registry)).await.unwrap();
+
+        let names: Vec<String> = tree.packages.iter().map(|p| format!("{}@{}", p.name, p.version)).collect();
+        assert_eq!(names, vec!["app-a@1.0.0", "app-b@1.0.0", "shared@1.2.3"]);
+        // The first pick is dropped along with the dependency only it needed
+        assert_eq!(tree.deduplicated, 2);
+        assert!(tree.conflicts.is_empty());
+        assert!(tree.warnings.is_empty());
+        assert!(tree.distributions.contains_key("shared@1.2.3"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_conflict_nests() {
+        let registry = conflict_registry();
+        let roots = vec![("app-a".to_string(), None), ("app-b".to_string(), None)];
+
+        let tree = resolve_tree(&roots, &InstallOptions::default(), fetcher(&registry)).await.unwrap();
+
+        let names: Vec<String> = tree.packages.iter().map(|p| format!("{}@{}", p.name, p.version)).collect();
+        assert_eq!(names, vec!["app-a@1.0.0", "app-b@1.0.0", "shared@1.4.0", "shared@2.1.0"]);
+        assert_eq!(tree.conflicts.len(), 1);
+        assert!(tree.warnings[0].contains("nested"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_statistics() {
+        // app-a -> (mid, leaf), mid -> leaf, app-b -> mid
+        let registry = registry(vec![
+            metadata(json!({
+                "name": "app-a",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("app-a", "1.0.0", json!({ "dependencies": { "mid": "^1.0.0", "leaf": "^1.0.0" } })) }
+            })),
+            metadata(json!({
+                "name": "app-b",
+                "dist-tags": { "latest": "1.0.0" },
+                "versions": { "1.0.0": version("app-b", "1.0.0", json!({ "dependencies": { "mid": "^1.0.0" } })) }
+            })),
+            metadata(json!({
+                "name": "mid",
+                "dist-tags": { "latest": "1.2.0" },
+                "versions": { "1.2.0": version("mid", "1.2.0", json!({ "dependencies": { "leaf": "^1.0.0" } })) }
+            })),
+            metadata(json!({
+                "name": "leaf",
+                "dist-tags": { "latest": "1.0.3" },
+                "versions": { "1.0.3": version("leaf", "1.0.3", json!({})) }
+            })),
+        ]);
+
+        let roots = vec![("app-a".to_string(), None), ("app-b".to_string(), None)];
+        let tree = resolve_tree(&roots, &InstallOptions::default(), fetcher(&registry)).await.unwrap();
+
+        assert_eq!(tree.packages.len(), 4);
+        assert_eq!(tree.direct_count, 2);
+        assert_eq!(tree.transitive_count, 2);
+        // The second requests for `mid` and `leaf` reuse the first resolution
+        assert_eq!(tree.reused, 2);
+        assert_eq!(tree.deduplicated, 0);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tree_statistics_with_local_packages() {
+        let registry = registry(vec![metadata(json!({
+            "name": "util",
+            "dist-tags": { "latest": "2.1.0" },
+            "versions": { "2.1.0": version("util", "2.1.0", json!({})) }
+        }))]);
+
+        let mut local = PackageInfo::new("from-git", "0.1.0");
+        local.dependencies.insert("util".to_string(), "^2.0.0".to_string());
+        let roots = vec![("util".to_string(), Some("^2.0.0".to_string()))];
+
+        let tree = resolve_tree_from(vec![local], &roots, &InstallOptions::default(), fetcher(&registry))
+            .await
+            .unwrap();
+
+        // `util` is both a root and a dependency of the local package
+        assert_eq!(tree.direct_count, 2);
+        assert_eq!(tree.transitive_count, 0);
+        assert_eq!(tree.reused, 1);
+    }
+}
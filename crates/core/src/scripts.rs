@@ -0,0 +1,135 @@
+//! Install lifecycle script detection
+//!
+//! npm packages can declare `preinstall`, `install` and `postinstall`
+//! scripts that run arbitrary commands when they're installed. Nothing here
+//! runs them: resolved packages are inspected and each script is given a
+//! decision from the install options, leaving execution to the caller.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{InstallOptions, PackageInfo};
+
+/// Scripts run when a package is installed, in the order npm runs them
+pub const INSTALL_LIFECYCLE_SCRIPTS: [&str; 3] = ["preinstall", "install", "postinstall"];
+
+/// What happens to a detected lifecycle script
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptDecision {
+    /// Scripts are ignored for the whole install
+    Ignored,
+    /// The package is allow-listed and its script may run
+    Allowed,
+    /// Scripts are enabled but the package isn't allow-listed
+    Blocked,
+}
+
+impl fmt::Display for ScriptDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptDecision::Ignored => write!(f, "ignored"),
+            ScriptDecision::Allowed => write!(f, "allowed"),
+            ScriptDecision::Blocked => write!(f, "blocked"),
+        }
+    }
+}
+
+/// An install lifecycle script declared by a resolved package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifecycleScript {
+    pub package: String,
+    pub version: String,
+    /// Lifecycle event, one of `INSTALL_LIFECYCLE_SCRIPTS`
+    pub event: String,
+    pub command: String,
+    pub decision: ScriptDecision,
+}
+
+impl fmt::Display for LifecycleScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}@{} {} script ({}): {}",
+            self.package, self.version, self.event, self.decision, self.command
+        )
+    }
+}
+
+/// Decide whether a package's lifecycle scripts may run
+///
+/// With `ignore_scripts` set no script runs. Otherwise only packages in
+/// `allowed_scripts` may run theirs; every other package is blocked.
+pub fn script_decision(package: &str, options: &InstallOptions) -> ScriptDecision {
+    if options.ignore_scripts {
+        ScriptDecision::Ignored
+    } else if options.allowed_scripts.contains(package) {
+        ScriptDecision::Allowed
+    } else {
+        ScriptDecision::Blocked
+    }
+}
+
+/// Find the install lifecycle scripts of resolved packages
+///
+/// # Arguments
+/// * `packages` - Resolved packages, in install order
+/// * `options` - Installation options holding the script policy
+///
+/// # Returns
+/// The scripts in install order, each package's scripts in lifecycle order
+pub fn detect_install_scripts(packages: &[PackageInfo], options: &InstallOptions) -> Vec<LifecycleScript> {
+    packages
+        .iter()
+        .flat_map(|pkg| {
+            INSTALL_LIFECYCLE_SCRIPTS.iter().filter_map(move |event| {
+                pkg.scripts.get(*event).map(|command| LifecycleScript {
+                    package: pkg.name.clone(),
+                    version: pkg.version.clone(),
+                    event: event.to_string(),
+                    command: command.clone(),
+                    decision: script_decision(&pkg.name, options),
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, scripts: &[(&str, &str)]) -> PackageInfo {
+        let mut pkg = PackageInfo::new(name, "1.0.0");
+        pkg.scripts = scripts.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        pkg
+    }
+
+    #[test]
+    fn test_detect_install_scripts() {
+        let packages = vec![
+            package("native", &[("postinstall", "node build.js"), ("preinstall", "node check.js"), ("test", "jest")]),
+            package("plain", &[("build", "tsc")]),
+            package("trusted", &[("install", "node-gyp rebuild")]),
+        ];
+
+        let ignored = detect_install_scripts(&packages, &InstallOptions::default());
+        let found: Vec<(&str, &str)> = ignored.iter().map(|s| (s.package.as_str(), s.event.as_str())).collect();
+        assert_eq!(found, vec![("native", "preinstall"), ("native", "postinstall"), ("trusted", "install")]);
+        assert!(ignored.iter().all(|s| s.decision == ScriptDecision::Ignored));
+
+        let options = InstallOptions {
+            ignore_scripts: false,
+            allowed_scripts: ["trusted".to_string()].into_iter().collect(),
+            ..Default::default()
+        };
+        let decisions: Vec<ScriptDecision> = detect_install_scripts(&packages, &options)
+            .iter()
+            .map(|s| s.decision)
+            .collect();
+        assert_eq!(
+            decisions,
+            vec![ScriptDecision::Blocked, ScriptDecision::Blocked, ScriptDecision::Allowed]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Package search through the registry's `/-/v1/search` endpoint
+
+use serde::{Deserialize, Serialize};
+
+/// Most results the npm registry returns for one search
+pub const MAX_SEARCH_LIMIT: usize = 250;
+
+/// A package matching a search query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub name: String,
+    /// Latest version
+    pub version: String,
+    pub description: Option<String>,
+    /// Overall search score, between 0 and 1
+    pub score: f64,
+}
+
+/// Body of a `/-/v1/search` response
+#[derive(Debug, Deserialize)]
+pub(crate) struct SearchResponse {
+    #[serde(default)]
+    objects: Vec<SearchObject>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchObject {
+    package: SearchPackage,
+    #[serde(default)]
+    score: SearchScore,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SearchScore {
+    #[serde(rename = "final", default)]
+    overall: f64,
+}
+
+impl SearchResponse {
+    /// Flatten the response into results, in the order the registry ranked them
+    pub(crate) fn into_results(self) -> Vec<SearchResult> {
+        self.objects
+            .into_iter()
+            .map(|object| SearchResult {
+                name: object.package.name,
+                version: object.package.version,
+                description: object.package.description,
+                score: object.score.overall,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,332 @@
+//! npm-flavoured semantic versioning
+//!
+//! npm ranges differ from Cargo's, which the `semver` crate implements:
+//! bare versions are exact rather than caret requirements, comparators are
+//! separated by whitespace, `||` separates alternatives, and `a - b`
+//! denotes an inclusive range. This module translates npm ranges into
+//! `semver` requirements so every feature matching versions against ranges
+//! agrees on what they mean. Pre-releases follow the same rule in both: a
+//! range only matches a pre-release of a version one of its comparators
+//! names with a pre-release tag.
+
+pub use ::semver::Version;
+use ::semver::{Comparator, Op, Prerelease, VersionReq};
+use anyhow::Result;
+use std::fmt;
+
+use crate::CoreError;
+
+/// A parsed npm range, matching a version if any of its alternatives does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range {
+    alternatives: Vec<VersionReq>,
+}
+
+impl Range {
+    /// The range accepting every release (`*`)
+    pub fn any() -> Self {
+        Self {
+            alternatives: vec![VersionReq::STAR],
+        }
+    }
+
+    /// Check whether a version satisfies the range
+    ///
+    /// A pre-release only satisfies the range if one of its comparators
+    /// names a pre-release of the same version, so `^1.0.0-beta` matches
+    /// `1.0.0-beta.2` but `^1.0.0` doesn't.
+    pub fn matches(&self, version: &Version) -> bool {
+        self.alternatives.iter().any(|req| req.matches(version))
+    }
+
+    /// Check whether a version satisfies the range, optionally letting any pre-release in
+    ///
+    /// With `include_prereleases`, pre-releases are held to the range's
+    /// bounds alone, so `^1.0.0` matches `1.2.0-beta.1` (but not `2.0.0-beta.1`).
+    pub fn matches_with(&self, version: &Version, include_prereleases: bool) -> bool {
+        if !include_prereleases || version.pre.is_empty() {
+            return self.matches(version);
+        }
+
+        // `semver` lets a pre-release through when a comparator names a
+        // pre-release of the same version; `>=X.Y.Z-0` does, and holds for
+        // every `X.Y.Z-*`, leaving only the range's own bounds to check
+        let lowest_prerelease = Comparator {
+            op: Op::GreaterEq,
+            major: version.major,
+            minor: Some(version.minor),
+            patch: Some(version.patch),
+            pre: Prerelease::new("0").expect("0 is a valid pre-release"),
+        };
+        self.alternatives.iter().any(|req| {
+            let mut req = req.clone();
+            req.comparators.push(lowest_prerelease.clone());
+            req.matches(version)
+        })
+    }
+}
+
+/// Largest change between two versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiffKind {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl fmt::Display for DiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DiffKind::Patch => "patch",
+            DiffKind::Minor => "minor",
+            DiffKind::Major => "major",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Parse an npm range
+///
+/// An empty range is the same as `*`.
+///
+/// # Arguments
+/// * `range` - Range such as `^1.2.0`, `>=14 <16`, `1.x || 2.x` or `1.2 - 2.3`
+///
+/// # Returns
+/// * `Ok(Range)` with the parsed range
+/// * `Err(anyhow::Error)` wrapping `CoreError::InvalidRange` if it isn't a range, such as a dist-tag
+pub fn parse_range(range: &str) -> Result<Range> {
+    let alternatives = range
+        .split("||")
+        .map(|alternative| {
+            let alternative = alternative.trim();
+            let comparators = if let Some((low, high)) = alternative.split_once(" - ") {
+                vec![
+                    format!(">={}", normalize_partial(low.trim())),
+                    format!("<={}", high.trim().trim_start_matches('v')),
+                ]
+            } else {
+                split_comparators(alternative)
+            };
+
+            if comparators.is_empty() {
+                return Ok(VersionReq::STAR);
+            }
+
+            VersionReq::parse(&comparators.join(", ")).map_err(|e| CoreError::InvalidRange {
+                range: range.to_string(),
+                reason: e.to_string(),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Range { alternatives })
+}
+
+/// Parse a version, tolerating a leading `v` or `=`
+///
+/// # Returns
+/// * `Ok(Version)` with the parsed version
+/// * `Err(anyhow::Error)` wrapping `CoreError::InvalidVersion` if it isn't a full version
+pub fn parse_version(version: &str) -> Result<Version> {
+    let trimmed = version.trim().trim_start_matches(['v', '=']);
+    Version::parse(trimmed).map_err(|e| {
+        CoreError::InvalidVersion {
+            version: version.to_string(),
+            reason: e.to_string(),
+        }
+        .into()
+    })
+}
+
+/// Parse a possibly partial version such as `v18` or `18.17` into a full version
+///
+/// Missing minor and patch components are zero.
+pub fn parse_loose_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    let mut parts: Vec<&str> = version.splitn(3, '.').collect();
+    while parts.len() < 3 {
+        parts.push("0");
+    }
+    Version::parse(&parts.join(".")).ok()
+}
+
+/// Check whether a version satisfies an npm range
+///
+/// # Returns
+/// * `Ok(bool)` telling whether `version` is in `range`
+/// * `Err(anyhow::Error)` if either fails to parse
+pub fn satisfies(version: &str, range: &str) -> Result<bool> {
+    let range = parse_range(range)?;
+    Ok(range.matches(&parse_version(version)?))
+}
+
+/// Find the newest version satisfying an npm range
+///
+/// Versions that don't parse are skipped, and pre-releases are subject to
+/// the same rule as in `Range::matches`.
+///
+/// # Arguments
+/// * `versions` - Candidate versions, in any order
+/// * `range` - Range they must satisfy
+///
+/// # Returns
+/// * `Ok(Some(&str))` with the newest satisfying candidate
+/// * `Ok(None)` if no candidate satisfies the range
+/// * `Err(anyhow::Error)` if the range fails to parse
+pub fn max_satisfying<'a>(versions: impl IntoIterator<Item = &'a str>, range: &str) -> Result<Option<&'a str>> {
+    let range = parse_range(range)?;
+    Ok(versions
+        .into_iter()
+        .filter_map(|version| parse_version(version).ok().map(|parsed| (parsed, version)))
+        .filter(|(parsed, _)| range.matches(parsed))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, version)| version))
+}
+
+/// Classify the largest change between two versions
+///
+/// Versions differing only in their pre-release or build metadata count as
+/// a patch change.
+///
+/// # Returns
+/// * `Ok(Some(DiffKind))` with the most significant component that changed
+/// * `Ok(None)` if the versions are equal
+/// * `Err(anyhow::Error)` if either fails to parse
+pub fn diff_kind(a: &str, b: &str) -> Result<Option<DiffKind>> {
+    let (a, b) = (parse_version(a)?, parse_version(b)?);
+    Ok(if a.major != b.major {
+        Some(DiffKind::Major)
+    } else if a.minor != b.minor {
+        Some(DiffKind::Minor)
+    } else if a != b {
+        Some(DiffKind::Patch)
+    } else {
+        None
+    })
+}
+
+/// Split a whitespace-separated comparator set into Cargo-style comparators
+fn split_comparators(set: &str) -> Vec<String> {
+    let mut comparators = Vec::new();
+    let mut pending_operator = String::new();
+
+    for token in set.split_whitespace() {
+        if token.chars().all(|c| matches!(c, '<' | '>' | '=' | '~' | '^')) {
+            pending_operator.push_str(token);
+            continue;
+        }
+
+        let token = format!("{}{}", std::mem::take(&mut pending_operator), token);
+        let operator_len = token
+            .find(|c: char| !matches!(c, '<' | '>' | '=' | '~' | '^'))
+            .unwrap_or(token.len());
+        let (operator, version) = token.split_at(operator_len);
+        let version = version.trim_start_matches('v');
+
+        let comparator = match operator {
+            // A bare version is an exact match in npm, or an x-range when partial
+            "" | "=" => {
+                if matches!(version, "*" | "x" | "X" | "") {
+                    "*".to_string()
+                } else if version.split('.').count() < 3 && !version.contains(['*', 'x', 'X']) {
+                    format!("{}.*", version)
+                } else if version.contains(['*', 'x', 'X']) {
+                    version.to_string()
+                } else {
+                    format!("={}", version)
+                }
+            }
+            _ => format!("{}{}", operator, version),
+        };
+        comparators.push(comparator);
+    }
+
+    comparators
+}
+
+/// Fill in missing minor/patch components with zeros for lower bounds
+fn normalize_partial(version: &str) -> String {
+    parse_loose_version(version)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| version.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let matches = |range: &str, version: &str| parse_range(range).unwrap().matches(&Version::parse(version).unwrap());
+
+        assert!(matches("1.2.3", "1.2.3"));
+        assert!(!matches("1.2.3", "1.2.4"));
+        assert!(matches(">=14 <16", "15.1.0"));
+        assert!(!matches(">=14 <16", "16.0.0"));
+        assert!(matches(">= 14", "18.0.0"));
+        assert!(matches("^14 || ^16", "16.3.0"));
+        assert!(matches("1.2 - 2.3", "2.3.9"));
+        assert!(!matches("1.2 - 2.3", "1.1.0"));
+        assert!(matches("*", "0.0.1"));
+        assert!(matches("", "5.0.0"));
+        assert!(matches("14", "14.9.0"));
+
+        let err = parse_range("latest").unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::InvalidRange { range, .. }) if range == "latest"));
+    }
+
+    #[test]
+    fn test_satisfies_prereleases() {
+        // Pre-releases only match ranges naming a pre-release of the same version
+        assert!(satisfies("2.0.0-beta.1", ">=2.0.0-alpha <3").unwrap());
+        assert!(!satisfies("2.1.0-beta.1", ">=2.0.0-alpha <3").unwrap());
+        assert!(!satisfies("1.3.0-rc.1", "^1.2.0").unwrap());
+        assert!(satisfies("1.2.0-rc.2", "^1.2.0-rc.1").unwrap());
+        assert!(satisfies("v1.2.3", "=1.2.3").unwrap());
+
+        let err = satisfies("not-a-version", "*").unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CoreError::InvalidVersion { .. })));
+    }
+
+    #[test]
+    fn test_matches_with_included_prereleases() {
+        let range = parse_range("^1.0.0").unwrap();
+        let version = |v: &str| Version::parse(v).unwrap();
+
+        assert!(!range.matches_with(&version("1.2.0-beta.1"), false));
+        assert!(range.matches_with(&version("1.2.0-beta.1"), true));
+        assert!(range.matches_with(&version("1.2.0"), true));
+        assert!(!range.matches_with(&version("2.0.0-beta.1"), true));
+        assert!(!range.matches_with(&version("1.0.0-rc.1"), true), "below the lower bound");
+        assert!(parse_range("<2.0.0").unwrap().matches_with(&version("2.0.0-rc.1"), true));
+    }
+
+    #[test]
+    fn test_max_satisfying() {
+        let versions = ["1.0.0", "1.4.2", "1.10.0", "2.0.0", "2.1.0-beta.1", "garbage"];
+
+        assert_eq!(max_satisfying(versions, "^1.0.0").unwrap(), Some("1.10.0"));
+        assert_eq!(max_satisfying(versions, "*").unwrap(), Some("2.0.0"));
+        assert_eq!(max_satisfying(versions, ">=2.1.0-alpha").unwrap(), Some("2.1.0-beta.1"));
+        assert_eq!(max_satisfying(versions, "^3").unwrap(), None);
+
+        // Pre-releases need a range on their own pre-release line
+        let betas = ["1.0.0-beta.1", "1.0.0-beta.2", "0.9.0"];
+        assert_eq!(max_satisfying(betas, "^1.0.0").unwrap(), None);
+        assert_eq!(max_satisfying(betas, "*").unwrap(), Some("0.9.0"));
+        assert_eq!(max_satisfying(betas, "^1.0.0-beta").unwrap(), Some("1.0.0-beta.2"));
+        assert!(max_satisfying(versions, "next").is_err());
+    }
+
+    #[test]
+    fn test_diff_kind() {
+        assert_eq!(diff_kind("1.2.3", "2.0.0").unwrap(), Some(DiffKind::Major));
+        assert_eq!(diff_kind("1.2.3", "1.3.0").unwrap(), Some(DiffKind::Minor));
+        assert_eq!(diff_kind("1.2.3", "1.2.4").unwrap(), Some(DiffKind::Patch));
+        assert_eq!(diff_kind("1.2.3-beta.1", "1.2.3").unwrap(), Some(DiffKind::Patch));
+        assert_eq!(diff_kind("2.0.0-rc.1", "1.9.0").unwrap(), Some(DiffKind::Major));
+        assert_eq!(diff_kind("1.2.3", "v1.2.3").unwrap(), None);
+        assert!(diff_kind("1.2", "1.2.3").is_err());
+        assert_eq!(DiffKind::Minor.to_string(), "minor");
+    }
+}
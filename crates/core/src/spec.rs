@@ -0,0 +1,477 @@
+//! Install spec parsing
+//!
+//! This module parses the package specs accepted by `install_packages`,
+//! such as `lodash`, `lodash@^4.17.0`, `@scope/pkg@1.0.0`,
+//! `git+https://github.com/org/repo#v1.0.0`, `file:../sibling`, or
+//! `https://example.com/pkg.tgz`.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::CoreError;
+
+/// Longest package name the registry accepts
+pub const MAX_PACKAGE_NAME_LENGTH: usize = 214;
+
+/// Names the registry reserves
+const RESERVED_NAMES: &[&str] = &["node_modules", "favicon.ico"];
+
+/// A reference to check out from a git repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitReference {
+    /// A commit SHA (7–40 hex characters)
+    Commit(String),
+    /// A tag, recognized by looking like a version (e.g. `v1.2.0`)
+    Tag(String),
+    /// Any other named ref
+    Branch(String),
+}
+
+impl GitReference {
+    /// Classify a `#ref` fragment as a commit, tag, or branch
+    pub fn parse(reference: &str) -> Self {
+        let is_hex = reference.chars().all(|c| c.is_ascii_hexdigit());
+        if is_hex && (7..=40).contains(&reference.len()) {
+            return GitReference::Commit(reference.to_string());
+        }
+
+        let unprefixed = reference.strip_prefix('v').unwrap_or(reference);
+        if unprefixed.starts_with(|c: char| c.is_ascii_digit()) && unprefixed.contains('.') {
+            return GitReference::Tag(reference.to_string());
+        }
+
+        GitReference::Branch(reference.to_string())
+    }
+
+    /// Get the ref name as written in the spec
+    pub fn as_str(&self) -> &str {
+        match self {
+            GitReference::Commit(r) | GitReference::Tag(r) | GitReference::Branch(r) => r,
+        }
+    }
+}
+
+/// A parsed install spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageSpec {
+    /// A package from the registry, with an optional version, tag, or range
+    Registry { name: String, range: Option<String> },
+    /// A package cloned from a git repository
+    Git {
+        /// Name given in the spec (`name@git+...`), if any
+        name: Option<String>,
+        /// URL that can be passed to `git clone`
+        url: String,
+        reference: Option<GitReference>,
+    },
+    /// A package in a local directory
+    File {
+        /// Name given in the spec (`name@file:...`), if any
+        name: Option<String>,
+        /// Path to the package directory, relative to the project root unless absolute
+        path: PathBuf,
+    },
+    /// A package tarball downloaded directly from a URL
+    Tarball {
+        /// Name given in the spec (`name@https://...`), if any
+        name: Option<String>,
+        url: String,
+        /// SRI string given as the URL fragment (`#sha512-...`), if any
+        integrity: Option<String>,
+    },
+}
+
+impl PackageSpec {
+    /// Parse an install spec
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            anyhow::bail!("Empty package spec");
+        }
+
+        if is_git_spec(spec) {
+            return parse_git(None, spec);
+        }
+        if is_file_spec(spec) {
+            return parse_file(None, spec);
+        }
+        if is_tarball_spec(spec) {
+            return Ok(parse_tarball(None, spec));
+        }
+
+        let (name, range) = split_name_and_range(spec);
+        if let Some(range) = range.filter(|range| is_git_spec(range)) {
+            return parse_git(Some(name.to_string()), range);
+        }
+        if let Some(range) = range.filter(|range| is_file_spec(range)) {
+            return parse_file(Some(name.to_string()), range);
+        }
+        if let Some(range) = range.filter(|range| is_tarball_spec(range)) {
+            return Ok(parse_tarball(Some(name.to_string()), range));
+        }
+
+        Ok(PackageSpec::Registry {
+            name: name.to_string(),
+            range: range.map(str::to_string),
+        })
+    }
+}
+
+/// Read install specs from a file, one per line
+///
+/// Lines are trimmed; blank lines and lines starting with `#` are ignored.
+/// Every remaining spec must parse, and registry specs must name a valid
+/// package.
+///
+/// # Arguments
+/// * `path` - File holding newline-separated specs
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with the specs in file order
+/// * `Err(anyhow::Error)` naming the first invalid line
+pub fn read_spec_file(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut specs = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let validated = PackageSpec::parse(line).and_then(|spec| match spec {
+            PackageSpec::Registry { name, .. } => validate_package_name(&name).map_err(Into::into),
+            _ => Ok(()),
+        });
+        validated.with_context(|| format!("Invalid spec on line {} of {}", index + 1, path.display()))?;
+        specs.push(line.to_string());
+    }
+
+    Ok(specs)
+}
+
+/// Validate a package name against npm's naming rules
+///
+/// Names must be non-empty, at most 214 characters, lowercase, must not
+/// start with `.` or `_`, and may only contain URL-safe characters
+/// (letters, digits, `-`, `.` and `_`). Scoped names must have the form
+/// `@scope/name` with both parts following the same character rules.
+///
+/// # Returns
+/// * `Ok(())` if the name is valid
+/// * `Err(CoreError::InvalidPackageName)` naming the first rule broken
+pub fn validate_package_name(name: &str) -> std::result::Result<(), CoreError> {
+    let invalid = |reason: &str| {
+        Err(CoreError::InvalidPackageName {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+
+    if name.is_empty() {
+        return invalid("name length must be greater than zero");
+    }
+    if name.trim() != name {
+        return invalid("name cannot contain leading or trailing spaces");
+    }
+    if name.len() > MAX_PACKAGE_NAME_LENGTH {
+        return invalid("name can't contain more than 214 characters");
+    }
+    if name.starts_with('.') {
+        return invalid("name cannot start with a period");
+    }
+    if name.starts_with('_') {
+        return invalid("name cannot start with an underscore");
+    }
+    if RESERVED_NAMES.contains(&name.to_lowercase().as_str()) {
+        return invalid("name is reserved");
+    }
+    if name.chars().any(|c| c.is_ascii_uppercase()) {
+        return invalid("name can't contain capital letters");
+    }
+    if name.chars().any(|c| "~'!()*".contains(c)) {
+        return invalid("name can't contain special characters (\"~'!()*\")");
+    }
+
+    let parts: Vec<&str> = match name.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('/') {
+            Some((scope, package)) if !scope.is_empty() && !package.is_empty() && !package.contains('/') => {
+                vec![scope, package]
+            }
+            _ => return invalid("scoped names must have the form @scope/name"),
+        },
+        None => vec![name],
+    };
+    let url_safe = |part: &str| {
+        part.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-._".contains(c))
+    };
+    if !parts.into_iter().all(url_safe) {
+        return invalid("name can only contain URL-friendly characters");
+    }
+
+    Ok(())
+}
+
+/// Split `name@range` into its parts, allowing for a leading scope `@`
+fn split_name_and_range(spec: &str) -> (&str, Option<&str>) {
+    // Search from the first character so a scope `@` isn't mistaken for the separator
+    match spec.get(1..).and_then(|rest| rest.find('@')) {
+        Some(index) => (&spec[..index + 1], Some(&spec[index + 2..])),
+        None => (spec, None),
+    }
+}
+
+/// Check whether a spec (or the range part of one) is a tarball URL
+fn is_tarball_spec(spec: &str) -> bool {
+    spec.starts_with("https://") || spec.starts_with("http://")
+}
+
+/// Parse a tarball URL, taking an SRI fragment as its integrity
+fn parse_tarball(name: Option<String>, spec: &str) -> PackageSpec {
+    let (url, integrity) = match spec.split_once('#') {
+        Some((url, fragment)) if fragment.contains('-') => (url, Some(fragment.to_string())),
+        _ => (spec, None),
+    };
+
+    PackageSpec::Tarball {
+        name,
+        url: url.to_string(),
+        integrity,
+    }
+}
+
+/// Check whether a spec (or the range part of one) refers to a git repository
+fn is_git_spec(spec: &str) -> bool {
+    ["git+", "git://", "git@", "github:", "gitlab:", "bitbucket:"]
+        .iter()
+        .any(|prefix| spec.starts_with(prefix))
+}
+
+/// Check whether a spec (or the range part of one) refers to a local directory
+fn is_file_spec(spec: &str) -> bool {
+    ["file:", "./", "../", "/"].iter().any(|prefix| spec.starts_with(prefix))
+}
+
+/// Parse a `file:` spec into the directory it points at
+fn parse_file(name: Option<String>, spec: &str) -> Result<PackageSpec> {
+    let path = spec.strip_prefix("file:").unwrap_or(spec);
+    if path.is_empty() {
+        anyhow::bail!("Empty path in {}", spec);
+    }
+
+    Ok(PackageSpec::File {
+        name,
+        path: PathBuf::from(path),
+    })
+}
+
+/// Parse a git spec into a clonable URL and reference
+fn parse_git(name: Option<String>, spec: &str) -> Result<PackageSpec> {
+    let (location, reference) = match spec.split_once('#') {
+        Some((location, reference)) => (location, Some(reference)),
+        None => (spec, None),
+    };
+
+    if let Some(reference) = reference {
+        if reference.is_empty() {
+            anyhow::bail!("Empty git reference in {}", spec);
+        }
+        if reference.starts_with("semver:") {
+            anyhow::bail!("semver git references are not supported: {}", spec);
+        }
+    }
+
+    let url = if let Some(path) = location.strip_prefix("github:") {
+        format!("https://github.com/{}.git", path.trim_end_matches(".git"))
+    } else if let Some(path) = location.strip_prefix("gitlab:") {
+        format!("https://gitlab.com/{}.git", path.trim_end_matches(".git"))
+    } else if let Some(path) = location.strip_prefix("bitbucket:") {
+        format!("https://bitbucket.org/{}.git", path.trim_end_matches(".git"))
+    } else {
+        location.strip_prefix("git+").unwrap_or(location).to_string()
+    };
+
+    Ok(PackageSpec::Git {
+        name,
+        url,
+        reference: reference.map(GitReference::parse),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(name: &str, range: Option<&str>) -> PackageSpec {
+        PackageSpec::Registry {
+            name: name.to_string(),
+            range: range.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_registry_specs() {
+        assert_eq!(PackageSpec::parse("lodash").unwrap(), registry("lodash", None));
+        assert_eq!(PackageSpec::parse("lodash@^4.17.0").unwrap(), registry("lodash", Some("^4.17.0")));
+        assert_eq!(PackageSpec::parse("@types/node").unwrap(), registry("@types/node", None));
+        assert_eq!(PackageSpec::parse("@types/node@18").unwrap(), registry("@types/node", Some("18")));
+        assert!(PackageSpec::parse("  ").is_err());
+    }
+
+    #[test]
+    fn test_read_spec_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("packages.txt");
+        std::fs::write(
+            &path,
+            "# runtime dependencies\nlodash@^4.17.0\n\n  @types/node@18  \n\t\n# git dependency\nrepo@git+https://github.com/org/repo#v1.0.0\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_spec_file(&path).unwrap(),
+            vec!["lodash@^4.17.0", "@types/node@18", "repo@git+https://github.com/org/repo#v1.0.0"]
+        );
+    }
+
+    #[test]
+    fn test_read_spec_file_rejects_invalid_specs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("packages.txt");
+        std::fs::write(&path, "lodash\n# ok so far\nBad_Name@1.0.0\n").unwrap();
+
+        let err = read_spec_file(&path).unwrap_err();
+        assert!(err.to_string().contains("line 3"));
+        assert!(matches!(err.downcast_ref::<CoreError>(), Some(CoreError::InvalidPackageName { .. })));
+
+        assert!(read_spec_file(&dir.path().join("missing.txt")).is_err());
+    }
+
+    #[test]
+    fn test_parse_git_refs() {
+        let spec = PackageSpec::parse("git+https://github.com/org/repo.git#v1.2.0").unwrap();
+        assert_eq!(
+            spec,
+            PackageSpec::Git {
+                name: None,
+                url: "https://github.com/org/repo.git".to_string(),
+                reference: Some(GitReference::Tag("v1.2.0".to_string())),
+            }
+        );
+
+        let spec = PackageSpec::parse("git+https://github.com/org/repo#main").unwrap();
+        assert!(matches!(spec, PackageSpec::Git { reference: Some(GitReference::Branch(ref b)), .. } if b == "main"));
+
+        let spec = PackageSpec::parse("git+ssh://git@github.com/org/repo.git#4f2a9c1").unwrap();
+        match spec {
+            PackageSpec::Git { url, reference, .. } => {
+                assert_eq!(url, "ssh://git@github.com/org/repo.git");
+                assert_eq!(reference, Some(GitReference::Commit("4f2a9c1".to_string())));
+            }
+            other => panic!("Expected git spec, got {:?}", other),
+        }
+
+        let spec = PackageSpec::parse("git://github.com/org/repo.git").unwrap();
+        assert!(matches!(spec, PackageSpec::Git { reference: None, .. }));
+    }
+
+    #[test]
+    fn test_parse_git_shorthands() {
+        let spec = PackageSpec::parse("github:org/repo#feature/x").unwrap();
+        assert_eq!(
+            spec,
+            PackageSpec::Git {
+                name: None,
+                url: "https://github.com/org/repo.git".to_string(),
+                reference: Some(GitReference::Branch("feature/x".to_string())),
+            }
+        );
+
+        let spec = PackageSpec::parse("my-lib@git+https://example.com/my-lib.git#1.0.0").unwrap();
+        assert!(matches!(spec, PackageSpec::Git { name: Some(ref n), .. } if n == "my-lib"));
+
+        assert!(PackageSpec::parse("git+https://github.com/org/repo#").is_err());
+        assert!(PackageSpec::parse("github:org/repo#semver:^1.0").is_err());
+    }
+
+    #[test]
+    fn test_parse_file_specs() {
+        assert_eq!(
+            PackageSpec::parse("file:../sibling").unwrap(),
+            PackageSpec::File {
+                name: None,
+                path: PathBuf::from("../sibling"),
+            }
+        );
+        assert_eq!(
+            PackageSpec::parse("shared@file:packages/shared").unwrap(),
+            PackageSpec::File {
+                name: Some("shared".to_string()),
+                path: PathBuf::from("packages/shared"),
+            }
+        );
+        assert!(matches!(PackageSpec::parse("./local").unwrap(), PackageSpec::File { .. }));
+        assert!(PackageSpec::parse("file:").is_err());
+    }
+
+    #[test]
+    fn test_parse_tarball_specs() {
+        assert_eq!(
+            PackageSpec::parse("https://example.com/pkg-1.0.0.tgz").unwrap(),
+            PackageSpec::Tarball {
+                name: None,
+                url: "https://example.com/pkg-1.0.0.tgz".to_string(),
+                integrity: None,
+            }
+        );
+        assert_eq!(
+            PackageSpec::parse("pkg@https://example.com/pkg.tgz#sha512-abc=").unwrap(),
+            PackageSpec::Tarball {
+                name: Some("pkg".to_string()),
+                url: "https://example.com/pkg.tgz".to_string(),
+                integrity: Some("sha512-abc=".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_package_name_valid() {
+        for name in ["lodash", "left-pad", "socket.io", "a", "@types/node", "@babel/plugin-transform-runtime", "d3_v4"] {
+            assert!(validate_package_name(name).is_ok(), "{} should be valid", name);
+        }
+        assert!(validate_package_name(&"a".repeat(MAX_PACKAGE_NAME_LENGTH)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_package_name_invalid() {
+        let cases = [
+            ("", "greater than zero"),
+            (" lodash", "leading or trailing spaces"),
+            (".hidden", "period"),
+            ("_private", "underscore"),
+            ("node_modules", "reserved"),
+            ("JSONStream", "capital letters"),
+            ("wow!", "special characters"),
+            ("@scope", "@scope/name"),
+            ("@/name", "@scope/name"),
+            ("@scope/", "@scope/name"),
+            ("@a/b/c", "@scope/name"),
+            ("foo/bar", "URL-friendly"),
+            ("caf\u{e9}", "URL-friendly"),
+            ("a b", "URL-friendly"),
+            ("%2e%2e", "URL-friendly"),
+        ];
+        for (name, rule) in cases {
+            match validate_package_name(name) {
+                Err(CoreError::InvalidPackageName { reason, .. }) => {
+                    assert!(reason.contains(rule), "{:?}: expected {:?}, got {:?}", name, rule, reason)
+                }
+                other => panic!("{:?} should be invalid, got {:?}", name, other),
+            }
+        }
+
+        let too_long = "a".repeat(MAX_PACKAGE_NAME_LENGTH + 1);
+        assert!(validate_package_name(&too_long).unwrap_err().to_string().contains("214"));
+    }
+}
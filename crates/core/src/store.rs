@@ -0,0 +1,551 @@
+//! Content-addressed tarball store
+//!
+//! Tarballs are stored under `<root>/<algorithm>/<hex digest>`, so an entry's
+//! path is enough to re-check its contents. Entries added from the registry
+//! also record their source URL in a `<hex digest>.source` file next to them,
+//! which lets corrupt entries be downloaded again.
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::backend::PackageRegistry;
+use crate::error::CoreError;
+use crate::integrity::{parse_sri, HashAlgorithm, Integrity};
+use crate::registry::default_cache_dir;
+use crate::PackageDistribution;
+
+/// Extension of the file recording where an entry was downloaded from
+const SOURCE_EXTENSION: &str = "source";
+
+/// Store configuration
+#[derive(Debug, Clone)]
+pub struct StoreConfig {
+    /// Directory holding the store entries
+    pub root: PathBuf,
+    /// Re-hash entries read with `Store::get_verified`; turning this off trusts the path
+    pub verify_on_read: bool,
+    /// Remove entries that fail verification on read
+    pub evict_corrupt: bool,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            root: default_cache_dir()
+                .unwrap_or_else(|| std::env::temp_dir().join("package-fast"))
+                .join("store"),
+            verify_on_read: true,
+            evict_corrupt: true,
+        }
+    }
+}
+
+/// A tarball held in the store
+#[derive(Debug, Clone)]
+pub struct StoreEntry {
+    pub integrity: Integrity,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Result of re-hashing the store
+#[derive(Debug, Clone, Default)]
+pub struct StoreVerifyReport {
+    /// Number of entries checked
+    pub checked: usize,
+    /// Entries whose contents didn't match their digest and were removed
+    pub removed: Vec<PathBuf>,
+    /// Bytes freed by removing corrupt entries
+    pub bytes_reclaimed: u64,
+}
+
+/// Result of repairing the store
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// Number of entries checked
+    pub checked: usize,
+    /// Corrupt entries replaced with freshly downloaded contents
+    pub repaired: Vec<PathBuf>,
+    /// Corrupt entries left alone because their source URL is unknown
+    pub skipped: Vec<PathBuf>,
+    /// Corrupt entries that could not be repaired, with the reason
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Content-addressed tarball store
+#[derive(Debug, Clone)]
+pub struct Store {
+    config: StoreConfig,
+}
+
+impl Store {
+    /// Create a store at the default location
+    pub fn new() -> Self {
+        Self::with_config(StoreConfig::default())
+    }
+
+    /// Create a store with custom configuration
+    pub fn with_config(config: StoreConfig) -> Self {
+        Self { config }
+    }
+
+    /// Get the store root directory
+    pub fn root(&self) -> &Path {
+        &self.config.root
+    }
+
+    /// Get the path an entry with the given integrity is stored at
+    pub fn path_for(&self, integrity: &Integrity) -> PathBuf {
+        self.config
+            .root
+            .join(integrity.algorithm.name())
+            .join(to_hex(&integrity.digest))
+    }
+
+    /// Add a tarball to the store
+    ///
+    /// # Arguments
+    /// * `bytes` - Tarball contents
+    ///
+    /// # Returns
+    /// * `Ok(Integrity)` with the SHA-512 integrity the entry is stored under
+    /// * `Err(anyhow::Error)` if the entry could not be written
+    pub fn put(&self, bytes: &[u8]) -> Result<Integrity> {
+        let integrity = Integrity {
+            algorithm: HashAlgorithm::Sha512,
+            digest: HashAlgorithm::Sha512.digest(bytes),
+        };
+        let path = self.path_for(&integrity);
+
+        if !path.exists() {
+            write_atomic(&path, bytes)?;
+        }
+
+        Ok(integrity)
+    }
+
+    /// Add a tarball to the store, recording the URL it was downloaded from
+    ///
+    /// # Arguments
+    /// * `bytes` - Tarball contents
+    /// * `source` - URL the tarball can be downloaded from again
+    pub fn put_from(&self, bytes: &[u8], source: &str) -> Result<Integrity> {
+        let integrity = self.put(bytes)?;
+        write_atomic(&source_path(&self.path_for(&integrity)), source.as_bytes())?;
+        Ok(integrity)
+    }
+
+    /// Get the URL an entry was downloaded from, if it was recorded
+    pub fn source_of(&self, integrity: &Integrity) -> Option<String> {
+        fs::read_to_string(source_path(&self.path_for(integrity))).ok()
+    }
+
+    /// Read a tarball from the store, if present
+    pub fn get(&self, integrity: &Integrity) -> Option<Vec<u8>> {
+        fs::read(self.path_for(integrity)).ok()
+    }
+
+    /// Read a tarball from the store, re-hashing it to detect on-disk corruption
+    ///
+    /// With `verify_on_read` off the bytes are returned as read. A corrupt
+    /// entry is removed, along with its source record, when `evict_corrupt`
+    /// is on, so the next install downloads it again.
+    ///
+    /// # Arguments
+    /// * `hash` - SRI integrity string of the entry, e.g. `sha512-<base64 digest>`
+    ///
+    /// # Returns
+    /// * `Ok(Bytes)` with the entry's contents
+    /// * `Err(CoreError::StoreEntryCorrupt)` if the contents don't match `hash`
+    /// * `Err(anyhow::Error)` if `hash` is invalid or the entry can't be read
+    pub fn get_verified(&self, hash: &str) -> Result<Bytes> {
+        let integrity = parse_sri(hash)?;
+        let path = self.path_for(&integrity);
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read store entry {}", path.display()))?;
+
+        if self.config.verify_on_read && !integrity.matches(&bytes) {
+            if self.config.evict_corrupt {
+                warn!("Evicting corrupt store entry {}", path.display());
+                fs::remove_file(&path)?;
+                let _ = fs::remove_file(source_path(&path));
+            }
+            return Err(CoreError::StoreEntryCorrupt {
+                path,
+                expected: integrity.to_sri(),
+            }
+            .into());
+        }
+
+        Ok(Bytes::from(bytes))
+    }
+
+    /// List all entries in the store
+    pub fn entries(&self) -> Result<Vec<StoreEntry>> {
+        let mut entries = Vec::new();
+
+        for algorithm in [HashAlgorithm::Sha1, HashAlgorithm::Sha256, HashAlgorithm::Sha512] {
+            let dir = self.config.root.join(algorithm.name());
+            if !dir.is_dir() {
+                continue;
+            }
+
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let Some(digest) = entry.file_name().to_str().and_then(from_hex) else {
+                    continue;
+                };
+                entries.push(StoreEntry {
+                    integrity: Integrity { algorithm, digest },
+                    path: entry.path(),
+                    size: entry.metadata()?.len(),
+                });
+            }
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    /// Re-hash every entry and remove the ones that don't match their digest
+    pub fn verify(&self) -> Result<StoreVerifyReport> {
+        let mut report = StoreVerifyReport::default();
+
+        for entry in self.entries()? {
+            report.checked += 1;
+            let bytes = fs::read(&entry.path)?;
+            if !entry.integrity.matches(&bytes) {
+                warn!("Removing corrupt store entry {}", entry.path.display());
+                fs::remove_file(&entry.path)?;
+                let _ = fs::remove_file(source_path(&entry.path));
+                report.bytes_reclaimed += entry.size;
+                report.removed.push(entry.path);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Re-hash every entry and download corrupt ones again from their source
+    ///
+    /// A replacement is only written if it matches the entry's digest.
+    /// Corrupt entries without a recorded source URL are left in place.
+    ///
+    /// # Arguments
+    /// * `registry` - Registry used to download replacements
+    ///
+    /// # Returns
+    /// * `Ok(RepairReport)` listing repaired, skipped and failed entries
+    /// * `Err(anyhow::Error)` if the store can't be read
+    pub async fn repair(&self, registry: &dyn PackageRegistry) -> Result<RepairReport> {
+        let mut report = RepairReport::default();
+
+        for entry in self.entries()? {
+            report.checked += 1;
+            let bytes = fs::read(&entry.path)?;
+            if entry.integrity.matches(&bytes) {
+                continue;
+            }
+
+            let Some(source) = self.source_of(&entry.integrity) else {
+                warn!("Store entry {} is corrupt but its source is unknown", entry.path.display());
+                report.skipped.push(entry.path);
+                continue;
+            };
+
+            info!("Repairing store entry {} from {}", entry.path.display(), source);
+            let dist = PackageDistribution {
+                tarball: source,
+                shasum: String::new(),
+                integrity: Some(entry.integrity.to_sri()),
+            };
+            let outcome = match registry.tarball(&dist).await {
+                Ok(fresh) if entry.integrity.matches(&fresh) => write_atomic(&entry.path, &fresh),
+                Ok(_) => Err(anyhow::anyhow!("downloaded contents don't match {}", entry.integrity.to_sri())),
+                Err(e) => Err(e),
+            };
+            match outcome {
+                Ok(()) => report.repaired.push(entry.path),
+                Err(e) => {
+                    warn!("Failed to repair store entry {}: {}", entry.path.display(), e);
+                    report.failed.push((entry.path, e.to_string()));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Remove every entry from the store
+    ///
+    /// # Returns
+    /// * `Ok(u64)` with the number of bytes reclaimed
+    pub fn clean(&self) -> Result<u64> {
+        info!("Cleaning store at {}", self.config.root.display());
+        remove_dir_contents(&self.config.root)
+    }
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Path of the file recording an entry's source URL
+fn source_path(entry: &Path) -> PathBuf {
+    entry.with_extension(SOURCE_EXTENSION)
+}
+
+/// Write a file through a temporary file so a crash never leaves it partial
+///
+/// Every call gets its own uniquely named temporary file, so concurrent
+/// writers in the same directory never rename each other's bytes.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let parent = path.parent().expect("store entries always have a parent");
+    fs::create_dir_all(parent).context("Failed to create store directory")?;
+
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".")
+        .suffix(".tmp")
+        .tempfile_in(parent)
+        .context("Failed to create a temporary store file")?;
+    tmp.write_all(bytes)?;
+    tmp.persist(path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Remove a directory tree, returning the number of file bytes it held
+///
+/// A missing directory counts as already empty.
+pub(crate) fn remove_dir_contents(dir: &Path) -> Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let size = dir_size(dir)?;
+    fs::remove_dir_all(dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    Ok(size)
+}
+
+/// Total size of the files under a directory
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store(dir: &TempDir) -> Store {
+        Store::with_config(StoreConfig {
+            root: dir.path().join("store"),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_put_and_get() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+
+        let integrity = store.put(b"tarball").unwrap();
+        assert!(integrity.matches(b"tarball"));
+        assert_eq!(store.get(&integrity).unwrap(), b"tarball");
+        assert_eq!(store.entries().unwrap().len(), 1);
+
+        // Adding the same contents again is a no-op
+        store.put(b"tarball").unwrap();
+        assert_eq!(store.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_write_atomic_concurrent_writers_keep_their_bytes() {
+        let dir = TempDir::new().unwrap();
+        std::thread::scope(|scope| {
+            for writer in 0..16 {
+                let path = dir.path().join(format!("entry-{}", writer));
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        write_atomic(&path, writer.to_string().repeat(4096).as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+
+        for writer in 0..16 {
+            let bytes = fs::read(dir.path().join(format!("entry-{}", writer))).unwrap();
+            assert_eq!(bytes, writer.to_string().repeat(4096).as_bytes());
+        }
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 16);
+    }
+
+    #[test]
+    fn test_clean_empties_store() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        store.put(b"first").unwrap();
+        store.put(b"second tarball").unwrap();
+
+        let reclaimed = store.clean().unwrap();
+        assert_eq!(reclaimed, 19);
+        assert!(store.entries().unwrap().is_empty());
+        assert_eq!(store.clean().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_verify_removes_corrupt_entries() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let good = store.put(b"good").unwrap();
+        let bad = store.put(b"bad").unwrap();
+        fs::write(store.path_for(&bad), b"bit rot").unwrap();
+
+        let report = store.verify().unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.removed, vec![store.path_for(&bad)]);
+        assert_eq!(report.bytes_reclaimed, 7);
+        assert!(store.get(&good).is_some());
+        assert!(store.get(&bad).is_none());
+    }
+
+    /// Registry serving tarballs from memory by URL
+    struct TarballRegistry(std::collections::HashMap<String, &'static [u8]>);
+
+    #[async_trait::async_trait]
+    impl PackageRegistry for TarballRegistry {
+        async fn metadata(&self, name: &str) -> Result<crate::PackageMetadata> {
+            anyhow::bail!("no metadata for {}", name)
+        }
+
+        async fn tarball(&self, dist: &PackageDistribution) -> Result<bytes::Bytes> {
+            self.0
+                .get(&dist.tarball)
+                .map(|bytes| bytes::Bytes::from_static(bytes))
+                .ok_or_else(|| anyhow::anyhow!("HTTP 404 for {}", dist.tarball))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repair_redownloads_corrupt_entries() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let good = store.put_from(b"good", "https://registry.example/good.tgz").unwrap();
+        let corrupt = store.put_from(b"corrupt", "https://registry.example/corrupt.tgz").unwrap();
+        let unknown = store.put(b"unknown source").unwrap();
+        fs::write(store.path_for(&corrupt), b"bit rot").unwrap();
+        fs::write(store.path_for(&unknown), b"bit rot").unwrap();
+        assert_eq!(store.source_of(&good).as_deref(), Some("https://registry.example/good.tgz"));
+
+        let registry = TarballRegistry(
+            [("https://registry.example/corrupt.tgz".to_string(), b"corrupt".as_slice())]
+                .into_iter()
+                .collect(),
+        );
+        let report = store.repair(&registry).await.unwrap();
+
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.repaired, vec![store.path_for(&corrupt)]);
+        assert_eq!(report.skipped, vec![store.path_for(&unknown)]);
+        assert!(report.failed.is_empty());
+        assert_eq!(store.get(&corrupt).unwrap(), b"corrupt");
+        assert_eq!(store.get(&good).unwrap(), b"good");
+    }
+
+    #[tokio::test]
+    async fn test_repair_rejects_mismatched_download() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let entry = store.put_from(b"original", "https://registry.example/pkg.tgz").unwrap();
+        fs::write(store.path_for(&entry), b"bit rot").unwrap();
+
+        let registry = TarballRegistry(
+            [("https://registry.example/pkg.tgz".to_string(), b"republished".as_slice())]
+                .into_iter()
+                .collect(),
+        );
+        let report = store.repair(&registry).await.unwrap();
+
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].1.contains("don't match"));
+        assert_eq!(store.get(&entry).unwrap(), b"bit rot");
+    }
+
+    #[test]
+    fn test_get_verified_rejects_corrupt_entries() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let good = store.put(b"good").unwrap();
+        let bad = store.put_from(b"bad", "https://registry.example/bad.tgz").unwrap();
+        fs::write(store.path_for(&bad), b"bit rot").unwrap();
+
+        assert_eq!(store.get_verified(&good.to_sri()).unwrap(), Bytes::from_static(b"good"));
+
+        let err = store.get_verified(&bad.to_sri()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::StoreEntryCorrupt { expected, .. }) if *expected == bad.to_sri()
+        ));
+        assert!(!store.path_for(&bad).exists(), "corrupt entry was not evicted");
+        assert!(store.source_of(&bad).is_none());
+        assert!(store.get_verified(&bad.to_sri()).is_err());
+    }
+
+    #[test]
+    fn test_get_verified_can_skip_verification() {
+        let dir = TempDir::new().unwrap();
+        let trusting = Store::with_config(StoreConfig {
+            root: dir.path().join("store"),
+            verify_on_read: false,
+            ..Default::default()
+        });
+        let bad = trusting.put(b"bad").unwrap();
+        fs::write(trusting.path_for(&bad), b"bit rot").unwrap();
+        assert_eq!(trusting.get_verified(&bad.to_sri()).unwrap(), Bytes::from_static(b"bit rot"));
+
+        let keeping = Store::with_config(StoreConfig {
+            root: dir.path().join("store"),
+            evict_corrupt: false,
+            ..Default::default()
+        });
+        assert!(keeping.get_verified(&bad.to_sri()).is_err());
+        assert!(keeping.path_for(&bad).exists());
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        assert_eq!(to_hex(&[0x00, 0xab, 0xff]), "00abff");
+        assert_eq!(from_hex("00abff"), Some(vec![0x00, 0xab, 0xff]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
@@ -0,0 +1,507 @@
+//! Package tarball handling
+//!
+//! This module downloads package tarballs, reads their embedded manifest,
+//! and extracts them into a package directory. Downloads are staged in a
+//! temp directory, the system one unless configured otherwise; extraction
+//! is staged in the configured one, or else next to the package directory.
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use crate::integrity::verify_sri;
+use crate::manifest::MANIFEST_FILE;
+use crate::registry::Registry;
+use crate::spec::validate_package_name;
+use crate::PackageInfo;
+
+/// Get the temp directory to stage downloads and extraction in
+///
+/// A configured directory is created if absent and must be writable; when
+/// none is configured, the system temp directory is used.
+///
+/// # Arguments
+/// * `configured` - Directory from `InstallOptions::temp_dir`, if any
+///
+/// # Returns
+/// * `Ok(PathBuf)` with the directory to use
+/// * `Err(anyhow::Error)` if the configured directory can't be created or written to
+pub fn prepare_temp_dir(configured: Option<&Path>) -> Result<PathBuf> {
+    let Some(dir) = configured else {
+        return Ok(std::env::temp_dir());
+    };
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create temp directory {}", dir.display()))?;
+    tempfile::tempfile_in(dir).with_context(|| format!("Temp directory {} is not writable", dir.display()))?;
+    Ok(dir.to_path_buf())
+}
+
+/// Download a tarball using the default registry client configuration
+///
+/// The tarball is written to a file in the temp directory while it
+/// downloads, so interrupted transfers are resumed, and removed once read.
+///
+/// # Arguments
+/// * `url` - URL of the `.tgz` file
+/// * `temp_dir` - Temp directory to download into, or `None` for the system one
+/// * `max_size` - Most bytes to accept, or `None` for no limit
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` with the compressed tarball bytes
+/// * `Err(anyhow::Error)` if the temp directory is unusable, the request
+///   fails or the tarball exceeds `max_size`
+pub async fn download_tarball(url: &str, temp_dir: Option<&Path>, max_size: Option<u64>) -> Result<Vec<u8>> {
+    let dir = prepare_temp_dir(temp_dir)?;
+    let dest = tempfile::Builder::new()
+        .prefix("package-fast-")
+        .suffix(".tgz")
+        .tempfile_in(&dir)
+        .with_context(|| format!("Failed to create a download file in {}", dir.display()))?
+        .into_temp_path();
+    Registry::new().download_tarball_to(url, &dest, max_size, |_| Ok(())).await
+}
+
+/// Download a tarball dependency and read its manifest
+///
+/// # Arguments
+/// * `url` - URL of the `.tgz` file
+/// * `integrity` - SRI string the tarball must match, if known
+/// * `temp_dir` - Temp directory to download into, or `None` for the system one
+/// * `max_size` - Most bytes to accept, or `None` for no limit
+///
+/// # Returns
+/// * `Ok((Vec<u8>, PackageInfo))` with the tarball bytes and embedded manifest
+pub async fn resolve_tarball_dependency(
+    url: &str,
+    integrity: Option<&str>,
+    temp_dir: Option<&Path>,
+    max_size: Option<u64>,
+) -> Result<(Vec<u8>, PackageInfo)> {
+    let bytes = download_tarball(url, temp_dir, max_size).await?;
+
+    if let Some(integrity) = integrity {
+        verify_sri(&bytes, integrity).with_context(|| format!("Tarball {} failed verification", url))?;
+    }
+
+    let pkg_info = read_tarball_manifest(&bytes).with_context(|| format!("Invalid package tarball {}", url))?;
+    // The name becomes a path under node_modules
+    validate_package_name(&pkg_info.name).with_context(|| format!("Invalid package tarball {}", url))?;
+    Ok((bytes, pkg_info))
+}
+
+/// Read the `package.json` embedded in a tarball
+///
+/// npm tarballs nest everything under a single top-level directory
+/// (usually `package/`), so the manifest is looked up one level down.
+pub fn read_tarball_manifest(bytes: &[u8]) -> Result<PackageInfo> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut components = path.components();
+        components.next();
+        if components.as_path() == Path::new(MANIFEST_FILE) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return serde_json::from_str(&content).context("Failed to parse embedded package.json");
+        }
+    }
+
+    anyhow::bail!("Tarball does not contain a {}", MANIFEST_FILE)
+}
+
+/// Extract a tarball into a package directory
+///
+/// The top-level directory inside the tarball is stripped. Entries that
+/// would escape `dest` (absolute paths or `..` components) are rejected.
+/// The tarball is unpacked into a staging directory first and only moved
+/// into `dest` once complete, so a malformed tarball leaves `dest`
+/// untouched. Without a configured temp directory, the staging directory is
+/// a hidden sibling of `dest`, so the move is a rename on the same
+/// filesystem; when `dest` doesn't exist yet, the whole directory is
+/// renamed into place at once. The previous contents of an existing `dest`
+/// are set aside and removed first, so no file of an older version is
+/// left behind; only its `node_modules`, where nested packages are
+/// installed, is kept.
+///
+/// # Arguments
+/// * `bytes` - Compressed tarball bytes
+/// * `dest` - Package directory to extract into
+/// * `temp_dir` - Temp directory to stage in, or `None` to stage next to `dest`
+pub fn extract_tarball(bytes: &[u8], dest: &Path, temp_dir: Option<&Path>) -> Result<()> {
+    let dir = match temp_dir {
+        Some(dir) => dir,
+        None => dest
+            .parent()
+            .with_context(|| format!("Cannot extract into {}", dest.display()))?,
+    };
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let staging = tempfile::Builder::new()
+        .prefix(".package-fast-extract-")
+        .tempdir_in(dir)
+        .with_context(|| format!("Failed to create a staging directory in {}", dir.display()))?;
+    unpack_tarball(bytes, staging.path())?;
+
+    if !dest.exists() && fs::rename(staging.path(), dest).is_ok() {
+        return Ok(());
+    }
+    fs::create_dir_all(dest)?;
+
+    // Other extractions may be nesting packages in `dest/node_modules` meanwhile, so it stays put
+    let parent = dest
+        .parent()
+        .with_context(|| format!("Cannot extract into {}", dest.display()))?;
+    let replaced = tempfile::Builder::new()
+        .prefix(".package-fast-replaced-")
+        .tempdir_in(parent)
+        .with_context(|| format!("Failed to create a directory in {}", parent.display()))?;
+    for entry in fs::read_dir(dest)? {
+        let entry = entry?;
+        if entry.file_name() != "node_modules" {
+            fs::rename(entry.path(), replaced.path().join(entry.file_name()))
+                .with_context(|| format!("Failed to set {} aside", entry.path().display()))?;
+        }
+    }
+    move_into(staging.path(), dest)
+}
+
+/// Unpack a tarball into a directory, stripping the top-level directory
+fn unpack_tarball(bytes: &[u8], dest: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let Some(relative) = sanitize_entry_path(&path)? else {
+            continue;
+        };
+
+        let target = dest.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if entry_type.is_file() {
+            entry.unpack(&target)?;
+        }
+        // Links and special files are never created from package tarballs
+    }
+
+    Ok(())
+}
+
+/// Move the contents of `src` into `dest`, merging into existing directories
+///
+/// Entries are renamed into place when possible and copied when the temp
+/// directory is on another filesystem.
+fn move_into(src: &Path, dest: &Path) -> Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = dest.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(&to)?;
+            move_into(&from, &to)?;
+        } else if fs::rename(&from, &to).is_err() {
+            fs::copy(&from, &to).with_context(|| format!("Failed to move {} into place", to.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Read the regular files of a tarball into memory
+///
+/// Paths are relative to the package directory, as `extract_tarball` would
+/// place them. Directories, links and special files are skipped.
+///
+/// # Returns
+/// * `Ok(Vec<(PathBuf, Vec<u8>)>)` with each file's path and contents, in archive order
+/// * `Err(anyhow::Error)` if the tarball is malformed or an entry escapes the package directory
+pub fn read_tarball_files(bytes: &[u8]) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    let mut files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let Some(relative) = sanitize_entry_path(&path)? else {
+            continue;
+        };
+        if entry.header().entry_type().is_file() {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            files.push((relative, content));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Strip the top-level directory from an entry path and reject path traversal
+///
+/// Returns `None` for the top-level directory entry itself.
+fn sanitize_entry_path(path: &Path) -> Result<Option<PathBuf>> {
+    if path.is_absolute() {
+        anyhow::bail!("Tarball entry {} escapes the package directory", path.display());
+    }
+
+    let mut components = path.components();
+    components.next();
+
+    let mut relative = PathBuf::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => anyhow::bail!("Tarball entry {} escapes the package directory", path.display()),
+        }
+    }
+
+    Ok((!relative.as_os_str().is_empty()).then_some(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrity::{HashAlgorithm, Integrity};
+    use crate::CoreError;
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::testing::build_tarball;
+
+    fn fixture_tarball() -> Vec<u8> {
+        build_tarball(&[
+            ("package/package.json", r#"{ "name": "remote-pkg", "version": "3.1.4" }"#),
+            ("package/index.js", "module.exports = 'remote';"),
+        ])
+    }
+
+    #[test]
+    fn test_read_tarball_manifest() {
+        let pkg = read_tarball_manifest(&fixture_tarball()).unwrap();
+        assert_eq!(pkg.name, "remote-pkg");
+        assert_eq!(pkg.version, "3.1.4");
+
+        let no_manifest = build_tarball(&[("package/index.js", "")]);
+        assert!(read_tarball_manifest(&no_manifest).is_err());
+    }
+
+    #[test]
+    fn test_extract_tarball() {
+        let dest = TempDir::new().unwrap();
+        extract_tarball(&fixture_tarball(), dest.path(), None).unwrap();
+
+        assert!(dest.path().join("package.json").is_file());
+        assert_eq!(
+            fs::read_to_string(dest.path().join("index.js")).unwrap(),
+            "module.exports = 'remote';"
+        );
+    }
+
+    #[test]
+    fn test_read_tarball_files() {
+        let files = read_tarball_files(&fixture_tarball()).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                (PathBuf::from("package.json"), br#"{ "name": "remote-pkg", "version": "3.1.4" }"#.to_vec()),
+                (PathBuf::from("index.js"), b"module.exports = 'remote';".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sanitize_entry_path_rejects_traversal() {
+        assert_eq!(
+            sanitize_entry_path(Path::new("package/lib/a.js")).unwrap(),
+            Some(PathBuf::from("lib/a.js"))
+        );
+        assert_eq!(sanitize_entry_path(Path::new("package/")).unwrap(), None);
+        assert!(sanitize_entry_path(Path::new("package/../../etc/passwd")).is_err());
+        assert!(sanitize_entry_path(Path::new("/etc/passwd")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tarball_dependency() {
+        let server = MockServer::start().await;
+        let tarball = fixture_tarball();
+        Mock::given(method("GET"))
+            .and(path("/remote-pkg-3.1.4.tgz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(tarball.clone()))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/remote-pkg-3.1.4.tgz", server.uri());
+        let (bytes, pkg) = resolve_tarball_dependency(&url, None, None, None).await.unwrap();
+        assert_eq!(bytes, tarball);
+        assert_eq!(pkg.name, "remote-pkg");
+        assert_eq!(pkg.version, "3.1.4");
+
+        let integrity = Integrity {
+            algorithm: HashAlgorithm::Sha512,
+            digest: HashAlgorithm::Sha512.digest(&tarball),
+        }
+        .to_sri();
+        assert!(resolve_tarball_dependency(&url, Some(&integrity), None, None).await.is_ok());
+
+        let wrong = Integrity {
+            algorithm: HashAlgorithm::Sha512,
+            digest: HashAlgorithm::Sha512.digest(b"other"),
+        }
+        .to_sri();
+        let err = resolve_tarball_dependency(&url, Some(&wrong), None, None).await.unwrap_err();
+        assert!(err.to_string().contains("failed verification"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_tarball_dependency_rejects_traversing_name() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/evil.tgz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(build_tarball(&[(
+                "package/package.json",
+                r#"{ "name": "../../escape", "version": "1.0.0" }"#,
+            )])))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/evil.tgz", server.uri());
+        let err = resolve_tarball_dependency(&url, None, None, None).await.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(CoreError::InvalidPackageName { .. })),
+            "{:#}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_tarball_into_custom_temp_dir() {
+        let work = TempDir::new().unwrap();
+        let temp_dir = work.path().join("scratch/tmp");
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let server = MockServer::start().await;
+        let tarball = fixture_tarball();
+        let (listing, dir, body) = (seen.clone(), temp_dir.clone(), tarball.clone());
+        Mock::given(method("GET"))
+            .and(path("/remote-pkg-3.1.4.tgz"))
+            .respond_with(move |_: &wiremock::Request| {
+                // Record what the download has placed in the temp dir so far
+                listing.lock().unwrap().extend(
+                    fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()),
+                );
+                ResponseTemplate::new(200).set_body_bytes(body.clone())
+            })
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/remote-pkg-3.1.4.tgz", server.uri());
+        let bytes = download_tarball(&url, Some(&temp_dir), None).await.unwrap();
+        assert_eq!(bytes, tarball);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert!(seen[0].starts_with("package-fast-") && seen[0].ends_with(".tgz"));
+        // The download is cleaned up once read
+        assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_extract_tarball_stages_in_temp_dir() {
+        let work = TempDir::new().unwrap();
+        let temp_dir = work.path().join("tmp");
+        let dest = work.path().join("node_modules/remote-pkg");
+
+        extract_tarball(&fixture_tarball(), &dest, Some(&temp_dir)).unwrap();
+        assert!(dest.join("package.json").is_file());
+        assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+        // A malformed tarball leaves the package directory as it was
+        let existing = work.path().join("node_modules/existing");
+        fs::create_dir_all(&existing).unwrap();
+        let mut truncated = fixture_tarball();
+        truncated.truncate(truncated.len() / 2);
+        assert!(extract_tarball(&truncated, &existing, Some(&temp_dir)).is_err());
+        assert_eq!(fs::read_dir(&existing).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_extract_tarball_stages_next_to_dest() {
+        let work = TempDir::new().unwrap();
+        let node_modules = work.path().join("node_modules");
+        let dest = node_modules.join("@scope/remote-pkg");
+
+        extract_tarball(&fixture_tarball(), &dest, None).unwrap();
+        assert!(dest.join("package.json").is_file());
+        // Extracting again replaces the existing directory
+        extract_tarball(&fixture_tarball(), &dest, None).unwrap();
+        assert!(dest.join("package.json").is_file());
+
+        let mut truncated = fixture_tarball();
+        truncated.truncate(truncated.len() / 2);
+        assert!(extract_tarball(&truncated, &node_modules.join("@scope/broken"), None).is_err());
+        let entries: Vec<_> = fs::read_dir(node_modules.join("@scope"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec!["remote-pkg"]);
+    }
+
+    #[test]
+    fn test_extract_tarball_replaces_older_version() {
+        let work = TempDir::new().unwrap();
+        let node_modules = work.path().join("node_modules");
+        let dest = node_modules.join("remote-pkg");
+        let older = build_tarball(&[
+            ("package/package.json", r#"{ "name": "remote-pkg", "version": "3.0.0" }"#),
+            ("package/index.js", "module.exports = 'old';"),
+            ("package/lib/removed.js", ""),
+        ]);
+        extract_tarball(&older, &dest, None).unwrap();
+        // A package installed nested under the older version
+        fs::create_dir_all(dest.join("node_modules/nested")).unwrap();
+        fs::write(dest.join("node_modules/nested/index.js"), "").unwrap();
+
+        extract_tarball(&fixture_tarball(), &dest, None).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("index.js")).unwrap(), "module.exports = 'remote';");
+        assert!(!dest.join("lib").exists());
+        assert!(dest.join("node_modules/nested/index.js").is_file());
+        assert_eq!(fs::read_dir(&node_modules).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_prepare_temp_dir() {
+        assert_eq!(prepare_temp_dir(None).unwrap(), std::env::temp_dir());
+
+        let work = TempDir::new().unwrap();
+        let nested = work.path().join("a/b");
+        assert_eq!(prepare_temp_dir(Some(&nested)).unwrap(), nested);
+        assert!(nested.is_dir());
+
+        let file = work.path().join("file");
+        fs::write(&file, "").unwrap();
+        let err = prepare_temp_dir(Some(&file)).unwrap_err();
+        assert!(err.to_string().contains("Failed to create temp directory"));
+    }
+
+    #[tokio::test]
+    async fn test_download_tarball_http_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let result = download_tarball(&format!("{}/missing.tgz", server.uri()), None, None).await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,363 @@
+//! In-memory registry for tests and examples
+//!
+//! Enabled with the `testing` feature. `InMemoryRegistry` implements
+//! `PackageRegistry` without any network access, so resolution and
+//! installation can be exercised against a fixed set of packages.
+//!
+//! ```
+//! use package_fast_core::resolver::resolve_tree;
+//! use package_fast_core::testing::{InMemoryRegistry, VersionBuilder};
+//! use package_fast_core::{InstallOptions, PackageRegistry};
+//!
+//! # tokio_test(async {
+//! let registry = InMemoryRegistry::new()
+//!     .with_version(VersionBuilder::new("app-lib", "1.0.0").dependency("util", "^2.0.0"))
+//!     .with_version(VersionBuilder::new("util", "2.1.0"))
+//!     .with_version(VersionBuilder::new("util", "3.0.0"));
+//!
+//! let roots = vec![("app-lib".to_string(), None)];
+//! let tree = resolve_tree(&roots, &InstallOptions::default(), |name| {
+//!     let registry = &registry;
+//!     async move { registry.metadata(&name).await }
+//! })
+//! .await
+//! .unwrap();
+//!
+//! let resolved: Vec<String> = tree.packages.iter().map(|p| format!("{}@{}", p.name, p.version)).collect();
+//! assert_eq!(resolved, vec!["app-lib@1.0.0", "util@2.1.0"]);
+//! # });
+//! # fn tokio_test<F: std::future::Future>(future: F) -> F::Output {
+//! #     tokio::runtime::Runtime::new().unwrap().block_on(future)
+//! # }
+//! ```
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+
+use crate::integrity::{HashAlgorithm, Integrity};
+use crate::{CoreError, PackageDistribution, PackageMetadata, PackageRegistry, PackageVersion};
+
+/// Build a gzipped tarball from `(path, contents)` pairs
+///
+/// Paths are used as-is, so npm-style tarballs should nest their files
+/// under `package/`.
+///
+/// # Panics
+/// Panics if the archive can't be written, which only happens for paths
+/// `tar` can't encode.
+pub fn build_tarball(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents.as_bytes()).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// Builder for a package version published to an `InMemoryRegistry`
+///
+/// The version's tarball contains a generated `package.json` plus any
+/// files added with `file`. Adding a `package.json` replaces the generated
+/// one.
+#[derive(Debug, Clone)]
+pub struct VersionBuilder {
+    name: String,
+    version: String,
+    dependencies: HashMap<String, String>,
+    dev_dependencies: HashMap<String, String>,
+    optional_dependencies: HashMap<String, String>,
+    peer_dependencies: HashMap<String, String>,
+    scripts: HashMap<String, String>,
+    license: Option<String>,
+    files: Vec<(String, String)>,
+}
+
+impl VersionBuilder {
+    /// Start a version with no dependencies or extra files
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: HashMap::new(),
+            dev_dependencies: HashMap::new(),
+            optional_dependencies: HashMap::new(),
+            peer_dependencies: HashMap::new(),
+            scripts: HashMap::new(),
+            license: None,
+            files: Vec::new(),
+        }
+    }
+
+    /// Add a regular dependency on `name` at `range`
+    pub fn dependency(mut self, name: &str, range: &str) -> Self {
+        self.dependencies.insert(name.to_string(), range.to_string());
+        self
+    }
+
+    /// Add a dev dependency on `name` at `range`
+    pub fn dev_dependency(mut self, name: &str, range: &str) -> Self {
+        self.dev_dependencies.insert(name.to_string(), range.to_string());
+        self
+    }
+
+    /// Add an optional dependency on `name` at `range`
+    pub fn optional_dependency(mut self, name: &str, range: &str) -> Self {
+        self.optional_dependencies.insert(name.to_string(), range.to_string());
+        self
+    }
+
+    /// Add a peer dependency on `name` at `range`
+    pub fn peer_dependency(mut self, name: &str, range: &str) -> Self {
+        self.peer_dependencies.insert(name.to_string(), range.to_string());
+        self
+    }
+
+    /// Add a script, such as a `postinstall` lifecycle script
+    pub fn script(mut self, event: &str, command: &str) -> Self {
+        self.scripts.insert(event.to_string(), command.to_string());
+        self
+    }
+
+    /// Set the SPDX license expression
+    pub fn license(mut self, license: &str) -> Self {
+        self.license = Some(license.to_string());
+        self
+    }
+
+    /// Add a file to the tarball, relative to the package directory
+    pub fn file(mut self, path: &str, contents: &str) -> Self {
+        self.files.push((path.to_string(), contents.to_string()));
+        self
+    }
+
+    /// Build the version's tarball
+    fn tarball(&self) -> Vec<u8> {
+        let manifest = serde_json::json!({
+            "name": self.name,
+            "version": self.version,
+            "dependencies": self.dependencies,
+            "devDependencies": self.dev_dependencies,
+            "optionalDependencies": self.optional_dependencies,
+            "peerDependencies": self.peer_dependencies,
+            "scripts": self.scripts,
+            "license": self.license,
+        })
+        .to_string();
+
+        let mut files: Vec<(String, &str)> = Vec::new();
+        if !self.files.iter().any(|(path, _)| path == crate::manifest::MANIFEST_FILE) {
+            files.push((format!("package/{}", crate::manifest::MANIFEST_FILE), &manifest));
+        }
+        files.extend(self.files.iter().map(|(path, contents)| (format!("package/{}", path), contents.as_str())));
+
+        let files: Vec<(&str, &str)> = files.iter().map(|(path, contents)| (path.as_str(), *contents)).collect();
+        build_tarball(&files)
+    }
+
+    /// Build the registry entry for this version, pointing at `tarball`
+    fn package_version(&self, dist: PackageDistribution) -> PackageVersion {
+        let non_empty = |deps: &HashMap<String, String>| (!deps.is_empty()).then(|| deps.clone());
+        PackageVersion {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            dependencies: Some(self.dependencies.clone()),
+            dev_dependencies: non_empty(&self.dev_dependencies),
+            peer_dependencies: non_empty(&self.peer_dependencies),
+            peer_dependencies_meta: None,
+            optional_dependencies: non_empty(&self.optional_dependencies),
+            engines: None,
+            os: None,
+            cpu: None,
+            deprecated: None,
+            bundled_dependencies: None,
+            bin: None,
+            scripts: non_empty(&self.scripts),
+            license: self.license.clone(),
+            dist,
+        }
+    }
+}
+
+/// Registry serving metadata and tarballs from memory
+///
+/// Unknown packages fail with `CoreError::PackageNotFound`, like a registry
+/// answering 404.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryRegistry {
+    packages: HashMap<String, PackageMetadata>,
+    tarballs: HashMap<String, Bytes>,
+}
+
+impl InMemoryRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry serving the given metadata
+    ///
+    /// No tarballs are available for these packages unless their versions
+    /// are also published; downloading one fails like a missing file.
+    ///
+    /// # Arguments
+    /// * `packages` - Registry metadata keyed by package name
+    pub fn from_metadata(packages: HashMap<String, PackageMetadata>) -> Self {
+        Self {
+            packages,
+            tarballs: HashMap::new(),
+        }
+    }
+
+    /// Publish a version, making it the `latest` dist-tag
+    ///
+    /// Its tarball is served from a `memory://` URL under a SHA-512
+    /// integrity, so installs verify it like a real download.
+    pub fn publish(&mut self, version: VersionBuilder) -> &mut Self {
+        let tarball = Bytes::from(version.tarball());
+        let url = format!("memory://{}/-/{}.tgz", version.name, version.version);
+        let dist = PackageDistribution {
+            tarball: url.clone(),
+            shasum: String::new(),
+            integrity: Some(
+                Integrity {
+                    algorithm: HashAlgorithm::Sha512,
+                    digest: HashAlgorithm::Sha512.digest(&tarball),
+                }
+                .to_sri(),
+            ),
+        };
+
+        let metadata = self
+            .packages
+            .entry(version.name.clone())
+            .or_insert_with(|| PackageMetadata {
+                name: version.name.clone(),
+                versions: HashMap::new(),
+                dist_tags: HashMap::new(),
+                time: HashMap::new(),
+            });
+        metadata.versions.insert(version.version.clone(), version.package_version(dist));
+        metadata.dist_tags.insert("latest".to_string(), version.version.clone());
+        self.tarballs.insert(url, tarball);
+        self
+    }
+
+    /// Publish a version, returning the registry for chaining
+    pub fn with_version(mut self, version: VersionBuilder) -> Self {
+        self.publish(version);
+        self
+    }
+
+    /// Point a dist-tag of a package at a version
+    ///
+    /// # Panics
+    /// Panics if the package has not been published.
+    pub fn tag(&mut self, name: &str, tag: &str, version: &str) -> &mut Self {
+        let metadata = self.packages.get_mut(name).unwrap_or_else(|| panic!("{} is not published", name));
+        metadata.dist_tags.insert(tag.to_string(), version.to_string());
+        self
+    }
+}
+
+#[async_trait]
+impl PackageRegistry for InMemoryRegistry {
+    async fn metadata(&self, name: &str) -> Result<PackageMetadata> {
+        self.packages
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CoreError::PackageNotFound { name: name.to_string() }.into())
+    }
+
+    async fn tarball(&self, dist: &PackageDistribution) -> Result<Bytes> {
+        self.tarballs
+            .get(&dist.tarball)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Failed to download tarball {}: HTTP 404", dist.tarball))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::{resolve_tree, ResolvedTree};
+    use crate::tarball::read_tarball_manifest;
+    use crate::InstallOptions;
+
+    async fn resolve(registry: &InMemoryRegistry, roots: &[(&str, Option<&str>)]) -> Result<ResolvedTree> {
+        let roots: Vec<(String, Option<String>)> = roots
+            .iter()
+            .map(|(name, range)| (name.to_string(), range.map(str::to_string)))
+            .collect();
+        resolve_tree(&roots, &InstallOptions::default(), |name| async move {
+            registry.metadata(&name).await
+        })
+        .await
+    }
+
+    fn resolved(tree: &ResolvedTree) -> Vec<String> {
+        tree.packages.iter().map(|p| format!("{}@{}", p.name, p.version)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_resolves_ranges_against_published_versions() {
+        let mut registry = InMemoryRegistry::new();
+        registry
+            .publish(VersionBuilder::new("app", "1.0.0").dependency("util", "^2.0.0"))
+            .publish(VersionBuilder::new("util", "2.1.0").dependency("tiny", "~1.0.0"))
+            .publish(VersionBuilder::new("util", "2.4.0").dependency("tiny", "~1.0.0"))
+            .publish(VersionBuilder::new("util", "3.0.0"))
+            .publish(VersionBuilder::new("tiny", "1.0.3"))
+            .publish(VersionBuilder::new("tiny", "1.1.0"));
+        registry.tag("util", "latest", "2.4.0");
+
+        let tree = resolve(&registry, &[("app", None)]).await.unwrap();
+        assert_eq!(resolved(&tree), vec!["app@1.0.0", "util@2.4.0", "tiny@1.0.3"]);
+
+        let tree = resolve(&registry, &[("util", None)]).await.unwrap();
+        assert_eq!(resolved(&tree)[0], "util@2.4.0");
+    }
+
+    #[tokio::test]
+    async fn test_optional_and_missing_dependencies() {
+        let registry = InMemoryRegistry::new().with_version(
+            VersionBuilder::new("app", "1.0.0")
+                .optional_dependency("native-addon", "^1.0.0")
+                .peer_dependency("react", ">=17"),
+        );
+
+        let tree = resolve(&registry, &[("app", None)]).await.unwrap();
+        assert_eq!(resolved(&tree), vec!["app@1.0.0"]);
+        assert_eq!(tree.skipped_optional[0].name, "native-addon");
+        assert_eq!(tree.packages[0].peer_dependencies["react"], ">=17");
+
+        let err = resolve(&registry, &[("missing", None)]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::PackageNotFound { name }) if name == "missing"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_serves_verified_tarballs() {
+        let registry = InMemoryRegistry::new()
+            .with_version(VersionBuilder::new("util", "2.1.0").file("index.js", "module.exports = {};"));
+        let metadata = registry.metadata("util").await.unwrap();
+        let dist = &metadata.versions["2.1.0"].dist;
+
+        let bytes = registry.tarball(dist).await.unwrap();
+        crate::integrity::verify_sri(&bytes, dist.integrity.as_deref().unwrap()).unwrap();
+        let manifest = read_tarball_manifest(&bytes).unwrap();
+        assert_eq!((manifest.name.as_str(), manifest.version.as_str()), ("util", "2.1.0"));
+
+        let seeded = InMemoryRegistry::from_metadata(HashMap::from([("util".to_string(), metadata.clone())]));
+        assert_eq!(seeded.metadata("util").await.unwrap().versions.len(), 1);
+        assert!(seeded.tarball(dist).await.is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Detection of typosquatted package names
+//!
+//! Typosquatting publishes malware under a name one slip away from a
+//! popular package (`lodahs` for `lodash`), waiting for a mistyped install.
+//! Requested names are compared with a list of popular packages, and a name
+//! within a small Damerau-Levenshtein distance of one is flagged, unless it
+//! is an established package that merely looks like a popular one
+//! (`preact` next to `react`).
+
+use std::sync::OnceLock;
+
+/// Most downloaded npm packages, seeding the typosquat check
+const POPULAR_PACKAGES: &[&str] = &[
+    "lodash", "react", "react-dom", "express", "axios", "chalk", "commander", "moment", "debug", "request",
+    "typescript", "webpack", "tslib", "uuid", "dotenv", "async", "underscore", "prop-types", "classnames",
+    "bluebird", "yargs", "glob", "minimist", "mkdirp", "rimraf", "semver", "colors", "inquirer", "jquery",
+    "vue", "angular", "next", "redux", "react-redux", "react-router", "react-router-dom", "eslint", "prettier",
+    "jest", "mocha", "chai", "babel-core", "@babel/core", "@babel/runtime", "@babel/preset-env", "core-js",
+    "body-parser", "cookie-parser", "cors", "helmet", "morgan", "mongoose", "mongodb", "mysql", "mysql2", "pg",
+    "redis", "sequelize", "socket.io", "ws", "node-fetch", "cross-env", "cross-spawn", "fs-extra", "graceful-fs",
+    "chokidar", "nodemon", "rxjs", "zone.js", "immutable", "ramda", "date-fns", "dayjs", "validator",
+    "jsonwebtoken", "bcrypt", "bcryptjs", "passport", "nodemailer", "handlebars", "ejs", "pug", "marked",
+    "cheerio", "puppeteer", "sharp", "qs", "ms", "node-sass", "sass", "less", "postcss", "autoprefixer",
+    "tailwindcss", "vite", "rollup", "esbuild", "parcel", "gulp", "grunt", "coffee-script", "styled-components",
+    "electron", "yaml", "js-yaml", "ini", "open", "ora", "execa", "got", "superagent", "form-data", "mime",
+    "mime-types", "through2", "readable-stream", "event-stream", "string-width", "strip-ansi", "ansi-styles",
+    "supports-color", "escape-string-regexp", "source-map", "source-map-support", "ts-node", "zod", "winston",
+    "pino", "bunyan", "shelljs", "optimist", "babel-loader", "css-loader", "style-loader", "file-loader",
+    "html-webpack-plugin", "webpack-dev-server", "nanoid", "lru-cache", "deepmerge", "object-assign",
+];
+
+/// Established packages within typo distance of a popular name, never flagged
+const LEGITIMATE_LOOKALIKES: &[&str] = &[
+    "preact", "react-dnd", "sass-loader", "ts-loader", "moments", "expresso", "glob2", "color", "tslint", "mssql",
+    "through",
+];
+
+/// Get the embedded list of popular package names
+pub fn popular_packages() -> &'static [String] {
+    static PACKAGES: OnceLock<Vec<String>> = OnceLock::new();
+    PACKAGES.get_or_init(|| POPULAR_PACKAGES.iter().map(|name| name.to_string()).collect())
+}
+
+/// Check whether a package name looks like a typo of a popular package
+///
+/// Names of five to eight characters may be one edit away from a popular
+/// name, and longer names two edits; a transposition (`lodahs`) counts as
+/// one edit. Shorter names are never flagged, since nearly every short name
+/// is one edit from another, and neither are the established look-alikes in
+/// `LEGITIMATE_LOOKALIKES`. The closest popular name wins, and ties go to
+/// the one listed first.
+///
+/// # Arguments
+/// * `name` - Requested package name
+/// * `known_popular` - Popular package names, most popular first
+///
+/// # Returns
+/// * `Some(String)` with the name the user likely meant
+/// * `None` if `name` is popular itself or not close to a popular name
+pub fn detect_typosquat(name: &str, known_popular: &[String]) -> Option<String> {
+    if known_popular.iter().any(|popular| popular == name) || LEGITIMATE_LOOKALIKES.contains(&name) {
+        return None;
+    }
+    let max_distance = match name.chars().count() {
+        0..=4 => return None,
+        5..=8 => 1,
+        _ => 2,
+    };
+
+    known_popular
+        .iter()
+        .map(|popular| (damerau_levenshtein(name, popular), popular))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, popular)| popular.clone())
+}
+
+/// Edit distance counting insertions, deletions, substitutions and
+/// transpositions of adjacent characters (optimal string alignment)
+fn damerau_levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distances[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damerau_levenshtein() {
+        assert_eq!(damerau_levenshtein("lodash", "lodash"), 0);
+        assert_eq!(damerau_levenshtein("lodahs", "lodash"), 1);
+        assert_eq!(damerau_levenshtein("lodas", "lodash"), 1);
+        assert_eq!(damerau_levenshtein("expresss", "express"), 1);
+        assert_eq!(damerau_levenshtein("kitten", "sitting"), 3);
+        assert_eq!(damerau_levenshtein("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_detect_typosquat_near_misses() {
+        let popular = popular_packages();
+        assert_eq!(detect_typosquat("lodahs", popular).as_deref(), Some("lodash"));
+        assert_eq!(detect_typosquat("expres", popular).as_deref(), Some("express"));
+        assert_eq!(detect_typosquat("raect", popular).as_deref(), Some("react"));
+        assert_eq!(detect_typosquat("typescirpt", popular).as_deref(), Some("typescript"));
+        assert_eq!(detect_typosquat("crossenv", popular).as_deref(), Some("cross-env"));
+    }
+
+    #[test]
+    fn test_detect_typosquat_ignores_exact_and_distant_names() {
+        let popular = popular_packages();
+        for name in ["lodash", "react", "react-dom", "@babel/core"] {
+            assert_eq!(detect_typosquat(name, popular), None, "{} was flagged", name);
+        }
+        // Too short to judge, and too far from anything popular
+        assert_eq!(detect_typosquat("qz", popular), None);
+        assert_eq!(detect_typosquat("left-pad", popular), None);
+        assert_eq!(detect_typosquat("my-internal-tool", popular), None);
+    }
+
+    #[test]
+    fn test_detect_typosquat_ignores_legitimate_lookalikes() {
+        let popular = popular_packages();
+        for name in ["preact", "sass-loader", "react-dnd", "moments", "expresso", "glob2", "color", "tslint"] {
+            assert_eq!(detect_typosquat(name, popular), None, "{} was flagged", name);
+        }
+        // Typos next to them are still caught
+        assert_eq!(detect_typosquat("raect-dom", popular).as_deref(), Some("react-dom"));
+        assert_eq!(detect_typosquat("expresss", popular).as_deref(), Some("express"));
+    }
+}
@@ -0,0 +1,321 @@
+//! Workspace (monorepo) support
+//!
+//! A root `package.json` may declare member packages through its
+//! `workspaces` field. Members are linked into the root `node_modules` so
+//! they resolve to each other, and their external dependencies are
+//! installed together.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::local::link_local_package;
+use crate::manifest::{read_package_info, MANIFEST_FILE};
+use crate::{InstallOptions, PackageInfo};
+
+/// The `workspaces` field of a `package.json`
+///
+/// npm uses a plain array of globs; yarn also accepts an object with a
+/// `packages` array.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Workspaces {
+    Patterns(Vec<String>),
+    Config {
+        #[serde(default)]
+        packages: Vec<String>,
+    },
+}
+
+impl Workspaces {
+    /// Get the member globs
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            Workspaces::Patterns(patterns) => patterns,
+            Workspaces::Config { packages } => packages,
+        }
+    }
+}
+
+/// A package belonging to a workspace
+#[derive(Debug, Clone)]
+pub struct WorkspaceMember {
+    pub dir: PathBuf,
+    pub package: PackageInfo,
+}
+
+/// A workspace root and its member packages
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub members: Vec<WorkspaceMember>,
+}
+
+impl Workspace {
+    /// Find a member by package name
+    pub fn member(&self, name: &str) -> Option<&WorkspaceMember> {
+        self.members.iter().find(|member| member.package.name == name)
+    }
+
+    /// Collect the dependencies of all members that live outside the workspace
+    ///
+    /// Dependencies on other members are left out, since those resolve to the
+    /// linked member itself. When several members request the same package,
+    /// the first request (in member order) wins.
+    ///
+    /// # Arguments
+    /// * `options` - Installation options selecting production and/or dev dependencies
+    pub fn external_dependencies(&self, options: &InstallOptions) -> Vec<(String, Option<String>)> {
+        let mut external: BTreeMap<String, String> = BTreeMap::new();
+
+        for member in &self.members {
+            for (name, range) in selected_dependencies(&member.package, options) {
+                if self.member(name).is_none() {
+                    external.entry(name.clone()).or_insert_with(|| range.clone());
+                }
+            }
+        }
+
+        external.into_iter().map(|(name, range)| (name, Some(range))).collect()
+    }
+
+    /// Link every member into a `node_modules` directory
+    pub fn link_members(&self, node_modules: &Path) -> Result<()> {
+        for member in &self.members {
+            link_local_package(&member.dir, node_modules, &member.package.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the workspace declared by the `package.json` in a directory
+///
+/// # Arguments
+/// * `root` - Directory containing the root `package.json`
+///
+/// # Returns
+/// * `Ok(Some(Workspace))` with the discovered members, sorted by directory
+/// * `Ok(None)` if the manifest declares no workspaces
+/// * `Err(anyhow::Error)` if the manifest or a member manifest can't be read
+pub fn read_workspace(root: &Path) -> Result<Option<Workspace>> {
+    let manifest = read_package_info(root)?;
+    let Some(workspaces) = manifest.workspaces else {
+        return Ok(None);
+    };
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let mut excluded: Vec<PathBuf> = Vec::new();
+    for pattern in workspaces.patterns() {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern.as_str()),
+        };
+        let full_pattern = root.join(pattern);
+        let full_pattern = full_pattern.to_str().context("Workspace path is not valid UTF-8")?;
+
+        for dir in glob::glob(full_pattern).with_context(|| format!("Invalid workspace pattern {:?}", pattern))? {
+            let dir = dir?;
+            if negated {
+                excluded.push(dir);
+            } else if dir.join(MANIFEST_FILE).is_file() {
+                dirs.push(dir);
+            }
+        }
+    }
+
+    dirs.sort();
+    dirs.dedup();
+    dirs.retain(|dir| !excluded.contains(dir));
+
+    let mut members: Vec<WorkspaceMember> = Vec::new();
+    for dir in dirs {
+        let package = read_package_info(&dir)?;
+        if members.iter().any(|member| member.package.name == package.name) {
+            warn!("Ignoring duplicate workspace member {} at {}", package.name, dir.display());
+            continue;
+        }
+        members.push(WorkspaceMember { dir, package });
+    }
+
+    info!("Found {} workspace members in {}", members.len(), root.display());
+    Ok(Some(Workspace {
+        root: root.to_path_buf(),
+        members,
+    }))
+}
+
+/// Get the dependencies of a manifest selected by the install options
+///
+/// Production dependencies are included unless `dev_only` is set, and dev
+/// dependencies unless `prod_only` is set. This only applies to the project
+/// and its workspace members: the resolver never follows the dev
+/// dependencies of installed packages.
+pub fn selected_dependencies<'a>(
+    pkg: &'a PackageInfo,
+    options: &InstallOptions,
+) -> impl Iterator<Item = (&'a String, &'a String)> {
+    let prod = (!options.dev_only).then_some(&pkg.dependencies);
+    let dev = (!options.prod_only).then_some(&pkg.dev_dependencies);
+    prod.into_iter().chain(dev).flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_manifest(dir: &Path, manifest: serde_json::Value) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+    }
+
+    fn fixture(workspaces: serde_json::Value) -> TempDir {
+        let root = TempDir::new().unwrap();
+        write_manifest(
+            root.path(),
+            serde_json::json!({ "name": "monorepo", "version": "0.0.0", "workspaces": workspaces }),
+        );
+        write_manifest(
+            &root.path().join("packages/api"),
+            serde_json::json!({
+                "name": "@repo/api",
+                "version": "1.0.0",
+                "dependencies": { "@repo/utils": "^1.0.0", "express": "^4.18.0" },
+                "devDependencies": { "jest": "^29.0.0" }
+            }),
+        );
+        write_manifest(
+            &root.path().join("packages/utils"),
+            serde_json::json!({
+                "name": "@repo/utils",
+                "version": "1.2.0",
+                "dependencies": { "@repo/api": "workspace:*", "lodash": "^4.17.0" }
+            }),
+        );
+        // Directories without a manifest aren't members
+        fs::create_dir_all(root.path().join("packages/scratch")).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_read_workspace_array_form() {
+        let root = fixture(serde_json::json!(["packages/*"]));
+
+        let workspace = read_workspace(root.path()).unwrap().unwrap();
+        let names: Vec<&str> = workspace.members.iter().map(|m| m.package.name.as_str()).collect();
+        assert_eq!(names, vec!["@repo/api", "@repo/utils"]);
+    }
+
+    #[test]
+    fn test_read_workspace_object_form_and_exclusion() {
+        let root = fixture(serde_json::json!({ "packages": ["packages/*", "!packages/api"] }));
+
+        let workspace = read_workspace(root.path()).unwrap().unwrap();
+        let names: Vec<&str> = workspace.members.iter().map(|m| m.package.name.as_str()).collect();
+        assert_eq!(names, vec!["@repo/utils"]);
+    }
+
+    #[test]
+    fn test_read_workspace_without_field() {
+        let root = TempDir::new().unwrap();
+        write_manifest(root.path(), serde_json::json!({ "name": "single", "version": "1.0.0" }));
+        assert!(read_workspace(root.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_external_dependencies_skip_members() {
+        let root = fixture(serde_json::json!(["packages/*"]));
+        let workspace = read_workspace(root.path()).unwrap().unwrap();
+
+        let external = workspace.external_dependencies(&InstallOptions::default());
+        let names: Vec<&str> = external.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["express", "jest", "lodash"]);
+
+        let prod_only = InstallOptions {
+            prod_only: true,
+            ..Default::default()
+        };
+        let names: Vec<String> = workspace
+            .external_dependencies(&prod_only)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["express", "lodash"]);
+    }
+
+    #[test]
+    fn test_selected_dependencies_of_root() {
+        let mut root = PackageInfo::new("app", "1.0.0");
+        root.dependencies.insert("express".to_string(), "^4.0.0".to_string());
+        root.dev_dependencies.insert("jest".to_string(), "^29.0.0".to_string());
+        let names = |options: &InstallOptions| -> Vec<&str> {
+            let mut names: Vec<&str> = selected_dependencies(&root, options).map(|(name, _)| name.as_str()).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(&InstallOptions::default()), vec!["express", "jest"]);
+        let prod_only = InstallOptions {
+            prod_only: true,
+            ..Default::default()
+        };
+        assert_eq!(names(&prod_only), vec!["express"]);
+        let dev_only = InstallOptions {
+            dev_only: true,
+            ..Default::default()
+        };
+        assert_eq!(names(&dev_only), vec!["jest"]);
+    }
+
+    #[test]
+    fn test_link_members_cross_links() {
+        let root = fixture(serde_json::json!(["packages/*"]));
+        let workspace = read_workspace(root.path()).unwrap().unwrap();
+
+        let node_modules = root.path().join("node_modules");
+        workspace.link_members(&node_modules).unwrap();
+
+        let linked = read_package_info(&node_modules.join("@repo/utils")).unwrap();
+        assert_eq!(linked.version, "1.2.0");
+        assert!(node_modules.join("@repo/api").join(MANIFEST_FILE).is_file());
+    }
+
+    #[tokio::test]
+    async fn test_members_resolve_to_each_other() {
+        let root = fixture(serde_json::json!(["packages/*"]));
+        let workspace = read_workspace(root.path()).unwrap().unwrap();
+        let options = InstallOptions::default();
+
+        // Only external packages are available; fetching a member would fail
+        let fetch = |name: String| async move {
+            let version = match name.as_str() {
+                "express" => "4.18.2",
+                "jest" => "29.7.0",
+                "lodash" => "4.17.21",
+                _ => anyhow::bail!("Failed to fetch package metadata: HTTP 404 for {}", name),
+            };
+            Ok(serde_json::from_value(serde_json::json!({
+                "name": name,
+                "dist-tags": { "latest": version },
+                "versions": {
+                    version: {
+                        "name": name,
+                        "version": version,
+                        "dist": { "tarball": "https://example.invalid/pkg.tgz", "shasum": "0" }
+                    }
+                }
+            }))?)
+        };
+
+        let members = workspace.members.iter().map(|m| m.package.clone()).collect();
+        let tree = crate::resolver::resolve_tree_from(members, &workspace.external_dependencies(&options), &options, fetch)
+            .await
+            .unwrap();
+
+        let names: Vec<&str> = tree.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["@repo/api", "@repo/utils", "express", "jest", "lodash"]);
+    }
+}
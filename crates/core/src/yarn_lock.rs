@@ -0,0 +1,247 @@
+//! yarn.lock import
+//!
+//! This module reads classic (v1) `yarn.lock` files into package-fast's
+//! `Lockfile`. The format is a custom indented syntax:
+//!
+//! ```text
+//! "chalk@^2.0.0", "chalk@^2.4.2":
+//!   version "2.4.2"
+//!   resolved "https://registry.yarnpkg.com/chalk/-/chalk-2.4.2.tgz#7f5..."
+//!   integrity sha512-...
+//!   dependencies:
+//!     ansi-styles "^3.2.1"
+//! ```
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::lockfile::{LockedPackage, Lockfile};
+
+/// Name of yarn's lockfile
+pub const YARN_LOCKFILE_NAME: &str = "yarn.lock";
+
+/// Import a `yarn.lock` file
+///
+/// # Arguments
+/// * `path` - Path of the `yarn.lock`
+///
+/// # Returns
+/// * `Ok(Lockfile)` with one entry per locked package version
+/// * `Err(anyhow::Error)` if the file can't be read or parsed
+pub fn import_yarn_lockfile(path: &Path) -> Result<Lockfile> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_yarn_lock(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Parse the contents of a classic `yarn.lock`
+///
+/// Entries whose key lists several specs (`"a@^1, a@^1.2":`) map all of
+/// them to a single locked version.
+pub fn parse_yarn_lock(content: &str) -> Result<Lockfile> {
+    let mut lockfile = Lockfile::new();
+    let mut entry: Option<YarnEntry> = None;
+    let mut section: Option<String> = None;
+
+    for (index, raw_line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw_line.trim_end();
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let line = line.trim_start();
+
+        match indent {
+            0 => {
+                if let Some(finished) = entry.take() {
+                    lockfile.insert(finished.into_locked(line_number)?);
+                }
+                let key = line
+                    .strip_suffix(':')
+                    .with_context(|| format!("Line {}: expected an entry key ending in ':'", line_number))?;
+                entry = Some(YarnEntry::new(key, line_number)?);
+                section = None;
+            }
+            2 => {
+                let current = entry
+                    .as_mut()
+                    .with_context(|| format!("Line {}: field outside of an entry", line_number))?;
+                if let Some(name) = line.strip_suffix(':') {
+                    section = Some(name.to_string());
+                    continue;
+                }
+                section = None;
+                let (key, value) = split_field(line);
+                current.fields.insert(key, value);
+            }
+            4 => {
+                let current = entry
+                    .as_mut()
+                    .with_context(|| format!("Line {}: field outside of an entry", line_number))?;
+                let section = section
+                    .as_deref()
+                    .with_context(|| format!("Line {}: nested field outside of a section", line_number))?;
+                if matches!(section, "dependencies" | "optionalDependencies") {
+                    let (name, range) = split_field(line);
+                    current.dependencies.insert(name, range);
+                }
+            }
+            _ => anyhow::bail!("Line {}: unexpected indentation", line_number),
+        }
+    }
+
+    if let Some(finished) = entry {
+        lockfile.insert(finished.into_locked(content.lines().count())?);
+    }
+
+    Ok(lockfile)
+}
+
+/// An entry being parsed
+struct YarnEntry {
+    name: String,
+    fields: BTreeMap<String, String>,
+    dependencies: BTreeMap<String, String>,
+}
+
+impl YarnEntry {
+    /// Start an entry from its (possibly multi-spec) key
+    fn new(key: &str, line_number: usize) -> Result<Self> {
+        let names: Vec<&str> = key
+            .split(", ")
+            .map(|spec| spec_name(unquote(spec.trim())))
+            .collect();
+        let name = names[0];
+        if name.is_empty() || names.iter().any(|other| *other != name) {
+            anyhow::bail!("Line {}: invalid entry key {:?}", line_number, key);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            fields: BTreeMap::new(),
+            dependencies: BTreeMap::new(),
+        })
+    }
+
+    fn into_locked(mut self, line_number: usize) -> Result<LockedPackage> {
+        let version = self
+            .fields
+            .remove("version")
+            .with_context(|| format!("Entry {} ending before line {} has no version", self.name, line_number))?;
+
+        Ok(LockedPackage {
+            resolved: self.fields.remove("resolved"),
+            integrity: self.fields.remove("integrity"),
+            dependencies: self.dependencies,
+            ..LockedPackage::new(&self.name, &version)
+        })
+    }
+}
+
+/// Split a `key value` line, unquoting both parts
+fn split_field(line: &str) -> (String, String) {
+    let (key, value) = if let Some(rest) = line.strip_prefix('"') {
+        // Quoted keys (such as scoped package names) may not contain spaces,
+        // but find the closing quote rather than relying on that
+        match rest.find('"') {
+            Some(end) => (&line[..end + 2], line[end + 2..].trim_start()),
+            None => (line, ""),
+        }
+    } else {
+        line.split_once(' ').unwrap_or((line, ""))
+    };
+    (unquote(key).to_string(), unquote(value.trim()).to_string())
+}
+
+/// Strip surrounding double quotes
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Get the package name from a `name@range` spec, keeping a leading scope `@`
+fn spec_name(spec: &str) -> &str {
+    match spec[1.min(spec.len())..].find('@') {
+        Some(index) => &spec[..index + 1],
+        None => spec,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+"@babel/code-frame@^7.0.0", "@babel/code-frame@^7.22.13":
+  version "7.22.13"
+  resolved "https://registry.yarnpkg.com/@babel/code-frame/-/code-frame-7.22.13.tgz#e3c1c099402598483b7a8c46a721d1038803755e"
+  integrity sha512-XktuhWlJ5g+3TJXc5upd9Ks1HutSArik6jf2eAjYFdZ5ntJUlOAllbatqX0eb8a9XVuwGqKMKVF8o9Z9e0CZbA==
+  dependencies:
+    "@babel/highlight" "^7.22.13"
+    chalk "^2.4.2"
+
+chalk@^2.0.0, chalk@^2.4.2:
+  version "2.4.2"
+  resolved "https://registry.yarnpkg.com/chalk/-/chalk-2.4.2.tgz#cd42541677a54333cf541a49108c1432b44c9424"
+  integrity sha512-Mti+f9lpJNcwF4tWV8/OrTTtF1gZi+f8FqlyAdouralcFWFQWF2+NgCHShjkCb+IFBLq9buZwE1xckQU4peSuw==
+  optionalDependencies:
+    fsevents "~2.3.2"
+
+left-pad@1.3.0:
+  version "1.3.0"
+  resolved "https://registry.yarnpkg.com/left-pad/-/left-pad-1.3.0.tgz#5b8a3a7765dfe001261dde915589e782f8c94d1e"
+"#;
+
+    #[test]
+    fn test_parse_yarn_lock() {
+        let lockfile = parse_yarn_lock(FIXTURE).unwrap();
+
+        let keys: Vec<&str> = lockfile.packages.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["@babel/code-frame@7.22.13", "chalk@2.4.2", "left-pad@1.3.0"]);
+
+        let code_frame = lockfile.get("@babel/code-frame", "7.22.13").unwrap();
+        assert!(code_frame.resolved.as_deref().unwrap().ends_with("code-frame-7.22.13.tgz#e3c1c099402598483b7a8c46a721d1038803755e"));
+        assert!(code_frame.integrity.as_deref().unwrap().starts_with("sha512-XktuhWlJ"));
+        assert_eq!(code_frame.dependencies.get("@babel/highlight"), Some(&"^7.22.13".to_string()));
+        assert_eq!(code_frame.dependencies.get("chalk"), Some(&"^2.4.2".to_string()));
+
+        let chalk = lockfile.get("chalk", "2.4.2").unwrap();
+        assert_eq!(chalk.dependencies.get("fsevents"), Some(&"~2.3.2".to_string()));
+
+        assert!(lockfile.get("left-pad", "1.3.0").unwrap().integrity.is_none());
+    }
+
+    #[test]
+    fn test_multi_key_entry_serves_every_range() {
+        let lockfile = parse_yarn_lock(FIXTURE).unwrap();
+
+        assert_eq!(lockfile.pinned_version("chalk", Some("^2.0.0"), false).unwrap().version, "2.4.2");
+        assert_eq!(lockfile.pinned_version("chalk", Some("^2.4.2"), false).unwrap().version, "2.4.2");
+        assert_eq!(
+            lockfile.pinned_version("@babel/code-frame", Some("^7.0.0"), false).unwrap().version,
+            "7.22.13"
+        );
+    }
+
+    #[test]
+    fn test_parse_yarn_lock_errors() {
+        assert!(parse_yarn_lock("left-pad@1.3.0:\n  resolved \"x\"\n").is_err());
+        assert!(parse_yarn_lock("a@1, b@1:\n  version \"1.0.0\"\n").is_err());
+        assert!(parse_yarn_lock("  version \"1.0.0\"\n").is_err());
+    }
+
+    #[test]
+    fn test_spec_name() {
+        assert_eq!(spec_name("chalk@^2.0.0"), "chalk");
+        assert_eq!(spec_name("@babel/core@7.0.0"), "@babel/core");
+        assert_eq!(spec_name("@babel/core"), "@babel/core");
+    }
+}
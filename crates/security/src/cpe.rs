@@ -0,0 +1,262 @@
+//! CPE matching of NVD configurations
+//!
+//! NVD describes the software a CVE affects as configurations of CPE 2.3
+//! names (`cpe:2.3:a:vendor:product:version:...`), optionally bounded by
+//! version ranges. This module decides whether a package version is one of
+//! them, so keyword search results that merely mention a package name can be
+//! told apart from CVEs that actually cover it.
+
+use std::cmp::Ordering;
+
+use crate::vuln_db::{CpeMatch, NvdConfiguration, NvdCve, NvdNode};
+
+/// Index of the product field in a CPE 2.3 formatted string
+const CPE_PRODUCT: usize = 4;
+/// Index of the version field in a CPE 2.3 formatted string
+const CPE_VERSION: usize = 5;
+
+/// Check whether an NVD configuration covers a package version
+///
+/// A package is covered when a vulnerable CPE match names it as the
+/// product and either its version field equals the package version or is a
+/// wildcard whose range bounds include it. Nodes are combined leniently: an
+/// `AND` node pairs the vulnerable software with the platform it runs on,
+/// not with other packages, so one matching CPE anywhere is enough. Negated
+/// nodes only exclude platforms and never cover a package.
+///
+/// # Arguments
+/// * `package` - Package name; the scope of a scoped npm package is ignored
+/// * `version` - Package version
+/// * `config` - Configuration from an NVD CVE entry
+///
+/// # Returns
+/// * `true` if the configuration covers the package version
+/// * `false` otherwise
+pub fn matches_cpe(package: &str, version: &str, config: &NvdConfiguration) -> bool {
+    config.nodes.iter().any(|node| node_matches(package, version, node))
+}
+
+/// Check whether an NVD CVE might affect a package version
+///
+/// CVEs that NVD hasn't analysed yet carry no configurations and can't be
+/// ruled out, so they are kept.
+pub fn cve_covers(package: &str, version: &str, cve: &NvdCve) -> bool {
+    match &cve.configurations {
+        Some(configurations) if !configurations.is_empty() => configurations
+            .iter()
+            .any(|config| matches_cpe(package, version, config)),
+        _ => true,
+    }
+}
+
+fn node_matches(package: &str, version: &str, node: &NvdNode) -> bool {
+    if node.negate == Some(true) {
+        return false;
+    }
+    node.cpe_match
+        .iter()
+        .flatten()
+        .any(|cpe_match| cpe_match_covers(package, version, cpe_match))
+        || node
+            .children
+            .iter()
+            .flatten()
+            .any(|child| node_matches(package, version, child))
+}
+
+fn cpe_match_covers(package: &str, version: &str, cpe_match: &CpeMatch) -> bool {
+    if !cpe_match.vulnerable {
+        return false;
+    }
+    let fields = cpe_fields(&cpe_match.criteria);
+    let (Some(product), Some(cpe_version)) = (fields.get(CPE_PRODUCT), fields.get(CPE_VERSION)) else {
+        return false;
+    };
+    if !same_product(product, package) {
+        return false;
+    }
+    if cpe_version != "*" && cpe_version != "-" {
+        return compare_versions(version, cpe_version) == Ordering::Equal;
+    }
+
+    let bound = |bound: &Option<String>, allowed: &[Ordering]| {
+        bound
+            .as_deref()
+            .is_none_or(|bound| allowed.contains(&compare_versions(version, bound)))
+    };
+    bound(&cpe_match.version_start_including, &[Ordering::Greater, Ordering::Equal])
+        && bound(&cpe_match.version_start_excluding, &[Ordering::Greater])
+        && bound(&cpe_match.version_end_including, &[Ordering::Less, Ordering::Equal])
+        && bound(&cpe_match.version_end_excluding, &[Ordering::Less])
+}
+
+/// Split a CPE 2.3 formatted string into its fields, removing escapes
+fn cpe_fields(criteria: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = criteria.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().unwrap().extend(chars.next()),
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Compare a CPE product with a package name, ignoring case, npm scopes and `-`/`_`
+fn same_product(product: &str, package: &str) -> bool {
+    let unscoped = package.rsplit('/').next().unwrap_or(package);
+    let normalize = |name: &str| name.to_lowercase().replace('-', "_");
+    normalize(product) == normalize(unscoped)
+}
+
+/// Compare versions as dot-separated numbers with an optional `-` pre-release
+///
+/// Missing release components count as zero, so `1.2` equals `1.2.0`. A
+/// pre-release sorts before its release. Non-numeric components compare as
+/// strings.
+fn compare_versions(left: &str, right: &str) -> Ordering {
+    let split = |version: &str| {
+        let version = version.trim().trim_start_matches('v');
+        match version.split_once('-') {
+            Some((release, pre)) => (release.to_string(), Some(pre.to_string())),
+            None => (version.to_string(), None),
+        }
+    };
+    let (left_release, left_pre) = split(left);
+    let (right_release, right_pre) = split(right);
+
+    let left_parts: Vec<&str> = left_release.split('.').collect();
+    let right_parts: Vec<&str> = right_release.split('.').collect();
+    for i in 0..left_parts.len().max(right_parts.len()) {
+        let left_part = left_parts.get(i).copied().unwrap_or("0");
+        let right_part = right_parts.get(i).copied().unwrap_or("0");
+        let ordering = match (left_part.parse::<u64>(), right_part.parse::<u64>()) {
+            (Ok(l), Ok(r)) => l.cmp(&r),
+            _ => left_part.cmp(right_part),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    match (left_pre, right_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(l), Some(r)) => l.cmp(&r),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Version bounds: start including, start excluding, end including, end excluding
+    type Bounds = [Option<&'static str>; 4];
+
+    fn cpe(criteria: &str, bounds: Bounds) -> CpeMatch {
+        let [start_including, start_excluding, end_including, end_excluding] = bounds.map(|b| b.map(String::from));
+        CpeMatch {
+            vulnerable: true,
+            criteria: criteria.to_string(),
+            version_start_including: start_including,
+            version_start_excluding: start_excluding,
+            version_end_including: end_including,
+            version_end_excluding: end_excluding,
+        }
+    }
+
+    fn config(matches: Vec<CpeMatch>) -> NvdConfiguration {
+        NvdConfiguration {
+            nodes: vec![NvdNode {
+                operator: "OR".to_string(),
+                negate: Some(false),
+                cpe_match: Some(matches),
+                children: None,
+            }],
+        }
+    }
+
+    const LODASH: &str = "cpe:2.3:a:lodash:lodash:*:*:*:*:*:node.js:*:*";
+
+    #[test]
+    fn test_matches_cpe_range_bounds() {
+        let cases: &[(Bounds, &[(&str, bool)])] = &[
+            // Before 4.17.21
+            (
+                [None, None, None, Some("4.17.21")],
+                &[("4.17.20", true), ("4.17.21", false), ("3.10.1", true), ("4.17.21-rc.1", true)],
+            ),
+            // 4.0.0 up to and including 4.17.15
+            (
+                [Some("4.0.0"), None, Some("4.17.15"), None],
+                &[("3.10.1", false), ("4.0.0", true), ("4.17.15", true), ("4.17.16", false)],
+            ),
+            // After 4.17.5, before 4.17.12
+            (
+                [None, Some("4.17.5"), None, Some("4.17.12")],
+                &[("4.17.5", false), ("4.17.6", true), ("4.17.11", true), ("4.17.12", false)],
+            ),
+            // Numeric, not lexicographic, ordering; missing components are zero
+            ([Some("4.9"), None, None, None], &[("4.10.0", true), ("4.8.99", false), ("4.9.0", true)]),
+            // No bounds: every version
+            ([None, None, None, None], &[("0.0.1", true), ("99.0.0", true)]),
+        ];
+
+        for (bounds, versions) in cases {
+            let config = config(vec![cpe(LODASH, *bounds)]);
+            for (version, expected) in *versions {
+                assert_eq!(
+                    matches_cpe("lodash", version, &config),
+                    *expected,
+                    "lodash@{} with bounds {:?}",
+                    version,
+                    bounds
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_cpe_product_and_exact_version() {
+        let exact = config(vec![cpe("cpe:2.3:a:npmjs:node\\-fetch:2.6.0:*:*:*:*:node.js:*:*", [None; 4])]);
+        assert!(matches_cpe("node-fetch", "2.6.0", &exact));
+        assert!(!matches_cpe("node-fetch", "2.6.1", &exact));
+        assert!(!matches_cpe("fetch", "2.6.0", &exact));
+
+        // Product names ignore scopes, case and `-`/`_`
+        let scoped = config(vec![cpe("cpe:2.3:a:vendor:Sanitize_HTML:*:*:*:*:*:*:*:*", [None; 4])]);
+        assert!(matches_cpe("@acme/sanitize-html", "1.0.0", &scoped));
+
+        // Keyword hits on another product, or on the platform, don't match
+        let mut platform = cpe("cpe:2.3:o:linux:linux_kernel:*:*:*:*:*:*:*:*", [None; 4]);
+        platform.vulnerable = false;
+        let other = config(vec![cpe("cpe:2.3:a:lodash_project:lodash_utils:*:*:*:*:*:*:*:*", [None; 4]), platform]);
+        assert!(!matches_cpe("lodash", "4.17.20", &other));
+        assert!(!matches_cpe("linux_kernel", "6.0.0", &other));
+    }
+
+    #[test]
+    fn test_matches_cpe_nested_and_negated_nodes() {
+        let child = NvdNode {
+            operator: "OR".to_string(),
+            negate: None,
+            cpe_match: Some(vec![cpe(LODASH, [None, None, None, Some("4.17.21")])]),
+            children: None,
+        };
+        let mut config = NvdConfiguration {
+            nodes: vec![NvdNode {
+                operator: "AND".to_string(),
+                negate: None,
+                cpe_match: None,
+                children: Some(vec![child]),
+            }],
+        };
+        assert!(matches_cpe("lodash", "4.17.20", &config));
+
+        config.nodes[0].negate = Some(true);
+        assert!(!matches_cpe("lodash", "4.17.20", &config));
+    }
+}
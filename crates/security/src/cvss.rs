@@ -0,0 +1,390 @@
+//! CVSS vector parsing
+//!
+//! This module decomposes CVSS v3.1 vector strings into their base metrics
+//! and recomputes the base score as defined by the CVSS 3.1 specification,
+//! so scores reported by advisory sources can be cross-checked. It also
+//! picks the severity of NVD entries scored with more than one CVSS version.
+
+use anyhow::{bail, Context, Result};
+use tracing::warn;
+
+use crate::vuln_db::{CvssDataV31, NvdCve};
+use crate::vulnerability::Severity;
+
+/// CVSS version whose metrics are preferred when an entry carries several
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CvssVersion {
+    V2,
+    #[default]
+    V3_1,
+}
+
+/// Non-base metrics that may appear in a vector but don't affect the base score
+const NON_BASE_METRICS: &[&str] = &[
+    "E", "RL", "RC", "CR", "IR", "AR", "MAV", "MAC", "MPR", "MUI", "MS", "MC", "MI", "MA",
+];
+
+/// Parse a CVSS v3.1 vector string and compute its base score
+///
+/// Both `CVSS:3.1/` and `CVSS:3.0/` prefixes are accepted; the 3.1 formula is
+/// used for either.
+///
+/// # Arguments
+/// * `vector` - Vector string such as `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`
+///
+/// # Returns
+/// * `Ok(CvssDataV31)` with every base metric, the base score and severity
+/// * `Err(anyhow::Error)` if the vector is malformed or misses a base metric
+pub fn parse_cvss_v31(vector: &str) -> Result<CvssDataV31> {
+    let mut parts = vector.trim().split('/');
+    let version = match parts.next() {
+        Some("CVSS:3.1") => "3.1",
+        Some("CVSS:3.0") => "3.0",
+        _ => bail!("Invalid CVSS vector {:?}: expected a CVSS:3.1 prefix", vector),
+    };
+
+    let mut metrics = BaseMetrics::default();
+    for part in parts {
+        let (key, value) = part
+            .split_once(':')
+            .with_context(|| format!("Invalid CVSS metric {:?} in {:?}", part, vector))?;
+        let slot = match key {
+            "AV" => &mut metrics.attack_vector,
+            "AC" => &mut metrics.attack_complexity,
+            "PR" => &mut metrics.privileges_required,
+            "UI" => &mut metrics.user_interaction,
+            "S" => &mut metrics.scope,
+            "C" => &mut metrics.confidentiality,
+            "I" => &mut metrics.integrity,
+            "A" => &mut metrics.availability,
+            _ if NON_BASE_METRICS.contains(&key) => continue,
+            _ => bail!("Unknown CVSS metric {:?} in {:?}", key, vector),
+        };
+        if slot.replace(value.to_string()).is_some() {
+            bail!("Duplicate CVSS metric {:?} in {:?}", key, vector);
+        }
+    }
+
+    let scope_changed = match metrics.get("S", &metrics.scope)? {
+        "U" => false,
+        "C" => true,
+        other => bail!("Invalid CVSS scope {:?}", other),
+    };
+    let attack_vector = metric_value("AV", metrics.get("AV", &metrics.attack_vector)?, &[
+        ("N", "NETWORK", 0.85),
+        ("A", "ADJACENT_NETWORK", 0.62),
+        ("L", "LOCAL", 0.55),
+        ("P", "PHYSICAL", 0.2),
+    ])?;
+    let attack_complexity = metric_value("AC", metrics.get("AC", &metrics.attack_complexity)?, &[
+        ("L", "LOW", 0.77),
+        ("H", "HIGH", 0.44),
+    ])?;
+    let privileges_required = metric_value("PR", metrics.get("PR", &metrics.privileges_required)?, &[
+        ("N", "NONE", 0.85),
+        ("L", "LOW", if scope_changed { 0.68 } else { 0.62 }),
+        ("H", "HIGH", if scope_changed { 0.5 } else { 0.27 }),
+    ])?;
+    let user_interaction = metric_value("UI", metrics.get("UI", &metrics.user_interaction)?, &[
+        ("N", "NONE", 0.85),
+        ("R", "REQUIRED", 0.62),
+    ])?;
+    let impact_values = [("H", "HIGH", 0.56), ("L", "LOW", 0.22), ("N", "NONE", 0.0)];
+    let confidentiality = metric_value("C", metrics.get("C", &metrics.confidentiality)?, &impact_values)?;
+    let integrity = metric_value("I", metrics.get("I", &metrics.integrity)?, &impact_values)?;
+    let availability = metric_value("A", metrics.get("A", &metrics.availability)?, &impact_values)?;
+
+    let iss = 1.0 - (1.0 - confidentiality.1) * (1.0 - integrity.1) * (1.0 - availability.1);
+    let impact = if scope_changed {
+        7.52 * (iss - 0.029) - 3.25 * (iss - 0.02).powi(15)
+    } else {
+        6.42 * iss
+    };
+    let exploitability = 8.22 * attack_vector.1 * attack_complexity.1 * privileges_required.1 * user_interaction.1;
+
+    let base_score = if impact <= 0.0 {
+        0.0
+    } else if scope_changed {
+        round_up((1.08 * (impact + exploitability)).min(10.0))
+    } else {
+        round_up((impact + exploitability).min(10.0))
+    };
+
+    Ok(CvssDataV31 {
+        version: version.to_string(),
+        vector_string: vector.trim().to_string(),
+        attack_vector: Some(attack_vector.0.to_string()),
+        attack_complexity: Some(attack_complexity.0.to_string()),
+        privileges_required: Some(privileges_required.0.to_string()),
+        user_interaction: Some(user_interaction.0.to_string()),
+        scope: Some(if scope_changed { "CHANGED" } else { "UNCHANGED" }.to_string()),
+        confidentiality_impact: Some(confidentiality.0.to_string()),
+        integrity_impact: Some(integrity.0.to_string()),
+        availability_impact: Some(availability.0.to_string()),
+        base_score,
+        base_severity: severity_name(base_score).to_string(),
+    })
+}
+
+/// A reported CVSS base score that disagrees with its vector
+#[derive(Debug, Clone, PartialEq)]
+pub struct CvssScoreMismatch {
+    pub vector_string: String,
+    pub reported: f64,
+    pub computed: f64,
+}
+
+/// Cross-check a provider-supplied base score against its vector string
+///
+/// # Arguments
+/// * `data` - CVSS data as reported by an advisory source
+///
+/// # Returns
+/// * `Ok(None)` if the reported score matches the recomputed one
+/// * `Ok(Some(CvssScoreMismatch))` if they differ
+/// * `Err(anyhow::Error)` if the vector string can't be parsed
+pub fn check_cvss_v31_score(data: &CvssDataV31) -> Result<Option<CvssScoreMismatch>> {
+    let computed = parse_cvss_v31(&data.vector_string)?.base_score;
+    // Scores have one decimal place; allow for float noise in the reported value
+    if (computed - data.base_score).abs() < 0.05 {
+        return Ok(None);
+    }
+
+    warn!(
+        "CVSS base score {} for {} doesn't match the computed score {}",
+        data.base_score, data.vector_string, computed
+    );
+    Ok(Some(CvssScoreMismatch {
+        vector_string: data.vector_string.clone(),
+        reported: data.base_score,
+        computed,
+    }))
+}
+
+/// Get the severity of an NVD entry from its CVSS metrics
+///
+/// The base score of the preferred version is used when the entry has one,
+/// falling back to the other version. Among several metrics of the same
+/// version, NVD's own (`Primary`) score wins over secondary sources. v3.1
+/// scores map to the v3 severity bands; v2 scores to the v2 bands, which
+/// top out at High.
+///
+/// # Arguments
+/// * `cve` - NVD entry to rate
+/// * `prefer` - CVSS version to use when both are present
+///
+/// # Returns
+/// * `Some(Severity)` for the chosen base score
+/// * `None` if the entry has no usable CVSS metrics
+pub fn effective_severity(cve: &NvdCve, prefer: CvssVersion) -> Option<Severity> {
+    let metrics = cve.metrics.as_ref()?;
+    let v31 = || {
+        let metrics = metrics.cvss_metric_v31.as_deref()?;
+        let metric = metrics.iter().find(|m| m.r#type == "Primary").or(metrics.first())?;
+        Severity::from_cvss_score(metric.cvss_data.base_score)
+    };
+    let v2 = || {
+        let metrics = metrics.cvss_metric_v2.as_deref()?;
+        let metric = metrics.iter().find(|m| m.r#type == "Primary").or(metrics.first())?;
+        v2_severity(metric.cvss_data.base_score)
+    };
+
+    match prefer {
+        CvssVersion::V3_1 => v31().or_else(v2),
+        CvssVersion::V2 => v2().or_else(v31),
+    }
+}
+
+/// Map a CVSS v2 base score to the v2 severity bands
+fn v2_severity(score: f64) -> Option<Severity> {
+    match score {
+        s if (0.0..4.0).contains(&s) => Some(Severity::Low),
+        s if (4.0..7.0).contains(&s) => Some(Severity::Medium),
+        s if (7.0..=10.0).contains(&s) => Some(Severity::High),
+        _ => None,
+    }
+}
+
+/// Base metric abbreviations as they appear in a vector
+#[derive(Default)]
+struct BaseMetrics {
+    attack_vector: Option<String>,
+    attack_complexity: Option<String>,
+    privileges_required: Option<String>,
+    user_interaction: Option<String>,
+    scope: Option<String>,
+    confidentiality: Option<String>,
+    integrity: Option<String>,
+    availability: Option<String>,
+}
+
+impl BaseMetrics {
+    fn get<'a>(&self, key: &str, value: &'a Option<String>) -> Result<&'a str> {
+        value
+            .as_deref()
+            .with_context(|| format!("CVSS vector is missing the {} metric", key))
+    }
+}
+
+/// Look up a metric abbreviation, returning its NVD name and weight
+fn metric_value(
+    key: &str,
+    value: &str,
+    values: &[(&str, &'static str, f64)],
+) -> Result<(&'static str, f64)> {
+    values
+        .iter()
+        .find(|(abbreviation, _, _)| *abbreviation == value)
+        .map(|(_, name, weight)| (*name, *weight))
+        .with_context(|| format!("Invalid value {:?} for CVSS metric {}", value, key))
+}
+
+/// The CVSS 3.1 `Roundup` function: the smallest one-decimal number >= `value`
+fn round_up(value: f64) -> f64 {
+    let int_input = (value * 100_000.0).round() as i64;
+    if int_input % 10_000 == 0 {
+        int_input as f64 / 100_000.0
+    } else {
+        ((int_input / 10_000) + 1) as f64 / 10.0
+    }
+}
+
+/// Qualitative severity name for a base score, as used by NVD
+fn severity_name(score: f64) -> &'static str {
+    match score {
+        s if s <= 0.0 => "NONE",
+        s if s < 4.0 => "LOW",
+        s if s < 7.0 => "MEDIUM",
+        s if s < 9.0 => "HIGH",
+        _ => "CRITICAL",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cvss_v31_known_scores() {
+        let cases = [
+            // CVE-2021-44228 (Log4Shell)
+            ("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:C/C:H/I:H/A:H", 10.0, "CRITICAL"),
+            // CVE-2022-22965 (Spring4Shell)
+            ("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H", 9.8, "CRITICAL"),
+            // CVE-2021-3156 (sudo Baron Samedit)
+            ("CVSS:3.1/AV:L/AC:L/PR:L/UI:N/S:U/C:H/I:H/A:H", 7.8, "HIGH"),
+            // Typical reflected XSS
+            ("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N", 6.1, "MEDIUM"),
+            // Typical ReDoS
+            ("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:H", 7.5, "HIGH"),
+            ("CVSS:3.0/AV:N/AC:H/PR:N/UI:N/S:U/C:L/I:N/A:N", 3.7, "LOW"),
+            ("CVSS:3.1/AV:N/AC:L/PR:H/UI:R/S:C/C:L/I:L/A:N", 4.8, "MEDIUM"),
+            ("CVSS:3.1/AV:P/AC:H/PR:H/UI:R/S:U/C:N/I:N/A:N", 0.0, "NONE"),
+        ];
+
+        for (vector, score, severity) in cases {
+            let data = parse_cvss_v31(vector).unwrap();
+            assert_eq!(data.base_score, score, "{}", vector);
+            assert_eq!(data.base_severity, severity, "{}", vector);
+        }
+    }
+
+    #[test]
+    fn test_parse_cvss_v31_metrics() {
+        let data = parse_cvss_v31("CVSS:3.1/AV:A/AC:H/PR:L/UI:R/S:U/C:L/I:N/A:H/E:P/RL:O").unwrap();
+        assert_eq!(data.version, "3.1");
+        assert_eq!(data.attack_vector.as_deref(), Some("ADJACENT_NETWORK"));
+        assert_eq!(data.attack_complexity.as_deref(), Some("HIGH"));
+        assert_eq!(data.privileges_required.as_deref(), Some("LOW"));
+        assert_eq!(data.user_interaction.as_deref(), Some("REQUIRED"));
+        assert_eq!(data.scope.as_deref(), Some("UNCHANGED"));
+        assert_eq!(data.integrity_impact.as_deref(), Some("NONE"));
+    }
+
+    #[test]
+    fn test_parse_cvss_v31_invalid() {
+        assert!(parse_cvss_v31("AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H").is_err());
+        assert!(parse_cvss_v31("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H").is_err());
+        assert!(parse_cvss_v31("CVSS:3.1/AV:X/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H").is_err());
+        assert!(parse_cvss_v31("CVSS:3.1/AV:N/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H").is_err());
+        assert!(parse_cvss_v31("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H/ZZ:1").is_err());
+    }
+
+    #[test]
+    fn test_check_cvss_v31_score() {
+        let mut data = parse_cvss_v31("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H").unwrap();
+        assert_eq!(check_cvss_v31_score(&data).unwrap(), None);
+
+        data.base_score = 7.2;
+        let mismatch = check_cvss_v31_score(&data).unwrap().unwrap();
+        assert_eq!(mismatch.reported, 7.2);
+        assert_eq!(mismatch.computed, 9.8);
+    }
+
+    fn cve_with_metrics(v31: Option<(&str, f64)>, v2: Option<(&str, f64)>) -> NvdCve {
+        let v31 = v31.map(|(kind, score)| {
+            serde_json::json!([{
+                "source": "nvd@nist.gov",
+                "type": kind,
+                "cvss_data": {
+                    "version": "3.1",
+                    "vector_string": "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H",
+                    "base_score": score,
+                    "base_severity": severity_name(score)
+                },
+                "base_severity": severity_name(score),
+                "exploitability_score": 3.9,
+                "impact_score": 5.9
+            }])
+        });
+        let v2 = v2.map(|(kind, score)| {
+            serde_json::json!([{
+                "source": "nvd@nist.gov",
+                "type": kind,
+                "cvss_data": { "version": "2.0", "vector_string": "AV:N/AC:L/Au:N/C:P/I:P/A:P", "base_score": score },
+                "base_severity": "MEDIUM",
+                "exploitability_score": 10.0,
+                "impact_score": 6.4,
+                "ac_insuf_info": false,
+                "obtain_all_privilege": false,
+                "obtain_user_privilege": false,
+                "obtain_other_privilege": false
+            }])
+        });
+        serde_json::from_value(serde_json::json!({
+            "id": "CVE-2023-0001",
+            "published": "2023-01-01T00:00:00.000",
+            "last_modified": "2023-01-01T00:00:00.000",
+            "descriptions": [],
+            "metrics": { "cvssMetricV31": v31, "cvssMetricV2": v2 },
+            "references": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_effective_severity_prefers_configured_version() {
+        let both = cve_with_metrics(Some(("Primary", 9.8)), Some(("Primary", 5.0)));
+        assert_eq!(effective_severity(&both, CvssVersion::V3_1), Some(Severity::Critical));
+        assert_eq!(effective_severity(&both, CvssVersion::V2), Some(Severity::Medium));
+        assert_eq!(effective_severity(&both, CvssVersion::default()), Some(Severity::Critical));
+
+        // v2 bands stop at High
+        let v2_high = cve_with_metrics(Some(("Primary", 6.5)), Some(("Primary", 10.0)));
+        assert_eq!(effective_severity(&v2_high, CvssVersion::V2), Some(Severity::High));
+    }
+
+    #[test]
+    fn test_effective_severity_falls_back() {
+        let v2_only = cve_with_metrics(None, Some(("Primary", 7.5)));
+        assert_eq!(effective_severity(&v2_only, CvssVersion::V3_1), Some(Severity::High));
+
+        let v31_only = cve_with_metrics(Some(("Secondary", 3.1)), None);
+        assert_eq!(effective_severity(&v31_only, CvssVersion::V2), Some(Severity::Low));
+
+        assert_eq!(effective_severity(&cve_with_metrics(None, None), CvssVersion::V3_1), None);
+        let mut unscored = cve_with_metrics(None, None);
+        unscored.metrics = None;
+        assert_eq!(effective_severity(&unscored, CvssVersion::V2), None);
+    }
+}
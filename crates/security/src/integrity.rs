@@ -3,11 +3,27 @@
 //! This module provides functions for verifying the integrity of packages
 //! using cryptographic hashes and digital signatures.
 
-use sha2::{Sha512, Digest};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512, Digest};
 use anyhow::Result;
+use base64::Engine;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::Signature;
+use p256::pkcs8::DecodePublicKey;
 use thiserror::Error;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::Read;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Public key used to verify registry signatures (ECDSA P-256)
+pub type PublicKey = p256::ecdsa::VerifyingKey;
+
+/// Upper bound on files hashed at once by `verify_batch`
+///
+/// Hashing is mostly disk-bound, so more threads than this only thrash the disk.
+pub const MAX_BATCH_CONCURRENCY: usize = 8;
 
 /// Error types for integrity verification
 #[derive(Error, Debug)]
@@ -18,6 +34,44 @@ pub enum IntegrityError {
     IoError(#[from] std::io::Error),
     #[error("Invalid hash format")]
     InvalidHashFormat,
+    #[error("Signature verification failed")]
+    SignatureInvalid,
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("Invalid provenance: {0}")]
+    InvalidProvenance(String),
+}
+
+/// Hash algorithms accepted for package verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// Only for legacy digests such as npm's `shasum`
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Hash some bytes, returning the digest as a lowercase hex string
+    pub fn hex_digest(self, bytes: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Sha1 => hex::encode(Sha1::digest(bytes)),
+            HashAlgorithm::Sha256 => hex::encode(Sha256::digest(bytes)),
+            HashAlgorithm::Sha512 => hex::encode(Sha512::digest(bytes)),
+        }
+    }
+
+    /// Infer the algorithm of a hex digest from its length
+    ///
+    /// 40 characters is SHA-1, 64 is SHA-256 and 128 is SHA-512.
+    pub fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            40 => Some(HashAlgorithm::Sha1),
+            64 => Some(HashAlgorithm::Sha256),
+            128 => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
 }
 
 /// Verify the integrity of a package file using SHA-512
@@ -33,13 +87,47 @@ pub fn verify_package_integrity(file_path: &Path, expected_hash: &str) -> Result
     // Read the file content
     let content = fs::read(file_path)?;
     
-    // Calculate SHA-512 hash of the content
-    let mut hasher = Sha512::new();
-    hasher.update(&content);
-    let calculated_hash = format!("{:x}", hasher.finalize());
+    verify_bytes_integrity(&content, expected_hash, HashAlgorithm::Sha512)
+}
+
+/// Verify a package file against a hex digest of unknown algorithm
+///
+/// The algorithm is inferred from the digest length (see
+/// `HashAlgorithm::from_hex_len`).
+///
+/// # Arguments
+/// * `file_path` - Path to the package file to verify
+/// * `hex` - Expected digest as a hex string (case-insensitive)
+///
+/// # Returns
+/// * `Ok(())` if the file's hash matches the digest
+/// * `Err(IntegrityError::InvalidHashFormat)` if the digest isn't hex of a known length
+/// * `Err(IntegrityError)` if reading the file or verification fails
+pub fn verify_auto(file_path: &Path, hex: &str) -> Result<(), IntegrityError> {
+    let algo = HashAlgorithm::from_hex_len(hex.len())
+        .filter(|_| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or(IntegrityError::InvalidHashFormat)?;
+    let content = fs::read(file_path)?;
+    
+    verify_bytes_integrity(&content, hex, algo)
+}
+
+/// Verify the integrity of package bytes held in memory
+///
+/// This avoids writing a freshly downloaded tarball to disk just to check it.
+///
+/// # Arguments
+/// * `bytes` - The package contents
+/// * `expected_hash` - Expected hash as a hex string (case-insensitive)
+/// * `algo` - Algorithm `expected_hash` was computed with
+///
+/// # Returns
+/// * `Ok(())` if the hash of `bytes` matches the expected hash
+/// * `Err(IntegrityError::HashMismatch)` otherwise
+pub fn verify_bytes_integrity(bytes: &[u8], expected_hash: &str, algo: HashAlgorithm) -> Result<(), IntegrityError> {
+    let calculated_hash = algo.hex_digest(bytes);
     
-    // Compare with expected hash
-    if calculated_hash == expected_hash {
+    if calculated_hash.eq_ignore_ascii_case(expected_hash) {
         Ok(())
     } else {
         Err(IntegrityError::HashMismatch {
@@ -49,6 +137,37 @@ pub fn verify_package_integrity(file_path: &Path, expected_hash: &str) -> Result
     }
 }
 
+/// Incremental hash function used to fingerprint package files
+///
+/// Implemented for the `sha1` and `sha2` hashers. Other crates can
+/// implement it to plug a faster or hardware-accelerated backend into
+/// `calculate_hash_with`.
+pub trait Hasher {
+    /// Feed more data into the hash
+    fn update(&mut self, data: &[u8]);
+
+    /// Finish hashing, returning the digest as a lowercase hex string
+    fn finalize_hex(self) -> String;
+}
+
+macro_rules! impl_digest_hasher {
+    ($($digest:ty),*) => {
+        $(
+            impl Hasher for $digest {
+                fn update(&mut self, data: &[u8]) {
+                    Digest::update(self, data);
+                }
+
+                fn finalize_hex(self) -> String {
+                    hex::encode(Digest::finalize(self))
+                }
+            }
+        )*
+    };
+}
+
+impl_digest_hasher!(Sha1, Sha256, Sha512);
+
 /// Calculate the SHA-512 hash of a package file
 /// 
 /// # Arguments
@@ -58,15 +177,216 @@ pub fn verify_package_integrity(file_path: &Path, expected_hash: &str) -> Result
 /// * `Ok(String)` with the SHA-512 hash as a hex string
 /// * `Err(IntegrityError)` if reading the file fails
 pub fn calculate_package_hash(file_path: &Path) -> Result<String, IntegrityError> {
-    // Read the file content
-    let content = fs::read(file_path)?;
-    
-    // Calculate SHA-512 hash of the content
-    let mut hasher = Sha512::new();
-    hasher.update(&content);
-    let hash = format!("{:x}", hasher.finalize());
-    
-    Ok(hash)
+    calculate_hash_with(file_path, Sha512::new())
+}
+
+/// Calculate the hash of a package file with a given hasher
+///
+/// The file is streamed through the hasher, so it is never held in memory
+/// whole.
+///
+/// # Arguments
+/// * `file_path` - Path to the package file
+/// * `hasher` - Fresh hasher to feed the file through
+///
+/// # Returns
+/// * `Ok(String)` with the hasher's hex digest
+/// * `Err(IntegrityError)` if reading the file fails
+pub fn calculate_hash_with<H: Hasher>(file_path: &Path, mut hasher: H) -> Result<String, IntegrityError> {
+    let mut file = fs::File::open(file_path)?;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize_hex())
+}
+
+/// Verify the integrity of many package files in parallel
+///
+/// Files are hashed across a thread pool of at most `MAX_BATCH_CONCURRENCY`
+/// threads. Every file is checked; a failure doesn't stop the others.
+///
+/// # Arguments
+/// * `items` - Pairs of file path and expected SHA-512 hex hash
+///
+/// # Returns
+/// * One result per item, in the order of `items`
+pub fn verify_batch(items: &[(PathBuf, String)]) -> Vec<(PathBuf, Result<(), IntegrityError>)> {
+    let verify_all = || {
+        items
+            .par_iter()
+            .map(|(path, expected)| (path.clone(), verify_package_integrity(path, expected)))
+            .collect()
+    };
+
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(MAX_BATCH_CONCURRENCY);
+    match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(verify_all),
+        Err(e) => {
+            tracing::warn!("Failed to build verification thread pool, verifying sequentially: {}", e);
+            items
+                .iter()
+                .map(|(path, expected)| (path.clone(), verify_package_integrity(path, expected)))
+                .collect()
+        }
+    }
+}
+
+/// Calculate the SRI integrity string (`sha512-<base64>`) of some bytes
+pub fn calculate_integrity(bytes: &[u8]) -> String {
+    format!(
+        "sha512-{}",
+        base64::engine::general_purpose::STANDARD.encode(Sha512::digest(bytes))
+    )
+}
+
+/// Parse a public key published by a registry
+///
+/// npm serves its signing keys at `/-/npm/v1/keys` as base64-encoded
+/// SubjectPublicKeyInfo DER.
+///
+/// # Arguments
+/// * `spki_base64` - Base64-encoded SPKI DER public key
+///
+/// # Returns
+/// * `Ok(PublicKey)` with the parsed key
+/// * `Err(IntegrityError::InvalidPublicKey)` if the key can't be decoded
+pub fn parse_public_key(spki_base64: &str) -> Result<PublicKey, IntegrityError> {
+    let der = base64::engine::general_purpose::STANDARD
+        .decode(spki_base64.trim())
+        .map_err(|e| IntegrityError::InvalidPublicKey(e.to_string()))?;
+    PublicKey::from_public_key_der(&der).map_err(|e| IntegrityError::InvalidPublicKey(e.to_string()))
+}
+
+/// Verify a signature over a package tarball's integrity string
+///
+/// The tarball's SHA-512 integrity string is computed and the signature is
+/// checked against it with ECDSA P-256/SHA-256.
+///
+/// # Arguments
+/// * `tarball` - Path to the package tarball
+/// * `signature` - DER-encoded (or raw 64-byte) ECDSA signature
+/// * `public_key` - Key of the signer
+///
+/// # Returns
+/// * `Ok(())` if the signature is valid for the tarball
+/// * `Err(IntegrityError::SignatureInvalid)` if it isn't
+pub fn verify_package_signature(tarball: &Path, signature: &[u8], public_key: &PublicKey) -> Result<(), IntegrityError> {
+    let integrity = calculate_integrity(&fs::read(tarball)?);
+    verify_signature(integrity.as_bytes(), signature, public_key)
+}
+
+/// Verify an npm registry signature from package metadata
+///
+/// The registry signs the message `<name>@<version>:<integrity>` for every
+/// published version, and includes the signature in `dist.signatures`.
+///
+/// # Arguments
+/// * `package` - Package name
+/// * `version` - Package version
+/// * `integrity` - The `dist.integrity` value from the metadata
+/// * `signature` - DER-encoded ECDSA signature
+/// * `public_key` - Registry key matching the signature's key id
+pub fn verify_registry_signature(
+    package: &str,
+    version: &str,
+    integrity: &str,
+    signature: &[u8],
+    public_key: &PublicKey,
+) -> Result<(), IntegrityError> {
+    let message = format!("{}@{}:{}", package, version, integrity);
+    verify_signature(message.as_bytes(), signature, public_key)
+}
+
+/// Verify an ECDSA P-256/SHA-256 signature over a message
+fn verify_signature(message: &[u8], signature: &[u8], public_key: &PublicKey) -> Result<(), IntegrityError> {
+    let signature = Signature::from_der(signature)
+        .or_else(|_| Signature::from_slice(signature))
+        .map_err(|_| IntegrityError::SignatureInvalid)?;
+    public_key
+        .verify(message, &signature)
+        .map_err(|_| IntegrityError::SignatureInvalid)
+}
+
+/// How a directory's files differ from a file manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileManifestDiff {
+    /// Files present now but not in the manifest
+    pub added: Vec<PathBuf>,
+    /// Files in the manifest that no longer exist
+    pub removed: Vec<PathBuf>,
+    /// Files whose contents no longer match their manifest hash
+    pub modified: Vec<PathBuf>,
+}
+
+impl FileManifestDiff {
+    /// Check whether the directory still matches the manifest exactly
+    pub fn is_clean(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Hash every file under a directory, such as an extracted package
+///
+/// Paths are relative to `dir`. Symbolic links are not followed or hashed.
+///
+/// # Arguments
+/// * `dir` - Directory to walk
+///
+/// # Returns
+/// * `Ok(HashMap<PathBuf, String>)` mapping each file to its SHA-256 hex hash
+/// * `Err(IntegrityError)` if the directory or a file can't be read
+pub fn build_file_manifest(dir: &Path) -> Result<HashMap<PathBuf, String>, IntegrityError> {
+    let mut manifest = HashMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                let relative = entry.path().strip_prefix(dir).map(Path::to_path_buf).unwrap_or_else(|_| entry.path());
+                manifest.insert(relative, HashAlgorithm::Sha256.hex_digest(&fs::read(entry.path())?));
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+/// Compare the files under a directory with a manifest from `build_file_manifest`
+///
+/// # Arguments
+/// * `dir` - Directory the manifest was built from
+/// * `manifest` - Expected SHA-256 hex hash of each file, by relative path
+///
+/// # Returns
+/// * `Ok(FileManifestDiff)` with the added, removed and modified files, each sorted
+/// * `Err(IntegrityError)` if the directory or a file can't be read
+pub fn verify_file_manifest(dir: &Path, manifest: &HashMap<PathBuf, String>) -> Result<FileManifestDiff, IntegrityError> {
+    let current = build_file_manifest(dir)?;
+    let mut diff = FileManifestDiff::default();
+
+    for (path, hash) in &current {
+        match manifest.get(path) {
+            None => diff.added.push(path.clone()),
+            Some(expected) if !expected.eq_ignore_ascii_case(hash) => diff.modified.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = manifest.keys().filter(|path| !current.contains_key(*path)).cloned().collect();
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.modified.sort();
+    Ok(diff)
 }
 
 #[cfg(test)]
@@ -84,6 +404,37 @@ mod tests {
         assert_eq!(hash.len(), 128); // SHA-512 produces 128 hex characters
     }
 
+    /// Sums the bytes fed to it, to check `calculate_hash_with` drives any `Hasher`
+    #[derive(Default)]
+    struct ByteSum(u64);
+
+    impl Hasher for ByteSum {
+        fn update(&mut self, data: &[u8]) {
+            self.0 += data.iter().map(|&b| u64::from(b)).sum::<u64>();
+        }
+
+        fn finalize_hex(self) -> String {
+            format!("{:016x}", self.0)
+        }
+    }
+
+    #[test]
+    fn test_calculate_hash_with_custom_hasher() {
+        let mut file = NamedTempFile::new().unwrap();
+        // Larger than the read buffer, so the hasher is fed several chunks
+        file.write_all(&vec![1u8; 100 * 1024]).unwrap();
+
+        let sum = calculate_hash_with(file.path(), ByteSum::default()).unwrap();
+        assert_eq!(sum, format!("{:016x}", 100 * 1024));
+
+        let sha256 = calculate_hash_with(file.path(), Sha256::new()).unwrap();
+        assert_eq!(sha256, HashAlgorithm::Sha256.hex_digest(&vec![1u8; 100 * 1024]));
+        assert_eq!(
+            calculate_package_hash(file.path()).unwrap(),
+            HashAlgorithm::Sha512.hex_digest(&vec![1u8; 100 * 1024])
+        );
+    }
+
     #[test]
     fn test_verify_package_integrity_success() {
         let mut file = NamedTempFile::new().unwrap();
@@ -93,6 +444,66 @@ mod tests {
         assert!(verify_package_integrity(file.path(), &hash).is_ok());
     }
 
+    #[test]
+    fn test_verify_bytes_integrity() {
+        let bytes = b"tarball bytes";
+        let sha512 = HashAlgorithm::Sha512.hex_digest(bytes);
+        let sha256 = HashAlgorithm::Sha256.hex_digest(bytes);
+        assert_eq!(sha256.len(), 64);
+
+        assert!(verify_bytes_integrity(bytes, &sha512, HashAlgorithm::Sha512).is_ok());
+        assert!(verify_bytes_integrity(bytes, &sha512.to_uppercase(), HashAlgorithm::Sha512).is_ok());
+        assert!(verify_bytes_integrity(bytes, &sha256, HashAlgorithm::Sha256).is_ok());
+
+        // The in-memory and on-disk checks agree
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        assert_eq!(calculate_package_hash(file.path()).unwrap(), sha512);
+    }
+
+    #[test]
+    fn test_verify_bytes_integrity_mismatch() {
+        let sha512 = HashAlgorithm::Sha512.hex_digest(b"tarball bytes");
+
+        match verify_bytes_integrity(b"tampered bytes", &sha512, HashAlgorithm::Sha512) {
+            Err(IntegrityError::HashMismatch { expected, actual }) => {
+                assert_eq!(expected, sha512);
+                assert_eq!(actual, HashAlgorithm::Sha512.hex_digest(b"tampered bytes"));
+            }
+            other => panic!("Expected HashMismatch error, got {:?}", other),
+        }
+        // A digest of the wrong algorithm never matches
+        assert!(verify_bytes_integrity(b"tarball bytes", &sha512, HashAlgorithm::Sha256).is_err());
+    }
+
+    #[test]
+    fn test_verify_auto_detects_algorithm() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"tarball bytes").unwrap();
+
+        for algo in [HashAlgorithm::Sha1, HashAlgorithm::Sha256, HashAlgorithm::Sha512] {
+            let hex = algo.hex_digest(b"tarball bytes");
+            assert_eq!(HashAlgorithm::from_hex_len(hex.len()), Some(algo));
+            assert!(verify_auto(file.path(), &hex).is_ok());
+            assert!(matches!(
+                verify_auto(file.path(), &algo.hex_digest(b"tampered")),
+                Err(IntegrityError::HashMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_auto_rejects_unknown_lengths() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"tarball bytes").unwrap();
+        let sha1 = HashAlgorithm::Sha1.hex_digest(b"tarball bytes");
+
+        assert!(matches!(verify_auto(file.path(), &sha1[..32]), Err(IntegrityError::InvalidHashFormat)));
+        assert!(matches!(verify_auto(file.path(), ""), Err(IntegrityError::InvalidHashFormat)));
+        let not_hex = "z".repeat(40);
+        assert!(matches!(verify_auto(file.path(), &not_hex), Err(IntegrityError::InvalidHashFormat)));
+    }
+
     #[test]
     fn test_verify_package_integrity_failure() {
         let mut file = NamedTempFile::new().unwrap();
@@ -106,4 +517,119 @@ mod tests {
             _ => panic!("Expected HashMismatch error"),
         }
     }
-}
\ No newline at end of file
+
+    fn signing_key() -> p256::ecdsa::SigningKey {
+        p256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap()
+    }
+
+    fn sign(message: &[u8]) -> Vec<u8> {
+        use p256::ecdsa::signature::Signer;
+        let signature: Signature = signing_key().sign(message);
+        signature.to_der().as_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_verify_package_signature() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "tarball bytes").unwrap();
+        let integrity = calculate_integrity(b"tarball bytes");
+        let signature = sign(integrity.as_bytes());
+        let public_key = *signing_key().verifying_key();
+
+        assert!(verify_package_signature(file.path(), &signature, &public_key).is_ok());
+    }
+
+    #[test]
+    fn test_verify_package_signature_tampered() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "tampered bytes").unwrap();
+        let signature = sign(calculate_integrity(b"tarball bytes").as_bytes());
+        let public_key = *signing_key().verifying_key();
+
+        match verify_package_signature(file.path(), &signature, &public_key) {
+            Err(IntegrityError::SignatureInvalid) => {}
+            other => panic!("Expected SignatureInvalid, got {:?}", other),
+        }
+        assert!(matches!(
+            verify_package_signature(file.path(), b"not a signature", &public_key),
+            Err(IntegrityError::SignatureInvalid)
+        ));
+    }
+
+    #[test]
+    fn test_verify_registry_signature() {
+        use p256::pkcs8::EncodePublicKey;
+
+        let integrity = calculate_integrity(b"tarball bytes");
+        let signature = sign(format!("left-pad@1.3.0:{}", integrity).as_bytes());
+
+        // Round-trip the key through the SPKI form the registry publishes
+        let der = signing_key().verifying_key().to_public_key_der().unwrap();
+        let public_key = parse_public_key(&base64::engine::general_purpose::STANDARD.encode(der.as_bytes())).unwrap();
+
+        assert!(verify_registry_signature("left-pad", "1.3.0", &integrity, &signature, &public_key).is_ok());
+        assert!(verify_registry_signature("left-pad", "1.3.1", &integrity, &signature, &public_key).is_err());
+        assert!(matches!(parse_public_key("not base64!"), Err(IntegrityError::InvalidPublicKey(_))));
+    }
+
+    #[test]
+    fn test_verify_batch_reports_each_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut items = Vec::new();
+        for i in 0..20 {
+            let path = dir.path().join(format!("pkg-{}.tgz", i));
+            fs::write(&path, format!("package {}", i)).unwrap();
+            let mut hash = calculate_package_hash(&path).unwrap();
+            if i % 3 == 0 {
+                hash = "0".repeat(128);
+            }
+            items.push((path, hash));
+        }
+        items.push((dir.path().join("missing.tgz"), "0".repeat(128)));
+
+        let results = verify_batch(&items);
+        assert_eq!(results.len(), items.len());
+        for (i, (path, result)) in results.iter().enumerate() {
+            assert_eq!(path, &items[i].0);
+            if i == 20 {
+                assert!(matches!(result, Err(IntegrityError::IoError(_))));
+            } else if i % 3 == 0 {
+                assert!(matches!(result, Err(IntegrityError::HashMismatch { .. })));
+            } else {
+                assert!(result.is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_manifest_detects_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("lib")).unwrap();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        fs::write(dir.path().join("index.js"), "module.exports = 1;").unwrap();
+        fs::write(dir.path().join("lib/util.js"), "exports.util = true;").unwrap();
+
+        let manifest = build_file_manifest(dir.path()).unwrap();
+        assert_eq!(manifest.len(), 3);
+        assert_eq!(
+            manifest[Path::new("lib/util.js")],
+            HashAlgorithm::Sha256.hex_digest(b"exports.util = true;")
+        );
+        assert!(verify_file_manifest(dir.path(), &manifest).unwrap().is_clean());
+
+        fs::write(dir.path().join("index.js"), "module.exports = 'pwned';").unwrap();
+        fs::write(dir.path().join("lib/extra.js"), "").unwrap();
+        fs::remove_file(dir.path().join("package.json")).unwrap();
+
+        let diff = verify_file_manifest(dir.path(), &manifest).unwrap();
+        assert_eq!(
+            diff,
+            FileManifestDiff {
+                added: vec![PathBuf::from("lib/extra.js")],
+                removed: vec![PathBuf::from("package.json")],
+                modified: vec![PathBuf::from("index.js")],
+            }
+        );
+        assert!(!diff.is_clean());
+    }
+}
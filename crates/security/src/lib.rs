@@ -9,20 +9,35 @@
 pub mod integrity;
 pub mod vulnerability;
 pub mod vuln_db;
+pub mod cvss;
+pub mod cpe;
 pub mod audit;
 pub mod runtime;
 pub mod sandbox;
 pub mod service;
 pub mod performance;
+pub mod policy;
+#[cfg(feature = "provenance")]
+pub mod provenance;
 
 // Re-export the main components for easier access
-pub use integrity::{verify_package_integrity, IntegrityError};
-pub use vulnerability::{scan_for_vulnerabilities, VulnerabilityReport};
-pub use audit::{AuditTrail, AuditEvent};
+pub use integrity::{
+    calculate_hash_with, verify_auto, verify_bytes_integrity, verify_package_integrity, HashAlgorithm, Hasher,
+    IntegrityError,
+};
+pub use vulnerability::{
+    scan_for_vulnerabilities, scan_for_vulnerabilities_in, scan_with_client, scan_with_threshold, Ecosystem, ScanOutcome,
+    Severity, VulnerabilityReport, VulnerabilitySource,
+};
+pub use audit::{
+    AuditEvent, AuditFormat, AuditSink, AuditTrail, BufferedSink, BufferedSinkConfig, FileSink, OverflowPolicy,
+    RotationInterval, RotationPolicy,
+};
 pub use runtime::{RuntimeProtection, RuntimeProtectionError};
 pub use sandbox::SandboxRuntimeProtection;
 pub use service::SecurityService;
 pub use performance::PerformanceMonitor;
+pub use policy::{PolicyDecision, SecurityPolicy};
 
 /// Security module configuration
 #[derive(Debug, Clone)]
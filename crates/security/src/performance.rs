@@ -3,7 +3,7 @@
 //! This module provides performance monitoring capabilities for security operations
 //! including timing, resource usage tracking, and performance alerts.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
 use tracing::{info, warn};
 
@@ -32,6 +32,28 @@ pub struct PerformanceMetric {
     pub timestamp: Instant,
 }
 
+/// Default number of metrics kept per metric type
+pub const DEFAULT_MAX_RETAINED_PER_TYPE: usize = 1000;
+
+/// Running totals over every metric recorded for a type, including evicted ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricAggregate {
+    /// Number of metrics recorded
+    pub count: u64,
+    /// Sum of their durations
+    pub total: Duration,
+}
+
+impl MetricAggregate {
+    /// Average duration, if anything was recorded
+    pub fn average(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64))
+    }
+}
+
 /// Performance monitoring configuration
 #[derive(Debug, Clone)]
 pub struct PerformanceConfig {
@@ -41,6 +63,8 @@ pub struct PerformanceConfig {
     pub thresholds: HashMap<MetricType, Duration>,
     /// Whether to log performance metrics
     pub log_metrics: bool,
+    /// Most metrics kept per metric type; the oldest are evicted first
+    pub max_retained_per_type: usize,
 }
 
 impl Default for PerformanceConfig {
@@ -56,6 +80,7 @@ impl Default for PerformanceConfig {
             enabled: true,
             thresholds,
             log_metrics: true,
+            max_retained_per_type: DEFAULT_MAX_RETAINED_PER_TYPE,
         }
     }
 }
@@ -64,23 +89,26 @@ impl Default for PerformanceConfig {
 #[derive(Debug)]
 pub struct PerformanceMonitor {
     config: PerformanceConfig,
-    metrics: Vec<PerformanceMetric>,
+    /// Retained metrics of each type, oldest first, with the order they were recorded in
+    metrics: HashMap<MetricType, VecDeque<(u64, PerformanceMetric)>>,
+    /// Number of metrics recorded, numbering the next one
+    recorded: u64,
+    aggregates: HashMap<MetricType, MetricAggregate>,
 }
 
 impl PerformanceMonitor {
     /// Create a new performance monitor with default configuration
     pub fn new() -> Self {
-        Self {
-            config: PerformanceConfig::default(),
-            metrics: Vec::new(),
-        }
+        Self::with_config(PerformanceConfig::default())
     }
 
     /// Create a new performance monitor with custom configuration
     pub fn with_config(config: PerformanceConfig) -> Self {
         Self {
             config,
-            metrics: Vec::new(),
+            metrics: HashMap::new(),
+            recorded: 0,
+            aggregates: HashMap::new(),
         }
     }
 
@@ -102,7 +130,20 @@ impl PerformanceMonitor {
             }
         }
         
-        self.metrics.push(metric);
+        let aggregate = self.aggregates.entry(metric.metric_type.clone()).or_default();
+        aggregate.count += 1;
+        aggregate.total += metric.duration;
+
+        if self.config.max_retained_per_type == 0 {
+            return;
+        }
+        let retained = self.metrics.entry(metric.metric_type.clone()).or_default();
+        if retained.len() >= self.config.max_retained_per_type {
+            // Evict the oldest metric of this type
+            retained.pop_front();
+        }
+        retained.push_back((self.recorded, metric));
+        self.recorded += 1;
     }
 
     /// Start timing an operation
@@ -123,37 +164,39 @@ impl PerformanceMonitor {
         self.record_metric(metric);
     }
 
-    /// Get all recorded metrics
-    pub fn metrics(&self) -> &[PerformanceMetric] {
-        &self.metrics
+    /// Get the retained metrics, oldest first
+    pub fn metrics(&self) -> Vec<&PerformanceMetric> {
+        let mut metrics: Vec<&(u64, PerformanceMetric)> = self.metrics.values().flatten().collect();
+        metrics.sort_unstable_by_key(|(recorded, _)| *recorded);
+        metrics.into_iter().map(|(_, metric)| metric).collect()
     }
 
-    /// Get metrics for a specific type
+    /// Get metrics for a specific type, oldest first
     pub fn metrics_for_type(&self, metric_type: &MetricType) -> Vec<&PerformanceMetric> {
         self.metrics
-            .iter()
-            .filter(|metric| &metric.metric_type == metric_type)
-            .collect()
+            .get(metric_type)
+            .map(|retained| retained.iter().map(|(_, metric)| metric).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the running totals for a metric type, covering evicted metrics too
+    pub fn aggregate(&self, metric_type: &MetricType) -> MetricAggregate {
+        self.aggregates.get(metric_type).copied().unwrap_or_default()
     }
 
     /// Calculate average duration for a specific metric type
+    ///
+    /// The average covers every metric recorded since the last clear, not
+    /// just the retained ones.
     pub fn average_duration(&self, metric_type: &MetricType) -> Option<Duration> {
-        let metrics: Vec<&PerformanceMetric> = self.metrics_for_type(metric_type);
-        if metrics.is_empty() {
-            return None;
-        }
-        
-        let total_duration: Duration = metrics
-            .iter()
-            .map(|metric| metric.duration)
-            .sum();
-            
-        Some(total_duration / metrics.len() as u32)
+        self.aggregate(metric_type).average()
     }
 
-    /// Clear all recorded metrics
+    /// Clear all recorded metrics and running totals
     pub fn clear_metrics(&mut self) {
         self.metrics.clear();
+        self.recorded = 0;
+        self.aggregates.clear();
     }
 }
 
@@ -183,6 +226,7 @@ mod tests {
             enabled: false,
             thresholds: HashMap::new(),
             log_metrics: false,
+            max_retained_per_type: 10,
         };
         
         let monitor = PerformanceMonitor::with_config(config);
@@ -216,7 +260,7 @@ mod tests {
         monitor.end_timing(start, MetricType::IntegrityVerification);
         assert_eq!(monitor.metrics().len(), 1);
         
-        let metric = &monitor.metrics()[0];
+        let metric = monitor.metrics()[0];
         assert_eq!(metric.metric_type, MetricType::IntegrityVerification);
         assert!(metric.duration >= Duration::from_millis(10));
     }
@@ -290,6 +334,63 @@ mod tests {
         assert_eq!(avg_duration.unwrap(), Duration::from_millis(75));
     }
 
+    fn metric(metric_type: MetricType, millis: u64) -> PerformanceMetric {
+        PerformanceMetric {
+            metric_type,
+            duration: Duration::from_millis(millis),
+            memory_usage: None,
+            cpu_usage: None,
+            timestamp: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_retention_window_is_per_type() {
+        let mut monitor = PerformanceMonitor::with_config(PerformanceConfig {
+            log_metrics: false,
+            max_retained_per_type: 3,
+            ..Default::default()
+        });
+
+        for millis in 1..=10 {
+            monitor.record_metric(metric(MetricType::IntegrityVerification, millis));
+        }
+        monitor.record_metric(metric(MetricType::VulnerabilityScan, 100));
+
+        let retained: Vec<Duration> = monitor
+            .metrics_for_type(&MetricType::IntegrityVerification)
+            .iter()
+            .map(|m| m.duration)
+            .collect();
+        assert_eq!(retained, [8, 9, 10].map(Duration::from_millis));
+        let all: Vec<u64> = monitor.metrics().iter().map(|m| m.duration.as_millis() as u64).collect();
+        assert_eq!(all, [8, 9, 10, 100]);
+
+        // The average still covers all ten samples
+        let aggregate = monitor.aggregate(&MetricType::IntegrityVerification);
+        assert_eq!(aggregate.count, 10);
+        assert_eq!(aggregate.total, Duration::from_millis(55));
+        assert_eq!(
+            monitor.average_duration(&MetricType::IntegrityVerification),
+            Some(Duration::from_micros(5500))
+        );
+        assert_eq!(monitor.average_duration(&MetricType::VulnerabilityScan), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_zero_retention_keeps_aggregates() {
+        let mut monitor = PerformanceMonitor::with_config(PerformanceConfig {
+            log_metrics: false,
+            max_retained_per_type: 0,
+            ..Default::default()
+        });
+        monitor.record_metric(metric(MetricType::SandboxExecution, 20));
+
+        assert!(monitor.metrics().is_empty());
+        assert_eq!(monitor.average_duration(&MetricType::SandboxExecution), Some(Duration::from_millis(20)));
+        assert_eq!(monitor.average_duration(&MetricType::RuntimeProtection), None);
+    }
+
     #[test]
     fn test_clear_metrics() {
         let mut monitor = PerformanceMonitor::new();
@@ -307,5 +408,6 @@ mod tests {
         
         monitor.clear_metrics();
         assert_eq!(monitor.metrics().len(), 0);
+        assert_eq!(monitor.average_duration(&MetricType::IntegrityVerification), None);
     }
 }
\ No newline at end of file
@@ -0,0 +1,159 @@
+//! Security policy module
+//!
+//! This module defines the rules a package must satisfy before it may be
+//! installed, and the decision produced by evaluating them.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::vulnerability::{Severity, VulnerabilityReport};
+
+/// Rules a package must satisfy to be allowed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecurityPolicy {
+    /// Highest vulnerability severity that is still allowed (`None` allows any)
+    pub max_severity: Option<Severity>,
+    /// Whether the package tarball must pass integrity verification
+    pub require_integrity: bool,
+    /// Package names that are never allowed
+    pub deny_list: Vec<String>,
+    /// Whether package scripts are blocked from running
+    pub block_scripts: bool,
+}
+
+impl SecurityPolicy {
+    /// Check whether a package is on the deny list
+    pub fn is_denied(&self, package_name: &str) -> bool {
+        self.deny_list.iter().any(|name| name == package_name)
+    }
+
+    /// Get the reasons a vulnerability report violates the severity limit
+    ///
+    /// An incomplete report (a scan that timed out, ran out of budget or
+    /// couldn't reach an advisory database) can't show the package is within
+    /// the limit, so it is a violation too.
+    ///
+    /// # Returns
+    /// * One reason per vulnerability above `max_severity` and one if the report
+    ///   is incomplete, empty if none
+    pub fn severity_violations(&self, report: &VulnerabilityReport) -> Vec<String> {
+        let Some(max_severity) = &self.max_severity else {
+            return Vec::new();
+        };
+
+        let incomplete = (!report.is_complete()).then(|| {
+            format!(
+                "Vulnerability scan of {}@{} is incomplete: {}",
+                report.package_name,
+                report.package_version,
+                report.warnings.join("; ")
+            )
+        });
+        report
+            .vulnerabilities
+            .iter()
+            .filter(|vuln| vuln.effective_severity() > *max_severity)
+            .map(|vuln| {
+                format!(
+                    "{} ({:?}) exceeds the maximum allowed severity {:?}",
+                    vuln.id,
+                    vuln.effective_severity(),
+                    max_severity
+                )
+            })
+            .chain(incomplete)
+            .collect()
+    }
+}
+
+/// Outcome of evaluating a package against a `SecurityPolicy`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PolicyDecision {
+    Allow,
+    Deny { reasons: Vec<String> },
+}
+
+impl PolicyDecision {
+    /// Build a decision from the collected violations
+    pub fn from_reasons(reasons: Vec<String>) -> Self {
+        if reasons.is_empty() {
+            PolicyDecision::Allow
+        } else {
+            PolicyDecision::Deny { reasons }
+        }
+    }
+
+    /// Check whether the package is allowed
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, PolicyDecision::Allow)
+    }
+
+    /// Get the reasons for a denial (empty when allowed)
+    pub fn reasons(&self) -> &[String] {
+        match self {
+            PolicyDecision::Allow => &[],
+            PolicyDecision::Deny { reasons } => reasons,
+        }
+    }
+}
+
+impl fmt::Display for PolicyDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyDecision::Allow => write!(f, "allow"),
+            PolicyDecision::Deny { reasons } => write!(f, "deny: {}", reasons.join("; ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vulnerability::Vulnerability;
+
+    fn report_with(severity: Severity) -> VulnerabilityReport {
+        let mut report = VulnerabilityReport::new("pkg".to_string(), "1.0.0".to_string());
+        report.add_vulnerability(Vulnerability {
+            id: "CVE-2024-0001".to_string(),
+            title: "Test".to_string(),
+            description: "Test vulnerability".to_string(),
+            severity,
+            cvss_score: None,
+            affected_versions: vec![],
+            patched_versions: vec![],
+            references: vec![],
+            sources: vec![],
+        });
+        report
+    }
+
+    #[test]
+    fn test_severity_violations() {
+        let policy = SecurityPolicy {
+            max_severity: Some(Severity::Medium),
+            ..Default::default()
+        };
+
+        assert!(policy.severity_violations(&report_with(Severity::Medium)).is_empty());
+        assert_eq!(policy.severity_violations(&report_with(Severity::High)).len(), 1);
+        assert!(SecurityPolicy::default()
+            .severity_violations(&report_with(Severity::Critical))
+            .is_empty());
+
+        let mut timed_out = report_with(Severity::Low);
+        timed_out.warnings.push("the scan timed out".to_string());
+        let violations = policy.severity_violations(&timed_out);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].contains("incomplete: the scan timed out"), "{:?}", violations);
+    }
+
+    #[test]
+    fn test_decision_from_reasons() {
+        assert_eq!(PolicyDecision::from_reasons(vec![]), PolicyDecision::Allow);
+
+        let decision = PolicyDecision::from_reasons(vec!["denied".to_string()]);
+        assert!(!decision.is_allowed());
+        assert_eq!(decision.reasons(), ["denied".to_string()]);
+        assert_eq!(decision.to_string(), "deny: denied");
+    }
+}
@@ -0,0 +1,695 @@
+//! npm build provenance verification
+//!
+//! npm publishes build provenance for packages built on supported CI
+//! systems as a Sigstore bundle: a DSSE envelope holding an in-toto
+//! statement about the tarball, signed with a short-lived key whose
+//! certificate chains to the Sigstore CA.
+//!
+//! Verification checks that the certificate chain leads to a trusted root
+//! through CA certificates, that the envelope signature was made by the leaf
+//! certificate's key, and that the statement's subject is the expected
+//! package and tarball digest. The build identity (repository, workflow and
+//! ref) is taken from the Fulcio extensions of the leaf certificate, and the
+//! statement's build workflow must agree with it.
+//! Certificate validity periods and transparency log inclusion are not
+//! checked: Sigstore leaf certificates expire minutes after signing and
+//! their validity must be judged against the log's integrated time.
+//!
+//! This module is only available with the `provenance` feature.
+
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::Value;
+use x509_cert::der::asn1::Utf8StringRef;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::{BasicConstraints, KeyUsage, SubjectAltName};
+use x509_cert::Certificate;
+
+use crate::integrity::IntegrityError;
+
+/// DSSE payload type of in-toto statements
+pub const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+/// `ecdsa-with-SHA256`
+const ECDSA_WITH_SHA256: &str = "1.2.840.10045.4.3.2";
+/// `ecdsa-with-SHA384`
+const ECDSA_WITH_SHA384: &str = "1.2.840.10045.4.3.3";
+
+/// Fulcio extension with the source repository URL, e.g. `https://github.com/owner/repo`
+const FULCIO_SOURCE_REPOSITORY_URI: &str = "1.3.6.1.4.1.57264.1.12";
+/// Fulcio extension with the git ref the build ran on
+const FULCIO_SOURCE_REPOSITORY_REF: &str = "1.3.6.1.4.1.57264.1.14";
+/// Fulcio extension with the URI of the top-level build workflow, `<repository>/<path>@<ref>`
+const FULCIO_BUILD_CONFIG_URI: &str = "1.3.6.1.4.1.57264.1.18";
+/// Legacy Fulcio extension with the GitHub repository as `owner/repo`
+const FULCIO_GITHUB_WORKFLOW_REPOSITORY: &str = "1.3.6.1.4.1.57264.1.5";
+/// Legacy Fulcio extension with the GitHub workflow ref
+const FULCIO_GITHUB_WORKFLOW_REF: &str = "1.3.6.1.4.1.57264.1.6";
+
+/// Build provenance of a verified package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvenanceInfo {
+    pub package: String,
+    pub version: String,
+    /// Repository the package was built from, e.g. `https://github.com/owner/repo`
+    pub source_repository: Option<String>,
+    /// Path of the build workflow in the repository, e.g. `.github/workflows/publish.yml`
+    pub workflow: Option<String>,
+    /// Git ref the workflow ran on, e.g. `refs/tags/v1.0.0`
+    pub git_ref: Option<String>,
+    /// Identifier of the build platform
+    pub builder_id: Option<String>,
+}
+
+/// Build identity Fulcio bound into a signing certificate
+#[derive(Debug, PartialEq, Eq)]
+struct SignerIdentity {
+    repository: String,
+    workflow: String,
+    git_ref: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Bundle {
+    verification_material: VerificationMaterial,
+    dsse_envelope: DsseEnvelope,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VerificationMaterial {
+    /// Bundle v0.2 and earlier: the leaf certificate followed by its issuers
+    x509_certificate_chain: Option<CertificateChain>,
+    /// Bundle v0.3: the leaf certificate only
+    certificate: Option<RawCertificate>,
+}
+
+#[derive(Deserialize)]
+struct CertificateChain {
+    certificates: Vec<RawCertificate>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawCertificate {
+    raw_bytes: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DsseEnvelope {
+    payload: String,
+    payload_type: String,
+    signatures: Vec<DsseSignature>,
+}
+
+#[derive(Deserialize)]
+struct DsseSignature {
+    sig: String,
+}
+
+#[derive(Deserialize)]
+struct Statement {
+    subject: Vec<Subject>,
+    #[serde(default)]
+    predicate: Value,
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    name: String,
+    digest: std::collections::HashMap<String, String>,
+}
+
+/// Verifies npm provenance bundles against a set of trusted root certificates
+#[derive(Debug, Clone)]
+pub struct ProvenanceVerifier {
+    trusted_roots: Vec<Certificate>,
+}
+
+impl ProvenanceVerifier {
+    /// Create a verifier trusting the given root certificates
+    ///
+    /// # Arguments
+    /// * `trusted_roots` - DER-encoded root certificates
+    pub fn new(trusted_roots: &[Vec<u8>]) -> Result<Self, IntegrityError> {
+        let trusted_roots = trusted_roots
+            .iter()
+            .map(|der| Certificate::from_der(der).map_err(|e| invalid(format!("invalid root certificate: {}", e))))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { trusted_roots })
+    }
+
+    /// Create a verifier trusting the root certificates in a PEM document
+    ///
+    /// The Sigstore public-good roots can be taken from its `trusted_root.json`.
+    pub fn from_pem(pem: &str) -> Result<Self, IntegrityError> {
+        let trusted_roots = Certificate::load_pem_chain(pem.as_bytes())
+            .map_err(|e| invalid(format!("invalid root certificate: {}", e)))?;
+        Ok(Self { trusted_roots })
+    }
+
+    /// Verify the provenance bundle of a package version
+    ///
+    /// # Arguments
+    /// * `package` - Package name, e.g. `@scope/name`
+    /// * `version` - Package version
+    /// * `integrity` - SRI string of the tarball, e.g. `sha512-...`
+    /// * `bundle` - Sigstore bundle JSON as served by the registry
+    ///
+    /// # Returns
+    /// * `Ok(ProvenanceInfo)` with the source repository and build workflow of the signing certificate
+    /// * `Err(IntegrityError::SignatureInvalid)` if a signature doesn't verify
+    /// * `Err(IntegrityError::HashMismatch)` if the attested digest isn't the tarball's
+    /// * `Err(IntegrityError::InvalidProvenance)` for malformed or untrusted bundles, or when the
+    ///   statement's build workflow isn't the one the certificate was issued to
+    pub fn verify_provenance(
+        &self,
+        package: &str,
+        version: &str,
+        integrity: &str,
+        bundle: &[u8],
+    ) -> Result<ProvenanceInfo, IntegrityError> {
+        let bundle: Bundle =
+            serde_json::from_slice(bundle).map_err(|e| invalid(format!("malformed bundle: {}", e)))?;
+        let chain = certificate_chain(&bundle.verification_material)?;
+        self.verify_chain(&chain)?;
+        let identity = signer_identity(&chain[0])?;
+
+        let envelope = &bundle.dsse_envelope;
+        if envelope.payload_type != IN_TOTO_PAYLOAD_TYPE {
+            return Err(invalid(format!("unexpected payload type {}", envelope.payload_type)));
+        }
+        let payload = decode_base64(&envelope.payload)?;
+        let message = pre_authentication_encoding(&envelope.payload_type, &payload);
+        let leaf_key = chain[0].tbs_certificate.subject_public_key_info.to_der().map_err(der_error)?;
+        let signed = envelope.signatures.iter().any(|signature| {
+            decode_base64(&signature.sig).is_ok_and(|sig| {
+                verify_ecdsa(&leaf_key, ECDSA_WITH_SHA256, &message, &sig)
+                    .or_else(|_| verify_ecdsa(&leaf_key, ECDSA_WITH_SHA384, &message, &sig))
+                    .is_ok()
+            })
+        });
+        if !signed {
+            return Err(IntegrityError::SignatureInvalid);
+        }
+
+        let statement: Statement =
+            serde_json::from_slice(&payload).map_err(|e| invalid(format!("malformed statement: {}", e)))?;
+        check_subject(&statement, package, version, integrity)?;
+
+        check_workflow(&statement, &identity)?;
+
+        let builder_id = statement
+            .predicate
+            .pointer("/runDetails/builder/id")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        Ok(ProvenanceInfo {
+            package: package.to_string(),
+            version: version.to_string(),
+            source_repository: Some(identity.repository),
+            workflow: Some(identity.workflow),
+            git_ref: Some(identity.git_ref),
+            builder_id,
+        })
+    }
+
+    /// Check that each certificate is issued by the next and the last by a trusted root
+    fn verify_chain(&self, chain: &[Certificate]) -> Result<(), IntegrityError> {
+        for pair in chain.windows(2) {
+            verify_issued_by(&pair[0], &pair[1])?;
+        }
+
+        let last = &chain[chain.len() - 1];
+        let trusted = self.trusted_roots.iter().any(|root| {
+            root == last || (root.tbs_certificate.subject == last.tbs_certificate.issuer && verify_issued_by(last, root).is_ok())
+        });
+        if trusted {
+            Ok(())
+        } else {
+            Err(invalid("certificate chain does not lead to a trusted root".to_string()))
+        }
+    }
+}
+
+/// Decode the certificates of a bundle, leaf first
+fn certificate_chain(material: &VerificationMaterial) -> Result<Vec<Certificate>, IntegrityError> {
+    let raw: Vec<&RawCertificate> = match (&material.x509_certificate_chain, &material.certificate) {
+        (Some(chain), _) => chain.certificates.iter().collect(),
+        (None, Some(certificate)) => vec![certificate],
+        (None, None) => Vec::new(),
+    };
+    if raw.is_empty() {
+        return Err(invalid("bundle has no signing certificate".to_string()));
+    }
+
+    raw.iter()
+        .map(|certificate| {
+            let der = decode_base64(&certificate.raw_bytes)?;
+            Certificate::from_der(&der).map_err(|e| invalid(format!("invalid certificate: {}", e)))
+        })
+        .collect()
+}
+
+/// Check that `certificate` names `issuer`, that `issuer` is a CA and that it signed `certificate`
+fn verify_issued_by(certificate: &Certificate, issuer: &Certificate) -> Result<(), IntegrityError> {
+    if certificate.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return Err(invalid("certificate issuer does not match the next certificate".to_string()));
+    }
+    check_certificate_authority(issuer)?;
+    let issuer_key = issuer.tbs_certificate.subject_public_key_info.to_der().map_err(der_error)?;
+    let tbs = certificate.tbs_certificate.to_der().map_err(der_error)?;
+    let signature = certificate
+        .signature
+        .as_bytes()
+        .ok_or_else(|| invalid("certificate signature has unused bits".to_string()))?;
+    verify_ecdsa(&issuer_key, &certificate.signature_algorithm.oid.to_string(), &tbs, signature)
+}
+
+/// Check that a certificate may issue certificates: `CA:TRUE` and the `keyCertSign` key usage
+fn check_certificate_authority(certificate: &Certificate) -> Result<(), IntegrityError> {
+    let tbs = &certificate.tbs_certificate;
+    let is_ca = tbs
+        .get::<BasicConstraints>()
+        .map_err(der_error)?
+        .is_some_and(|(_, constraints)| constraints.ca);
+    let signs_certificates = tbs
+        .get::<KeyUsage>()
+        .map_err(der_error)?
+        .is_some_and(|(_, usage)| usage.key_cert_sign());
+    if is_ca && signs_certificates {
+        Ok(())
+    } else {
+        Err(invalid(format!("issuer {} is not a certificate authority", tbs.subject)))
+    }
+}
+
+/// Read the build identity from the Fulcio extensions and SAN of a signing certificate
+fn signer_identity(certificate: &Certificate) -> Result<SignerIdentity, IntegrityError> {
+    let repository = fulcio_extension(certificate, FULCIO_SOURCE_REPOSITORY_URI, true)
+        .or_else(|| {
+            fulcio_extension(certificate, FULCIO_GITHUB_WORKFLOW_REPOSITORY, false)
+                .map(|repository| format!("https://github.com/{}", repository))
+        })
+        .ok_or_else(|| invalid("signing certificate has no source repository".to_string()))?;
+    let git_ref = fulcio_extension(certificate, FULCIO_SOURCE_REPOSITORY_REF, true)
+        .or_else(|| fulcio_extension(certificate, FULCIO_GITHUB_WORKFLOW_REF, false))
+        .ok_or_else(|| invalid("signing certificate has no source ref".to_string()))?;
+
+    // The build config URI names the workflow that started the build; older
+    // certificates only carry it as the SAN, which differs for reusable workflows
+    let workflow_uri = match fulcio_extension(certificate, FULCIO_BUILD_CONFIG_URI, true) {
+        Some(uri) => uri,
+        None => subject_alternative_uri(certificate)?
+            .ok_or_else(|| invalid("signing certificate has no build identity".to_string()))?,
+    };
+    let workflow = workflow_uri
+        .strip_prefix(&format!("{}/", repository))
+        .and_then(|rest| rest.rsplit_once('@'))
+        .map(|(path, _)| path.to_string())
+        .ok_or_else(|| invalid(format!("build workflow {} is not in {}", workflow_uri, repository)))?;
+
+    Ok(SignerIdentity {
+        repository,
+        workflow,
+        git_ref,
+    })
+}
+
+/// Value of a Fulcio extension: a DER `UTF8String`, or raw text for the legacy extensions
+fn fulcio_extension(certificate: &Certificate, oid: &str, der_encoded: bool) -> Option<String> {
+    let extension = certificate
+        .tbs_certificate
+        .extensions
+        .as_ref()?
+        .iter()
+        .find(|extension| extension.extn_id.to_string() == oid)?;
+    let value = extension.extn_value.as_bytes();
+    if der_encoded {
+        Utf8StringRef::from_der(value).ok().map(|text| text.as_str().to_string())
+    } else {
+        std::str::from_utf8(value).ok().map(str::to_string)
+    }
+}
+
+/// The URI subject alternative name of a certificate
+fn subject_alternative_uri(certificate: &Certificate) -> Result<Option<String>, IntegrityError> {
+    let names = certificate.tbs_certificate.get::<SubjectAltName>().map_err(der_error)?;
+    Ok(names.and_then(|(_, names)| {
+        names.0.into_iter().find_map(|name| match name {
+            GeneralName::UniformResourceIdentifier(uri) => Some(uri.as_str().to_string()),
+            _ => None,
+        })
+    }))
+}
+
+/// Check the statement's build workflow is the one the signing certificate was issued to
+fn check_workflow(statement: &Statement, identity: &SignerIdentity) -> Result<(), IntegrityError> {
+    let workflow = statement.predicate.pointer("/buildDefinition/externalParameters/workflow");
+    let field = |name: &str| workflow.and_then(|workflow| workflow.get(name)).and_then(Value::as_str);
+    let claimed_repository = field("repository");
+
+    if claimed_repository != Some(identity.repository.as_str())
+        || field("path") != Some(identity.workflow.as_str())
+        || field("ref") != Some(identity.git_ref.as_str())
+    {
+        return Err(invalid(format!(
+            "statement claims a build from {} that the certificate for {} does not vouch for",
+            claimed_repository.unwrap_or("an unknown repository"),
+            identity.repository
+        )));
+    }
+    Ok(())
+}
+
+/// Verify a DER-encoded ECDSA signature with a P-256 or P-384 SPKI public key
+fn verify_ecdsa(spki_der: &[u8], algorithm: &str, message: &[u8], signature: &[u8]) -> Result<(), IntegrityError> {
+    use p256::ecdsa::signature::Verifier;
+    use p256::pkcs8::DecodePublicKey;
+
+    match algorithm {
+        ECDSA_WITH_SHA256 => {
+            let key = p256::ecdsa::VerifyingKey::from_public_key_der(spki_der)
+                .map_err(|e| IntegrityError::InvalidPublicKey(e.to_string()))?;
+            let signature = p256::ecdsa::Signature::from_der(signature).map_err(|_| IntegrityError::SignatureInvalid)?;
+            key.verify(message, &signature).map_err(|_| IntegrityError::SignatureInvalid)
+        }
+        ECDSA_WITH_SHA384 => {
+            let key = p384::ecdsa::VerifyingKey::from_public_key_der(spki_der)
+                .map_err(|e| IntegrityError::InvalidPublicKey(e.to_string()))?;
+            let signature = p384::ecdsa::Signature::from_der(signature).map_err(|_| IntegrityError::SignatureInvalid)?;
+            key.verify(message, &signature).map_err(|_| IntegrityError::SignatureInvalid)
+        }
+        other => Err(invalid(format!("unsupported signature algorithm {}", other))),
+    }
+}
+
+/// Check the statement attests the expected package and tarball digest
+fn check_subject(statement: &Statement, package: &str, version: &str, integrity: &str) -> Result<(), IntegrityError> {
+    let expected_name = package_url(package, version);
+    let subject = statement
+        .subject
+        .iter()
+        .find(|subject| subject.name == expected_name)
+        .ok_or_else(|| invalid(format!("statement does not attest {}", expected_name)))?;
+
+    let (key, digest) = integrity.split_once('-').ok_or(IntegrityError::InvalidHashFormat)?;
+    if key != "sha512" && key != "sha256" {
+        return Err(IntegrityError::InvalidHashFormat);
+    }
+    let expected = hex::encode(decode_base64(digest).map_err(|_| IntegrityError::InvalidHashFormat)?);
+    let attested = subject
+        .digest
+        .get(key)
+        .ok_or_else(|| invalid(format!("statement has no {} digest", key)))?;
+
+    if attested.eq_ignore_ascii_case(&expected) {
+        Ok(())
+    } else {
+        Err(IntegrityError::HashMismatch {
+            expected,
+            actual: attested.clone(),
+        })
+    }
+}
+
+/// The package URL npm uses as the attestation subject, e.g. `pkg:npm/%40scope/name@1.0.0`
+fn package_url(package: &str, version: &str) -> String {
+    format!("pkg:npm/{}@{}", package.replacen('@', "%40", 1), version)
+}
+
+/// DSSE pre-authentication encoding of a payload
+fn pre_authentication_encoding(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!("DSSEv1 {} {} {} ", payload_type.len(), payload_type, payload.len()).into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, IntegrityError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(value.trim())
+        .map_err(|e| invalid(format!("invalid base64: {}", e)))
+}
+
+fn der_error(error: x509_cert::der::Error) -> IntegrityError {
+    invalid(format!("invalid certificate encoding: {}", error))
+}
+
+fn invalid(reason: String) -> IntegrityError {
+    IntegrityError::InvalidProvenance(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrity::HashAlgorithm;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::{DerSignature, SigningKey};
+    use std::str::FromStr;
+    use std::time::Duration;
+    use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+    use x509_cert::der::asn1::{BitString, Ia5String, ObjectIdentifier, OctetString};
+    use x509_cert::der::EncodePem;
+    use x509_cert::ext::Extension;
+    use x509_cert::name::Name;
+    use x509_cert::serial_number::SerialNumber;
+    use x509_cert::spki::SubjectPublicKeyInfoOwned;
+    use x509_cert::time::Validity;
+
+    const TARBALL: &[u8] = b"left-pad tarball bytes";
+    const REPOSITORY: &str = "https://github.com/left-pad/left-pad";
+
+    fn integrity() -> String {
+        crate::integrity::calculate_integrity(TARBALL)
+    }
+
+    fn certificate(profile: Profile, subject: &str, key: &SigningKey, signer: &SigningKey) -> Certificate {
+        let spki = SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap();
+        CertificateBuilder::new(
+            profile,
+            SerialNumber::from(1u32),
+            Validity::from_now(Duration::from_secs(600)).unwrap(),
+            Name::from_str(subject).unwrap(),
+            spki,
+            signer,
+        )
+        .unwrap()
+        .build::<DerSignature>()
+        .unwrap()
+    }
+
+    /// Fulcio's identity extensions for a build of `repository`
+    fn identity(repository: &str) -> Vec<Extension> {
+        let workflow = format!("{}/.github/workflows/publish.yml@refs/tags/v1.3.0", repository);
+        let san = SubjectAltName(vec![GeneralName::UniformResourceIdentifier(
+            Ia5String::new(&workflow).unwrap(),
+        )]);
+        let extension = |oid: &str, value: Vec<u8>| Extension {
+            extn_id: ObjectIdentifier::new_unwrap(oid),
+            critical: false,
+            extn_value: OctetString::new(value).unwrap(),
+        };
+        let text = |value: &str| Utf8StringRef::new(value).unwrap().to_der().unwrap();
+        vec![
+            extension("2.5.29.17", san.to_der().unwrap()),
+            extension(FULCIO_SOURCE_REPOSITORY_URI, text(repository)),
+            extension(FULCIO_SOURCE_REPOSITORY_REF, text("refs/tags/v1.3.0")),
+            extension(FULCIO_BUILD_CONFIG_URI, text(&workflow)),
+        ]
+    }
+
+    /// Add extensions to a certificate and sign it again with its issuer's key
+    fn with_extensions(mut certificate: Certificate, extensions: Vec<Extension>, signer: &SigningKey) -> Certificate {
+        certificate
+            .tbs_certificate
+            .extensions
+            .get_or_insert_with(Vec::new)
+            .extend(extensions);
+        let signature: DerSignature = signer.sign(&certificate.tbs_certificate.to_der().unwrap());
+        certificate.signature = BitString::from_bytes(signature.as_bytes()).unwrap();
+        certificate
+    }
+
+    /// A root, an intermediate and a leaf certificate for a build of `REPOSITORY`, with the leaf's key
+    fn pki(seed: u8) -> (Certificate, Vec<Certificate>, SigningKey) {
+        pki_with(seed, true, identity(REPOSITORY))
+    }
+
+    fn pki_with(
+        seed: u8,
+        intermediate_is_ca: bool,
+        leaf_extensions: Vec<Extension>,
+    ) -> (Certificate, Vec<Certificate>, SigningKey) {
+        let root_key = SigningKey::from_slice(&[seed; 32]).unwrap();
+        let intermediate_key = SigningKey::from_slice(&[seed + 1; 32]).unwrap();
+        let leaf_key = SigningKey::from_slice(&[seed + 2; 32]).unwrap();
+
+        let root = certificate(Profile::Root, "CN=test-root", &root_key, &root_key);
+        let intermediate_profile = if intermediate_is_ca {
+            Profile::SubCA {
+                issuer: root.tbs_certificate.subject.clone(),
+                path_len_constraint: Some(0),
+            }
+        } else {
+            Profile::Leaf {
+                issuer: root.tbs_certificate.subject.clone(),
+                enable_key_agreement: false,
+                enable_key_encipherment: false,
+            }
+        };
+        let intermediate = certificate(intermediate_profile, "CN=test-intermediate", &intermediate_key, &root_key);
+        let leaf = certificate(
+            Profile::Leaf {
+                issuer: intermediate.tbs_certificate.subject.clone(),
+                enable_key_agreement: false,
+                enable_key_encipherment: false,
+            },
+            "CN=sigstore",
+            &leaf_key,
+            &intermediate_key,
+        );
+        let leaf = with_extensions(leaf, leaf_extensions, &intermediate_key);
+        (root, vec![leaf, intermediate], leaf_key)
+    }
+
+    fn statement(subject: &str, sha512: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": [{ "name": subject, "digest": { "sha512": sha512 } }],
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "predicate": {
+                "buildDefinition": {
+                    "externalParameters": {
+                        "workflow": {
+                            "repository": "https://github.com/left-pad/left-pad",
+                            "path": ".github/workflows/publish.yml",
+                            "ref": "refs/tags/v1.3.0"
+                        }
+                    }
+                },
+                "runDetails": { "builder": { "id": "https://github.com/actions/runner/github-hosted" } }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn bundle(chain: &[Certificate], key: &SigningKey, payload: &[u8]) -> Vec<u8> {
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+        let signature: p256::ecdsa::Signature = key.sign(&pre_authentication_encoding(IN_TOTO_PAYLOAD_TYPE, payload));
+        let certificates: Vec<Value> = chain
+            .iter()
+            .map(|cert| serde_json::json!({ "rawBytes": encode(&cert.to_der().unwrap()) }))
+            .collect();
+        serde_json::to_vec(&serde_json::json!({
+            "mediaType": "application/vnd.dev.sigstore.bundle+json;version=0.2",
+            "verificationMaterial": { "x509CertificateChain": { "certificates": certificates } },
+            "dsseEnvelope": {
+                "payload": encode(payload),
+                "payloadType": IN_TOTO_PAYLOAD_TYPE,
+                "signatures": [{ "sig": encode(signature.to_der().as_bytes()), "keyid": "" }]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn tarball_sha512() -> String {
+        HashAlgorithm::Sha512.hex_digest(TARBALL)
+    }
+
+    #[test]
+    fn test_verify_valid_bundle() {
+        let (root, chain, leaf_key) = pki(1);
+        let verifier = ProvenanceVerifier::new(&[root.to_der().unwrap()]).unwrap();
+        let bundle = bundle(&chain, &leaf_key, &statement("pkg:npm/left-pad@1.3.0", &tarball_sha512()));
+
+        let info = verifier.verify_provenance("left-pad", "1.3.0", &integrity(), &bundle).unwrap();
+        assert_eq!(info.source_repository.as_deref(), Some("https://github.com/left-pad/left-pad"));
+        assert_eq!(info.workflow.as_deref(), Some(".github/workflows/publish.yml"));
+        assert_eq!(info.git_ref.as_deref(), Some("refs/tags/v1.3.0"));
+        assert_eq!(info.builder_id.as_deref(), Some("https://github.com/actions/runner/github-hosted"));
+
+        let pem = root.to_pem(x509_cert::der::pem::LineEnding::LF).unwrap();
+        let from_pem = ProvenanceVerifier::from_pem(&pem).unwrap();
+        assert!(from_pem.verify_provenance("left-pad", "1.3.0", &integrity(), &bundle).is_ok());
+    }
+
+    #[test]
+    fn test_verify_scoped_package_subject() {
+        let (root, chain, leaf_key) = pki(1);
+        let verifier = ProvenanceVerifier::new(&[root.to_der().unwrap()]).unwrap();
+        let bundle = bundle(&chain, &leaf_key, &statement("pkg:npm/%40scope/pkg@2.0.0", &tarball_sha512()));
+
+        assert!(verifier.verify_provenance("@scope/pkg", "2.0.0", &integrity(), &bundle).is_ok());
+        assert!(matches!(
+            verifier.verify_provenance("@scope/pkg", "2.0.1", &integrity(), &bundle),
+            Err(IntegrityError::InvalidProvenance(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_tampered_bundles() {
+        let (root, chain, leaf_key) = pki(1);
+        let verifier = ProvenanceVerifier::new(&[root.to_der().unwrap()]).unwrap();
+        let payload = statement("pkg:npm/left-pad@1.3.0", &tarball_sha512());
+
+        // Payload swapped after signing
+        let mut tampered: Value = serde_json::from_slice(&bundle(&chain, &leaf_key, &payload)).unwrap();
+        let forged = statement("pkg:npm/left-pad@1.3.0", &HashAlgorithm::Sha512.hex_digest(b"malware"));
+        tampered["dsseEnvelope"]["payload"] = base64::engine::general_purpose::STANDARD.encode(&forged).into();
+        let tampered = serde_json::to_vec(&tampered).unwrap();
+        assert!(matches!(
+            verifier.verify_provenance("left-pad", "1.3.0", &integrity(), &tampered),
+            Err(IntegrityError::SignatureInvalid)
+        ));
+
+        // Validly signed, but for a different tarball
+        let other = crate::integrity::calculate_integrity(b"another tarball");
+        let valid = bundle(&chain, &leaf_key, &payload);
+        assert!(matches!(
+            verifier.verify_provenance("left-pad", "1.3.0", &other, &valid),
+            Err(IntegrityError::HashMismatch { .. })
+        ));
+
+        // Signed by a certificate from an untrusted CA
+        let (_, rogue_chain, rogue_key) = pki(10);
+        let rogue = bundle(&rogue_chain, &rogue_key, &payload);
+        let err = verifier.verify_provenance("left-pad", "1.3.0", &integrity(), &rogue).unwrap_err();
+        assert!(err.to_string().contains("trusted root"));
+
+        assert!(verifier.verify_provenance("left-pad", "1.3.0", &integrity(), b"{}").is_err());
+    }
+
+    #[test]
+    fn test_identity_comes_from_the_certificate() {
+        let payload = statement("pkg:npm/left-pad@1.3.0", &tarball_sha512());
+
+        // A certificate issued to another repository can't vouch for left-pad's workflow
+        let (root, chain, leaf_key) = pki_with(1, true, identity("https://github.com/mallory/left-pad"));
+        let verifier = ProvenanceVerifier::new(&[root.to_der().unwrap()]).unwrap();
+        let err = verifier
+            .verify_provenance("left-pad", "1.3.0", &integrity(), &bundle(&chain, &leaf_key, &payload))
+            .unwrap_err();
+        assert!(err.to_string().contains("does not vouch for"));
+
+        // Nor can a certificate without any build identity
+        let (root, chain, leaf_key) = pki_with(1, true, Vec::new());
+        let verifier = ProvenanceVerifier::new(&[root.to_der().unwrap()]).unwrap();
+        let err = verifier
+            .verify_provenance("left-pad", "1.3.0", &integrity(), &bundle(&chain, &leaf_key, &payload))
+            .unwrap_err();
+        assert!(err.to_string().contains("no source repository"));
+    }
+
+    #[test]
+    fn test_reject_chain_through_non_ca_certificate() {
+        let (root, chain, leaf_key) = pki_with(1, false, identity(REPOSITORY));
+        let verifier = ProvenanceVerifier::new(&[root.to_der().unwrap()]).unwrap();
+        let bundle = bundle(&chain, &leaf_key, &statement("pkg:npm/left-pad@1.3.0", &tarball_sha512()));
+
+        let err = verifier.verify_provenance("left-pad", "1.3.0", &integrity(), &bundle).unwrap_err();
+        assert!(err.to_string().contains("not a certificate authority"));
+    }
+}
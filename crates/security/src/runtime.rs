@@ -149,7 +149,10 @@ impl RuntimeProtection {
         
         // Simulate a successful execution
         #[cfg(unix)]
-        let status = std::process::ExitStatus::from_raw(0);
+        let status = {
+            use std::os::unix::process::ExitStatusExt;
+            std::process::ExitStatus::from_raw(0)
+        };
         #[cfg(windows)]
         let status = {
             use std::os::windows::process::ExitStatusExt;
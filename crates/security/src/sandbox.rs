@@ -2,15 +2,24 @@
 //! 
 //! This module provides advanced runtime protection features including
 //! sandboxing, resource limits, and monitoring.
+//!
+//! On Linux, sandboxed commands get network isolation through namespaces:
+//! with `allow_network` off the command runs in an empty network namespace,
+//! and with `allow_network` on and a non-empty `allowed_network_hosts` it
+//! runs in a mount namespace whose resolver only knows the allowed hosts.
+//! Where the kernel doesn't let unprivileged users create namespaces, and on
+//! other platforms, the network isn't isolated and a warning is logged.
 
 use anyhow::Result;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::time::Duration;
 use tokio::process::Command;
 use tokio::time::timeout;
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::integrity::{build_file_manifest, HashAlgorithm};
 
 /// Sandbox configuration
 #[derive(Debug, Clone)]
@@ -20,6 +29,9 @@ pub struct SandboxConfig {
     /// Allowed directories for file system access
     pub allowed_directories: HashSet<PathBuf>,
     /// Allowed network hosts
+    ///
+    /// When network access is allowed and this is non-empty, sandboxed
+    /// commands can only resolve these hosts (Linux only).
     pub allowed_network_hosts: HashSet<String>,
     /// Maximum execution time (in seconds)
     pub max_execution_time: u64,
@@ -31,6 +43,11 @@ pub struct SandboxConfig {
     pub allow_network: bool,
     /// Whether to allow process creation
     pub allow_process_creation: bool,
+    /// Output files or directories, relative to the working directory, to hash after a run
+    pub hashed_outputs: Vec<PathBuf>,
+    /// Whether to copy the command's output to this process's stdout and
+    /// stderr as it is produced; it is still captured in the result
+    pub stream_output: bool,
 }
 
 impl Default for SandboxConfig {
@@ -48,6 +65,8 @@ impl Default for SandboxConfig {
             max_file_descriptors: 1024,
             allow_network: false,
             allow_process_creation: false,
+            hashed_outputs: Vec::new(),
+            stream_output: false,
         }
     }
 }
@@ -65,6 +84,12 @@ pub struct SandboxResult {
     pub timed_out: bool,
     /// Any error that occurred during execution
     pub error: Option<String>,
+    /// SHA-256 hex hash of each configured output that exists after the run
+    ///
+    /// Keys are the paths from `SandboxConfig::hashed_outputs`; a directory
+    /// contributes one entry per file under it. Empty unless the command ran
+    /// to completion.
+    pub produced_hashes: HashMap<PathBuf, String>,
 }
 
 /// Advanced runtime protection with sandboxing
@@ -117,6 +142,7 @@ impl SandboxRuntimeProtection {
     ) -> Result<SandboxResult> {
         info!("Executing command without sandbox: {} {:?}", command, args);
         
+        let working_dir = working_dir.as_ref();
         let mut cmd = Command::new(command);
         cmd.args(args)
             .current_dir(working_dir)
@@ -124,7 +150,7 @@ impl SandboxRuntimeProtection {
             .stderr(Stdio::piped());
         
         let timeout_duration = Duration::from_secs(self.config.max_execution_time);
-        let output = match timeout(timeout_duration, cmd.output()).await {
+        let output = match timeout(timeout_duration, collect_output(&mut cmd, self.config.stream_output)).await {
             Ok(Ok(output)) => output,
             Ok(Err(e)) => {
                 return Ok(SandboxResult {
@@ -133,6 +159,7 @@ impl SandboxRuntimeProtection {
                     stderr: format!("Failed to execute command: {}", e).into_bytes(),
                     timed_out: false,
                     error: Some(e.to_string()),
+                    produced_hashes: HashMap::new(),
                 });
             }
             Err(_) => {
@@ -142,6 +169,7 @@ impl SandboxRuntimeProtection {
                     stderr: b"Command timed out".to_vec(),
                     timed_out: true,
                     error: Some("Command timed out".to_string()),
+                    produced_hashes: HashMap::new(),
                 });
             }
         };
@@ -152,6 +180,7 @@ impl SandboxRuntimeProtection {
             stderr: output.stderr,
             timed_out: false,
             error: None,
+            produced_hashes: self.hash_outputs(working_dir),
         })
     }
 
@@ -164,18 +193,44 @@ impl SandboxRuntimeProtection {
     ) -> Result<SandboxResult> {
         info!("Executing command with limits: {} {:?}", command, args);
         
-        let mut cmd = Command::new(command);
-        cmd.args(args)
-            .current_dir(working_dir)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        let working_dir = working_dir.as_ref();
+        let build_command = || {
+            let mut cmd = Command::new(command);
+            cmd.args(args)
+                .current_dir(working_dir)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            cmd
+        };
+        let mut cmd = build_command();
         
         // Apply resource limits
         // Note: These are basic limits. A real implementation would use OS-specific
         // sandboxing mechanisms for stronger isolation.
+
+        // Held until the command finishes; removes the generated resolver files
+        #[cfg(target_os = "linux")]
+        let isolation = network::isolate(&mut cmd, &self.config).await?;
+        #[cfg(not(target_os = "linux"))]
+        if !self.config.allow_network || !self.config.allowed_network_hosts.is_empty() {
+            warn!("Network isolation is only supported on Linux; running {} with full network access", command);
+        }
         
         let timeout_duration = Duration::from_secs(self.config.max_execution_time);
-        let output = match timeout(timeout_duration, cmd.output()).await {
+        let mut result = timeout(timeout_duration, collect_output(&mut cmd, self.config.stream_output)).await;
+        // Entering the namespaces failed before the command started, so it can run again unisolated
+        #[cfg(target_os = "linux")]
+        if let Ok(Err(e)) = &result {
+            if isolation.is_active() && network::is_isolation_error(e) {
+                warn!(
+                    "Network isolation is unavailable ({}); running {} with full network access",
+                    e, command
+                );
+                let mut cmd = build_command();
+                result = timeout(timeout_duration, collect_output(&mut cmd, self.config.stream_output)).await;
+            }
+        }
+        let output = match result {
             Ok(Ok(output)) => output,
             Ok(Err(e)) => {
                 return Ok(SandboxResult {
@@ -184,6 +239,7 @@ impl SandboxRuntimeProtection {
                     stderr: format!("Failed to execute command: {}", e).into_bytes(),
                     timed_out: false,
                     error: Some(e.to_string()),
+                    produced_hashes: HashMap::new(),
                 });
             }
             Err(_) => {
@@ -193,6 +249,7 @@ impl SandboxRuntimeProtection {
                     stderr: b"Command timed out".to_vec(),
                     timed_out: true,
                     error: Some("Command timed out".to_string()),
+                    produced_hashes: HashMap::new(),
                 });
             }
         };
@@ -203,9 +260,37 @@ impl SandboxRuntimeProtection {
             stderr: output.stderr,
             timed_out: false,
             error: None,
+            produced_hashes: self.hash_outputs(working_dir),
         })
     }
 
+    /// Hash the configured outputs that exist under `working_dir`
+    ///
+    /// Outputs that can't be read are logged and left out.
+    fn hash_outputs(&self, working_dir: &Path) -> HashMap<PathBuf, String> {
+        let mut hashes = HashMap::new();
+        for output in &self.config.hashed_outputs {
+            let path = working_dir.join(output);
+            let hashed = if path.is_dir() {
+                build_file_manifest(&path).map(|manifest| {
+                    hashes.extend(manifest.into_iter().map(|(file, hash)| (output.join(file), hash)));
+                })
+            } else if path.is_file() {
+                std::fs::read(&path)
+                    .map(|bytes| {
+                        hashes.insert(output.clone(), HashAlgorithm::Sha256.hex_digest(&bytes));
+                    })
+                    .map_err(Into::into)
+            } else {
+                continue;
+            };
+            if let Err(e) = hashed {
+                warn!("Failed to hash sandbox output {}: {}", path.display(), e);
+            }
+        }
+        hashes
+    }
+
     /// Check if a path is allowed for access
     pub fn is_path_allowed(&self, path: &Path) -> bool {
         if !self.config.enabled {
@@ -269,12 +354,246 @@ impl SandboxRuntimeProtection {
     }
 }
 
+/// Linux network isolation for sandboxed commands
+///
+/// Unprivileged users get a user namespace mapping their own uid/gid, which
+/// is what allows them to create the network and mount namespaces.
+#[cfg(target_os = "linux")]
+mod network {
+    use anyhow::{Context, Result};
+    use std::ffi::CString;
+    use std::io;
+    use std::path::PathBuf;
+    use tokio::process::Command;
+    use tracing::{info, warn};
+
+    use super::SandboxConfig;
+
+    /// Resolver configuration pointing at an unroutable (TEST-NET-1) nameserver,
+    /// so direct DNS queries fail instead of reaching a real resolver
+    const BLOCKED_RESOLV_CONF: &str = "nameserver 192.0.2.1\noptions timeout:1 attempts:1\n";
+
+    /// Generated files bind-mounted into the sandbox, removed on drop
+    pub(super) struct Isolation {
+        files: Vec<PathBuf>,
+        active: bool,
+    }
+
+    impl Isolation {
+        /// Whether the command was set up to enter new namespaces
+        pub(super) fn is_active(&self) -> bool {
+            self.active
+        }
+    }
+
+    /// Whether a spawn error came from entering the namespaces rather than from the command
+    ///
+    /// Kernels without unprivileged user namespaces (or with them limited to
+    /// zero) fail `unshare` with one of these.
+    pub(super) fn is_isolation_error(error: &io::Error) -> bool {
+        matches!(
+            error.raw_os_error(),
+            Some(libc::EPERM | libc::EINVAL | libc::ENOSPC | libc::ENOSYS | libc::EUSERS)
+        )
+    }
+
+    impl Drop for Isolation {
+        fn drop(&mut self) {
+            for file in &self.files {
+                let _ = std::fs::remove_file(file);
+            }
+        }
+    }
+
+    /// Configure a command to run with the network isolation its config requires
+    pub(super) async fn isolate(cmd: &mut Command, config: &SandboxConfig) -> Result<Isolation> {
+        let mut isolation = Isolation {
+            files: Vec::new(),
+            active: false,
+        };
+        let mut mounts: Vec<(CString, CString)> = Vec::new();
+
+        let flags = if !config.allow_network {
+            info!("Running sandboxed command without network access");
+            libc::CLONE_NEWNET
+        } else if !config.allowed_network_hosts.is_empty() {
+            info!("Restricting sandboxed command to hosts {:?}", config.allowed_network_hosts);
+            let files = [
+                ("/etc/hosts", allowed_hosts_file(config).await),
+                ("/etc/nsswitch.conf", files_only_nsswitch()),
+                ("/etc/resolv.conf", BLOCKED_RESOLV_CONF.to_string()),
+            ];
+            for (target, contents) in files {
+                let path = std::env::temp_dir().join(format!("package-fast-sandbox-{}", uuid::Uuid::new_v4()));
+                std::fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+                mounts.push((c_path(path.to_str().context("Temp path is not valid UTF-8")?)?, c_path(target)?));
+                isolation.files.push(path);
+            }
+            libc::CLONE_NEWNS
+        } else {
+            return Ok(isolation);
+        };
+        isolation.active = true;
+
+        // SAFETY: geteuid/getegid have no preconditions
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let id_maps = (uid != 0).then(|| IdMaps {
+            setgroups: c_path("/proc/self/setgroups").expect("static path"),
+            uid_map_path: c_path("/proc/self/uid_map").expect("static path"),
+            gid_map_path: c_path("/proc/self/gid_map").expect("static path"),
+            uid_map: format!("{} {} 1", uid, uid).into_bytes(),
+            gid_map: format!("{} {} 1", gid, gid).into_bytes(),
+        });
+        let root = c_path("/")?;
+
+        // SAFETY: the closure only makes async-signal-safe syscalls on data
+        // prepared before forking
+        unsafe {
+            cmd.pre_exec(move || {
+                let flags = if id_maps.is_some() { flags | libc::CLONE_NEWUSER } else { flags };
+                if libc::unshare(flags) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if let Some(maps) = &id_maps {
+                    // Older kernels have no setgroups file
+                    let _ = write_file(&maps.setgroups, b"deny");
+                    write_file(&maps.uid_map_path, &maps.uid_map)?;
+                    write_file(&maps.gid_map_path, &maps.gid_map)?;
+                }
+                if !mounts.is_empty() {
+                    // Keep the bind mounts from propagating back to the host
+                    let private = libc::MS_REC | libc::MS_PRIVATE;
+                    if libc::mount(std::ptr::null(), root.as_ptr(), std::ptr::null(), private, std::ptr::null()) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    for (source, target) in &mounts {
+                        let bind = libc::MS_BIND;
+                        if libc::mount(source.as_ptr(), target.as_ptr(), std::ptr::null(), bind, std::ptr::null()) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        Ok(isolation)
+    }
+
+    /// Identity mappings written into a new user namespace
+    struct IdMaps {
+        setgroups: CString,
+        uid_map_path: CString,
+        gid_map_path: CString,
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+    }
+
+    /// Build a hosts file containing only the allowed hosts, resolved now
+    async fn allowed_hosts_file(config: &SandboxConfig) -> String {
+        let mut hosts = String::from("127.0.0.1 localhost\n::1 localhost\n");
+        let mut allowed: Vec<&String> = config.allowed_network_hosts.iter().collect();
+        allowed.sort();
+        for host in allowed {
+            match tokio::net::lookup_host((host.as_str(), 0)).await {
+                Ok(addrs) => {
+                    for addr in addrs {
+                        hosts.push_str(&format!("{} {}\n", addr.ip(), host));
+                    }
+                }
+                Err(e) => warn!("Failed to resolve allowed host {}: {}", host, e),
+            }
+        }
+        hosts
+    }
+
+    /// Copy the host's nsswitch.conf, resolving hostnames from files only
+    fn files_only_nsswitch() -> String {
+        let current = std::fs::read_to_string("/etc/nsswitch.conf").unwrap_or_default();
+        let mut lines: Vec<String> = current
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("hosts:"))
+            .map(str::to_string)
+            .collect();
+        lines.push("hosts: files".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    fn c_path(path: &str) -> Result<CString> {
+        CString::new(path).with_context(|| format!("Path {:?} contains a NUL byte", path))
+    }
+
+    /// Write a whole buffer to a file using raw syscalls (safe after fork)
+    fn write_file(path: &CString, contents: &[u8]) -> io::Result<()> {
+        // SAFETY: `path` is NUL-terminated and `contents` is a valid buffer
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+            let result = if written == contents.len() as isize {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            };
+            libc::close(fd);
+            result
+        }
+    }
+}
+
 impl Default for SandboxRuntimeProtection {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Run a command to completion and capture its output
+///
+/// With `stream` set, each chunk is also written to this process's stdout or
+/// stderr as soon as it's read, so long-running commands show progress.
+async fn collect_output(cmd: &mut Command, stream: bool) -> std::io::Result<std::process::Output> {
+    if !stream {
+        return cmd.output().await;
+    }
+
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().map(|pipe| tokio::spawn(tee(pipe, tokio::io::stdout())));
+    let stderr = child.stderr.take().map(|pipe| tokio::spawn(tee(pipe, tokio::io::stderr())));
+    let status = child.wait().await?;
+
+    let mut output = std::process::Output { status, stdout: Vec::new(), stderr: Vec::new() };
+    if let Some(handle) = stdout {
+        output.stdout = handle.await.map_err(std::io::Error::other)??;
+    }
+    if let Some(handle) = stderr {
+        output.stderr = handle.await.map_err(std::io::Error::other)??;
+    }
+    Ok(output)
+}
+
+/// Copy a child pipe to `sink` until EOF, returning everything read
+async fn tee<R, W>(mut pipe: R, mut sink: W) -> std::io::Result<Vec<u8>>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut captured = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let read = pipe.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(captured);
+        }
+        sink.write_all(&buf[..read]).await?;
+        sink.flush().await?;
+        captured.extend_from_slice(&buf[..read]);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -331,4 +650,98 @@ mod tests {
         // so we'll just check that the function doesn't panic
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_streamed_output_is_still_captured() {
+        let protection = SandboxRuntimeProtection::with_config(SandboxConfig {
+            stream_output: true,
+            allow_network: true,
+            ..Default::default()
+        });
+        let temp_dir = TempDir::new().unwrap();
+
+        let script = "echo out; echo err >&2; exit 4".to_string();
+        let result = protection
+            .execute_sandboxed("sh", &["-c".to_string(), script], temp_dir.path())
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, Some(4));
+        assert_eq!(result.stdout, b"out\n");
+        assert_eq!(result.stderr, b"err\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sandbox_hashes_produced_outputs() {
+        let temp_dir = TempDir::new().unwrap();
+        let protection = SandboxRuntimeProtection::with_config(SandboxConfig {
+            hashed_outputs: vec![PathBuf::from("dist/bundle.js"), PathBuf::from("lib"), PathBuf::from("missing.js")],
+            ..Default::default()
+        });
+        let build = vec![
+            "-c".to_string(),
+            "mkdir -p dist lib && printf 'bundle' > dist/bundle.js && printf 'util' > lib/util.js".to_string(),
+        ];
+
+        let result = protection.execute_sandboxed("sh", &build, temp_dir.path()).await.unwrap();
+        assert_eq!(result.exit_code, Some(0), "{}", String::from_utf8_lossy(&result.stderr));
+        let expected: HashMap<PathBuf, String> = [
+            ("dist/bundle.js", HashAlgorithm::Sha256.hex_digest(b"bundle")),
+            ("lib/util.js", HashAlgorithm::Sha256.hex_digest(b"util")),
+        ]
+        .into_iter()
+        .map(|(path, hash)| (PathBuf::from(path), hash))
+        .collect();
+        assert_eq!(result.produced_hashes, expected);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_sandbox_blocks_network_when_disallowed() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let temp_dir = TempDir::new().unwrap();
+        let connect = vec!["-c".to_string(), format!("echo hi > /dev/tcp/127.0.0.1/{}", port)];
+
+        let protection = SandboxRuntimeProtection::new();
+        let result = protection.execute_sandboxed("bash", &connect, temp_dir.path()).await.unwrap();
+        assert_ne!(result.exit_code, Some(0), "connection unexpectedly succeeded: {:?}", result);
+
+        let protection = SandboxRuntimeProtection::with_config(SandboxConfig {
+            allow_network: true,
+            ..Default::default()
+        });
+        let result = protection.execute_sandboxed("bash", &connect, temp_dir.path()).await.unwrap();
+        assert_eq!(result.exit_code, Some(0), "{}", String::from_utf8_lossy(&result.stderr));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_namespace_failures_are_isolation_errors() {
+        use std::io;
+
+        assert!(network::is_isolation_error(&io::Error::from_raw_os_error(libc::EPERM)));
+        assert!(network::is_isolation_error(&io::Error::from_raw_os_error(libc::ENOSPC)));
+        // A missing command is the command's problem, not the sandbox's
+        assert!(!network::is_isolation_error(&io::Error::from_raw_os_error(libc::ENOENT)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_sandbox_resolves_only_allowed_hosts() {
+        let protection = SandboxRuntimeProtection::with_config(SandboxConfig {
+            allow_network: true,
+            allowed_network_hosts: ["localhost".to_string()].into_iter().collect(),
+            ..Default::default()
+        });
+        let temp_dir = TempDir::new().unwrap();
+
+        let lookup = |host: &str| vec!["hosts".to_string(), host.to_string()];
+        let allowed = protection.execute_sandboxed("getent", &lookup("localhost"), temp_dir.path()).await.unwrap();
+        assert_eq!(allowed.exit_code, Some(0), "{}", String::from_utf8_lossy(&allowed.stderr));
+
+        let blocked = protection.execute_sandboxed("getent", &lookup("registry.npmjs.org"), temp_dir.path()).await.unwrap();
+        assert_ne!(blocked.exit_code, Some(0));
+    }
+}
@@ -5,7 +5,12 @@
 //! and runtime protection.
 
 use anyhow::Result;
+use std::future::Future;
 use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tracing::{info, warn};
 
 #[cfg(unix)]
@@ -13,12 +18,17 @@ use std::os::unix::process::ExitStatusExt;
 #[cfg(windows)]
 use std::os::windows::process::ExitStatusExt;
 
-use crate::integrity::{verify_package_integrity, calculate_package_hash, IntegrityError};
-use crate::vulnerability::{scan_for_vulnerabilities, VulnerabilityReport};
-use crate::audit::{AuditTrail, AuditEvent, AuditEventType};
+use crate::integrity::{verify_bytes_integrity, verify_package_integrity, calculate_package_hash, HashAlgorithm, IntegrityError};
+use crate::vuln_db::VulnerabilityDatabaseClient;
+use crate::vulnerability::{scan_with_client, Ecosystem, VulnerabilityReport};
+use crate::audit::{AuditFormat, AuditSink, AuditTrail, AuditEvent, AuditEventType, RotationPolicy};
 use crate::runtime::{RuntimeProtection, RuntimeProtectionError};
 use crate::sandbox::SandboxRuntimeProtection;
-use crate::performance::{PerformanceMonitor, MetricType};
+use crate::performance::{PerformanceMonitor, PerformanceMetric, MetricType};
+use crate::policy::{PolicyDecision, SecurityPolicy};
+
+/// Maximum number of vulnerability scans `scan_many` runs at once
+pub const MAX_CONCURRENT_SCANS: usize = 8;
 
 /// Security service configuration
 #[derive(Debug, Clone)]
@@ -33,6 +43,18 @@ pub struct SecurityServiceConfig {
     pub enable_runtime_protection: bool,
     /// Audit trail output file (optional)
     pub audit_trail_file: Option<String>,
+    /// Format events are written to `audit_trail_file` in
+    pub audit_trail_format: AuditFormat,
+    /// When `audit_trail_file` rolls over to a new file
+    pub audit_trail_rotation: RotationPolicy,
+    /// Policy packages must satisfy to be allowed
+    pub policy: SecurityPolicy,
+    /// Longest a single vulnerability scan may take (`None` waits indefinitely)
+    pub scan_timeout: Option<Duration>,
+    /// Total time all scans of this service may take (`None` is unlimited)
+    pub scan_budget: Option<Duration>,
+    /// Ecosystem scanned packages belong to
+    pub ecosystem: Ecosystem,
 }
 
 impl Default for SecurityServiceConfig {
@@ -43,18 +65,32 @@ impl Default for SecurityServiceConfig {
             generate_audit_trail: true,
             enable_runtime_protection: true,
             audit_trail_file: None,
+            audit_trail_format: AuditFormat::default(),
+            audit_trail_rotation: RotationPolicy::default(),
+            policy: SecurityPolicy::default(),
+            scan_timeout: Some(Duration::from_secs(30)),
+            scan_budget: None,
+            ecosystem: Ecosystem::default(),
         }
     }
 }
 
 /// Security service that provides a unified interface for all security features
+///
+/// All operations take `&self`, so one service can be shared (for example
+/// in an `Arc`) by concurrent install tasks. Audit events are appended under
+/// a lock, so every sink sees them in the same order as the trail.
 #[derive(Debug)]
 pub struct SecurityService {
     config: SecurityServiceConfig,
-    audit_trail: AuditTrail,
+    audit_trail: Arc<Mutex<AuditTrail>>,
     runtime_protection: RuntimeProtection,
     sandbox_protection: SandboxRuntimeProtection,
-    performance_monitor: PerformanceMonitor,
+    performance_monitor: Arc<Mutex<PerformanceMonitor>>,
+    /// Advisory databases vulnerability scans query
+    vulnerability_db: Arc<VulnerabilityDatabaseClient>,
+    /// Time spent scanning so far, counted against `scan_budget`
+    scan_time_spent: Mutex<Duration>,
 }
 
 impl SecurityService {
@@ -62,40 +98,53 @@ impl SecurityService {
     pub fn new() -> Self {
         let config = SecurityServiceConfig::default();
         let audit_trail = if let Some(ref file) = config.audit_trail_file {
-            AuditTrail::with_output_file(file.clone())
+            AuditTrail::with_rotating_output_file(file.clone(), config.audit_trail_format, config.audit_trail_rotation)
         } else {
             AuditTrail::new()
         };
         
         Self {
             config,
-            audit_trail,
+            audit_trail: Arc::new(Mutex::new(audit_trail)),
             runtime_protection: RuntimeProtection::new(),
             sandbox_protection: SandboxRuntimeProtection::new(),
-            performance_monitor: PerformanceMonitor::new(),
+            performance_monitor: Arc::new(Mutex::new(PerformanceMonitor::new())),
+            vulnerability_db: Arc::new(VulnerabilityDatabaseClient::new()),
+            scan_time_spent: Mutex::new(Duration::ZERO),
         }
     }
 
     /// Create a new security service with custom configuration
     pub fn with_config(config: SecurityServiceConfig) -> Self {
         let audit_trail = if let Some(ref file) = config.audit_trail_file {
-            AuditTrail::with_output_file(file.clone())
+            AuditTrail::with_rotating_output_file(file.clone(), config.audit_trail_format, config.audit_trail_rotation)
         } else {
             AuditTrail::new()
         };
         
         Self {
             config,
-            audit_trail,
+            audit_trail: Arc::new(Mutex::new(audit_trail)),
             runtime_protection: RuntimeProtection::new(),
             sandbox_protection: SandboxRuntimeProtection::new(),
-            performance_monitor: PerformanceMonitor::new(),
+            performance_monitor: Arc::new(Mutex::new(PerformanceMonitor::new())),
+            vulnerability_db: Arc::new(VulnerabilityDatabaseClient::new()),
+            scan_time_spent: Mutex::new(Duration::ZERO),
         }
     }
 
+    /// Query the advisory databases through `client` instead of the default one
+    ///
+    /// # Arguments
+    /// * `client` - Client with the API keys, endpoints and offline mode to scan with
+    pub fn with_vulnerability_database(mut self, client: VulnerabilityDatabaseClient) -> Self {
+        self.vulnerability_db = Arc::new(client);
+        self
+    }
+
     /// Verify the integrity of a package file
     pub async fn verify_package_file_integrity(
-        &mut self,
+        &self,
         package_name: &str,
         package_version: &str,
         file_path: &Path,
@@ -103,21 +152,45 @@ impl SecurityService {
     ) -> Result<(), IntegrityError> {
         info!("Verifying integrity of package {}@{}", package_name, package_version);
         
-        let start = self.performance_monitor.start_timing();
+        let start = Instant::now();
         
         // Add audit event
         let event = AuditEvent::new(AuditEventType::IntegrityCheck)
             .with_package_name(package_name.to_string())
             .with_package_version(package_version.to_string());
         
-        if let Err(e) = self.audit_trail.add_event(event) {
-            warn!("Failed to add audit event: {}", e);
-        }
+        self.record_event(event);
         
         // Verify integrity
         let result = verify_package_integrity(file_path, expected_hash);
         
-        self.performance_monitor.end_timing(start, MetricType::IntegrityVerification);
+        self.end_timing(start, MetricType::IntegrityVerification);
+        
+        result
+    }
+
+    /// Verify the integrity of a downloaded package before it is persisted
+    pub async fn verify_package_bytes_integrity(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        bytes: &[u8],
+        expected_hash: &str,
+        algo: HashAlgorithm,
+    ) -> Result<(), IntegrityError> {
+        info!("Verifying integrity of downloaded package {}@{}", package_name, package_version);
+        
+        let start = Instant::now();
+        
+        let event = AuditEvent::new(AuditEventType::IntegrityCheck)
+            .with_package_name(package_name.to_string())
+            .with_package_version(package_version.to_string());
+        
+        self.record_event(event);
+        
+        let result = verify_bytes_integrity(bytes, expected_hash, algo);
+        
+        self.end_timing(start, MetricType::IntegrityVerification);
         
         result
     }
@@ -129,58 +202,354 @@ impl SecurityService {
 
     /// Scan a package for vulnerabilities
     pub async fn scan_package_for_vulnerabilities(
-        &mut self,
+        &self,
+        package_name: &str,
+        package_version: &str,
+    ) -> Result<VulnerabilityReport, anyhow::Error> {
+        let scan = scan_with_client(&self.vulnerability_db, package_name, package_version, self.config.ecosystem);
+        self.scan_package_with(package_name, package_version, scan).await
+    }
+
+    /// Scan a package for vulnerabilities without recording an audit event
+    ///
+    /// Meant for exploratory scans that shouldn't appear in the compliance
+    /// log. The scan is otherwise identical to `scan_package_for_vulnerabilities`,
+    /// including its timeout, budget and performance metric.
+    pub async fn scan_without_audit(
+        &self,
         package_name: &str,
         package_version: &str,
     ) -> Result<VulnerabilityReport, anyhow::Error> {
+        let scan = scan_with_client(&self.vulnerability_db, package_name, package_version, self.config.ecosystem);
+        self.run_scan(package_name, package_version, scan, false).await
+    }
+
+    /// Run a vulnerability scan under the configured timeout and budget
+    ///
+    /// A scan that exceeds `scan_timeout` (or the rest of `scan_budget`) is
+    /// abandoned and an empty report carrying a warning is returned instead
+    /// of an error. Once the budget is spent, scans are skipped the same way.
+    ///
+    /// # Arguments
+    /// * `package_name` - Name of the package being scanned
+    /// * `package_version` - Version of the package being scanned
+    /// * `scan` - The provider call producing the report
+    pub async fn scan_package_with<Fut>(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        scan: Fut,
+    ) -> Result<VulnerabilityReport, anyhow::Error>
+    where
+        Fut: Future<Output = Result<VulnerabilityReport>>,
+    {
+        self.run_scan(package_name, package_version, scan, true).await
+    }
+
+    /// Run a scan under the timeout and budget, optionally auditing it
+    async fn run_scan<Fut>(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        scan: Fut,
+        record_audit: bool,
+    ) -> Result<VulnerabilityReport, anyhow::Error>
+    where
+        Fut: Future<Output = Result<VulnerabilityReport>>,
+    {
         info!("Scanning package {}@{} for vulnerabilities", package_name, package_version);
         
-        let start = self.performance_monitor.start_timing();
+        let start = Instant::now();
         
         // Add audit event
-        let event = AuditEvent::new(AuditEventType::VulnerabilityScan)
-            .with_package_name(package_name.to_string())
-            .with_package_version(package_version.to_string());
-        
-        if let Err(e) = self.audit_trail.add_event(event) {
-            warn!("Failed to add audit event: {}", e);
+        if record_audit {
+            let event = AuditEvent::new(AuditEventType::VulnerabilityScan)
+                .with_package_name(package_name.to_string())
+                .with_package_version(package_version.to_string());
+            
+            self.record_event(event);
         }
         
+        let spent = *self.scan_time_spent.lock().unwrap();
+        let remaining_budget = self.config.scan_budget.map(|budget| budget.saturating_sub(spent));
+        let limit = match (self.config.scan_timeout, remaining_budget) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        };
+        
         // Scan for vulnerabilities
-        let result = scan_for_vulnerabilities(package_name, package_version).await;
+        let result = match limit {
+            Some(limit) if limit.is_zero() => Err("the scan budget is exhausted"),
+            Some(limit) => tokio::time::timeout(limit, scan)
+                .await
+                .map_err(|_| "the scan timed out"),
+            None => Ok(scan.await),
+        };
         
-        self.performance_monitor.end_timing(start, MetricType::VulnerabilityScan);
+        self.end_timing(start, MetricType::VulnerabilityScan);
+        *self.scan_time_spent.lock().unwrap() += start.elapsed();
         
-        result
+        result.unwrap_or_else(|reason| {
+            let warning = format!("Skipped vulnerability scan of {}@{}: {}", package_name, package_version, reason);
+            warn!("{}", warning);
+            let mut report = VulnerabilityReport::new(package_name.to_string(), package_version.to_string());
+            report.warnings.push(warning);
+            Ok(report)
+        })
+    }
+
+    /// Evaluate a package against the configured security policy
+    ///
+    /// Runs the deny-list, integrity and vulnerability checks required by the
+    /// policy and records the decision in the audit trail. Without a tarball
+    /// to verify, a policy that requires integrity denies the package.
+    ///
+    /// # Arguments
+    /// * `package_name` - Name of the package to evaluate
+    /// * `package_version` - Version of the package to evaluate
+    ///
+    /// # Returns
+    /// * `PolicyDecision::Allow`, or `PolicyDecision::Deny` with every violated rule
+    pub async fn evaluate_policy(&self, package_name: &str, package_version: &str) -> PolicyDecision {
+        self.evaluate_policy_with_tarball(package_name, package_version, None).await
+    }
+
+    /// Evaluate a package against the configured security policy, verifying its tarball
+    ///
+    /// # Arguments
+    /// * `package_name` - Name of the package to evaluate
+    /// * `package_version` - Version of the package to evaluate
+    /// * `tarball` - Path of the downloaded tarball and its expected SHA-512 hex hash
+    pub async fn evaluate_policy_with_tarball(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        tarball: Option<(&Path, &str)>,
+    ) -> PolicyDecision {
+        let policy = self.config.policy.clone();
+        let mut reasons = Vec::new();
+
+        if policy.is_denied(package_name) {
+            reasons.push(format!("{} is on the deny list", package_name));
+        }
+
+        match tarball {
+            Some((file_path, expected_hash)) => {
+                if let Err(e) = self
+                    .verify_package_file_integrity(package_name, package_version, file_path, expected_hash)
+                    .await
+                {
+                    reasons.push(format!("Integrity verification failed: {}", e));
+                }
+            }
+            None if policy.require_integrity => {
+                reasons.push("Integrity is required but no tarball was provided to verify".to_string());
+            }
+            None => {}
+        }
+
+        if policy.max_severity.is_some() {
+            match self.scan_package_for_vulnerabilities(package_name, package_version).await {
+                Ok(report) => reasons.extend(policy.severity_violations(&report)),
+                Err(e) => reasons.push(format!("Vulnerability scan failed: {}", e)),
+            }
+        }
+
+        let decision = PolicyDecision::from_reasons(reasons);
+        self.record_policy_decision(package_name, package_version, &decision);
+        decision
+    }
+
+    /// Verify, scan and admit a downloaded package in one step
+    ///
+    /// The checks run in order — deny list, integrity, vulnerability scan —
+    /// and the first one that denies the package stops the pipeline, so a
+    /// tampered tarball is never scanned or installed. An allowed package is
+    /// recorded as installed in the audit trail.
+    ///
+    /// # Arguments
+    /// * `package_name` - Name of the package being installed
+    /// * `package_version` - Version of the package being installed
+    /// * `tarball` - Path of the downloaded tarball
+    /// * `expected_hash` - Expected SHA-512 hash of the tarball as a hex string
+    ///
+    /// # Returns
+    /// * `Ok(PolicyDecision)` with the outcome; the install must not proceed on `Deny`
+    /// * `Err(anyhow::Error)` if the tarball can't be read or the scan fails
+    pub async fn secure_install(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        tarball: &Path,
+        expected_hash: &str,
+    ) -> Result<PolicyDecision> {
+        let policy = self.config.policy.clone();
+
+        let decision = if policy.is_denied(package_name) {
+            PolicyDecision::from_reasons(vec![format!("{} is on the deny list", package_name)])
+        } else {
+            match self
+                .verify_package_file_integrity(package_name, package_version, tarball, expected_hash)
+                .await
+            {
+                Err(IntegrityError::IoError(e)) => return Err(e.into()),
+                Err(e) => PolicyDecision::from_reasons(vec![format!("Integrity verification failed: {}", e)]),
+                Ok(()) => {
+                    let report = self.scan_package_for_vulnerabilities(package_name, package_version).await?;
+                    PolicyDecision::from_reasons(policy.severity_violations(&report))
+                }
+            }
+        };
+
+        self.record_policy_decision(package_name, package_version, &decision);
+
+        if decision.is_allowed() {
+            let start = Instant::now();
+            let event = AuditEvent::new(AuditEventType::PackageInstall)
+                .with_package_name(package_name.to_string())
+                .with_package_version(package_version.to_string());
+            self.record_event(event);
+            self.end_timing(start, MetricType::AuditTrailGeneration);
+        } else {
+            warn!("Aborting install of {}@{}: {}", package_name, package_version, decision);
+        }
+
+        Ok(decision)
+    }
+
+    /// Record a policy decision in the audit trail
+    fn record_policy_decision(&self, package_name: &str, package_version: &str, decision: &PolicyDecision) {
+        info!("Policy decision for {}@{}: {}", package_name, package_version, decision);
+
+        let mut event = AuditEvent::new(AuditEventType::PolicyEvaluation)
+            .with_package_name(package_name.to_string())
+            .with_package_version(package_version.to_string())
+            .with_detail("decision".to_string(), if decision.is_allowed() { "allow" } else { "deny" }.to_string());
+        if !decision.is_allowed() {
+            event = event.with_error(decision.reasons().join("; "));
+        }
+        self.record_event(event);
+    }
+
+    /// Scan a set of packages for vulnerabilities concurrently
+    ///
+    /// # Arguments
+    /// * `packages` - Pairs of package name and version
+    ///
+    /// # Returns
+    /// * One `(name, result)` pair per package, in the order of `packages`
+    pub async fn scan_many(&self, packages: &[(String, String)]) -> Vec<(String, Result<VulnerabilityReport>)> {
+        let ecosystem = self.config.ecosystem;
+        let client = Arc::clone(&self.vulnerability_db);
+        self.scan_many_with(packages, move |name, version| {
+            let client = Arc::clone(&client);
+            async move { scan_with_client(&client, &name, &version, ecosystem).await }
+        })
+        .await
+    }
+
+    /// Scan a set of packages concurrently with a custom scanner
+    ///
+    /// At most `MAX_CONCURRENT_SCANS` scans run at once. A failing scan is
+    /// reported in its own result and doesn't stop the others. One
+    /// `VulnerabilityScan` metric and audit event is recorded per package.
+    ///
+    /// # Arguments
+    /// * `packages` - Pairs of package name and version
+    /// * `scan` - Scanner called with the name and version of each package
+    pub async fn scan_many_with<F, Fut>(
+        &self,
+        packages: &[(String, String)],
+        scan: F,
+    ) -> Vec<(String, Result<VulnerabilityReport>)>
+    where
+        F: Fn(String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<VulnerabilityReport>> + Send + 'static,
+    {
+        info!("Scanning {} packages for vulnerabilities", packages.len());
+
+        let scan = Arc::new(scan);
+        let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_SCANS));
+        let mut tasks = JoinSet::new();
+        for (index, (name, version)) in packages.iter().cloned().enumerate() {
+            let scan = Arc::clone(&scan);
+            let permits = Arc::clone(&permits);
+            let performance_monitor = Arc::clone(&self.performance_monitor);
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let start = Instant::now();
+                let result = scan(name, version).await;
+                performance_monitor.lock().unwrap().record_metric(PerformanceMetric {
+                    metric_type: MetricType::VulnerabilityScan,
+                    duration: start.elapsed(),
+                    memory_usage: None,
+                    cpu_usage: None,
+                    timestamp: start,
+                });
+                (index, result)
+            });
+        }
+
+        let mut results: Vec<Option<Result<VulnerabilityReport>>> = packages.iter().map(|_| None).collect();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((index, result)) => results[index] = Some(result),
+                Err(e) => warn!("Vulnerability scan task failed: {}", e),
+            }
+        }
+
+        // Audit events are recorded afterwards so they follow the order of `packages`
+
+        packages
+            .iter()
+            .zip(results)
+            .map(|((name, version), result)| {
+                let result = result.unwrap_or_else(|| Err(anyhow::anyhow!("Scan of {}@{} did not complete", name, version)));
+
+                let mut event = AuditEvent::new(AuditEventType::VulnerabilityScan)
+                    .with_package_name(name.clone())
+                    .with_package_version(version.clone());
+                if let Err(e) = &result {
+                    event = event.with_error(e.to_string());
+                }
+                self.record_event(event);
+
+                (name.clone(), result)
+            })
+            .collect()
     }
 
     /// Execute a package script with runtime protection
     pub async fn execute_package_script<P: AsRef<Path>>(
-        &mut self,
+        &self,
         package_name: &str,
         script_name: &str,
         script_path: P,
         working_dir: P,
     ) -> Result<std::process::Output, RuntimeProtectionError> {
         info!("Executing script '{}' for package {}", script_name, package_name);
+
+        if self.config.policy.block_scripts {
+            return Err(RuntimeProtectionError::ExecutionBlocked {
+                reason: format!("Scripts are blocked by policy (script '{}' of {})", script_name, package_name),
+            });
+        }
         
-        let start = self.performance_monitor.start_timing();
+        let start = Instant::now();
         
         // Add audit event
         let event = AuditEvent::new(AuditEventType::RuntimeProtection)
             .with_package_name(package_name.to_string())
             .with_detail("script_name".to_string(), script_name.to_string());
         
-        if let Err(e) = self.audit_trail.add_event(event) {
-            warn!("Failed to add audit event: {}", e);
-        }
+        self.record_event(event);
         
         // Execute with sandbox protection
         let result = self.sandbox_protection
             .execute_sandboxed(script_path.as_ref().to_str().unwrap_or(""), &[], working_dir)
             .await;
             
-        self.performance_monitor.end_timing(start, MetricType::SandboxExecution);
+        self.end_timing(start, MetricType::SandboxExecution);
             
         match result {
             Ok(sandbox_result) => {
@@ -202,19 +571,59 @@ impl SecurityService {
         }
     }
 
+    /// Get a reference to the current configuration
+    pub fn config(&self) -> &SecurityServiceConfig {
+        &self.config
+    }
+
     /// Get the audit trail
-    pub fn audit_trail(&self) -> &AuditTrail {
-        &self.audit_trail
+    ///
+    /// The trail stays locked while the guard is held, blocking other
+    /// operations that record events.
+    pub fn audit_trail(&self) -> MutexGuard<'_, AuditTrail> {
+        self.audit_trail.lock().unwrap()
+    }
+
+    /// Get the performance monitor
+    ///
+    /// The monitor stays locked while the guard is held.
+    pub fn performance_monitor(&self) -> MutexGuard<'_, PerformanceMonitor> {
+        self.performance_monitor.lock().unwrap()
+    }
+
+    /// Forward audit events recorded from now on to an additional sink
+    pub fn add_audit_sink(&self, sink: Box<dyn AuditSink>) {
+        self.audit_trail().add_sink(sink);
+    }
+
+    /// Get the number of audit events sinks dropped instead of writing
+    pub fn dropped_audit_events(&self) -> u64 {
+        self.audit_trail().dropped_events()
     }
 
     /// Export the audit trail to a JSON file
     pub fn export_audit_trail_to_json<P: AsRef<Path>>(&self, path: P) -> Result<(), anyhow::Error> {
-        self.audit_trail.export_to_json(path)
+        self.audit_trail().export_to_json(path)
     }
 
     /// Export the audit trail to a CSV file
     pub fn export_audit_trail_to_csv<P: AsRef<Path>>(&self, path: P) -> Result<(), anyhow::Error> {
-        self.audit_trail.export_to_csv(path)
+        self.audit_trail().export_to_csv(path)
+    }
+
+    /// Append an event to the audit trail, logging any sink failure
+    fn record_event(&self, mut event: AuditEvent) {
+        let mut audit_trail = self.audit_trail();
+        // Stamp under the lock so timestamps never go backwards along the trail
+        event.timestamp = chrono::Utc::now();
+        if let Err(e) = audit_trail.add_event(event) {
+            warn!("Failed to add audit event: {}", e);
+        }
+    }
+
+    /// Record how long an operation took since `start`
+    fn end_timing(&self, start: Instant, metric_type: MetricType) {
+        self.performance_monitor().end_timing(start, metric_type);
     }
 
     /// Check if a file system access is allowed
@@ -242,9 +651,12 @@ impl Default for SecurityService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vulnerability::Severity;
     use std::io::Write;
     use std::path::PathBuf;
     use tempfile::NamedTempFile;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
     fn test_security_service_creation() {
@@ -263,6 +675,12 @@ mod tests {
             generate_audit_trail: false,
             enable_runtime_protection: false,
             audit_trail_file: Some("test.log".to_string()),
+            audit_trail_format: AuditFormat::Cbor,
+            audit_trail_rotation: RotationPolicy::default(),
+            policy: SecurityPolicy::default(),
+            scan_timeout: None,
+            scan_budget: None,
+            ecosystem: Ecosystem::PyPI,
         };
         
         let service = SecurityService::with_config(config);
@@ -271,6 +689,7 @@ mod tests {
         assert!(!service.config.generate_audit_trail);
         assert!(!service.config.enable_runtime_protection);
         assert_eq!(service.config.audit_trail_file, Some("test.log".to_string()));
+        assert_eq!(service.config.ecosystem, Ecosystem::PyPI);
     }
 
     #[tokio::test]
@@ -285,7 +704,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_audit_trail_functionality() {
-        let mut service = SecurityService::new();
+        let service = SecurityService::new();
         
         // Add some events
         let event1 = AuditEvent::new(AuditEventType::PackageInstall)
@@ -293,11 +712,11 @@ mod tests {
         let event2 = AuditEvent::new(AuditEventType::IntegrityCheck)
             .with_package_name("test-package".to_string());
             
-        service.audit_trail.add_event(event1).unwrap();
-        service.audit_trail.add_event(event2).unwrap();
+        service.audit_trail().add_event(event1).unwrap();
+        service.audit_trail().add_event(event2).unwrap();
         
         // Check that events were added
-        assert_eq!(service.audit_trail.events().len(), 2);
+        assert_eq!(service.audit_trail().events().len(), 2);
     }
 
     #[tokio::test]
@@ -306,4 +725,327 @@ mod tests {
         let path = PathBuf::from("./package.json");
         assert!(service.check_filesystem_access(&path).is_ok());
     }
-}
\ No newline at end of file
+
+    fn service_with_policy(policy: SecurityPolicy) -> SecurityService {
+        SecurityService::with_config(SecurityServiceConfig {
+            policy,
+            ..Default::default()
+        })
+    }
+
+    /// Advisory databases where OSV reports `vulns` for every package and NVD knows nothing
+    async fn advisory_databases(vulns: serde_json::Value) -> (MockServer, VulnerabilityDatabaseClient) {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "vulns": vulns })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/nvd"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results_per_page": 0,
+                "start_index": 0,
+                "total_results": 0,
+                "format": "NVD_CVE",
+                "version": "2.0",
+                "timestamp": "2024-01-01T00:00:00.000",
+                "vulnerabilities": []
+            })))
+            .mount(&server)
+            .await;
+
+        let mut client = VulnerabilityDatabaseClient::new();
+        client.set_nvd_api_url(format!("{}/nvd", server.uri()));
+        client.set_osv_api_url(server.uri());
+        (server, client)
+    }
+
+    fn osv_vuln(id: &str, severity: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "summary": format!("{} fixture", id),
+            "modified": "2024-01-01T00:00:00Z",
+            "database_specific": { "severity": severity }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_policy_denies_deny_listed_package() {
+        let service = service_with_policy(SecurityPolicy {
+            deny_list: vec!["event-stream".to_string()],
+            ..Default::default()
+        });
+
+        let decision = service.evaluate_policy("event-stream", "3.3.6").await;
+        assert!(!decision.is_allowed());
+        assert!(decision.reasons()[0].contains("deny list"));
+        assert!(service.evaluate_policy("left-pad", "1.3.0").await.is_allowed());
+
+        let audit_trail = service.audit_trail();
+        let events: Vec<_> = audit_trail
+            .events_for_package("event-stream")
+            .into_iter()
+            .filter(|event| event.event_type == AuditEventType::PolicyEvaluation)
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].details.get("decision").map(String::as_str), Some("deny"));
+        assert!(!events[0].success);
+    }
+
+    #[tokio::test]
+    async fn test_policy_denies_over_severity_package() {
+        let (_server, client) =
+            advisory_databases(serde_json::json!([osv_vuln("CVE-2023-0001", "HIGH"), osv_vuln("CVE-2023-0002", "MODERATE")]))
+                .await;
+        let service = service_with_policy(SecurityPolicy {
+            max_severity: Some(Severity::Medium),
+            ..Default::default()
+        })
+        .with_vulnerability_database(client);
+
+        match service.evaluate_policy("test-package-with-vulns", "1.0.0").await {
+            PolicyDecision::Deny { reasons } => {
+                assert_eq!(reasons.len(), 1);
+                assert!(reasons[0].contains("CVE-2023-0001"));
+            }
+            PolicyDecision::Allow => panic!("Expected the High vulnerability to be denied"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_policy_integrity_requirement() {
+        let service = service_with_policy(SecurityPolicy {
+            require_integrity: true,
+            ..Default::default()
+        });
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "tarball").unwrap();
+        let hash = calculate_package_hash(file.path()).unwrap();
+
+        assert!(!service.evaluate_policy("pkg", "1.0.0").await.is_allowed());
+        assert!(service
+            .evaluate_policy_with_tarball("pkg", "1.0.0", Some((file.path(), &hash)))
+            .await
+            .is_allowed());
+        assert!(!service
+            .evaluate_policy_with_tarball("pkg", "1.0.0", Some((file.path(), &"0".repeat(128))))
+            .await
+            .is_allowed());
+    }
+
+    #[tokio::test]
+    async fn test_secure_install_happy_path() {
+        let (_server, client) = advisory_databases(serde_json::json!([])).await;
+        let service = service_with_policy(SecurityPolicy {
+            max_severity: Some(Severity::High),
+            ..Default::default()
+        })
+        .with_vulnerability_database(client);
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "tarball").unwrap();
+        let hash = calculate_package_hash(file.path()).unwrap();
+
+        let decision = service.secure_install("lodash", "4.17.21", file.path(), &hash).await.unwrap();
+        assert!(decision.is_allowed());
+
+        let audit_trail = service.audit_trail();
+        let types: Vec<&AuditEventType> = audit_trail.events().iter().map(|e| &e.event_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                &AuditEventType::IntegrityCheck,
+                &AuditEventType::VulnerabilityScan,
+                &AuditEventType::PolicyEvaluation,
+                &AuditEventType::PackageInstall,
+            ]
+        );
+        assert_eq!(service.performance_monitor().metrics_for_type(&MetricType::IntegrityVerification).len(), 1);
+        assert_eq!(service.performance_monitor().metrics_for_type(&MetricType::VulnerabilityScan).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_scan_denies_under_severity_policy() {
+        let (_server, client) = advisory_databases(serde_json::json!([])).await;
+        let service = SecurityService::with_config(SecurityServiceConfig {
+            policy: SecurityPolicy {
+                max_severity: Some(Severity::High),
+                ..Default::default()
+            },
+            scan_budget: Some(Duration::ZERO),
+            ..Default::default()
+        })
+        .with_vulnerability_database(client);
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "tarball").unwrap();
+        let hash = calculate_package_hash(file.path()).unwrap();
+
+        let decision = service.secure_install("lodash", "4.17.21", file.path(), &hash).await.unwrap();
+        assert!(!decision.is_allowed());
+        assert!(decision.reasons()[0].contains("budget is exhausted"), "{:?}", decision.reasons());
+        assert!(!service.evaluate_policy("lodash", "4.17.21").await.is_allowed());
+        assert!(service.audit_trail().events().iter().all(|e| e.event_type != AuditEventType::PackageInstall));
+    }
+
+    #[tokio::test]
+    async fn test_secure_install_aborts_on_integrity_mismatch() {
+        let service = SecurityService::new();
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "tampered tarball").unwrap();
+
+        let decision = service
+            .secure_install("lodash", "4.17.21", file.path(), &"0".repeat(128))
+            .await
+            .unwrap();
+        assert!(!decision.is_allowed());
+        assert!(decision.reasons()[0].contains("Integrity verification failed"));
+
+        // Nothing after the failed integrity check ran
+        {
+            let audit_trail = service.audit_trail();
+            let events = audit_trail.events();
+            assert!(events.iter().all(|e| e.event_type != AuditEventType::VulnerabilityScan));
+            assert!(events.iter().all(|e| e.event_type != AuditEventType::PackageInstall));
+        }
+        assert!(service
+            .secure_install("lodash", "4.17.21", Path::new("/nonexistent/pkg.tgz"), &"0".repeat(128))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_many_scans_every_package() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let service = SecurityService::new();
+        let packages: Vec<(String, String)> = (0..20)
+            .map(|i| (format!("pkg-{}", i), "1.0.0".to_string()))
+            .collect();
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (in_flight_scan, peak_scan) = (Arc::clone(&in_flight), Arc::clone(&peak));
+        let results = service
+            .scan_many_with(&packages, move |name, version| {
+                let in_flight = Arc::clone(&in_flight_scan);
+                let peak = Arc::clone(&peak_scan);
+                async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    if name == "pkg-7" {
+                        anyhow::bail!("advisory source unavailable");
+                    }
+                    Ok(VulnerabilityReport::new(name, version))
+                }
+            })
+            .await;
+
+        assert_eq!(results.len(), 20);
+        for (i, (name, result)) in results.iter().enumerate() {
+            assert_eq!(name, &format!("pkg-{}", i));
+            assert_eq!(result.is_err(), i == 7);
+        }
+        assert!(peak.load(Ordering::SeqCst) <= MAX_CONCURRENT_SCANS);
+        assert_eq!(service.performance_monitor().metrics_for_type(&MetricType::VulnerabilityScan).len(), 20);
+        assert_eq!(service.audit_trail().events().len(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_scan_timeout_returns_warning() {
+        let service = SecurityService::with_config(SecurityServiceConfig {
+            scan_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        });
+
+        let slow_provider = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(VulnerabilityReport::new("slow".to_string(), "1.0.0".to_string()))
+        };
+        let report = service.scan_package_with("slow", "1.0.0", slow_provider).await.unwrap();
+        assert!(!report.is_complete());
+        assert!(report.warnings[0].contains("timed out"));
+
+        let performance_monitor = service.performance_monitor();
+        let metrics = performance_monitor.metrics_for_type(&MetricType::VulnerabilityScan);
+        assert_eq!(metrics.len(), 1);
+        assert!(metrics[0].duration >= Duration::from_millis(50));
+        assert!(metrics[0].duration < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_scan_budget_is_shared_across_scans() {
+        let service = SecurityService::with_config(SecurityServiceConfig {
+            scan_timeout: None,
+            scan_budget: Some(Duration::from_millis(60)),
+            ..Default::default()
+        });
+        let slow_provider = || async {
+            tokio::time::sleep(Duration::from_millis(40)).await;
+            Ok(VulnerabilityReport::new("pkg".to_string(), "1.0.0".to_string()))
+        };
+
+        assert!(service.scan_package_with("pkg", "1.0.0", slow_provider()).await.unwrap().is_complete());
+        // Only ~20ms of budget remain, so the second scan is cut short
+        let report = service.scan_package_with("pkg", "1.0.0", slow_provider()).await.unwrap();
+        assert!(!report.is_complete());
+        let report = service.scan_package_with("pkg", "1.0.0", slow_provider()).await.unwrap();
+        assert!(report.warnings[0].contains("budget is exhausted"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_without_audit_records_metrics_only() {
+        let (_server, client) =
+            advisory_databases(serde_json::json!([osv_vuln("CVE-2023-0001", "HIGH"), osv_vuln("CVE-2023-0002", "MODERATE")]))
+                .await;
+        let service = SecurityService::new().with_vulnerability_database(client);
+
+        let report = service.scan_without_audit("test-package-with-vulns", "1.0.0").await.unwrap();
+        assert_eq!(report.vulnerabilities.len(), 2);
+        assert!(service.audit_trail().events().is_empty());
+        assert_eq!(service.performance_monitor().metrics_for_type(&MetricType::VulnerabilityScan).len(), 1);
+
+        service.scan_package_for_vulnerabilities("test-package-with-vulns", "1.0.0").await.unwrap();
+        assert_eq!(service.audit_trail().events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shared_service_scans_concurrently() {
+        let service = Arc::new(SecurityService::new());
+
+        let mut tasks = JoinSet::new();
+        for i in 0..8 {
+            let service = Arc::clone(&service);
+            tasks.spawn(async move {
+                let name = format!("package-{}", i);
+                let scan = async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(VulnerabilityReport::new(name.clone(), "1.0.0".to_string()))
+                };
+                service.scan_package_with(&name, "1.0.0", scan).await.unwrap()
+            });
+        }
+        let started = Instant::now();
+        let mut reports = 0;
+        while let Some(report) = tasks.join_next().await {
+            assert!(report.unwrap().is_complete());
+            reports += 1;
+        }
+
+        assert_eq!(reports, 8);
+        // The scans overlapped rather than queueing behind one another
+        assert!(started.elapsed() < Duration::from_millis(8 * 50));
+        assert_eq!(service.performance_monitor().metrics_for_type(&MetricType::VulnerabilityScan).len(), 8);
+
+        let audit_trail = service.audit_trail();
+        let events = audit_trail.events();
+        assert_eq!(events.len(), 8);
+        assert!(events.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        let mut packages: Vec<&str> = events.iter().filter_map(|e| e.package_name.as_deref()).collect();
+        packages.sort();
+        packages.dedup();
+        assert_eq!(packages.len(), 8);
+    }
+}
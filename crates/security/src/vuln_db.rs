@@ -3,11 +3,17 @@
 //! This module provides clients for integrating with various vulnerability databases
 //! such as NVD, OSV, and GitHub Advisory Database.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use tracing::info;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::cpe::cve_covers;
 
 /// NVD (National Vulnerability Database) CVE entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -344,12 +350,54 @@ pub struct OsvReference {
     pub url: String,
 }
 
+/// Body of an OSV `/v1/query` request
+#[derive(Debug, Serialize)]
+struct OsvQuery<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<&'a str>,
+    package: OsvQueryPackage<'a>,
+}
+
+/// Package identified in an OSV query
+#[derive(Debug, Serialize)]
+struct OsvQueryPackage<'a> {
+    name: &'a str,
+    ecosystem: &'a str,
+}
+
+/// Response of an OSV `/v1/query` request
+#[derive(Debug, Deserialize)]
+struct OsvQueryResponse {
+    /// Omitted by OSV when nothing matches
+    #[serde(default)]
+    vulns: Vec<OsvEntry>,
+}
+
+/// CVE endpoint of the public NVD API
+pub const DEFAULT_NVD_API_URL: &str = "https://services.nvd.nist.gov/rest/json/cves/2.0";
+
+/// Base URL of the public OSV API
+pub const DEFAULT_OSV_API_URL: &str = "https://api.osv.dev";
+
+/// Base URL of the public GitHub REST API
+pub const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+
+/// How long a rate-limited token is skipped when GitHub doesn't say when it resets
+const DEFAULT_RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+
 /// Vulnerability database client
 #[derive(Debug)]
 pub struct VulnerabilityDatabaseClient {
     client: Client,
     nvd_api_key: Option<String>,
-    github_token: Option<String>,
+    github_tokens: Vec<String>,
+    /// When each rate-limited GitHub token becomes usable again, by token index
+    github_rate_limits: Mutex<HashMap<usize, SystemTime>>,
+    offline: bool,
+    nvd_api_url: String,
+    osv_api_url: String,
+    github_api_url: String,
+    local_osv_index: HashMap<(String, String), Vec<OsvEntry>>,
 }
 
 impl VulnerabilityDatabaseClient {
@@ -364,30 +412,129 @@ impl VulnerabilityDatabaseClient {
         Self {
             client,
             nvd_api_key: None,
-            github_token: None,
+            github_tokens: Vec::new(),
+            github_rate_limits: Mutex::new(HashMap::new()),
+            offline: false,
+            nvd_api_url: DEFAULT_NVD_API_URL.to_string(),
+            osv_api_url: DEFAULT_OSV_API_URL.to_string(),
+            github_api_url: DEFAULT_GITHUB_API_URL.to_string(),
+            local_osv_index: HashMap::new(),
         }
     }
 
     /// Create a new vulnerability database client with API keys
     pub fn with_api_keys(nvd_api_key: Option<String>, github_token: Option<String>) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("package-fast-security/0.1.0")
-            .build()
-            .expect("Failed to create HTTP client");
-            
-        Self {
-            client,
-            nvd_api_key,
-            github_token,
+        Self::with_github_tokens(nvd_api_key, github_token.into_iter().collect())
+    }
+
+    /// Create a new vulnerability database client with several GitHub tokens
+    ///
+    /// GitHub requests use the first token that isn't rate limited, moving
+    /// on to the next one when GitHub reports the current one exhausted.
+    ///
+    /// # Arguments
+    /// * `nvd_api_key` - NVD API key, if any
+    /// * `github_tokens` - GitHub tokens, in the order they should be used
+    pub fn with_github_tokens(nvd_api_key: Option<String>, github_tokens: Vec<String>) -> Self {
+        let mut client = Self::new();
+        client.nvd_api_key = nvd_api_key;
+        client.set_github_tokens(github_tokens);
+        client
+    }
+
+    /// Replace the GitHub tokens, forgetting any rate limits seen so far
+    pub fn set_github_tokens(&mut self, tokens: Vec<String>) {
+        self.github_tokens = tokens;
+        self.github_rate_limits = Mutex::new(HashMap::new());
+    }
+
+    /// Enable or disable offline mode
+    ///
+    /// In offline mode, OSV queries are answered from the local database
+    /// loaded with `load_local_db` and no network requests are made.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Check whether the client is in offline mode
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Point NVD queries at a different CVE endpoint, such as a mirror
+    pub fn set_nvd_api_url(&mut self, url: impl Into<String>) {
+        self.nvd_api_url = url.into().trim_end_matches('/').to_string();
+    }
+
+    /// Point OSV queries at a different API, such as a mirror
+    pub fn set_osv_api_url(&mut self, url: impl Into<String>) {
+        self.osv_api_url = url.into().trim_end_matches('/').to_string();
+    }
+
+    /// Point GitHub advisory queries at a different API, such as GitHub Enterprise
+    pub fn set_github_api_url(&mut self, url: impl Into<String>) {
+        self.github_api_url = url.into().trim_end_matches('/').to_string();
+    }
+
+    /// Load a local OSV database snapshot from a directory of JSON files
+    ///
+    /// Each `*.json` file in the directory must contain a single OSV entry.
+    /// Entries are indexed by every package and ecosystem they affect, and
+    /// are added to anything loaded previously.
+    pub fn load_local_db(&mut self, path: &Path) -> Result<()> {
+        info!("Loading local vulnerability database from {:?}", path);
+
+        let mut loaded = 0;
+        for dir_entry in fs::read_dir(path)
+            .with_context(|| format!("Failed to read local database directory {:?}", path))?
+        {
+            let file_path = dir_entry?.path();
+            if file_path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            let content = fs::read_to_string(&file_path)?;
+            let entry: OsvEntry = match serde_json::from_str(&content) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Skipping malformed OSV entry {:?}: {}", file_path, e);
+                    continue;
+                }
+            };
+
+            for affected in entry.affected.iter().flatten() {
+                let key = (affected.package.name.clone(), affected.package.ecosystem.clone());
+                self.local_osv_index.entry(key).or_default().push(entry.clone());
+            }
+            loaded += 1;
         }
+
+        info!("Loaded {} entries into the local vulnerability database", loaded);
+        Ok(())
+    }
+
+    /// Look up entries for a package in the local OSV database
+    fn query_local_osv(&self, package_name: &str, ecosystem: &str) -> Vec<OsvEntry> {
+        self.local_osv_index
+            .get(&(package_name.to_string(), ecosystem.to_string()))
+            .cloned()
+            .unwrap_or_default()
     }
 
     /// Query NVD for vulnerabilities affecting a specific package
+    ///
+    /// NVD only supports keyword search, which also returns CVEs that merely
+    /// mention the package. With a version, results are filtered down to the
+    /// CVEs whose CPE configurations cover it; see `cpe::cve_covers`. NVD
+    /// has no local snapshot, so nothing is returned in offline mode.
     pub async fn query_nvd(&self, package_name: &str, version: Option<&str>) -> Result<Vec<NvdVulnerability>> {
         info!("Querying NVD for package: {} version: {:?}", package_name, version);
         
-        let mut url = format!("https://services.nvd.nist.gov/rest/json/cves/2.0?keywordSearch={}", package_name);
+        if self.offline {
+            return Ok(vec![]);
+        }
+        
+        let mut url = format!("{}?keywordSearch={}", self.nvd_api_url, package_name);
         
         if let Some(_version) = version {
             url.push_str("&keywordExactMatch");
@@ -401,31 +548,147 @@ impl VulnerabilityDatabaseClient {
         
         if response.status().is_success() {
             let nvd_response: NvdResponse = response.json().await?;
-            Ok(nvd_response.vulnerabilities)
+            let mut vulnerabilities = nvd_response.vulnerabilities;
+            if let Some(version) = version {
+                vulnerabilities.retain(|vulnerability| cve_covers(package_name, version, &vulnerability.cve));
+            }
+            Ok(vulnerabilities)
         } else {
             anyhow::bail!("Failed to query NVD: HTTP {}", response.status());
         }
     }
 
     /// Query GitHub Advisory Database for vulnerabilities affecting a specific package
+    ///
+    /// GitHub is only queried when at least one token is configured, and
+    /// never in offline mode. A token that hits the rate limit is skipped
+    /// until the time in `X-RateLimit-Reset`, and the request is retried
+    /// with the next token. Once every token is exhausted, the request is
+    /// made unauthenticated.
+    ///
+    /// # Arguments
+    /// * `package_name` - Name of the package to look up
+    /// * `ecosystem` - GitHub ecosystem name, such as `npm` or `pip`
+    ///
+    /// # Returns
+    /// * `Ok(Vec<GithubAdvisory>)` with the advisories affecting the package
+    /// * `Err(anyhow::Error)` if the request fails or even unauthenticated requests are rate limited
     pub async fn query_github_advisories(&self, package_name: &str, ecosystem: &str) -> Result<Vec<GithubAdvisory>> {
         info!("Querying GitHub Advisory Database for package: {} ecosystem: {}", package_name, ecosystem);
         
-        // This is a simplified implementation. In practice, you would use the GitHub GraphQL API
-        // or the REST API with proper authentication and pagination.
-        Ok(vec![])
+        if self.offline || self.github_tokens.is_empty() {
+            return Ok(vec![]);
+        }
+        
+        let url = format!("{}/advisories", self.github_api_url);
+        loop {
+            let token = self.available_github_token();
+            let mut request = self
+                .client
+                .get(&url)
+                .query(&[("ecosystem", ecosystem), ("affects", package_name)])
+                .header(reqwest::header::ACCEPT, "application/vnd.github+json");
+            if let Some(index) = token {
+                request = request.bearer_auth(&self.github_tokens[index]);
+            }
+            let response = request.send().await?;
+            
+            if let Some(reset) = rate_limit_reset(&response) {
+                match token {
+                    Some(index) => {
+                        warn!("GitHub token {} is rate limited, trying the next one", index + 1);
+                        self.github_rate_limits.lock().unwrap().insert(index, reset);
+                        continue;
+                    }
+                    None => anyhow::bail!("GitHub API rate limit exceeded for all tokens"),
+                }
+            }
+            
+            if response.status().is_success() {
+                return Ok(response.json().await?);
+            }
+            anyhow::bail!("Failed to query GitHub Advisory Database: HTTP {}", response.status());
+        }
+    }
+
+    /// Index of the first GitHub token that isn't rate limited, if any
+    fn available_github_token(&self) -> Option<usize> {
+        let now = SystemTime::now();
+        let mut rate_limits = self.github_rate_limits.lock().unwrap();
+        rate_limits.retain(|_, reset| *reset > now);
+        (0..self.github_tokens.len()).find(|index| !rate_limits.contains_key(index))
     }
 
     /// Query OSV for vulnerabilities affecting a specific package
-    pub async fn query_osv(&self, package_name: &str, ecosystem: &str) -> Result<Vec<OsvEntry>> {
-        info!("Querying OSV for package: {} ecosystem: {}", package_name, ecosystem);
+    ///
+    /// With a version, OSV only returns entries affecting that version. The
+    /// local database used in offline mode is indexed by package only, so
+    /// offline queries return every entry for the package.
+    ///
+    /// # Arguments
+    /// * `package_name` - Name of the package to look up
+    /// * `version` - Version to restrict the results to, if any
+    /// * `ecosystem` - OSV ecosystem name, such as `npm` or `PyPI`
+    pub async fn query_osv(&self, package_name: &str, version: Option<&str>, ecosystem: &str) -> Result<Vec<OsvEntry>> {
+        info!("Querying OSV for package: {} version: {:?} ecosystem: {}", package_name, version, ecosystem);
         
-        // This is a simplified implementation. In practice, you would use the OSV API
-        // with proper query parameters.
-        Ok(vec![])
+        if self.offline {
+            return Ok(self.query_local_osv(package_name, ecosystem));
+        }
+        
+        let query = OsvQuery {
+            version,
+            package: OsvQueryPackage {
+                name: package_name,
+                ecosystem,
+            },
+        };
+        let response = self
+            .client
+            .post(format!("{}/v1/query", self.osv_api_url))
+            .json(&query)
+            .send()
+            .await?;
+        
+        if response.status().is_success() {
+            let osv_response: OsvQueryResponse = response.json().await?;
+            Ok(osv_response.vulns)
+        } else {
+            anyhow::bail!("Failed to query OSV: HTTP {}", response.status());
+        }
     }
 }
 
+/// When a rate-limited GitHub response says the limit resets
+///
+/// Returns `None` unless the response is a 403 or 429 caused by the rate
+/// limit: an exhausted primary limit (`X-RateLimit-Remaining: 0`) or a
+/// secondary limit (`Retry-After`).
+fn rate_limit_reset(response: &reqwest::Response) -> Option<SystemTime> {
+    let status = response.status();
+    if status != reqwest::StatusCode::FORBIDDEN && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    if header("x-ratelimit-remaining") == Some(0) {
+        let reset = header("x-ratelimit-reset")
+            .map(|epoch| UNIX_EPOCH + Duration::from_secs(epoch))
+            .unwrap_or_else(|| SystemTime::now() + DEFAULT_RATE_LIMIT_BACKOFF);
+        return Some(reset);
+    }
+    if let Some(seconds) = header("retry-after") {
+        return Some(SystemTime::now() + Duration::from_secs(seconds));
+    }
+    (status == reqwest::StatusCode::TOO_MANY_REQUESTS).then(|| SystemTime::now() + DEFAULT_RATE_LIMIT_BACKOFF)
+}
+
 impl Default for VulnerabilityDatabaseClient {
     fn default() -> Self {
         Self::new()
@@ -435,12 +698,14 @@ impl Default for VulnerabilityDatabaseClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wiremock::matchers::{header, header_exists, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
     fn test_client_creation() {
         let client = VulnerabilityDatabaseClient::new();
         assert!(client.nvd_api_key.is_none());
-        assert!(client.github_token.is_none());
+        assert!(client.github_tokens.is_empty());
     }
 
     #[test]
@@ -450,6 +715,174 @@ mod tests {
             Some("github-token".to_string()),
         );
         assert_eq!(client.nvd_api_key, Some("nvd-key".to_string()));
-        assert_eq!(client.github_token, Some("github-token".to_string()));
+        assert_eq!(client.github_tokens, vec!["github-token".to_string()]);
+    }
+
+    fn write_osv_fixture(dir: &Path, id: &str, package: &str) {
+        let entry = serde_json::json!({
+            "id": id,
+            "summary": "Fixture vulnerability",
+            "modified": "2023-01-01T00:00:00Z",
+            "affected": [{
+                "package": { "name": package, "ecosystem": "npm" },
+                "ranges": [{
+                    "type": "SEMVER",
+                    "events": [{ "introduced": "0" }, { "fixed": "4.17.21" }]
+                }]
+            }]
+        });
+        fs::write(dir.join(format!("{}.json", id)), entry.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_local_db_offline() {
+        let dir = tempfile::TempDir::new().unwrap();
+        write_osv_fixture(dir.path(), "GHSA-0001", "lodash");
+        write_osv_fixture(dir.path(), "GHSA-0002", "lodash");
+        write_osv_fixture(dir.path(), "GHSA-0003", "minimist");
+        fs::write(dir.path().join("README.txt"), "not an entry").unwrap();
+
+        let mut client = VulnerabilityDatabaseClient::new();
+        client.load_local_db(dir.path()).unwrap();
+        client.set_offline(true);
+
+        let entries = client.query_osv("lodash", None, "npm").await.unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let entries = client.query_osv("express", None, "npm").await.unwrap();
+        assert!(entries.is_empty());
+
+        let entries = client.query_osv("lodash", Some("4.17.20"), "PyPI").await.unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_load_local_db_missing_directory() {
+        let mut client = VulnerabilityDatabaseClient::new();
+        assert!(client.load_local_db(Path::new("/nonexistent/osv-snapshot")).is_err());
+    }
+
+    /// A GitHub user as embedded in advisories
+    fn github_user(login: &str) -> serde_json::Value {
+        let url = format!("https://api.github.com/users/{}", login);
+        let mut user = serde_json::json!({
+            "login": login,
+            "id": 1,
+            "node_id": "MDQ6VXNlcjE=",
+            "gravatar_id": "",
+            "type": "User",
+            "site_admin": false
+        });
+        for field in [
+            "avatar_url", "url", "html_url", "followers_url", "following_url", "gists_url", "starred_url",
+            "subscriptions_url", "organizations_url", "repos_url", "events_url", "received_events_url",
+        ] {
+            user[field] = serde_json::Value::String(url.clone());
+        }
+        user
+    }
+
+    fn github_advisory(ghsa_id: &str, package: &str) -> serde_json::Value {
+        serde_json::json!({
+            "database_id": 1,
+            "id": ghsa_id,
+            "ghsa_id": ghsa_id,
+            "node_id": "GSA_1",
+            "url": format!("https://api.github.com/advisories/{}", ghsa_id),
+            "html_url": format!("https://github.com/advisories/{}", ghsa_id),
+            "identifiers": [{ "type": "GHSA", "value": ghsa_id }],
+            "summary": "Fixture advisory",
+            "description": "Fixture advisory",
+            "severity": "high",
+            "author": github_user("author"),
+            "publisher": github_user("publisher"),
+            "references": [],
+            "published_at": "2023-01-01T00:00:00Z",
+            "updated_at": "2023-01-01T00:00:00Z",
+            "vulnerabilities": [{
+                "package": { "ecosystem": "npm", "name": package },
+                "severity": "high",
+                "vulnerable_version_range": "< 4.17.21",
+                "first_patched_version": { "identifier": "4.17.21" }
+            }],
+            "cwes": [],
+            "credits": []
+        })
     }
-}
\ No newline at end of file
+
+    /// A primary rate limit response resetting an hour from now
+    fn rate_limited() -> ResponseTemplate {
+        let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 3600;
+        ResponseTemplate::new(403)
+            .insert_header("x-ratelimit-remaining", "0")
+            .insert_header("x-ratelimit-reset", reset.to_string().as_str())
+    }
+
+    #[tokio::test]
+    async fn test_github_token_rotation_on_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/advisories"))
+            .and(header("authorization", "Bearer token-a"))
+            .respond_with(rate_limited())
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/advisories"))
+            .and(query_param("ecosystem", "npm"))
+            .and(query_param("affects", "lodash"))
+            .and(header("authorization", "Bearer token-b"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![github_advisory("GHSA-0001", "lodash")]))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let mut client =
+            VulnerabilityDatabaseClient::with_github_tokens(None, vec!["token-a".to_string(), "token-b".to_string()]);
+        client.set_github_api_url(server.uri());
+
+        let advisories = client.query_github_advisories("lodash", "npm").await.unwrap();
+        assert_eq!(advisories.len(), 1);
+        assert_eq!(advisories[0].ghsa_id, "GHSA-0001");
+
+        // Token A stays skipped until its limit resets
+        let advisories = client.query_github_advisories("lodash", "npm").await.unwrap();
+        assert_eq!(advisories.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_github_unauthenticated_when_all_tokens_exhausted() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/advisories"))
+            .and(header_exists("authorization"))
+            .respond_with(rate_limited())
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/advisories"))
+            .and(|request: &wiremock::Request| !request.headers.contains_key(&"authorization".into()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Vec::<serde_json::Value>::new()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client =
+            VulnerabilityDatabaseClient::with_github_tokens(None, vec!["token-a".to_string(), "token-b".to_string()]);
+        client.set_github_api_url(server.uri());
+        assert!(client.query_github_advisories("lodash", "npm").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_github_skipped_without_tokens_or_offline() {
+        let mut client = VulnerabilityDatabaseClient::new();
+        client.set_github_api_url("http://127.0.0.1:9");
+        assert!(client.query_github_advisories("lodash", "npm").await.unwrap().is_empty());
+
+        client.set_github_tokens(vec!["token-a".to_string()]);
+        client.set_offline(true);
+        assert!(client.query_github_advisories("lodash", "npm").await.unwrap().is_empty());
+    }
+}
@@ -5,7 +5,12 @@
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::{info, warn};
+
+use crate::cvss::{self, CvssVersion};
+use crate::vuln_db::{GithubAdvisory, NvdCve, OsvEntry, VulnerabilityDatabaseClient};
 
 /// Vulnerability severity levels
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -16,6 +21,125 @@ pub enum Severity {
     Critical,
 }
 
+impl Severity {
+    /// Map a CVSS base score to its qualitative severity band
+    ///
+    /// Uses the CVSS v3 rating scale: 0.1–3.9 Low, 4.0–6.9 Medium,
+    /// 7.0–8.9 High and 9.0–10.0 Critical. A score of 0.0 (or anything
+    /// outside the valid range) has no severity and yields `None`.
+    pub fn from_cvss_score(score: f64) -> Option<Severity> {
+        match score {
+            s if (0.1..4.0).contains(&s) => Some(Severity::Low),
+            s if (4.0..7.0).contains(&s) => Some(Severity::Medium),
+            s if (7.0..9.0).contains(&s) => Some(Severity::High),
+            s if (9.0..=10.0).contains(&s) => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    /// Parse a severity name, accepting npm's `moderate` as an alias for `Medium`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" | "moderate" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("Unknown severity '{}', expected low, medium, high or critical", s)),
+        }
+    }
+}
+
+/// Package ecosystem a scanned package belongs to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Ecosystem {
+    #[default]
+    Npm,
+    PyPI,
+    Cargo,
+    Go,
+    Maven,
+    NuGet,
+    RubyGems,
+    Packagist,
+}
+
+impl Ecosystem {
+    /// Name of the ecosystem in OSV queries
+    pub fn osv_name(self) -> &'static str {
+        match self {
+            Ecosystem::Npm => "npm",
+            Ecosystem::PyPI => "PyPI",
+            Ecosystem::Cargo => "crates.io",
+            Ecosystem::Go => "Go",
+            Ecosystem::Maven => "Maven",
+            Ecosystem::NuGet => "NuGet",
+            Ecosystem::RubyGems => "RubyGems",
+            Ecosystem::Packagist => "Packagist",
+        }
+    }
+
+    /// Name of the ecosystem in GitHub Advisory Database queries
+    pub fn github_name(self) -> &'static str {
+        match self {
+            Ecosystem::Npm => "npm",
+            Ecosystem::PyPI => "pip",
+            Ecosystem::Cargo => "rust",
+            Ecosystem::Go => "go",
+            Ecosystem::Maven => "maven",
+            Ecosystem::NuGet => "nuget",
+            Ecosystem::RubyGems => "rubygems",
+            Ecosystem::Packagist => "composer",
+        }
+    }
+}
+
+impl std::fmt::Display for Ecosystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.osv_name())
+    }
+}
+
+impl std::str::FromStr for Ecosystem {
+    type Err = String;
+
+    /// Parse an ecosystem name, accepting both the OSV and GitHub spellings
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "npm" => Ok(Ecosystem::Npm),
+            "pypi" | "pip" => Ok(Ecosystem::PyPI),
+            "cargo" | "crates.io" | "rust" => Ok(Ecosystem::Cargo),
+            "go" => Ok(Ecosystem::Go),
+            "maven" => Ok(Ecosystem::Maven),
+            "nuget" => Ok(Ecosystem::NuGet),
+            "rubygems" => Ok(Ecosystem::RubyGems),
+            "packagist" | "composer" => Ok(Ecosystem::Packagist),
+            _ => Err(format!("Unknown ecosystem '{}'", s)),
+        }
+    }
+}
+
+/// Advisory database that reported a finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum VulnerabilitySource {
+    Nvd,
+    Osv,
+    GithubAdvisory,
+}
+
+impl std::fmt::Display for VulnerabilitySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            VulnerabilitySource::Nvd => "NVD",
+            VulnerabilitySource::Osv => "OSV",
+            VulnerabilitySource::GithubAdvisory => "GitHub Advisory Database",
+        })
+    }
+}
+
 /// Vulnerability information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vulnerability {
@@ -23,9 +147,27 @@ pub struct Vulnerability {
     pub title: String,
     pub description: String,
     pub severity: Severity,
+    /// CVSS base score reported by the advisory source, if any
+    #[serde(default)]
+    pub cvss_score: Option<f64>,
     pub affected_versions: Vec<String>,
     pub patched_versions: Vec<String>,
     pub references: Vec<String>,
+    /// Advisory databases that reported this finding, in source order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<VulnerabilitySource>,
+}
+
+impl Vulnerability {
+    /// Get the severity of this vulnerability
+    ///
+    /// The CVSS base score takes precedence when present; otherwise the
+    /// severity declared by the advisory source is used.
+    pub fn effective_severity(&self) -> Severity {
+        self.cvss_score
+            .and_then(Severity::from_cvss_score)
+            .unwrap_or_else(|| self.severity.clone())
+    }
 }
 
 /// Vulnerability scan report
@@ -35,6 +177,9 @@ pub struct VulnerabilityReport {
     pub package_version: String,
     pub vulnerabilities: Vec<Vulnerability>,
     pub scan_timestamp: chrono::DateTime<chrono::Utc>,
+    /// Problems that left the scan incomplete, such as a provider timing out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 impl VulnerabilityReport {
@@ -45,6 +190,7 @@ impl VulnerabilityReport {
             package_version,
             vulnerabilities: Vec::new(),
             scan_timestamp: chrono::Utc::now(),
+            warnings: Vec::new(),
         }
     }
 
@@ -53,74 +199,503 @@ impl VulnerabilityReport {
         self.vulnerabilities.push(vulnerability);
     }
 
-    /// Get the highest severity vulnerability in the report
-    pub fn highest_severity(&self) -> Option<&Severity> {
+    /// Get the highest severity among the findings
+    ///
+    /// Uses each finding's effective severity, so a CVSS score outranks the
+    /// declared severity.
+    pub fn highest_severity(&self) -> Option<Severity> {
         self.vulnerabilities
             .iter()
-            .map(|v| &v.severity)
+            .map(Vulnerability::effective_severity)
             .max()
     }
 
+    /// Sort the findings by CVSS base score, highest first
+    ///
+    /// Findings without a score come last. Ties are broken by effective
+    /// severity (highest first) and then by ID, so the order is deterministic.
+    pub fn sort_by_score(&mut self) {
+        self.vulnerabilities.sort_by(compare_by_score);
+    }
+
+    /// Group the findings by effective severity band
+    ///
+    /// Within a band, findings are ordered as by `sort_by_score`. The map
+    /// iterates from `Low` to `Critical`; use `.iter().rev()` for the most
+    /// severe band first.
+    pub fn group_by_severity(&self) -> BTreeMap<Severity, Vec<&Vulnerability>> {
+        let mut sorted: Vec<&Vulnerability> = self.vulnerabilities.iter().collect();
+        sorted.sort_by(|a, b| compare_by_score(a, b));
+
+        let mut groups: BTreeMap<Severity, Vec<&Vulnerability>> = BTreeMap::new();
+        for vulnerability in sorted {
+            groups.entry(vulnerability.effective_severity()).or_default().push(vulnerability);
+        }
+        groups
+    }
+
+    /// Check whether the scan finished without warnings
+    pub fn is_complete(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
     /// Check if the report contains critical vulnerabilities
     pub fn has_critical_vulnerabilities(&self) -> bool {
         self.vulnerabilities
             .iter()
             .any(|v| v.severity == Severity::Critical)
     }
+
+    /// Check if any vulnerability in the report meets or exceeds a severity threshold
+    pub fn meets_severity(&self, threshold: &Severity) -> bool {
+        self.vulnerabilities
+            .iter()
+            .any(|v| v.effective_severity() >= *threshold)
+    }
+
+    /// Export the report as a SARIF 2.1.0 log
+    ///
+    /// Each advisory becomes a rule of the `package-fast` driver, keyed by its
+    /// CVE or GHSA ID, and each finding a result pointing at the package's
+    /// manifest in `node_modules`. Code scanning tools rank results by the
+    /// rule's `security-severity` property, which carries the CVSS score when
+    /// the advisory has one.
+    ///
+    /// # Returns
+    /// * `Ok(String)` holding the pretty-printed SARIF document
+    /// * `Err(anyhow::Error)` if the document can't be serialized
+    pub fn to_sarif(&self) -> Result<String> {
+        let mut rules: Vec<serde_json::Value> = Vec::new();
+        let mut rule_indices: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut results = Vec::with_capacity(self.vulnerabilities.len());
+        let artifact = format!("node_modules/{}/package.json", self.package_name);
+
+        for vulnerability in &self.vulnerabilities {
+            let severity = vulnerability.effective_severity();
+            let rule_index = *rule_indices.entry(vulnerability.id.as_str()).or_insert_with(|| {
+                rules.push(sarif_rule(vulnerability));
+                rules.len() - 1
+            });
+
+            let mut message = format!(
+                "{}@{} is affected by {}: {}",
+                self.package_name, self.package_version, vulnerability.id, vulnerability.title
+            );
+            if !vulnerability.patched_versions.is_empty() {
+                message.push_str(&format!(" (fixed in {})", vulnerability.patched_versions.join(", ")));
+            }
+
+            results.push(serde_json::json!({
+                "ruleId": vulnerability.id,
+                "ruleIndex": rule_index,
+                "level": sarif_level(&severity),
+                "message": { "text": message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": artifact }
+                    }
+                }],
+                "properties": {
+                    "severity": severity,
+                    "package": self.package_name,
+                    "version": self.package_version,
+                    "patchedVersions": vulnerability.patched_versions,
+                },
+            }));
+        }
+
+        let sarif = serde_json::json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "package-fast",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "invocations": [{
+                    "executionSuccessful": self.is_complete(),
+                    "endTimeUtc": self.scan_timestamp.to_rfc3339(),
+                    "toolExecutionNotifications": self.warnings.iter().map(|warning| serde_json::json!({
+                        "level": "warning",
+                        "message": { "text": warning },
+                    })).collect::<Vec<_>>(),
+                }],
+                "results": results,
+            }],
+        });
+        Ok(serde_json::to_string_pretty(&sarif)?)
+    }
 }
 
-/// Scan a package for known vulnerabilities
-/// 
+/// Build the SARIF rule describing an advisory
+fn sarif_rule(vulnerability: &Vulnerability) -> serde_json::Value {
+    let mut rule = serde_json::json!({
+        "id": vulnerability.id,
+        "name": vulnerability.id,
+        "shortDescription": { "text": vulnerability.title },
+        "fullDescription": {
+            "text": if vulnerability.description.is_empty() {
+                &vulnerability.title
+            } else {
+                &vulnerability.description
+            }
+        },
+        "defaultConfiguration": { "level": sarif_level(&vulnerability.effective_severity()) },
+        "properties": {
+            "tags": ["security", "vulnerability"],
+        },
+    });
+    if let Some(score) = vulnerability.cvss_score {
+        rule["properties"]["security-severity"] = serde_json::json!(format!("{:.1}", score));
+    }
+    if let Some(help_uri) = vulnerability.references.first() {
+        rule["helpUri"] = serde_json::json!(help_uri);
+        rule["help"] = serde_json::json!({ "text": vulnerability.references.join("\n") });
+    }
+    rule
+}
+
+/// Map a severity to a SARIF result level
+fn sarif_level(severity: &Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low => "note",
+    }
+}
+
+/// Order findings by CVSS score descending, unscored last, then severity and ID
+fn compare_by_score(a: &Vulnerability, b: &Vulnerability) -> Ordering {
+    let by_score = match (a.cvss_score, b.cvss_score) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    by_score
+        .then_with(|| b.effective_severity().cmp(&a.effective_severity()))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+/// Result of a vulnerability scan evaluated against a severity threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanOutcome {
+    pub report: VulnerabilityReport,
+    /// Whether any finding met or exceeded the requested threshold
+    pub threshold_exceeded: bool,
+}
+
+impl ScanOutcome {
+    /// Evaluate a report against a severity threshold
+    ///
+    /// # Arguments
+    /// * `report` - Report of a finished scan
+    /// * `fail_on_severity` - Minimum severity that should fail the scan, if any
+    pub fn new(report: VulnerabilityReport, fail_on_severity: Option<Severity>) -> Self {
+        let threshold_exceeded = fail_on_severity
+            .map(|threshold| report.meets_severity(&threshold))
+            .unwrap_or(false);
+        Self {
+            report,
+            threshold_exceeded,
+        }
+    }
+}
+
+/// Scan an npm package for known vulnerabilities
+///
+/// Queries the public advisory databases; see `scan_for_vulnerabilities_in`.
+///
 /// # Arguments
 /// * `package_name` - Name of the package to scan
 /// * `package_version` - Version of the package to scan
-/// 
+///
 /// # Returns
 /// * `Ok(VulnerabilityReport)` with the scan results
-/// * `Err(anyhow::Error)` if the scan fails
+/// * `Err(anyhow::Error)` if every advisory database query fails
 pub async fn scan_for_vulnerabilities(package_name: &str, package_version: &str) -> Result<VulnerabilityReport> {
-    info!("Scanning package {}@{} for vulnerabilities", package_name, package_version);
-    
-    // In a real implementation, this would query security databases like:
-    // - NVD (National Vulnerability Database)
-    // - OSV (Open Source Vulnerabilities)
-    // - Snyk
-    // - GitHub Advisory Database
-    
-    // For now, we'll return an empty report
+    scan_for_vulnerabilities_in(package_name, package_version, Ecosystem::Npm).await
+}
+
+/// Scan a package of any ecosystem for known vulnerabilities
+///
+/// Queries NVD, OSV and GitHub with a default `VulnerabilityDatabaseClient`.
+/// Use `scan_with_client` to set API keys or endpoints, or to scan offline.
+///
+/// # Arguments
+/// * `package_name` - Name of the package to scan
+/// * `package_version` - Version of the package to scan
+/// * `ecosystem` - Ecosystem the package belongs to
+///
+/// # Returns
+/// * `Ok(VulnerabilityReport)` with the scan results
+/// * `Err(anyhow::Error)` if every advisory database query fails
+pub async fn scan_for_vulnerabilities_in(
+    package_name: &str,
+    package_version: &str,
+    ecosystem: Ecosystem,
+) -> Result<VulnerabilityReport> {
+    let client = VulnerabilityDatabaseClient::new();
+    scan_with_client(&client, package_name, package_version, ecosystem).await
+}
+
+/// Scan a package against the NVD, OSV and GitHub advisory databases
+///
+/// The three databases are queried concurrently, each with its own name for
+/// `ecosystem`. NVD and OSV are asked about `package_version` only; GitHub
+/// advisories are reported for every affected range of the package. A
+/// finding reported by several databases, such as a GHSA and the CVE it
+/// aliases, is merged into one that lists every database in `sources`. A
+/// database that fails is logged and noted in the report's warnings, and
+/// the scan goes on with the others.
+///
+/// NVD is searched by keyword, and a CVE it hasn't mapped to products yet
+/// may be about something else entirely. Such CVEs only add detail to a
+/// finding OSV or GitHub attribute to the package; they aren't reported on
+/// their own.
+///
+/// # Arguments
+/// * `client` - Client used to query the databases
+/// * `package_name` - Name of the package to scan
+/// * `package_version` - Version of the package to scan
+/// * `ecosystem` - Ecosystem the package belongs to
+///
+/// # Returns
+/// * `Ok(VulnerabilityReport)` with the merged findings of the databases that answered
+/// * `Err(anyhow::Error)` if every database query fails
+pub async fn scan_with_client(
+    client: &VulnerabilityDatabaseClient,
+    package_name: &str,
+    package_version: &str,
+    ecosystem: Ecosystem,
+) -> Result<VulnerabilityReport> {
+    info!("Scanning {} package {}@{} for vulnerabilities", ecosystem, package_name, package_version);
+
+    let (nvd, osv, github) = tokio::join!(
+        client.query_nvd(package_name, Some(package_version)),
+        client.query_osv(package_name, Some(package_version), ecosystem.osv_name()),
+        client.query_github_advisories(package_name, ecosystem.github_name()),
+    );
+
+    let mut findings = Vec::new();
+    let mut unanalysed = Vec::new();
+    let mut failures = Vec::new();
+    match nvd {
+        Ok(entries) => {
+            for entry in entries {
+                if entry.cve.configurations.as_ref().is_some_and(|configurations| !configurations.is_empty()) {
+                    merge_finding(&mut findings, vulnerability_from_nvd(&entry.cve), []);
+                } else {
+                    unanalysed.push(entry.cve);
+                }
+            }
+        }
+        Err(e) => failures.push((VulnerabilitySource::Nvd, e)),
+    }
+    match osv {
+        Ok(entries) => {
+            for entry in &entries {
+                let aliases = entry.aliases.iter().flatten().cloned();
+                merge_finding(&mut findings, vulnerability_from_osv(entry, package_name, ecosystem), aliases);
+            }
+        }
+        Err(e) => failures.push((VulnerabilitySource::Osv, e)),
+    }
+    match github {
+        Ok(advisories) => {
+            for advisory in &advisories {
+                let aliases = advisory.identifiers.iter().map(|identifier| identifier.value.clone());
+                merge_finding(&mut findings, vulnerability_from_github(advisory, package_name), aliases);
+            }
+        }
+        Err(e) => failures.push((VulnerabilitySource::GithubAdvisory, e)),
+    }
+    for cve in &unanalysed {
+        if findings.iter().any(|(_, ids)| ids.contains(&cve.id)) {
+            merge_finding(&mut findings, vulnerability_from_nvd(cve), []);
+        }
+    }
+
+    let failed: Vec<String> = failures.iter().map(|(source, e)| format!("{} query failed: {:#}", source, e)).collect();
+    if failed.len() == 3 {
+        anyhow::bail!("Every advisory database failed for {}: {}", package_name, failed.join("; "));
+    }
+
     let mut report = VulnerabilityReport::new(package_name.to_string(), package_version.to_string());
-    
-    // Simulate some vulnerabilities for testing
-    if package_name == "test-package-with-vulns" {
-        report.add_vulnerability(Vulnerability {
-            id: "CVE-2023-0001".to_string(),
-            title: "Prototype Pollution".to_string(),
-            description: "The package is vulnerable to Prototype Pollution leading to Remote Code Execution.".to_string(),
-            severity: Severity::High,
-            affected_versions: vec!["< 1.2.3".to_string()],
-            patched_versions: vec!["1.2.3".to_string()],
-            references: vec![
-                "https://nvd.nist.gov/vuln/detail/CVE-2023-0001".to_string(),
-                "https://github.com/advisories/GHSA-xxxx-xxxx-xxxx".to_string(),
-            ],
-        });
-        
-        report.add_vulnerability(Vulnerability {
-            id: "CVE-2023-0002".to_string(),
-            title: "Regular Expression Denial of Service (ReDoS)".to_string(),
-            description: "The package is vulnerable to Regular Expression Denial of Service (ReDoS) via a regex in the parse function.".to_string(),
-            severity: Severity::Medium,
-            affected_versions: vec!["< 2.1.0".to_string()],
-            patched_versions: vec!["2.1.0".to_string()],
-            references: vec![
-                "https://nvd.nist.gov/vuln/detail/CVE-2023-0002".to_string(),
-            ],
-        });
+    for warning in failed {
+        warn!("Scanning {}@{}: {}", package_name, package_version, warning);
+        report.warnings.push(warning);
+    }
+    for (vulnerability, _) in findings {
+        report.add_vulnerability(vulnerability);
     }
-    
     Ok(report)
 }
 
+/// Add a finding, merging it into an earlier one that shares an identifier
+///
+/// The earlier finding keeps its id and declared severity. It gains the new
+/// finding's sources and references, and takes its title, score, and
+/// version ranges where it has none of its own.
+///
+/// # Arguments
+/// * `findings` - Findings so far, each with every identifier it is known by
+/// * `finding` - Finding to add
+/// * `aliases` - Other identifiers of `finding`, such as the CVE of a GHSA
+fn merge_finding(
+    findings: &mut Vec<(Vulnerability, BTreeSet<String>)>,
+    finding: Vulnerability,
+    aliases: impl IntoIterator<Item = String>,
+) {
+    let mut ids: BTreeSet<String> = aliases.into_iter().collect();
+    ids.insert(finding.id.clone());
+    let Some((existing, known_ids)) = findings.iter_mut().find(|(_, known_ids)| !known_ids.is_disjoint(&ids)) else {
+        findings.push((finding, ids));
+        return;
+    };
+
+    known_ids.extend(ids);
+    for source in finding.sources {
+        if !existing.sources.contains(&source) {
+            existing.sources.push(source);
+        }
+    }
+    for reference in finding.references {
+        if !existing.references.contains(&reference) {
+            existing.references.push(reference);
+        }
+    }
+    if existing.title == existing.id {
+        existing.title = finding.title;
+    }
+    if existing.description.is_empty() {
+        existing.description = finding.description;
+    }
+    existing.cvss_score = existing.cvss_score.or(finding.cvss_score);
+    if existing.affected_versions.is_empty() {
+        existing.affected_versions = finding.affected_versions;
+    }
+    if existing.patched_versions.is_empty() {
+        existing.patched_versions = finding.patched_versions;
+    }
+}
+
+/// Convert an NVD CVE into a vulnerability
+///
+/// NVD describes affected products as CPEs rather than package ranges, so
+/// the version lists are left for the other databases to fill in.
+fn vulnerability_from_nvd(cve: &NvdCve) -> Vulnerability {
+    let description = cve
+        .descriptions
+        .iter()
+        .find(|description| description.lang == "en")
+        .or(cve.descriptions.first())
+        .map(|description| description.value.clone())
+        .unwrap_or_default();
+    let cvss_score = cve
+        .metrics
+        .as_ref()
+        .and_then(|metrics| metrics.cvss_metric_v31.as_deref())
+        .and_then(|metrics| metrics.iter().find(|m| m.r#type == "Primary").or(metrics.first()))
+        .map(|metric| metric.cvss_data.base_score);
+
+    Vulnerability {
+        id: cve.id.clone(),
+        title: cve.id.clone(),
+        description,
+        severity: cvss::effective_severity(cve, CvssVersion::default()).unwrap_or(Severity::Medium),
+        cvss_score,
+        affected_versions: vec![],
+        patched_versions: vec![],
+        references: cve.references.iter().map(|r| r.url.clone()).collect(),
+        sources: vec![VulnerabilitySource::Nvd],
+    }
+}
+
+/// Convert an OSV entry into a vulnerability of the given package
+fn vulnerability_from_osv(entry: &OsvEntry, package_name: &str, ecosystem: Ecosystem) -> Vulnerability {
+    let severity = entry
+        .database_specific
+        .as_ref()
+        .and_then(|specific| specific.get("severity"))
+        .and_then(|severity| severity.as_str())
+        .and_then(|severity| severity.parse().ok())
+        .unwrap_or(Severity::Medium);
+
+    let mut affected_versions = Vec::new();
+    let mut patched_versions = Vec::new();
+    let affected = entry.affected.iter().flatten().filter(|affected| {
+        affected.package.name == package_name && affected.package.ecosystem == ecosystem.osv_name()
+    });
+    for event in affected.flat_map(|affected| affected.ranges.iter().flatten()).flat_map(|range| &range.events) {
+        if let Some(introduced) = &event.introduced {
+            affected_versions.push(format!(">= {}", introduced));
+        }
+        if let Some(fixed) = &event.fixed {
+            affected_versions.push(format!("< {}", fixed));
+            patched_versions.push(fixed.clone());
+        }
+    }
+
+    Vulnerability {
+        id: entry.id.clone(),
+        title: entry.summary.clone().unwrap_or_else(|| entry.id.clone()),
+        description: entry.details.clone().or_else(|| entry.summary.clone()).unwrap_or_default(),
+        severity,
+        cvss_score: None,
+        affected_versions,
+        patched_versions,
+        references: entry.references.iter().flatten().map(|r| r.url.clone()).collect(),
+        sources: vec![VulnerabilitySource::Osv],
+    }
+}
+
+/// Convert a GitHub advisory into a vulnerability of the given package
+fn vulnerability_from_github(advisory: &GithubAdvisory, package_name: &str) -> Vulnerability {
+    let affected = advisory
+        .vulnerabilities
+        .iter()
+        .filter(|vulnerability| vulnerability.package.name == package_name);
+
+    Vulnerability {
+        id: advisory.ghsa_id.clone(),
+        title: advisory.summary.clone(),
+        description: advisory.description.clone(),
+        severity: advisory.severity.parse().unwrap_or(Severity::Medium),
+        cvss_score: advisory.cvss.as_ref().map(|cvss| cvss.score),
+        affected_versions: affected.clone().map(|v| v.vulnerable_version_range.clone()).collect(),
+        patched_versions: affected
+            .filter_map(|v| v.first_patched_version.as_ref().map(|p| p.identifier.clone()))
+            .collect(),
+        references: std::iter::once(advisory.html_url.clone())
+            .chain(advisory.references.iter().map(|r| r.url.clone()))
+            .collect(),
+        sources: vec![VulnerabilitySource::GithubAdvisory],
+    }
+}
+
+/// Scan a package for known vulnerabilities and evaluate a severity threshold
+///
+/// # Arguments
+/// * `package_name` - Name of the package to scan
+/// * `package_version` - Version of the package to scan
+/// * `fail_on_severity` - Minimum severity that should fail the scan, if any
+///
+/// # Returns
+/// * `Ok(ScanOutcome)` with the report and whether the threshold was exceeded
+/// * `Err(anyhow::Error)` if the scan fails
+pub async fn scan_with_threshold(
+    package_name: &str,
+    package_version: &str,
+    fail_on_severity: Option<Severity>,
+) -> Result<ScanOutcome> {
+    let report = scan_for_vulnerabilities(package_name, package_version).await?;
+    Ok(ScanOutcome::new(report, fail_on_severity))
+}
+
 /// Check if a package version is affected by a specific vulnerability
 /// 
 /// # Arguments
@@ -136,8 +711,7 @@ pub fn is_version_affected(package_version: &str, affected_versions: &[String])
     // For now, we'll just do a simple check
     affected_versions.iter().any(|constraint| {
         // Simple check for "< X.Y.Z" format
-        if constraint.starts_with("< ") {
-            let constraint_version = &constraint[2..];
+        if let Some(constraint_version) = constraint.strip_prefix("< ") {
             package_version < constraint_version
         } else {
             // For other formats, we'll just return false for now
@@ -149,28 +723,346 @@ pub fn is_version_affected(package_version: &str, affected_versions: &[String])
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_severity_from_cvss_score_boundaries() {
+        assert_eq!(Severity::from_cvss_score(0.0), None);
+        assert_eq!(Severity::from_cvss_score(0.1), Some(Severity::Low));
+        assert_eq!(Severity::from_cvss_score(3.9), Some(Severity::Low));
+        assert_eq!(Severity::from_cvss_score(4.0), Some(Severity::Medium));
+        assert_eq!(Severity::from_cvss_score(6.9), Some(Severity::Medium));
+        assert_eq!(Severity::from_cvss_score(7.0), Some(Severity::High));
+        assert_eq!(Severity::from_cvss_score(8.9), Some(Severity::High));
+        assert_eq!(Severity::from_cvss_score(9.0), Some(Severity::Critical));
+        assert_eq!(Severity::from_cvss_score(10.0), Some(Severity::Critical));
+        assert_eq!(Severity::from_cvss_score(10.1), None);
+    }
+
+    #[test]
+    fn test_severity_from_str() {
+        assert_eq!("HIGH".parse::<Severity>(), Ok(Severity::High));
+        assert_eq!("moderate".parse::<Severity>(), Ok(Severity::Medium));
+        assert!("severe".parse::<Severity>().is_err());
+    }
+
+    #[test]
+    fn test_cvss_score_overrides_declared_severity() {
+        let mut report = VulnerabilityReport::new("pkg".to_string(), "1.0.0".to_string());
+        report.add_vulnerability(Vulnerability {
+            id: "CVE-2023-0003".to_string(),
+            title: "Underrated finding".to_string(),
+            description: "Declared as low but scored critical.".to_string(),
+            severity: Severity::Low,
+            cvss_score: Some(9.8),
+            affected_versions: vec![],
+            patched_versions: vec![],
+            references: vec![],
+            sources: vec![],
+        });
+
+        assert!(report.meets_severity(&Severity::Critical));
+    }
+
+    #[test]
+    fn test_ecosystem_names() {
+        assert_eq!(Ecosystem::default(), Ecosystem::Npm);
+        assert_eq!(Ecosystem::Cargo.osv_name(), "crates.io");
+        assert_eq!(Ecosystem::PyPI.github_name(), "pip");
+        assert_eq!("crates.io".parse::<Ecosystem>(), Ok(Ecosystem::Cargo));
+        assert_eq!("PIP".parse::<Ecosystem>(), Ok(Ecosystem::PyPI));
+        assert!("cpan".parse::<Ecosystem>().is_err());
+    }
+
+    /// Serve one OSV entry, but only to queries for `package` in `ecosystem`
+    async fn mock_osv(package: &str, version: &str, ecosystem: &str, id: &str, aliases: &[&str]) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/query"))
+            .and(body_json(serde_json::json!({
+                "version": version,
+                "package": { "name": package, "ecosystem": ecosystem }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "vulns": [{
+                    "id": id,
+                    "summary": "Fixture vulnerability",
+                    "modified": "2024-01-01T00:00:00Z",
+                    "aliases": aliases,
+                    "affected": [{
+                        "package": { "name": package, "ecosystem": ecosystem },
+                        "ranges": [{
+                            "type": "ECOSYSTEM",
+                            "events": [{ "introduced": "0" }, { "fixed": "9.9.9" }]
+                        }]
+                    }],
+                    "database_specific": { "severity": "HIGH" }
+                }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn test_scan_with_client_queries_osv_with_ecosystem() {
+        for (ecosystem, osv_name, package, id) in [
+            (Ecosystem::PyPI, "PyPI", "requests", "PYSEC-2024-0001"),
+            (Ecosystem::Cargo, "crates.io", "hyper", "RUSTSEC-2024-0001"),
+        ] {
+            let server = mock_osv(package, "1.0.0", osv_name, id, &[]).await;
+            mock_nvd(&server, ResponseTemplate::new(200).set_body_json(nvd_response(vec![]))).await;
+            let mut client = VulnerabilityDatabaseClient::new();
+            client.set_nvd_api_url(format!("{}/nvd", server.uri()));
+            client.set_osv_api_url(server.uri());
+
+            let report = scan_with_client(&client, package, "1.0.0", ecosystem).await.unwrap();
+
+            assert!(report.is_complete());
+            assert_eq!(report.vulnerabilities.len(), 1);
+            let vulnerability = &report.vulnerabilities[0];
+            assert_eq!(vulnerability.id, id);
+            assert_eq!(vulnerability.severity, Severity::High);
+            assert_eq!(vulnerability.affected_versions, vec![">= 0", "< 9.9.9"]);
+            assert_eq!(vulnerability.patched_versions, vec!["9.9.9"]);
+            assert_eq!(vulnerability.sources, vec![VulnerabilitySource::Osv]);
+        }
+    }
+
+    async fn mock_nvd(server: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("GET")).and(path("/nvd")).respond_with(response).mount(server).await;
+    }
+
+    fn nvd_response(cves: Vec<serde_json::Value>) -> serde_json::Value {
+        serde_json::json!({
+            "results_per_page": cves.len(),
+            "start_index": 0,
+            "total_results": cves.len(),
+            "format": "NVD_CVE",
+            "version": "2.0",
+            "timestamp": "2024-01-01T00:00:00.000",
+            "vulnerabilities": cves.into_iter().map(|cve| serde_json::json!({ "cve": cve })).collect::<Vec<_>>()
+        })
+    }
+
+    /// An NVD entry without CPE configurations, scored 9.8 by NVD itself
+    fn nvd_cve(id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "published": "2024-01-01T00:00:00.000",
+            "last_modified": "2024-01-01T00:00:00.000",
+            "descriptions": [{ "lang": "en", "value": "Fixture CVE" }],
+            "metrics": {
+                "cvssMetricV31": [{
+                    "source": "nvd@nist.gov",
+                    "type": "Primary",
+                    "cvss_data": {
+                        "version": "3.1",
+                        "vector_string": "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H",
+                        "base_score": 9.8,
+                        "base_severity": "CRITICAL"
+                    },
+                    "base_severity": "CRITICAL",
+                    "exploitability_score": 3.9,
+                    "impact_score": 5.9
+                }]
+            },
+            "references": [{ "url": format!("https://nvd.nist.gov/vuln/detail/{}", id) }]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_scan_with_client_merges_sources_when_github_fails() {
+        let server = mock_osv("lodash", "4.17.15", "npm", "GHSA-p6mc-m468-83gw", &["CVE-2020-8203"]).await;
+        mock_nvd(&server, ResponseTemplate::new(200).set_body_json(nvd_response(vec![nvd_cve("CVE-2020-8203")]))).await;
+        Mock::given(method("GET"))
+            .and(path("/advisories"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut client = VulnerabilityDatabaseClient::with_api_keys(None, Some("github-token".to_string()));
+        client.set_nvd_api_url(format!("{}/nvd", server.uri()));
+        client.set_osv_api_url(server.uri());
+        client.set_github_api_url(server.uri());
+
+        let report = scan_with_client(&client, "lodash", "4.17.15", Ecosystem::Npm).await.unwrap();
+
+        // NVD and OSV report the same finding under its CVE and GHSA ids
+        assert_eq!(report.vulnerabilities.len(), 1);
+        let vulnerability = &report.vulnerabilities[0];
+        assert_eq!(vulnerability.id, "GHSA-p6mc-m468-83gw");
+        assert_eq!(vulnerability.sources, vec![VulnerabilitySource::Osv, VulnerabilitySource::Nvd]);
+        assert_eq!(vulnerability.title, "Fixture vulnerability");
+        assert_eq!(vulnerability.cvss_score, Some(9.8));
+        assert_eq!(vulnerability.patched_versions, vec!["9.9.9"]);
+
+        assert!(!report.is_complete());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("GitHub Advisory Database query failed"), "{:?}", report.warnings);
+    }
 
     #[tokio::test]
-    async fn test_scan_for_vulnerabilities_no_vulns() {
-        let report = scan_for_vulnerabilities("safe-package", "1.0.0").await.unwrap();
-        assert_eq!(report.vulnerabilities.len(), 0);
+    async fn test_scan_with_client_drops_uncorroborated_unanalysed_cves() {
+        let server = mock_osv("lodash", "4.17.15", "npm", "GHSA-p6mc-m468-83gw", &["CVE-2020-8203"]).await;
+        let cves = vec![nvd_cve("CVE-2020-8203"), nvd_cve("CVE-2099-0001")];
+        mock_nvd(&server, ResponseTemplate::new(200).set_body_json(nvd_response(cves))).await;
+        let mut client = VulnerabilityDatabaseClient::new();
+        client.set_nvd_api_url(format!("{}/nvd", server.uri()));
+        client.set_osv_api_url(server.uri());
+
+        let report = scan_with_client(&client, "lodash", "4.17.15", Ecosystem::Npm).await.unwrap();
+
+        // CVE-2099-0001 only matched the keyword search, so nothing ties it to lodash
+        let ids: Vec<&str> = report.vulnerabilities.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, vec!["GHSA-p6mc-m468-83gw"]);
+        assert!(report.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_scan_with_client_tolerates_failing_nvd() {
+        let server = mock_osv("lodash", "4.17.15", "npm", "GHSA-p6mc-m468-83gw", &[]).await;
+        mock_nvd(&server, ResponseTemplate::new(503)).await;
+        let mut client = VulnerabilityDatabaseClient::new();
+        client.set_nvd_api_url(format!("{}/nvd", server.uri()));
+        client.set_osv_api_url(server.uri());
+
+        let report = scan_with_client(&client, "lodash", "4.17.15", Ecosystem::Npm).await.unwrap();
+
+        assert_eq!(report.vulnerabilities.len(), 1);
+        assert_eq!(report.vulnerabilities[0].sources, vec![VulnerabilitySource::Osv]);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("NVD query failed"), "{:?}", report.warnings);
     }
 
     #[tokio::test]
-    async fn test_scan_for_vulnerabilities_with_vulns() {
-        let report = scan_for_vulnerabilities("test-package-with-vulns", "1.0.0").await.unwrap();
-        assert_eq!(report.vulnerabilities.len(), 2);
-        
-        let highest_severity = report.highest_severity().unwrap();
-        assert_eq!(*highest_severity, Severity::High);
-        
-        assert!(!report.has_critical_vulnerabilities());
+    async fn test_scan_with_client_fails_when_every_source_fails() {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        let mut client = VulnerabilityDatabaseClient::with_api_keys(None, Some("github-token".to_string()));
+        client.set_nvd_api_url(format!("{}/nvd", server.uri()));
+        client.set_osv_api_url(server.uri());
+        client.set_github_api_url(server.uri());
+
+        let error = scan_with_client(&client, "lodash", "4.17.15", Ecosystem::Npm).await.unwrap_err();
+        assert!(error.to_string().contains("Every advisory database failed"), "{}", error);
+    }
+
+    fn finding(id: &str, severity: Severity, cvss_score: Option<f64>) -> Vulnerability {
+        Vulnerability {
+            id: id.to_string(),
+            title: id.to_string(),
+            description: String::new(),
+            severity,
+            cvss_score,
+            affected_versions: vec![],
+            patched_versions: vec![],
+            references: vec![],
+            sources: vec![],
+        }
+    }
+
+    fn mixed_report() -> VulnerabilityReport {
+        let mut report = VulnerabilityReport::new("pkg".to_string(), "1.0.0".to_string());
+        report.add_vulnerability(finding("GHSA-unscored-low", Severity::Low, None));
+        report.add_vulnerability(finding("CVE-medium", Severity::High, Some(5.3)));
+        report.add_vulnerability(finding("GHSA-unscored-high", Severity::High, None));
+        report.add_vulnerability(finding("CVE-critical", Severity::Low, Some(9.8)));
+        report.add_vulnerability(finding("CVE-high-b", Severity::High, Some(7.5)));
+        report.add_vulnerability(finding("CVE-high-a", Severity::High, Some(7.5)));
+        report
+    }
+
+    #[test]
+    fn test_scan_outcome_threshold() {
+        assert!(ScanOutcome::new(mixed_report(), Some(Severity::Critical)).threshold_exceeded);
+        assert!(!ScanOutcome::new(mixed_report(), None).threshold_exceeded);
+
+        let mut report = VulnerabilityReport::new("pkg".to_string(), "1.0.0".to_string());
+        report.add_vulnerability(finding("CVE-medium", Severity::Medium, Some(5.3)));
+        assert!(!ScanOutcome::new(report.clone(), Some(Severity::High)).threshold_exceeded);
+        assert!(ScanOutcome::new(report, Some(Severity::Medium)).threshold_exceeded);
+    }
+
+    #[test]
+    fn test_sort_by_score() {
+        let mut report = mixed_report();
+        report.sort_by_score();
+
+        let ids: Vec<&str> = report.vulnerabilities.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["CVE-critical", "CVE-high-a", "CVE-high-b", "CVE-medium", "GHSA-unscored-high", "GHSA-unscored-low"]
+        );
+        assert_eq!(report.highest_severity(), Some(Severity::Critical));
+    }
+
+    #[test]
+    fn test_group_by_severity() {
+        let report = mixed_report();
+        let groups = report.group_by_severity();
+
+        let ids = |severity: Severity| -> Vec<&str> { groups[&severity].iter().map(|v| v.id.as_str()).collect() };
+        assert_eq!(ids(Severity::Critical), vec!["CVE-critical"]);
+        assert_eq!(ids(Severity::High), vec!["CVE-high-a", "CVE-high-b", "GHSA-unscored-high"]);
+        // The score puts the declared-high finding in the medium band
+        assert_eq!(ids(Severity::Medium), vec!["CVE-medium"]);
+        assert_eq!(ids(Severity::Low), vec!["GHSA-unscored-low"]);
+        assert_eq!(groups.keys().next_back(), Some(&Severity::Critical));
+
+        let empty = VulnerabilityReport::new("pkg".to_string(), "1.0.0".to_string());
+        assert!(empty.group_by_severity().is_empty());
+        assert_eq!(empty.highest_severity(), None);
+    }
+
+    #[test]
+    fn test_to_sarif() {
+        let mut report = VulnerabilityReport::new("lodash".to_string(), "4.17.15".to_string());
+        let mut prototype_pollution = finding("CVE-2020-8203", Severity::High, Some(7.4));
+        prototype_pollution.title = "Prototype pollution in zipObjectDeep".to_string();
+        prototype_pollution.patched_versions = vec!["4.17.19".to_string()];
+        prototype_pollution.references = vec!["https://nvd.nist.gov/vuln/detail/CVE-2020-8203".to_string()];
+        report.add_vulnerability(prototype_pollution);
+        let mut redos = finding("GHSA-29mw-wpgm-hmr9", Severity::Medium, None);
+        redos.references = vec!["https://github.com/advisories/GHSA-29mw-wpgm-hmr9".to_string()];
+        report.add_vulnerability(redos);
+
+        let sarif: serde_json::Value = serde_json::from_str(&report.to_sarif().unwrap()).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+        assert!(sarif["$schema"].as_str().unwrap().contains("sarif-2.1.0"));
+
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "package-fast");
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0]["helpUri"], "https://nvd.nist.gov/vuln/detail/CVE-2020-8203");
+        assert_eq!(rules[0]["properties"]["security-severity"], "7.4");
+        assert_eq!(rules[1]["helpUri"], "https://github.com/advisories/GHSA-29mw-wpgm-hmr9");
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["ruleId"], "CVE-2020-8203");
+        assert_eq!(results[0]["ruleIndex"], 0);
+        assert_eq!(results[0]["level"], "error");
+        assert!(results[0]["message"]["text"].as_str().unwrap().contains("lodash@4.17.15"));
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "node_modules/lodash/package.json"
+        );
+        assert_eq!(results[1]["ruleId"], "GHSA-29mw-wpgm-hmr9");
+        assert_eq!(results[1]["ruleIndex"], 1);
+        assert_eq!(results[1]["level"], "warning");
     }
 
     #[test]
     fn test_is_version_affected() {
-        assert!(is_version_affected("1.0.0", &vec!["< 1.2.3".to_string()]));
-        assert!(!is_version_affected("1.5.0", &vec!["< 1.2.3".to_string()]));
-        assert!(!is_version_affected("1.2.3", &vec!["< 1.2.3".to_string()]));
+        assert!(is_version_affected("1.0.0", &["< 1.2.3".to_string()]));
+        assert!(!is_version_affected("1.5.0", &["< 1.2.3".to_string()]));
+        assert!(!is_version_affected("1.2.3", &["< 1.2.3".to_string()]));
     }
 }
\ No newline at end of file